@@ -1,28 +1,47 @@
-//! Check CPU usage of the currently-running container
+//! Check CPU usage of a container, by default the currently-running one
 
+use std::cmp::Ordering;
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use serde::Deserialize;
 use structopt::StructOpt;
 
+use tabin_plugins::check::worst_of;
+use tabin_plugins::cli::{CGroupSelector, CommonArgs, Hogs, Roots, Thresholds};
 use tabin_plugins::linux::{Jiffies, Ratio};
+use tabin_plugins::perfdata::{PerfData, Unit};
 use tabin_plugins::procfs::{Calculations, LoadProcsError, ProcField, ProcFsError, RunningProcs};
-use tabin_plugins::sys::fs::cgroup::cpu::shares;
-use tabin_plugins::sys::fs::cgroup::cpuacct::Stat as CGroupStat;
-use tabin_plugins::Status;
+use tabin_plugins::sys::fs::cgroup::{CGroup, CpuThrottling, CpuUsage};
+use tabin_plugins::watchdog;
+use tabin_plugins::{CheckResult, Status, Threshold};
 
 /// Check the cpu usage of the currently-running container.
 ///
-/// This must be run from inside the container to be checked.
+/// By default this checks the container that it is running in. From the
+/// host, select a container with --cgroup, --pid or --container-id, and add
+/// --children to check every container under e.g. a slice and report the
+/// worst. Both cgroup v1 and v2 are supported. Thresholds default to going
+/// critical above 80 percent.
+///
+/// If the container has a CFS quota (cpu.cfs_quota_us, or cpu.max on cgroup
+/// v2), as Docker and Kubernetes CPU limits set, thresholds are scaled by the
+/// number of CPUs it allows. --warn-throttled and --crit-throttled alert on
+/// the percent of scheduling periods in the sample in which the container was
+/// throttled for using up its quota.
 #[derive(Deserialize, StructOpt, Debug)]
 #[structopt(
     name = "check-container-cpu (part of tabin-plugins)",
     setting = structopt::clap::AppSettings::ColoredHelp,
     after_help = "About usage percentages:
 
-    If you don't specify '--shares-per-cpu', percentages should be specified
-    relative to a single CPU's usage. So if you have a process that you want to
+    If the container has a CFS quota, percentages should be specified out of
+    100% of the CPUs that the quota allows, and will be scaled by them just as
+    for '--shares-per-cpu'. A quota of 150000us every 100000us is 1.5 CPUs,
+    so '--crit 90' goes critical above 135% of one CPU.
+
+    Otherwise, if you don't specify '--shares-per-cpu', percentages should be
+    specified relative to a single CPU's usage. So if you have a process that you want to
     be allowed to use 4 CPUs worth of processor time, and you were planning on
     going critical at 90%, you should specify something like '--crit 360'
 
@@ -45,7 +64,9 @@ use tabin_plugins::Status;
     scale it for your process.
 
     Here are some examples, where 'shares granted' is the value in
-    /sys/fs/cgroup/cpu/cpu.shares:
+    /sys/fs/cgroup/cpu/cpu.shares. On cgroup v2 shares are converted from
+    /sys/fs/cgroup/cpu.weight the way runc converts them to it, so 1024 shares
+    show up as about 998:
 
         * args: --shares-per-cpu 1024 --crit 90
           shares granted: 1024
@@ -59,26 +80,26 @@ use tabin_plugins::Status;
 "
 )]
 struct Args {
-    #[structopt(
-        short = "w",
-        long = "warn",
-        help = "Percent to warn at",
-        default_value = "80"
-    )]
-    warn: f64,
-    #[structopt(
-        short = "c",
-        long = "crit",
-        help = "Percent to go critical at",
-        default_value = "80"
-    )]
-    crit: f64,
+    #[structopt(flatten)]
+    thresholds: Thresholds,
     #[structopt(
         long = "shares-per-cpu",
         help = "The number of CPU shares given to a cgroup when \
                 it has exactly one CPU allocated to it."
     )]
     shares_per_cpu: Option<u32>,
+    #[structopt(
+        long = "warn-throttled",
+        help = "Percent of CFS periods in which the container was throttled \
+                to warn at, as a nagios range"
+    )]
+    warn_throttled: Option<Threshold>,
+    #[structopt(
+        long = "crit-throttled",
+        help = "Percent of CFS periods in which the container was throttled \
+                to go critical at, as a nagios range"
+    )]
+    crit_throttled: Option<Threshold>,
 
     #[structopt(
         short = "s",
@@ -88,90 +109,303 @@ struct Args {
         default_value = "5"
     )]
     sample: u64,
-    #[structopt(
-        long = "show-hogs",
-        name = "count",
-        help = "Show <count> most cpu-intensive processes in this container.",
-        default_value = "0"
-    )]
-    show_hogs: usize,
+    #[structopt(flatten)]
+    selector: CGroupSelector,
+    #[structopt(flatten)]
+    hogs: Hogs,
+    #[structopt(flatten)]
+    roots: Roots,
+    #[structopt(flatten)]
+    common: CommonArgs,
 }
 
+const DEFAULT_WARN: f64 = 80.0;
+const DEFAULT_CRIT: f64 = 80.0;
+
 fn main() {
     let args: Args = Args::from_args();
-
-    let start_cpu = Calculations::load_per_cpu().unwrap();
-    let start_container = CGroupStat::load();
-    let mut start_per_proc = None;
-    let (cpus, crit, warn) = if let Some(shares_per_cpu) = args.shares_per_cpu {
-        let available_cpus = shares().unwrap() as f64 / shares_per_cpu as f64;
-        let crit = args.crit * available_cpus;
-        let warn = args.warn * available_cpus;
-        (Some(available_cpus), crit, warn)
+    let started = Instant::now();
+    args.common.init();
+    let context = args.roots.context();
+
+    watchdog::phase("reading cpu usage");
+    let start_cpu = Calculations::load_per_cpu_in(&context).unwrap_or_else(|e| {
+        args.common
+            .fail(format!("Unable to read cpu usage: {}", e), started)
+    });
+    let cgroup = args.selector.cgroup(&context).unwrap_or_else(|e| {
+        args.common.fail(
+            format!("Unable to find the cgroup to check: {}", e),
+            started,
+        )
+    });
+    let targets = if args.selector.children {
+        cgroup.children().unwrap_or_else(|e| {
+            args.common
+                .fail(format!("Unable to list child cgroups: {}", e), started)
+        })
     } else {
-        (None, args.crit, args.warn)
+        vec![cgroup.clone()]
     };
+    let samples = targets
+        .into_iter()
+        .map(|cgroup| Sample::start(&args, cgroup))
+        .collect::<Vec<_>>();
+    let mut start_per_proc = None;
 
     let mut per_proc_errors = vec![];
-    if args.show_hogs > 0 {
-        start_per_proc = Some(load_procs(&mut per_proc_errors));
+    if args.hogs.show_hogs > 0 {
+        start_per_proc = Some(load_procs(&mut per_proc_errors, &args, started));
     }
 
+    watchdog::phase(format!("sampling cpu usage for {}s", args.sample));
     sleep(Duration::from_millis(args.sample * 1000));
 
-    let end_cpu = Calculations::load_per_cpu().unwrap();
-    let end_container = CGroupStat::load();
+    let end_cpu = Calculations::load_per_cpu_in(&context).unwrap_or_else(|e| {
+        args.common
+            .fail(format!("Unable to read cpu usage: {}", e), started)
+    });
 
     let median_jiffies = find_median_jiffies_used(&start_cpu, &end_cpu);
 
-    let container_usage = end_container.unwrap().total() - start_container.unwrap().total();
-    let percent = container_usage.duration().ratio(&median_jiffies.duration());
-
-    let mut status = Status::Ok;
-    let mut cpu_msg = String::new();
-    let mut percent_msg = "";
-    if let Some(cpus) = cpus {
-        cpu_msg = format!(" of {:.1} CPUs", cpus);
-        percent_msg = " of 1"
-    }
-    if percent > crit as f64 {
-        println!(
-            "CRITICAL: Container is using {}%{} CPU (> {}%{})",
-            percent, percent_msg, args.crit, cpu_msg
-        );
-        status = Status::Critical;
-    } else if percent > warn as f64 {
-        println!(
-            "WARNING: Container is using {}%{} CPU (> {}%{})",
-            percent, percent_msg, args.warn, cpu_msg
-        );
-        status = Status::Warning;
+    let mut checked = samples
+        .into_iter()
+        .map(|sample| {
+            let name = sample.cgroup.name();
+            (name, sample.finish(&args, median_jiffies))
+        })
+        .collect::<Vec<_>>();
+    let mut result = if args.selector.children {
+        checked.sort_by(|a, b| {
+            let percent = |checked: &Result<(f64, CheckResult), String>| {
+                checked.as_ref().map(|c| c.0).unwrap_or(-1.0)
+            };
+            percent(&b.1)
+                .partial_cmp(&percent(&a.1))
+                .unwrap_or(Ordering::Equal)
+        });
+        worst_of(
+            &format!("child cgroups of {}", cgroup.path().display()),
+            checked
+                .into_iter()
+                .map(|(name, checked)| match checked {
+                    Ok((_, result)) => (name, result),
+                    Err(e) => (name, args.common.error(e)),
+                })
+                .collect(),
+            args.selector.show_children,
+        )
     } else {
-        println!(
-            "OK: Container is using {}%{} CPU (< {}%{})",
-            percent, percent_msg, args.warn, cpu_msg
-        );
-    }
-    if args.show_hogs > 0 {
-        let end_per_proc = load_procs(&mut per_proc_errors);
+        match checked.remove(0).1 {
+            Ok((_, result)) => result,
+            Err(e) => args.common.fail(e, started),
+        }
+    };
+    if args.hogs.show_hogs > 0 {
+        let end_per_proc = load_procs(&mut per_proc_errors, &args, started);
         let start_per_proc = start_per_proc.unwrap();
         let mut per_proc = end_per_proc.percent_cpu_util_since(&start_per_proc, median_jiffies);
         per_proc.sort_by_field(ProcField::TotalCpu);
-        println!(
+        let mut hogs = per_proc.iter().collect::<Vec<_>>();
+        if args.selector.is_selected() {
+            match cgroup.pids() {
+                Ok(pids) => hogs.retain(|usage| pids.contains(&usage.process.stat.pid.as_raw())),
+                Err(e) => {
+                    // rather than show every process on the host as a hog
+                    result.add_line(format!("Unable to list processes in cgroup: {}", e));
+                    hogs.clear();
+                }
+            }
+        }
+        result.add_line(format!(
             "INFO [check-container-cpu]: {} processes running, top {} cpu hogs:",
-            per_proc.len(),
-            args.show_hogs
-        );
-        for usage in per_proc.iter().take(args.show_hogs) {
-            println!(
+            hogs.len(),
+            args.hogs.show_hogs
+        ));
+        for usage in hogs.iter().take(args.hogs.show_hogs) {
+            result.add_line(format!(
                 "[{:>5}]{:>5.1}%: {}",
                 usage.process.stat.pid,
                 usage.total,
                 usage.process.useful_cmdline()
-            );
+            ));
         }
     }
-    status.exit();
+    args.common.exit(result, started);
+}
+
+/// The start of sampling one cgroup's cpu usage
+///
+/// Errors are kept as messages until the end, so that one unreadable child
+/// cgroup doesn't stop the others from being checked.
+struct Sample {
+    cgroup: CGroup,
+    start: Result<CpuUsage, String>,
+    /// The number of CPUs worth of shares if `--shares-per-cpu` was given,
+    /// otherwise the number the CFS quota allows, if there is one
+    cpus: Result<Option<f64>, String>,
+    throttling: Result<CpuThrottling, String>,
+}
+
+impl Sample {
+    fn start(args: &Args, cgroup: CGroup) -> Sample {
+        let start = cgroup
+            .cpu_usage()
+            .map_err(|e| format!("Unable to read cgroup cpu usage: {}", e));
+        let cpus = match args.shares_per_cpu {
+            Some(shares_per_cpu) => cgroup
+                .cpu_shares()
+                .map(|shares| Some(shares as f64 / f64::from(shares_per_cpu)))
+                .map_err(|e| format!("Unable to read cpu shares: {}", e)),
+            None => cgroup
+                .cpu_quota()
+                .map(|quota| quota.cpus())
+                .map_err(|e| format!("Unable to read cpu quota: {}", e)),
+        };
+        let throttling = cgroup
+            .cpu_throttling()
+            .map_err(|e| format!("Unable to read cpu throttling: {}", e));
+        Sample {
+            cgroup,
+            start,
+            cpus,
+            throttling,
+        }
+    }
+
+    /// The percent of a cpu used since `start`, and the check result for it
+    fn finish(self, args: &Args, median_jiffies: Jiffies) -> Result<(f64, CheckResult), String> {
+        let start = self.start?;
+        let cpus = self.cpus?;
+        let end = self
+            .cgroup
+            .cpu_usage()
+            .map_err(|e| format!("Unable to read cgroup cpu usage: {}", e))?;
+        let used = end
+            .total()
+            .checked_sub(start.total())
+            .ok_or("cgroup cpu usage went backwards, was the cgroup recreated?")?;
+        let percent = 100.0 * used.ratio(&median_jiffies.duration());
+        let mut result = check(args, percent, cpus);
+        // Only containers with a quota are throttled, so not being able to
+        // tell is only an error if it was asked about
+        let cgroup = &self.cgroup;
+        let throttled = self.throttling.and_then(|start| {
+            cgroup
+                .cpu_throttling()
+                .map(|end| end.since(&start))
+                .map_err(|e| format!("Unable to read cpu throttling: {}", e))
+        });
+        match throttled {
+            Ok(throttled) => result.merge(check_throttling(args, &throttled)),
+            Err(e) if args.warn_throttled.is_some() || args.crit_throttled.is_some() => {
+                return Err(e)
+            }
+            Err(_) => {}
+        }
+        Ok((percent, result))
+    }
+}
+
+/// Compare the container's cpu usage against the thresholds
+///
+/// `cpus` is the number of CPUs worth of shares the container has, if
+/// `--shares-per-cpu` was given, the thresholds are scaled by it.
+fn check(args: &Args, percent: f64, cpus: Option<f64>) -> CheckResult {
+    let (unscaled_crit, unscaled_warn) = (
+        args.thresholds.crit_or(DEFAULT_CRIT),
+        args.thresholds.warn_or(DEFAULT_WARN),
+    );
+    let crit = unscaled_crit.scale(cpus.unwrap_or(1.0));
+    let warn = unscaled_warn.scale(cpus.unwrap_or(1.0));
+    let mut perf = PerfData::new("cpu", percent)
+        .unit(Unit::Percent)
+        .warn(warn)
+        .crit(crit)
+        .min(0.0);
+    let mut cpu_msg = String::new();
+    let mut percent_msg = "";
+    if let Some(cpus) = cpus {
+        perf = perf.max(100.0 * cpus);
+        cpu_msg = format!(" of {:.1} CPUs", cpus);
+        percent_msg = " of 1"
+    }
+
+    let mut result = if crit.is_violated(percent) {
+        CheckResult::new(
+            Status::Critical,
+            format!(
+                "Container is using {}%{} CPU ({}{})",
+                percent,
+                percent_msg,
+                unscaled_crit.describe(),
+                cpu_msg
+            ),
+        )
+    } else if warn.is_violated(percent) {
+        CheckResult::new(
+            Status::Warning,
+            format!(
+                "Container is using {}%{} CPU ({}{})",
+                percent,
+                percent_msg,
+                unscaled_warn.describe(),
+                cpu_msg
+            ),
+        )
+    } else {
+        CheckResult::new(
+            Status::Ok,
+            format!(
+                "Container is using {}%{} CPU ({}{})",
+                percent,
+                percent_msg,
+                unscaled_warn.describe_ok(),
+                cpu_msg
+            ),
+        )
+    };
+    result.add_perf(perf);
+    result
+}
+
+/// Compare the percent of periods that were throttled against the thresholds
+fn check_throttling(args: &Args, throttled: &CpuThrottling) -> CheckResult {
+    let percent = throttled.percent_throttled().unwrap_or(0.0);
+    let summary = format!(
+        "Container was throttled in {:.1}% of {} periods, for {:.1}s",
+        percent,
+        throttled.periods,
+        throttled.throttled_time.as_secs_f64()
+    );
+    let mut perf = PerfData::new("throttled", percent)
+        .unit(Unit::Percent)
+        .min(0.0)
+        .max(100.0);
+    if let Some(warn) = args.warn_throttled {
+        perf = perf.warn(warn);
+    }
+    if let Some(crit) = args.crit_throttled {
+        perf = perf.crit(crit);
+    }
+    let violated = |threshold: Option<Threshold>| {
+        threshold.filter(|threshold| throttled.periods > 0 && threshold.is_violated(percent))
+    };
+    let mut result = if let Some(crit) = violated(args.crit_throttled) {
+        CheckResult::new(
+            Status::Critical,
+            format!("{} ({})", summary, crit.describe()),
+        )
+    } else if let Some(warn) = violated(args.warn_throttled) {
+        CheckResult::new(
+            Status::Warning,
+            format!("{} ({})", summary, warn.describe()),
+        )
+    } else {
+        CheckResult::new(Status::Ok, summary)
+    };
+    result.add_perf(perf);
+    result
 }
 
 // These all should be extremely similar to each other, so just taking the
@@ -187,17 +421,16 @@ fn find_median_jiffies_used(start: &[Calculations], end: &[Calculations]) -> Jif
 }
 
 /// Load currently running procs, and die if there is a surprising error
-fn load_procs(load_errors: &mut Vec<ProcFsError>) -> RunningProcs {
-    match RunningProcs::currently_running() {
+fn load_procs(load_errors: &mut Vec<ProcFsError>, args: &Args, started: Instant) -> RunningProcs {
+    let common = &args.common;
+    watchdog::phase("loading processes");
+    match RunningProcs::currently_running_in(&args.roots.context()) {
         Ok(procs) => procs,
         Err(ProcFsError::LoadProcsError(LoadProcsError { procs, errors })) => {
-            load_errors.extend(errors.into_iter());
+            load_errors.extend(errors);
             procs
         }
-        Err(err) => {
-            eprintln!("Unexpected error loading procs: {}", err);
-            Status::Unknown.exit()
-        }
+        Err(err) => common.fail(format!("Unexpected error loading procs: {}", err), started),
     }
 }
 
@@ -205,22 +438,61 @@ fn load_procs(load_errors: &mut Vec<ProcFsError>) -> RunningProcs {
 mod unit {
     use structopt::StructOpt;
 
-    use super::{find_median_jiffies_used, Args};
+    use std::time::Duration;
+
+    use super::{check, check_throttling, find_median_jiffies_used, Args};
     use tabin_plugins::linux::Jiffies;
     use tabin_plugins::procfs::Calculations; //, RunningProcs};
+    use tabin_plugins::sys::fs::cgroup::CpuThrottling;
+    use tabin_plugins::{Status, Threshold};
 
     #[test]
     fn opts_parse() {
         let args: Args = Args::from_iter(["arg0", "--crit", "480", "--warn", "20"].iter());
-        assert_eq!(args.crit, 480.0);
+        assert_eq!(args.thresholds.crit, Some(Threshold::above(480.0)));
         assert_eq!(args.shares_per_cpu, None);
 
         let args: Args =
             Args::from_iter(["arg0", "--crit", "480", "--shares-per-cpu", "100"].iter());
-        assert_eq!(args.crit, 480.0);
+        assert_eq!(args.thresholds.crit, Some(Threshold::above(480.0)));
         assert_eq!(args.shares_per_cpu, Some(100));
     }
 
+    #[test]
+    fn check_scales_by_cpus() {
+        let args: Args = Args::from_iter(["arg0", "--crit", "90", "--warn", "50"].iter());
+        let result = check(&args, 150.0, Some(2.0));
+        assert_eq!(result.status, Status::Warning);
+        assert_eq!(
+            result.summary,
+            "Container is using 150% of 1 CPU (> 50 of 2.0 CPUs)"
+        );
+        assert_eq!(result.perfdata[0].to_string(), "cpu=150%;100;180;0;200");
+    }
+
+    #[test]
+    fn check_throttling_alerts_on_percent_of_periods() {
+        let args: Args = Args::from_iter(["arg0", "--warn-throttled", "10"].iter());
+        let throttled = CpuThrottling {
+            periods: 50,
+            throttled_periods: 10,
+            throttled_time: Duration::from_millis(1500),
+        };
+        let result = check_throttling(&args, &throttled);
+        assert_eq!(result.status, Status::Warning);
+        assert_eq!(
+            result.summary,
+            "Container was throttled in 20.0% of 50 periods, for 1.5s (> 10)"
+        );
+        assert_eq!(result.perfdata[0].to_string(), "throttled=20%;10;;0;100");
+
+        let args: Args = Args::from_iter(["arg0"].iter());
+        assert_eq!(check_throttling(&args, &throttled).status, Status::Ok);
+        let args: Args = Args::from_iter(["arg0", "--crit-throttled", "0"].iter());
+        let idle = CpuThrottling::default();
+        assert_eq!(check_throttling(&args, &idle).status, Status::Ok);
+    }
+
     fn start() -> Calculations {
         Calculations {
             user: Jiffies::new(100),
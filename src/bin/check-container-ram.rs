@@ -1,43 +1,68 @@
-//! Check RAM usage of the currently-running container
+//! Check RAM usage of a container, by default the currently-running one
 
-use std::cmp::max;
+use std::cmp::{min, Ordering};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use structopt::StructOpt;
 
-use tabin_plugins::linux::{bytes_to_human_size, pages_to_human_size};
-use tabin_plugins::procfs::{LoadProcsError, MemInfo, ProcFsError, RunningProcs};
-use tabin_plugins::sys::fs::cgroup::memory::{limit_in_bytes, Stat};
-use tabin_plugins::Status;
+use tabin_plugins::check::worst_of;
+use tabin_plugins::cli::{CGroupSelector, CommonArgs, RamHogs, Roots, Thresholds};
+use tabin_plugins::linux::bytes_to_human_size;
+use tabin_plugins::perfdata::{PerfData, Unit};
+use tabin_plugins::procfs::{LoadProcsError, MemInfo, ProcFsError, ProcessTree, RunningProcs};
+use tabin_plugins::sys::fs::cgroup::{CGroup, MemoryEventCounts};
+use tabin_plugins::sys::SysFsError;
+use tabin_plugins::watchdog;
+use tabin_plugins::{CheckResult, Status, Threshold};
 
-/// Check the RAM usage of the currently-running container.
+/// Check the RAM usage of a container.
 ///
-/// This must be run from inside the container to be checked.
+/// By default this checks the container that it is running in. From the
+/// host, select a container with --cgroup, --pid or --container-id, and add
+/// --children to check every container under e.g. a slice and report the
+/// worst. Both cgroup v1 and v2 are supported.
 ///
 /// This checks as a ratio of the limit specified in the cgroup memory limit, and
 /// if there is no limit set (or the limit is greater than the total memory
 /// available on the system) this checks against the total system memory.
+/// Thresholds are percentages of the limit, and default to warning above 85
+/// and critical above 95.
+///
+/// With --oom-state the check also remembers how many processes the OOM
+/// killer has killed in the cgroup and how often it has hit its limit, and
+/// goes critical if anything was OOM killed since the last run, or warns if
+/// the limit was hit.
 #[derive(Deserialize, StructOpt, Debug)]
 #[structopt(
     name = "check-container-ram (part of tabin-plugins)",
     setting = structopt::clap::AppSettings::ColoredHelp,
+    after_help = "About measures:
+
+    '--measure' picks which number is compared against the limit:
+
+        * rss: anonymous memory, i.e. memory that can only be freed by
+          swapping or killing a process. This is the default.
+        * working-set: all memory charged to the cgroup except for page cache
+          the kernel is about to drop (inactive_file). This is what the
+          kubelet uses to decide which pods to evict, and what 'kubectl top'
+          shows.
+        * usage: all memory charged to the cgroup, including page cache. The
+          kernel reclaims cache before it OOM kills anything, so this is
+          usually close to the limit on a busy container.
+
+    On cgroup v1 rss and the page cache include descendant cgroups when the
+    kernel reports hierarchical (total_) statistics."
 )]
 struct Args {
-    #[structopt(
-        short = "w",
-        long = "warn",
-        help = "Percent to warn at",
-        default_value = "85"
-    )]
-    warn: f64,
-    #[structopt(
-        short = "c",
-        long = "crit",
-        help = "Percent to go critical at",
-        default_value = "95"
-    )]
-    crit: f64,
+    #[structopt(flatten)]
+    thresholds: Thresholds,
 
     #[structopt(
         long = "invalid-limit",
@@ -47,12 +72,86 @@ struct Args {
     )]
     invalid_limit: Status,
     #[structopt(
-        long = "show-hogs",
-        name = "count",
-        help = "Show <count> most ram-intensive processes in this container.",
-        default_value = "0"
+        long = "measure",
+        default_value = "rss",
+        possible_values = &Measure::str_values(),
+        help = "Which memory usage to compare against the limit"
     )]
-    show_hogs: usize,
+    measure: Measure,
+    #[structopt(
+        long = "oom-state",
+        value_name = "FILE",
+        help = "Remember OOM kill and limit hit counts in FILE, and alert if \
+                they increased since the last run. Cgroups that this run \
+                doesn't check are forgotten, so each check needs its own FILE"
+    )]
+    oom_state: Option<PathBuf>,
+    #[structopt(flatten)]
+    selector: CGroupSelector,
+    #[structopt(flatten)]
+    hogs: RamHogs,
+    #[structopt(flatten)]
+    roots: Roots,
+    #[structopt(flatten)]
+    common: CommonArgs,
+}
+
+const DEFAULT_WARN: f64 = 85.0;
+const DEFAULT_CRIT: f64 = 95.0;
+
+impl Args {
+    fn warn(&self) -> Threshold {
+        self.thresholds.warn_or(DEFAULT_WARN)
+    }
+
+    fn crit(&self) -> Threshold {
+        self.thresholds.crit_or(DEFAULT_CRIT)
+    }
+}
+
+/// What counts as memory used by the cgroup
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum Measure {
+    Rss,
+    WorkingSet,
+    Usage,
+}
+
+impl Measure {
+    fn str_values() -> [&'static str; 3] {
+        ["rss", "working-set", "usage"]
+    }
+
+    /// The name of the perfdata for this measure
+    fn label(self) -> &'static str {
+        match self {
+            Measure::Rss => "rss",
+            Measure::WorkingSet => "working_set",
+            Measure::Usage => "usage",
+        }
+    }
+
+    /// Read the bytes this measure says `cgroup` is using
+    fn read(self, cgroup: &CGroup) -> Result<usize, SysFsError> {
+        match self {
+            Measure::Rss => Ok(cgroup.memory_stat()?.rss),
+            Measure::WorkingSet => cgroup.memory_working_set(),
+            Measure::Usage => cgroup.memory_usage(),
+        }
+    }
+}
+
+impl FromStr for Measure {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Measure, String> {
+        match s {
+            "rss" => Ok(Measure::Rss),
+            "working-set" => Ok(Measure::WorkingSet),
+            "usage" => Ok(Measure::Usage),
+            _ => Err(format!("Unexpected measure: {}", s)),
+        }
+    }
 }
 
 enum Limit {
@@ -71,113 +170,544 @@ impl fmt::Display for Limit {
 
 fn main() {
     let args: Args = Args::from_args();
-    let mut status = Status::Ok;
-
-    let mut limit = limit_in_bytes().unwrap();
-    let mut limit_type = Limit::CGroup;
-    let mem = MemInfo::load();
-    let system_bytes = mem.total.unwrap() * 1024;
-    if limit > system_bytes {
-        if args.invalid_limit != Status::Ok {
-            println!(
-                "{}: CGroup memory limit is greater than system memory ({} > {})",
-                args.invalid_limit,
-                bytes_to_human_size(limit as u64),
-                bytes_to_human_size(system_bytes as u64)
-            );
-            status = args.invalid_limit;
-        }
-        limit = system_bytes;
-        limit_type = Limit::System;
-    }
+    let started = Instant::now();
+    args.common.init();
+    let context = args.roots.context();
 
-    let cgroup_stat = Stat::load().unwrap();
-    let ratio = cgroup_stat.rss as f64 / limit as f64;
-    let percent = ratio * 100.0;
-
-    if percent > args.crit {
-        println!(
-            "CRITICAL: cgroup is using {:.1}% of {} {} (greater than {}%)",
-            percent,
-            bytes_to_human_size(limit as u64),
-            limit_type,
-            args.crit
-        );
-        status = Status::Critical;
-    } else if percent > args.warn {
-        println!(
-            "WARNING: cgroup is using {:.1}% of {} {} (greater than {}%)",
-            percent,
-            bytes_to_human_size(limit as u64),
-            limit_type,
-            args.warn
-        );
-        status = max(status, Status::Warning);
+    watchdog::phase("reading cgroup memory usage");
+    let cgroup = args.selector.cgroup(&context).unwrap_or_else(|e| {
+        args.common.fail(
+            format!("Unable to find the cgroup to check: {}", e),
+            started,
+        )
+    });
+    let mem = MemInfo::load_in(&context).unwrap_or_else(|e| {
+        args.common
+            .fail(format!("Unable to read system memory: {}", e), started)
+    });
+    let system_bytes = match mem.total {
+        Some(total_kb) => total_kb * 1024,
+        None => args
+            .common
+            .fail("Unable to read total system memory", started),
+    };
+    let mut events = args.oom_state.as_ref().map(|path| {
+        watchdog::phase(format!("reading {}", path.display()));
+        EventState::load(path).unwrap_or_else(|e| {
+            args.common.fail(
+                format!("Unable to read OOM state {}: {}", path.display(), e),
+                started,
+            )
+        })
+    });
+    let (mut result, limit) = if args.selector.children {
+        let children = cgroup.children().unwrap_or_else(|e| {
+            args.common
+                .fail(format!("Unable to list child cgroups: {}", e), started)
+        });
+        let checked = children
+            .iter()
+            .map(
+                |child| match check_cgroup(&args, child, system_bytes, events.as_mut()) {
+                    Ok((ratio, result)) => (ratio, child.name(), result),
+                    Err(e) => (
+                        -1.0,
+                        child.name(),
+                        args.common
+                            .error(format!("Unable to read cgroup memory: {}", e)),
+                    ),
+                },
+            )
+            .collect::<Vec<_>>();
+        if let Some(events) = events.as_mut() {
+            events.forget_others(children.iter().map(|child| child.path()));
+        }
+        let result = check_children(&args, cgroup.path(), checked);
+        // the root cgroup has no limit file
+        let limit = cgroup.memory_limit().ok().and_then(|limit| limit);
+        (result, limit)
     } else {
-        println!(
-            "OK: cgroup is using {:.1}% of {} {} (less than {}%)",
-            percent,
-            bytes_to_human_size(limit as u64),
-            limit_type,
-            args.warn
-        );
+        let limit = cgroup.memory_limit().unwrap_or_else(|e| {
+            args.common.fail(
+                format!("Unable to read cgroup memory limit: {}", e),
+                started,
+            )
+        });
+        let used = args.measure.read(&cgroup).unwrap_or_else(|e| {
+            args.common
+                .fail(format!("Unable to read cgroup memory: {}", e), started)
+        });
+        let mut result = check(&args, used, limit, system_bytes);
+        if let Some(events) = events.as_mut() {
+            let checked = events.check(&cgroup).unwrap_or_else(|e| {
+                args.common
+                    .fail(format!("Unable to read cgroup OOM events: {}", e), started)
+            });
+            result.merge(checked);
+            events.forget_others(Some(cgroup.path()));
+        }
+        (result, limit)
+    };
+    if let (Some(path), Some(events)) = (&args.oom_state, &events) {
+        if let Err(e) = events.save(path) {
+            args.common.fail(
+                format!("Unable to save OOM state to {}: {}", path.display(), e),
+                started,
+            );
+        }
     }
+    let limit = effective_limit(limit, system_bytes);
 
-    if args.show_hogs > 0 {
+    if args.hogs.show_hogs > 0 {
         let mut load_errors = None;
-        let per_proc = match RunningProcs::currently_running() {
+        watchdog::phase("loading processes");
+        let per_proc = match RunningProcs::currently_running_in(&context) {
             Ok(procs) => procs,
             Err(ProcFsError::LoadProcsError(LoadProcsError { procs, errors })) => {
                 load_errors = Some(errors);
                 procs
             }
             Err(err) => {
-                eprintln!("UNKNOWN: Unexpected error loading procs: {}", err);
+                result.add_line(format!("Unable to load processes: {}", err));
                 RunningProcs::empty()
             }
         };
 
         let mut procs = per_proc.0.values().collect::<Vec<_>>();
-        procs.sort_by(|l, r| r.stat.rss.cmp(&l.stat.rss));
-        println!(
-            "INFO [check-container-ram]: {} processes running, top {} ram hogs:",
-            procs.len(),
-            args.show_hogs
-        );
-        for process in procs.iter().take(args.show_hogs as usize) {
-            let percent = process.percent_ram(limit);
-            println!(
-                "[{:>6}]{:>5.1}% {:>6}: {}",
+        if args.selector.is_selected() {
+            match cgroup.pids() {
+                Ok(pids) => procs.retain(|p| pids.contains(&p.stat.pid.as_raw())),
+                Err(e) => {
+                    // rather than show every process on the host as a hog
+                    result.add_line(format!("Unable to list processes in cgroup: {}", e));
+                    procs.clear();
+                }
+            }
+        }
+        watchdog::phase("measuring processes");
+        let ranking = args.hogs.hogs_by.rank_in(procs.iter().cloned(), &context);
+        let percent = |bytes| bytes as f64 / limit as f64 * 100.0;
+        if args.hogs.hogs_per_tree {
+            // only the selected processes, so their trees stop at the
+            // container's init
+            let trees = ranking.by_tree(&ProcessTree::new(procs.iter().cloned()));
+            result.add_line(format!(
+                "INFO [check-container-ram]: {} processes running in {} trees, \
+                 top {} ram hogs by {}:",
+                procs.len(),
+                trees.len(),
+                args.hogs.show_hogs,
+                args.hogs.hogs_by
+            ));
+            for &(process, count, bytes) in trees.iter().take(args.hogs.show_hogs) {
+                result.add_line(format!(
+                    "[{:>6}]{:>5.1}% {:>6}: {} ({} processes)",
+                    process.stat.pid,
+                    percent(bytes),
+                    bytes_to_human_size(bytes),
+                    process.useful_cmdline(),
+                    count
+                ));
+            }
+        } else {
+            result.add_line(format!(
+                "INFO [check-container-ram]: {} processes running, top {} ram hogs by {}:",
+                procs.len(),
+                args.hogs.show_hogs,
+                args.hogs.hogs_by
+            ));
+            for &(process, bytes) in ranking.measured.iter().take(args.hogs.show_hogs) {
+                result.add_line(format!(
+                    "[{:>6}]{:>5.1}% {:>6}: {}",
+                    process.stat.pid,
+                    percent(bytes),
+                    bytes_to_human_size(bytes),
+                    process.useful_cmdline()
+                ));
+            }
+        }
+        if let Some((process, err)) = ranking.errors.first() {
+            eprintln!(
+                "Unable to measure the {} of {} processes, e.g. {}: {}",
+                args.hogs.hogs_by,
+                ranking.errors.len(),
                 process.stat.pid,
-                percent,
-                pages_to_human_size(process.stat.rss),
-                process.useful_cmdline()
+                err
             );
         }
 
         if let Some(errors) = load_errors {
             for err in errors {
-                eprintln!("UNKNOWN: error loading process: {}", err);
+                eprintln!("Unable to load process: {}", err);
+            }
+        }
+    }
+    args.common.exit(result, started);
+}
+
+/// Check one cgroup, returning how much of its limit it uses for sorting
+fn check_cgroup(
+    args: &Args,
+    cgroup: &CGroup,
+    system_bytes: usize,
+    events: Option<&mut EventState>,
+) -> Result<(f64, CheckResult), SysFsError> {
+    let limit = cgroup.memory_limit()?;
+    let used = args.measure.read(cgroup)?;
+    let ratio = used as f64 / effective_limit(limit, system_bytes) as f64;
+    let mut result = check(args, used, limit, system_bytes);
+    if let Some(events) = events {
+        result.merge(events.check(cgroup)?);
+    }
+    Ok((ratio, result))
+}
+
+/// The OOM kill and limit hit counts of each cgroup, as of the last run
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+struct EventState {
+    cgroups: BTreeMap<String, Seen>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+struct Seen {
+    max: u64,
+    oom_kill: Option<u64>,
+}
+
+impl EventState {
+    /// Load the state file, which doesn't exist on the first run
+    fn load(path: &Path) -> Result<EventState, String> {
+        match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(EventState::default()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Replace the state file, without leaving it half written if we die
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        let contents = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Compare `cgroup`'s counts against the last run and remember them
+    fn check(&mut self, cgroup: &CGroup) -> Result<CheckResult, SysFsError> {
+        let current = cgroup.memory_events()?;
+        let key = cgroup.path().display().to_string();
+        let previous = self.cgroups.insert(
+            key,
+            Seen {
+                max: current.max,
+                oom_kill: current.oom_kill,
+            },
+        );
+        Ok(check_events(previous, current))
+    }
+
+    /// Forget every cgroup but `checked`, so that removed containers don't
+    /// pile up in the state file
+    fn forget_others<'p, I>(&mut self, checked: I)
+    where
+        I: IntoIterator<Item = &'p Path>,
+    {
+        let checked = checked
+            .into_iter()
+            .map(|path| path.display().to_string())
+            .collect::<BTreeSet<_>>();
+        self.cgroups.retain(|key, _| checked.contains(key));
+    }
+}
+
+/// Alert if processes were OOM killed or the limit was hit since `previous`
+///
+/// Counts that went down mean the cgroup was recreated, so everything it has
+/// counted since then is new.
+fn check_events(previous: Option<Seen>, current: MemoryEventCounts) -> CheckResult {
+    let since = |now: u64, then: u64| if now >= then { now - then } else { now };
+    let mut result = match previous {
+        None => CheckResult::new(
+            Status::Ok,
+            "no previous OOM counts, comparing from the next run",
+        ),
+        Some(previous) => {
+            let killed = match (current.oom_kill, previous.oom_kill) {
+                (Some(now), Some(then)) => since(now, then),
+                (Some(now), None) => now,
+                (None, _) => 0,
+            };
+            let hits = since(current.max, previous.max);
+            if killed > 0 && hits > 0 {
+                CheckResult::new(
+                    Status::Critical,
+                    format!(
+                        "{} processes OOM killed and memory limit hit {} times since the last run",
+                        killed, hits
+                    ),
+                )
+            } else if killed > 0 {
+                CheckResult::new(
+                    Status::Critical,
+                    format!("{} processes OOM killed since the last run", killed),
+                )
+            } else if hits > 0 {
+                CheckResult::new(
+                    Status::Warning,
+                    format!("memory limit hit {} times since the last run", hits),
+                )
+            } else {
+                CheckResult::new(
+                    Status::Ok,
+                    "no OOM kills or memory limit hits since the last run",
+                )
             }
         }
+    };
+    if let Some(oom_kill) = current.oom_kill {
+        result.add_perf(PerfData::new("oom_kill", oom_kill as f64).unit(Unit::Counter));
     }
-    status.exit();
+    result.add_perf(PerfData::new("limit_hits", current.max as f64).unit(Unit::Counter));
+    result
+}
+
+/// The memory available to a cgroup
+fn effective_limit(limit: Option<usize>, system_bytes: usize) -> usize {
+    limit.map_or(system_bytes, |limit| min(limit, system_bytes))
+}
+
+/// Combine the results of checking each child cgroup, most used first
+///
+/// Children that couldn't be read have a ratio below zero, and are ranked
+/// by their status by `worst_of`.
+fn check_children(
+    args: &Args,
+    parent: &Path,
+    mut checked: Vec<(f64, String, CheckResult)>,
+) -> CheckResult {
+    checked.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+    worst_of(
+        &format!("child cgroups of {}", parent.display()),
+        checked
+            .into_iter()
+            .map(|(_, name, result)| (name, result))
+            .collect(),
+        args.selector.show_children,
+    )
+}
+
+/// Compare the cgroup's memory, as measured by `--measure`, against its limit,
+/// or the system ram if the limit is larger than that or there is no limit
+fn check(args: &Args, used: usize, limit: Option<usize>, system_bytes: usize) -> CheckResult {
+    let mut invalid_limit = None;
+    let (limit, limit_type) = match limit {
+        None => (system_bytes, Limit::System),
+        Some(limit) if limit > system_bytes => {
+            if args.invalid_limit != Status::Ok {
+                invalid_limit = Some(CheckResult::new(
+                    args.invalid_limit,
+                    format!(
+                        "CGroup memory limit is greater than system memory ({} > {})",
+                        bytes_to_human_size(limit as u64),
+                        bytes_to_human_size(system_bytes as u64)
+                    ),
+                ));
+            }
+            (system_bytes, Limit::System)
+        }
+        Some(limit) => (limit, Limit::CGroup),
+    };
+
+    let ratio = used as f64 / limit as f64;
+    let percent = ratio * 100.0;
+    let (crit, warn) = (args.crit(), args.warn());
+
+    let mut result = if crit.is_violated(percent) {
+        CheckResult::new(
+            Status::Critical,
+            format!(
+                "cgroup is using {:.1}% of {} {} ({})",
+                percent,
+                bytes_to_human_size(limit as u64),
+                limit_type,
+                crit.describe()
+            ),
+        )
+    } else if warn.is_violated(percent) {
+        CheckResult::new(
+            Status::Warning,
+            format!(
+                "cgroup is using {:.1}% of {} {} ({})",
+                percent,
+                bytes_to_human_size(limit as u64),
+                limit_type,
+                warn.describe()
+            ),
+        )
+    } else {
+        CheckResult::new(
+            Status::Ok,
+            format!(
+                "cgroup is using {:.1}% of {} {} ({})",
+                percent,
+                bytes_to_human_size(limit as u64),
+                limit_type,
+                warn.describe_ok()
+            ),
+        )
+    };
+    result.add_perf(
+        PerfData::new("ram_used", percent)
+            .unit(Unit::Percent)
+            .warn(warn)
+            .crit(crit)
+            .min(0.0)
+            .max(100.0),
+    );
+    result.add_perf(
+        PerfData::new(args.measure.label(), used as f64)
+            .unit(Unit::Bytes)
+            .min(0.0)
+            .max(limit as f64),
+    );
+    if let Some(invalid_limit) = invalid_limit {
+        result.merge(invalid_limit);
+    }
+    result
 }
 
 #[cfg(test)]
 mod unit {
+    use std::path::Path;
 
-    use super::Args;
+    use super::{check, check_children, check_events, Args, EventState, Measure, Seen};
     use structopt::StructOpt;
-    use tabin_plugins::Status;
+    use tabin_plugins::sys::fs::cgroup::MemoryEventCounts;
+    use tabin_plugins::{CheckResult, Status, Threshold};
 
     #[test]
     fn usage_is_valid() {
         let argv: [&str; 0] = [];
         let args = Args::from_iter(argv.iter());
-        assert_eq!(args.crit, 95.0);
+        assert_eq!(args.crit(), Threshold::above(95.0));
         assert_eq!(args.invalid_limit, Status::Ok);
+        assert_eq!(args.measure, Measure::Rss);
         let args: Args = Args::from_iter(["arg0", "--crit", "80", "--warn", "20"].iter());
-        assert_eq!(args.crit, 80.0);
+        assert_eq!(args.crit(), Threshold::above(80.0));
+    }
+
+    #[test]
+    fn unreadable_children_are_worse_than_ok_ones() {
+        let args =
+            Args::from_iter(["arg0", "--cgroup=/", "--children", "--show-children=1"].iter());
+        let checked = vec![
+            (
+                -1.0,
+                "gone".into(),
+                CheckResult::new(Status::Unknown, "Unable to read cgroup memory"),
+            ),
+            (0.5, "web".into(), CheckResult::new(Status::Ok, "using 50%")),
+        ];
+        let result = check_children(&args, Path::new("/system.slice"), checked);
+        assert_eq!(result.status, Status::Unknown);
+        assert_eq!(
+            result.summary,
+            "2 child cgroups of /system.slice, worst is gone: Unable to read cgroup memory"
+        );
+        assert_eq!(
+            result.long_output,
+            ["UNKNOWN gone: Unable to read cgroup memory"]
+        );
+    }
+
+    #[test]
+    fn check_falls_back_to_system_ram() {
+        let args: Args = Args::from_iter(["arg0", "--invalid-limit", "warning"].iter());
+        let gb = 1024 * 1024 * 1024;
+        let result = check(&args, gb, Some(8 * gb), 4 * gb);
+        assert_eq!(result.status, Status::Warning);
+        assert_eq!(
+            result.summary,
+            "CGroup memory limit is greater than system memory (8.0G > 4.0G)"
+        );
+        assert_eq!(
+            result.long_output,
+            ["cgroup is using 25.0% of 4.0G system ram (<= 85)"]
+        );
+
+        let result = check(&args, 3 * gb, Some(4 * gb), 8 * gb);
+        assert_eq!(result.status, Status::Ok);
+        assert_eq!(
+            result.summary,
+            "cgroup is using 75.0% of 4.0G cgroup limit (<= 85)"
+        );
+
+        // cgroup v2 says when there is no limit
+        let result = check(&args, 2 * gb, None, 8 * gb);
+        assert_eq!(result.status, Status::Ok);
+        assert_eq!(
+            result.summary,
+            "cgroup is using 25.0% of 8.0G system ram (<= 85)"
+        );
+    }
+
+    #[test]
+    fn labels_perfdata_by_measure() {
+        let args: Args = Args::from_iter(["arg0", "--measure", "working-set"].iter());
+        assert_eq!(args.measure, Measure::WorkingSet);
+        let gb = 1024 * 1024 * 1024;
+        let result = check(&args, gb, Some(2 * gb), 4 * gb);
+        assert_eq!(
+            result.perfdata[1].to_string(),
+            format!("working_set={}B;;;0;{}", gb, 2 * gb)
+        );
+        assert!(Args::from_iter_safe(["arg0", "--measure", "pss"].iter()).is_err());
+    }
+
+    #[test]
+    fn alerts_on_new_oom_events() {
+        let current = MemoryEventCounts {
+            max: 7,
+            oom_kill: Some(2),
+        };
+        let first = check_events(None, current);
+        assert_eq!(first.status, Status::Ok);
+        assert_eq!(first.perfdata[0].to_string(), "oom_kill=2c");
+
+        let seen = |max, oom_kill| Some(Seen { max, oom_kill });
+        let killed = check_events(seen(5, Some(1)), current);
+        assert_eq!(killed.status, Status::Critical);
+        assert_eq!(
+            killed.summary,
+            "1 processes OOM killed and memory limit hit 2 times since the last run"
+        );
+        let hit = check_events(seen(5, Some(2)), current);
+        assert_eq!(hit.status, Status::Warning);
+        assert_eq!(hit.summary, "memory limit hit 2 times since the last run");
+        assert_eq!(check_events(seen(7, Some(2)), current).status, Status::Ok);
+        // the cgroup was recreated
+        let recreated = check_events(seen(9, Some(5)), current);
+        assert_eq!(
+            recreated.summary,
+            "2 processes OOM killed and memory limit hit 7 times since the last run"
+        );
+        let killed = check_events(seen(7, Some(0)), current);
+        assert_eq!(killed.summary, "2 processes OOM killed since the last run");
+    }
+
+    #[test]
+    fn forgets_cgroups_that_were_not_checked() {
+        let seen = Seen {
+            max: 1,
+            oom_kill: None,
+        };
+        let mut state = EventState::default();
+        for name in &["/web", "/db", "/removed"] {
+            state.cgroups.insert(name.to_string(), seen);
+        }
+        state.forget_others(vec![Path::new("/web"), Path::new("/db"), Path::new("/new")]);
+        assert_eq!(state.cgroups.keys().collect::<Vec<_>>(), ["/db", "/web"]);
+        state.forget_others(None);
+        assert!(state.cgroups.is_empty());
     }
 }
@@ -1,19 +1,27 @@
 //! Check CPU usage
 
-use std::cmp::PartialOrd;
 use std::fmt::Display;
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use serde::Deserialize;
 use structopt::StructOpt;
 
+use tabin_plugins::cli::{CommonArgs, Hogs, Roots, Thresholds};
+use tabin_plugins::perfdata::{PerfData, Unit};
 use tabin_plugins::procfs::{
     Calculations, LoadProcsError, ProcField, ProcFsError, RunningProcs, WorkSource,
 };
-use tabin_plugins::Status;
+use tabin_plugins::watchdog;
+use tabin_plugins::{CheckResult, Status, Threshold};
+
+const DEFAULT_WARN: f64 = 80.0;
+const DEFAULT_CRIT: f64 = 80.0;
 
 /// Check cpu usage of the current computer
+///
+/// Thresholds are percentages of cpu time, and default to going critical
+/// above 80.
 #[derive(Deserialize, StructOpt, Debug)]
 #[structopt(
     name = "check-cpu  (part of tabin-plugins)",
@@ -36,20 +44,8 @@ use tabin_plugins::Status;
                                 idle iowait [default: active]"
 )]
 struct Args {
-    #[structopt(
-        short = "w",
-        long = "warn",
-        help = "Percent to warn at",
-        default_value = "80"
-    )]
-    warn: f64,
-    #[structopt(
-        short = "c",
-        long = "crit",
-        help = "Percent to go critical at",
-        default_value = "80"
-    )]
-    crit: f64,
+    #[structopt(flatten)]
+    thresholds: Thresholds,
     #[structopt(
         short = "s",
         long = "sample",
@@ -58,13 +54,8 @@ struct Args {
         default_value = "5"
     )]
     sample: u64,
-    #[structopt(
-        long = "show-hogs",
-        name = "count",
-        help = "Show <count> most cpu-intensive processes in this container.",
-        default_value = "0"
-    )]
-    show_hogs: usize,
+    #[structopt(flatten)]
+    hogs: Hogs,
 
     #[structopt(
         long = "per-cpu",
@@ -85,62 +76,104 @@ struct Args {
         help = "See 'CPU Work Types, below"
     )]
     work_type: Vec<WorkSource>,
+    #[structopt(flatten)]
+    roots: Roots,
+    #[structopt(flatten)]
+    common: CommonArgs,
+}
+
+impl Args {
+    fn warn(&self) -> Threshold {
+        self.thresholds.warn_or(DEFAULT_WARN)
+    }
+
+    fn crit(&self) -> Threshold {
+        self.thresholds.crit_or(DEFAULT_CRIT)
+    }
 }
 
-fn print_errors_and_status<T: Display, V: PartialOrd<V> + Display>(
+fn compare<T: Display>(
     flag: T,
-    total: V,
-    critical: V,
-    warning: V,
-    mut exit_status: Status,
-) -> Status {
-    if total > critical {
-        exit_status = std::cmp::max(exit_status, Status::Critical);
-        println!(
-            "CRITICAL [check-cpu]: {} {:.2} > {}%",
-            flag, total, critical,
-        );
-    } else if total > warning {
-        exit_status = std::cmp::max(exit_status, Status::Warning);
-        println!("WARNING [check-cpu]: {} {:.2} > {}%", flag, total, warning);
+    total: f64,
+    critical: &Threshold,
+    warning: &Threshold,
+) -> (Status, String) {
+    if critical.is_violated(total) {
+        (
+            Status::Critical,
+            format!("{} {:.2} {}", flag, total, critical.describe()),
+        )
+    } else if warning.is_violated(total) {
+        (
+            Status::Warning,
+            format!("{} {:.2} {}", flag, total, warning.describe()),
+        )
     } else {
-        println!("OK [check-cpu]: {} {:.2} < {}%", flag, total, warning);
+        (
+            Status::Ok,
+            format!("{} {:.2} {}", flag, total, warning.describe_ok()),
+        )
     }
-    exit_status
 }
 
 /// Check if we have exceeded the warn or critical limits
-///
-/// The *cstat parameters, if present, mean that in addition to performing the
-/// standard checks we check the currently-running container against the same
-/// thresholds.
-fn do_comparison(args: &Args, start: &Calculations, end: &Calculations) -> Status {
+fn do_comparison(args: &Args, start: &Calculations, end: &Calculations) -> CheckResult {
     let mut exit_status = Status::Ok;
+    let mut descriptions = vec![];
 
     for flag in &args.work_type {
-        let total = end.percent_util_since(flag, &start);
-        exit_status = std::cmp::max(
-            exit_status,
-            print_errors_and_status(flag, total, args.crit.into(), args.warn, exit_status),
-        );
+        let total = end.percent_util_since(flag, start);
+        let (status, description) = compare(flag, total, &args.crit(), &args.warn());
+        exit_status = std::cmp::max(exit_status, status);
+        descriptions.push(description);
     }
-    println!("INFO [check-cpu]: Usage breakdown: {}", end - start);
+    let mut result = CheckResult::new(exit_status, descriptions.join(", "));
+    result.add_line(format!(
+        "INFO [check-cpu]: Usage breakdown: {}",
+        end - start
+    ));
 
-    exit_status
+    result
 }
 
 fn determine_status_per_cpu(
     args: &Args,
     start: &[Calculations],
     end: &[Calculations],
-) -> Vec<Status> {
+) -> Vec<CheckResult> {
     start
         .iter()
         .enumerate()
-        .map(|(i, val)| do_comparison(&args, &val, &end[i]))
+        .map(|(i, val)| do_comparison(args, val, &end[i]))
         .collect::<Vec<_>>()
 }
 
+/// Usage of every work type, for every cpu if we are checking per-cpu
+fn perf_data(args: &Args, start: &[Calculations], end: &[Calculations]) -> Vec<PerfData> {
+    let mut perf = vec![];
+    for (i, (start, end)) in start.iter().zip(end).enumerate() {
+        let prefix = if args.per_cpu {
+            format!("cpu{}_", i)
+        } else {
+            String::new()
+        };
+        for flag in &args.work_type {
+            perf.push(
+                PerfData::new(
+                    format!("{}{}", prefix, flag),
+                    end.percent_util_since(flag, start),
+                )
+                .unit(Unit::Percent)
+                .warn(args.warn())
+                .crit(args.crit())
+                .min(0.0)
+                .max(100.0),
+            );
+        }
+    }
+    perf
+}
+
 fn determine_exit(args: &Args, statuses: &[Status]) -> Status {
     let crit = statuses
         .iter()
@@ -159,49 +192,75 @@ fn determine_exit(args: &Args, statuses: &[Status]) -> Status {
     }
 }
 
+/// Compare every cpu against the thresholds and combine them into one result
+fn check(args: &Args, start: &[Calculations], end: &[Calculations]) -> CheckResult {
+    let perf = perf_data(args, start, end);
+    let mut results = determine_status_per_cpu(args, start, end);
+    let mut result = if args.per_cpu {
+        let statuses = results.iter().map(|r| r.status).collect::<Vec<_>>();
+        let count = |status| statuses.iter().filter(|s| **s == status).count();
+        let mut result = CheckResult::new(
+            determine_exit(args, &statuses),
+            format!(
+                "{} of {} cpus critical, {} warning (alerting at {})",
+                count(Status::Critical),
+                statuses.len(),
+                count(Status::Warning),
+                args.cpu_count
+            ),
+        );
+        for (i, cpu) in results.into_iter().enumerate() {
+            result.add_line(format!("cpu{}: {}: {}", i, cpu.status, cpu.summary));
+            for line in cpu.long_output {
+                result.add_line(format!("cpu{}: {}", i, line));
+            }
+        }
+        result
+    } else {
+        results.remove(0)
+    };
+    result.perfdata = perf;
+    result
+}
+
 #[cfg_attr(test, allow(dead_code))]
 fn main() {
     let args: Args = Args::from_args();
+    let started = Instant::now();
+    args.common.init();
 
-    let start = if args.per_cpu {
-        Calculations::load_per_cpu().unwrap()
-    } else {
-        vec![Calculations::load().unwrap()]
-    };
+    let start = load_cpus(&args, started);
     let mut load_errors = vec![];
     let mut start_per_proc = None;
-    if args.show_hogs > 0 {
-        start_per_proc = Some(load_procs(&mut load_errors));
+    if args.hogs.show_hogs > 0 {
+        start_per_proc = Some(load_procs(&mut load_errors, &args, started));
     }
-    sleep(Duration::from_millis(args.sample as u64 * 1000));
+    watchdog::phase(format!("sampling cpu usage for {}s", args.sample));
+    sleep(Duration::from_millis(args.sample * 1000));
 
-    let end = if args.per_cpu {
-        Calculations::load_per_cpu().unwrap()
-    } else {
-        vec![Calculations::load().unwrap()]
-    };
-    let statuses = determine_status_per_cpu(&args, &start, &end);
+    let end = load_cpus(&args, started);
+    let mut result = check(&args, &start, &end);
 
-    if args.show_hogs > 0 {
-        let end_per_proc = load_procs(&mut load_errors);
+    if args.hogs.show_hogs > 0 {
+        let end_per_proc = load_procs(&mut load_errors, &args, started);
         let start_per_proc = start_per_proc.unwrap();
         let single_start = &start[0];
         let single_end = &end[0];
         let mut per_proc = end_per_proc
             .percent_cpu_util_since(&start_per_proc, single_end.total() - single_start.total());
         per_proc.sort_by_field(ProcField::TotalCpu);
-        println!(
+        result.add_line(format!(
             "INFO [check-cpu]: {} processes running, top {} cpu hogs:",
             per_proc.len(),
-            args.show_hogs
-        );
-        for usage in per_proc.iter().take(args.show_hogs) {
-            println!(
+            args.hogs.show_hogs
+        ));
+        for usage in per_proc.iter().take(args.hogs.show_hogs) {
+            result.add_line(format!(
                 "[{:>5}]{:>5.1}%: {}",
                 usage.process.stat.pid,
                 usage.total,
                 usage.process.useful_cmdline()
-            );
+            ));
         }
         if !load_errors.is_empty() {
             eprintln!("Error loading some per-process information:");
@@ -211,27 +270,42 @@ fn main() {
         }
     }
 
-    determine_exit(&args, &statuses).exit()
+    args.common.exit(result, started)
+}
+
+/// Load the cpu counters we are checking, and die if they can't be read
+fn load_cpus(args: &Args, started: Instant) -> Vec<Calculations> {
+    watchdog::phase("reading cpu usage");
+    let cpus = if args.per_cpu {
+        Calculations::load_per_cpu_in(&args.roots.context())
+    } else {
+        Calculations::load_in(&args.roots.context()).map(|cpu| vec![cpu])
+    };
+    match cpus {
+        Ok(cpus) => cpus,
+        Err(err) => args
+            .common
+            .fail(format!("Unable to read cpu usage: {}", err), started),
+    }
 }
 
 /// Load currently running procs, and die if there is a surprising error
-fn load_procs(load_errors: &mut Vec<ProcFsError>) -> RunningProcs {
-    match RunningProcs::currently_running() {
+fn load_procs(load_errors: &mut Vec<ProcFsError>, args: &Args, started: Instant) -> RunningProcs {
+    let common = &args.common;
+    watchdog::phase("loading processes");
+    match RunningProcs::currently_running_in(&args.roots.context()) {
         Ok(procs) => procs,
         Err(ProcFsError::LoadProcsError(LoadProcsError { procs, errors })) => {
-            load_errors.extend(errors.into_iter());
+            load_errors.extend(errors);
             procs
         }
-        Err(err) => {
-            eprintln!("Unexpected error loading procs: {}", err);
-            Status::Unknown.exit()
-        }
+        Err(err) => common.fail(format!("Unexpected error loading procs: {}", err), started),
     }
 }
 
 #[cfg(test)]
 mod unit {
-    use super::{determine_exit, determine_status_per_cpu, do_comparison, Args};
+    use super::{check, determine_exit, determine_status_per_cpu, do_comparison, perf_data, Args};
 
     use structopt::StructOpt;
 
@@ -240,13 +314,14 @@ mod unit {
     use tabin_plugins::Status;
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn validate_docstring() {
         let _: Args = Args::from_iter(["arg0", "--per-cpu"].iter());
         let args: Args = Args::from_iter(["arg0", "--per-cpu", "--cpu-count", "2"].iter());
         assert_eq!(args.per_cpu, true);
         let args: Args = Args::from_iter(["arg0", "--show-hogs", "5"].iter());
         assert_eq!(args.per_cpu, false);
-        assert_eq!(args.show_hogs, 5);
+        assert_eq!(args.hogs.show_hogs, 5);
     }
 
     #[test]
@@ -356,7 +431,9 @@ mod unit {
             .iter(),
         );
 
-        assert_eq!(do_comparison(&args, &start, &end), Status::Critical);
+        let result = do_comparison(&args, &start, &end);
+        assert_eq!(result.status, Status::Critical);
+        assert_eq!(result.summary, "active 50.00 > 49, steal 0.00 <= 80");
     }
 
     // Exactly the same as does_alert, but also validate the determine* functions
@@ -381,11 +458,58 @@ mod unit {
             .iter(),
         );
         assert_eq!(args.work_type, vec![WorkSource::Active, WorkSource::Steal]);
-        let statuses = determine_status_per_cpu(&args, &start, &end);
+        let statuses = cpu_statuses(&args, &start, &end);
         assert_eq!(statuses, vec![Status::Critical]);
         assert_eq!(determine_exit(&args, &statuses), Status::Critical);
     }
 
+    fn cpu_statuses(args: &Args, start: &[Calculations], end: &[Calculations]) -> Vec<Status> {
+        determine_status_per_cpu(args, start, end)
+            .iter()
+            .map(|result| result.status)
+            .collect()
+    }
+
+    #[test]
+    fn perf_data_labels_cpus() {
+        let start = vec![start(), start()];
+        let end = vec![
+            Calculations {
+                user: Jiffies::new(110),
+                idle: Jiffies::new(110),
+                ..start[0]
+            },
+            Calculations {
+                idle: Jiffies::new(110),
+                ..start[0]
+            },
+        ];
+        let args: Args = Args::from_iter(
+            [
+                "check-cpu",
+                "--per-cpu",
+                "--type",
+                "active",
+                "--type",
+                "steal",
+            ]
+            .iter(),
+        );
+        let perf: Vec<_> = perf_data(&args, &start, &end)
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            perf,
+            [
+                "cpu0_active=50%;80;80;0;100",
+                "cpu0_steal=0%;80;80;0;100",
+                "cpu1_active=0%;80;80;0;100",
+                "cpu1_steal=0%;80;80;0;100",
+            ]
+        );
+    }
+
     #[test]
     fn does_alert_per_cpu_with_some_ok() {
         let start = vec![start(), start(), start()];
@@ -419,7 +543,7 @@ mod unit {
             ]
         };
         let args: super::Args = Args::from_iter(argv().iter());
-        let statuses = determine_status_per_cpu(&args, &start, &end);
+        let statuses = cpu_statuses(&args, &start, &end);
         assert_eq!(statuses, vec![Status::Critical, Status::Ok, Status::Ok]);
         assert_eq!(determine_exit(&args, &statuses), Status::Ok);
 
@@ -429,11 +553,17 @@ mod unit {
             ..start[0]
         };
         let args: super::Args = Args::from_iter(argv().iter());
-        let statuses = determine_status_per_cpu(&args, &start, &end);
+        let statuses = cpu_statuses(&args, &start, &end);
         assert_eq!(
             statuses,
             vec![Status::Critical, Status::Critical, Status::Ok]
         );
         assert_eq!(determine_exit(&args, &statuses), Status::Critical);
+        let result = check(&args, &start, &end);
+        assert_eq!(result.status, Status::Critical);
+        assert_eq!(
+            result.summary,
+            "2 of 3 cpus critical, 0 warning (alerting at 2)"
+        );
     }
 }
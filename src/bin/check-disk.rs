@@ -1,8 +1,8 @@
 //! Check Disk usage
 
-use std::cmp::max;
 use std::collections::HashSet;
 use std::fmt;
+use std::time::Instant;
 
 use derive_more::From;
 use log::debug;
@@ -11,49 +11,43 @@ use regex::Regex;
 use serde::Deserialize;
 use structopt::StructOpt;
 
+use tabin_plugins::cli::{CommonArgs, Roots, Thresholds};
 use tabin_plugins::linux::bytes_to_human_size;
+use tabin_plugins::perfdata::{PerfData, Unit};
 use tabin_plugins::procfs::Mount;
-use tabin_plugins::Status;
+use tabin_plugins::watchdog;
+use tabin_plugins::{CheckResult, Status, Threshold};
 
 /// Check all mounted file systems for disk usage.
 ///
 /// For some reason this check generally generates values that are between 1% and
 /// 3% higher than `df`, even though AFAICT we're both just calling statvfs a bunch
 /// of times.
+///
+/// Thresholds are percentages of disk space used, and default to warning above
+/// 80 and critical above 90.
 #[derive(StructOpt, Deserialize, Debug)]
 #[structopt(
     name = "check-disk (part of tabin-plugins)",
     setting = structopt::clap::AppSettings::ColoredHelp,
 )]
 struct Args {
-    #[structopt(
-        short = "w",
-        long = "warn",
-        help = "Percent to warn at",
-        default_value = "80"
-    )]
-    warn: f64,
-    #[structopt(
-        short = "c",
-        long = "crit",
-        help = "Percent to go critical at",
-        default_value = "90"
-    )]
-    crit: f64,
+    #[structopt(flatten)]
+    thresholds: Thresholds,
     #[structopt(
         short = "W",
         long = "warn-inodes",
-        help = "Percent of inode usage to warn at",
+        help = "Percent of inode usage to warn at, as a nagios range",
         default_value = "80"
     )]
-    warn_inodes: f64,
+    warn_inodes: Threshold,
     #[structopt(
         short = "C",
         long = "crit-inodes",
-        help = "Percent of inode usage to go critical at",
+        help = "Percent of inode usage to go critical at, as a nagios range",
         default_value = "90"
     )]
-    crit_inodes: f64,
+    crit_inodes: Threshold,
 
     #[structopt(
         long = "pattern",
@@ -94,49 +88,62 @@ struct Args {
                 Choices: [critical, warning, ok]"
     )]
     inaccessible_status: Option<Status>,
+    #[structopt(flatten)]
+    roots: Roots,
+    #[structopt(flatten)]
+    common: CommonArgs,
 }
 
-const LOG_VAR: &str = "TABIN_LOG";
+const DEFAULT_WARN: f64 = 80.0;
+const DEFAULT_CRIT: f64 = 90.0;
+
+impl Args {
+    fn warn(&self) -> Threshold {
+        self.thresholds.warn_or(DEFAULT_WARN)
+    }
+
+    fn crit(&self) -> Threshold {
+        self.thresholds.crit_or(DEFAULT_CRIT)
+    }
+}
 
 fn main() {
     let args = Args::from_args();
-    env_logger::Builder::from_env(LOG_VAR).init();
+    let started = Instant::now();
+    args.common.init();
 
-    let mut mounts = match Mount::load_all() {
+    watchdog::phase("reading mounts");
+    let mut mounts = match Mount::load_all_in(&args.roots.context()) {
         Ok(mounts) => mounts,
-        Err(e) => {
-            println!("CRITICAL error loading mounts: {}", e);
-            Status::Critical.exit();
-        }
+        Err(e) => args
+            .common
+            .fail(format!("error loading mounts: {}", e), started),
     };
-    mounts.sort_by(|l, r| l.file.len().cmp(&r.file.len()));
+    mounts.sort_by_key(|l| l.file.len());
 
-    let status = match filter(mounts, &args) {
+    let result = match filter(mounts, &args) {
         Ok(ms) => do_check(&ms, &args),
         Err(Error::NotAccessible {
             accessible,
             not_accessible,
         }) => {
-            let check_result = do_check(&accessible, &args);
-            if args.inaccessible_status.is_some() {
-                let status = args.inaccessible_status.unwrap();
-                println!(
-                    "{}: {} filesystems were not accessible, \
-                     run with TABIN_LOG=debug for details",
-                    status, not_accessible
-                );
-                max(check_result, status)
-            } else {
-                check_result
+            let mut result = do_check(&accessible, &args);
+            if let Some(status) = args.inaccessible_status {
+                result.merge(CheckResult::new(
+                    status,
+                    format!(
+                        "{} filesystems were not accessible, \
+                         run with TABIN_LOG=debug for details",
+                        not_accessible
+                    ),
+                ));
             }
+            result
         }
-        Err(e) => {
-            println!("{}", e);
-            Status::Critical
-        }
+        Err(e) => args.common.error(e.to_string()),
     };
 
-    status.exit();
+    args.common.exit(result, started);
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -176,9 +183,16 @@ fn percent(part: u64, whole: u64) -> f64 {
     100.0 - (part as f64 / whole as f64) * 100.0
 }
 
+/// The percent of a filesystem's inodes that are used, from its free inodes
+// statvfs field widths differ between unixes
+#[allow(clippy::useless_conversion)]
+fn inode_percent(stat: &Statvfs) -> f64 {
+    percent(stat.files_available().into(), stat.files().into())
+}
+
 fn maybe_regex(pattern: &Option<String>) -> DiskResult<Option<Regex>> {
     if let Some(ref pattern) = *pattern {
-        let re = match Regex::new(&pattern) {
+        let re = match Regex::new(pattern) {
             Ok(re) => re,
             Err(e) => {
                 return Err(ErrorMsg {
@@ -222,6 +236,7 @@ fn filter(mounts: Vec<Mount>, args: &Args) -> DiskResult<Vec<MountStat>> {
                 .unwrap_or(true)
         })
         .filter_map(|mount| {
+            watchdog::phase(format!("checking {}", mount.file));
             let stat = match statvfs(mount.file.as_bytes()) {
                 Ok(stat) => stat,
                 Err(e) => {
@@ -231,10 +246,7 @@ fn filter(mounts: Vec<Mount>, args: &Args) -> DiskResult<Vec<MountStat>> {
                 }
             };
             if stat.blocks() > 0 && !mount.file.starts_with("/proc") {
-                Some(MountStat {
-                    mount: mount,
-                    stat: stat,
-                })
+                Some(MountStat { mount, stat })
             } else {
                 None
             }
@@ -277,94 +289,157 @@ fn filter(mounts: Vec<Mount>, args: &Args) -> DiskResult<Vec<MountStat>> {
     }
 }
 
-fn do_check(mountstats: &[MountStat], args: &Args) -> Status {
-    let mut status = Status::Ok;
+// statvfs field widths differ between unixes, so the conversions are only
+// no-ops on some of them
+#[allow(clippy::useless_conversion, clippy::unnecessary_cast)]
+fn do_check(mountstats: &[MountStat], args: &Args) -> CheckResult {
+    let (warn, crit) = (args.warn(), args.crit());
+    let mut problems = vec![];
     for ms in mountstats {
         let pcnt = percent(ms.stat.blocks_available().into(), ms.stat.blocks().into());
-        if pcnt > args.crit {
-            status = Status::Critical;
-            println!(
-                "CRITICAL: {} has {:.1}% of its {}B used (> {:.1}%)",
-                ms.mount.file,
-                pcnt,
-                bytes_to_human_size(ms.stat.blocks() as u64 * ms.stat.fragment_size()),
-                args.crit
-            )
-        } else if pcnt > args.warn {
-            status = max(status, Status::Warning);
-            println!(
-                "WARNING: {} has {:.1}% of its {}B used (> {:.1}%)",
-                ms.mount.file,
-                pcnt,
-                bytes_to_human_size(ms.stat.blocks() as u64 * ms.stat.fragment_size()),
-                args.warn
-            )
+        let threshold = if crit.is_violated(pcnt) {
+            Some((Status::Critical, crit))
+        } else if warn.is_violated(pcnt) {
+            Some((Status::Warning, warn))
+        } else {
+            None
+        };
+        if let Some((status, threshold)) = threshold {
+            problems.push(CheckResult::new(
+                status,
+                format!(
+                    "{} has {:.1}% of its {}B used ({})",
+                    ms.mount.file,
+                    pcnt,
+                    bytes_to_human_size(ms.stat.blocks() as u64 * ms.stat.fragment_size()),
+                    threshold.describe()
+                ),
+            ));
         }
 
-        let ipcnt = percent(ms.stat.blocks_available().into(), ms.stat.files().into());
-        if ipcnt > args.crit_inodes {
-            status = Status::Critical;
-            println!(
-                "CRITICAL: {} has {:.1}% of its {} inodes used (> {:.1}%)",
-                ms.mount.file,
-                ipcnt,
-                bytes_to_human_size(ms.stat.files() as u64),
-                args.crit_inodes
-            );
-        } else if ipcnt > args.warn_inodes {
-            status = max(status, Status::Warning);
-            println!(
-                "WARNING: {} has {:.1}% of its {} inodes used (> {:.1}%)",
-                ms.mount.file,
-                ipcnt,
-                bytes_to_human_size(ms.stat.files() as u64),
-                args.warn_inodes
-            );
+        let ipcnt = inode_percent(&ms.stat);
+        let threshold = if args.crit_inodes.is_violated(ipcnt) {
+            Some((Status::Critical, args.crit_inodes))
+        } else if args.warn_inodes.is_violated(ipcnt) {
+            Some((Status::Warning, args.warn_inodes))
+        } else {
+            None
+        };
+        if let Some((status, threshold)) = threshold {
+            problems.push(CheckResult::new(
+                status,
+                format!(
+                    "{} has {:.1}% of its {} inodes used ({})",
+                    ms.mount.file,
+                    ipcnt,
+                    bytes_to_human_size(ms.stat.files() as u64),
+                    threshold.describe()
+                ),
+            ));
         }
     }
-    if status == Status::Ok {
-        println!(
-            "OKAY: {} filesystems checked, none are above {}% disk or {}% inode usage",
-            mountstats.len(),
-            args.warn,
-            args.warn_inodes
-        );
-    }
+
+    let mut problems = problems.into_iter();
+    let mut result = match problems.next() {
+        Some(first) => problems.fold(first, |mut result, problem| {
+            result.merge(problem);
+            result
+        }),
+        None => CheckResult::new(
+            Status::Ok,
+            format!(
+                "{} filesystems checked, none have disk usage {} or inode usage {}",
+                mountstats.len(),
+                warn.describe(),
+                args.warn_inodes.describe()
+            ),
+        ),
+    };
 
     if args.info {
-        println!(
+        result.add_line(format!(
             "{:<15} {:>7} {:>5}% {:>7} {:>5}% {:<20}",
             "Filesystem", "Size", "Use", "INodes", "IUse", "Mounted on"
-        );
+        ));
         for ms in mountstats {
-            println!(
+            result.add_line(format!(
                 "{:<15} {:>7} {:>5.1}% {:>7} {:>5.1}% {:<20}",
                 ms.mount.spec,
                 bytes_to_human_size(ms.stat.blocks() as u64 * ms.stat.fragment_size()),
                 percent(ms.stat.blocks_available().into(), ms.stat.blocks().into()),
                 bytes_to_human_size(ms.stat.files() as u64),
-                percent(ms.stat.files_available().into(), ms.stat.files().into()),
+                inode_percent(&ms.stat),
                 ms.mount.file
-            );
+            ));
         }
     }
 
-    status
+    result.perfdata = perf_data(mountstats, args);
+    result
+}
+
+/// Disk and inode usage for every mount point
+#[allow(clippy::useless_conversion)]
+fn perf_data(mountstats: &[MountStat], args: &Args) -> Vec<PerfData> {
+    let mut perf = vec![];
+    for ms in mountstats {
+        perf.push(
+            PerfData::new(
+                ms.mount.file.clone(),
+                percent(ms.stat.blocks_available().into(), ms.stat.blocks().into()),
+            )
+            .unit(Unit::Percent)
+            .warn(args.warn())
+            .crit(args.crit())
+            .min(0.0)
+            .max(100.0),
+        );
+        // Some filesystems (e.g. btrfs) don't report inodes at all
+        if ms.stat.files() > 0 {
+            perf.push(
+                PerfData::new(format!("{}_inodes", ms.mount.file), inode_percent(&ms.stat))
+                    .unit(Unit::Percent)
+                    .warn(args.warn_inodes)
+                    .crit(args.crit_inodes)
+                    .min(0.0)
+                    .max(100.0),
+            );
+        }
+    }
+    perf
 }
 
 #[cfg(test)]
 mod unit {
-    use super::{maybe_regex, Args};
+    use nix::sys::statvfs::Statvfs;
+
+    use super::{inode_percent, maybe_regex, Args};
     use structopt::StructOpt;
+    use tabin_plugins::Threshold;
 
     #[test]
     fn validate_docstring() {
         let args: Args = Args::from_iter(["arg0", "--crit", "5"].iter());
-        assert_eq!(args.crit, 5.0);
+        assert_eq!(args.crit(), Threshold::above(5.0));
         let args: Args = Args::from_iter(["arg0", "--pattern", "hello"].iter());
         assert_eq!(args.pattern.unwrap(), "hello");
     }
 
+    #[test]
+    fn inode_usage_is_from_free_inodes() {
+        // a filesystem with 90% of its blocks free but only 25% of its inodes
+        let mut raw: libc::statvfs = unsafe { std::mem::zeroed() };
+        raw.f_blocks = 1000;
+        raw.f_bfree = 900;
+        raw.f_bavail = 900;
+        raw.f_files = 400;
+        raw.f_ffree = 100;
+        raw.f_favail = 100;
+        // Statvfs is a repr(transparent) wrapper around libc's struct
+        let stat: Statvfs = unsafe { std::mem::transmute(raw) };
+        assert_eq!(inode_percent(&stat), 75.0);
+    }
+
     #[test]
     fn check_maybe_regex() {
         if let Err(emsg) = maybe_regex(&Some("[hello".to_owned())) {
@@ -374,7 +449,7 @@ mod unit {
                 "\nExpected something containing: {}\n\
                  But instead received         : {}",
                 expected,
-                emsg.to_string()
+                emsg
             );
         } else {
             panic!("Should have gotten an error");
@@ -4,18 +4,22 @@ use std::fs;
 use std::io::ErrorKind;
 use std::io::Write;
 use std::path::Path;
-use std::process;
 use std::sync::mpsc::channel;
 use std::thread;
+use std::time::Instant;
 
 use serde::Deserialize;
 use structopt::StructOpt;
 
+use tabin_plugins::cli::CommonArgs;
+use tabin_plugins::watchdog;
+use tabin_plugins::{CheckResult, Status};
+
 /// Check that we can write to a filesystem by writing a byte to a file.
 ///
 /// Does not try to create the directory, or do anything else. Just writes a
 /// single byte to a file, errors if it cannot, and then deletes the file.
-#[derive(StructOpt, Deserialize)]
+#[derive(StructOpt, Deserialize, Debug)]
 #[structopt(
     name = "check-fs-writeable (part of tabin-plugins)",
     setting = structopt::clap::AppSettings::ColoredHelp,
@@ -23,43 +27,37 @@ use structopt::StructOpt;
 struct Args {
     #[structopt(help = "The file to write to")]
     filename: String,
+    #[structopt(flatten)]
+    common: CommonArgs,
 }
 
 #[cfg_attr(test, allow(dead_code))]
 fn main() {
     let args = Args::from_args();
-    let mut exit_status = 0;
-    match check_file_writeable(args.filename) {
-        Ok(msg) => {
-            println!("{}", msg);
-        }
-        Err(msg) => {
-            println!("{}", msg);
-            exit_status = 2;
-        }
-    }
-    process::exit(exit_status);
+    let started = Instant::now();
+    args.common.init();
+    watchdog::phase(format!("writing to {}", args.filename));
+    let result = check_file_writeable(&args.filename);
+    args.common.exit(result, started);
 }
 
-fn check_file_writeable(filename: String) -> Result<String, String> {
+fn check_file_writeable(filename: &str) -> CheckResult {
+    let filename = filename.to_owned();
     let (tx, rx) = channel();
     // We spawn a thread because rust doesn't provide a way to close a file, it
     // just panics if issues happen when it goes out of scope.
     let child = thread::spawn(move || {
         let path = Path::new(&filename);
-        match fs::File::create(&path) {
+        match fs::File::create(path) {
             Err(ref e) => match e.kind() {
                 ErrorKind::NotFound => {
                     let dir = path.parent().unwrap_or(Path::new("/"));
-                    tx.send(Err(format!(
-                        "CRITICAL: directory {} does not exist.",
-                        dir.display()
-                    )))
-                    .unwrap();
+                    tx.send(Err(format!("directory {} does not exist.", dir.display())))
+                        .unwrap();
                 }
                 _ => tx
                     .send(Err(format!(
-                        "CRITICAL: unexpected error writing to {}: {}",
+                        "unexpected error writing to {}: {}",
                         path.display(),
                         e
                     )))
@@ -70,32 +68,30 @@ fn check_file_writeable(filename: String) -> Result<String, String> {
                 match f.flush() {
                     Ok(()) => {}
                     Err(_) => tx
-                        .send(Err(format!(
-                            "CRITICAL: Couldn't flush bytes to {}",
-                            path.display()
-                        )))
+                        .send(Err(format!("Couldn't flush bytes to {}", path.display())))
                         .unwrap(),
                 }
-                fs::remove_file(&path).unwrap();
+                fs::remove_file(path).unwrap();
             }
         }
-        tx.send(Ok(format!("OK: wrote some bytes to {}", path.display())))
+        tx.send(Ok(format!("wrote some bytes to {}", path.display())))
             .unwrap()
     });
 
     if let Err(kind) = child.join() {
-        return Err(format!("CRITICAL: error writing to file: {:?}", kind));
+        return CheckResult::new(
+            Status::Critical,
+            format!("error writing to file: {:?}", kind),
+        );
     }
     match rx.recv() {
-        Ok(join_result) => {
-            return join_result;
-        }
-        Err(_) => {
-            return Err(format!(
-                "UNKNOWN: unexpected status receiving info about file writing."
-            ));
-        }
-    };
+        Ok(Ok(msg)) => CheckResult::new(Status::Ok, msg),
+        Ok(Err(msg)) => CheckResult::new(Status::Critical, msg),
+        Err(_) => CheckResult::new(
+            Status::Unknown,
+            "unexpected status receiving info about file writing.",
+        ),
+    }
 }
 
 #[cfg(test)]
@@ -1,7 +1,10 @@
 use std::str::FromStr;
 
 use clap::{self, value_t};
+// `augment_clap` lets us add the shared flags to a hand-built clap App
+use structopt::{StructOpt, StructOptInternal};
 
+use tabin_plugins::cli::CommonArgs;
 use tabin_plugins::Status;
 
 use crate::assertions::Assertion;
@@ -17,6 +20,7 @@ pub(crate) struct Args {
     pub graphite_error: Status,
     pub no_data: Status,
     pub print_url: bool,
+    pub common: CommonArgs,
 }
 
 lazy_static! {
@@ -56,7 +60,7 @@ lazy_static! {
                 );
 }
 
-static ASSERTION_EXAMPLES: &'static [&'static str] = &[
+static ASSERTION_EXAMPLES: &[&str] = &[
     "critical if any point is > 0",
     "critical if any point in at least 40% of series is > 0",
     "critical if any point is not > 0",
@@ -68,8 +72,9 @@ static ASSERTION_EXAMPLES: &'static [&'static str] = &[
 ];
 
 fn build_parser() -> clap::App<'static, 'static> {
-    let allowed_no_data = Status::str_values(); // block-local var for borrowck
-    clap::App::new("check-graphite (part of tabin-plugins)")
+    // block-local vars for borrowck
+    let allowed_no_data = Status::str_values();
+    let app = clap::App::new("check-graphite (part of tabin-plugins)")
             .version(env!("CARGO_PKG_VERSION"))
             .author("Brandon W Maister <quodlibetor@gmail.com>")
             .setting(clap::AppSettings::ColoredHelp)
@@ -106,7 +111,8 @@ fn build_parser() -> clap::App<'static, 'static> {
                     .takes_value(true)
                     .possible_values(&allowed_no_data),
             )
-            .after_help(&**EPILOG)
+            .after_help(&**EPILOG);
+    CommonArgs::augment_clap(app)
 }
 
 impl Args {
@@ -121,15 +127,15 @@ impl Args {
     }
 
     fn from_args(args: clap::ArgMatches) -> Args {
+        let common = CommonArgs::from_clap(&args);
         let assertions = args
             .values_of("ASSERTION")
             .unwrap()
             .map(|assertion_str| match Assertion::from_str(assertion_str) {
                 Ok(a) => a,
-                Err(e) => {
-                    println!("Error `{}` in assertion `{}`", e, assertion_str);
-                    Status::Critical.exit();
-                }
+                Err(e) => common
+                    .error(format!("Error `{}` in assertion `{}`", e, assertion_str))
+                    .exit_as(common.format),
             })
             .collect();
 
@@ -142,7 +148,7 @@ impl Args {
         Args {
             url: args.value_of("URL").unwrap().to_owned(),
             path: args.value_of("PATH").unwrap().to_owned(),
-            assertions: assertions,
+            assertions,
             window: start_offset + window,
             start_at: start_offset,
             retries: value_t!(args.value_of("retries"), u8).unwrap_or(4),
@@ -153,6 +159,7 @@ impl Args {
             no_data: Status::from_str(args.value_of("NO_DATA_STATUS").unwrap_or("warning"))
                 .unwrap(),
             print_url: args.is_present("print-url"),
+            common,
         }
     }
 }
@@ -161,6 +168,7 @@ impl Args {
 mod test {
     use super::*;
     use crate::assertions::*;
+    use tabin_plugins::output::Format;
 
     #[test]
     fn all_examples_are_accurate() {
@@ -197,6 +205,7 @@ mod test {
                 graphite_error: Status::Unknown,
                 no_data: Status::Warning,
                 print_url: false,
+                common: CommonArgs::default(),
             }
         )
     }
@@ -211,6 +220,8 @@ mod test {
             "--graphite-error=ok",
             "--no-data=critical",
             "--print-url",
+            "--format=json",
+            "--timeout=30",
             "https://graphite.example.com",
             "*",
             "critical if any point is > 0",
@@ -234,6 +245,11 @@ mod test {
                 graphite_error: Status::Ok,
                 no_data: Status::Critical,
                 print_url: true,
+                common: CommonArgs {
+                    format: Format::Json,
+                    timeout: Some(30.0),
+                    ..CommonArgs::default()
+                },
             }
         )
     }
@@ -2,7 +2,7 @@ use std::fmt;
 use std::str::FromStr;
 
 use itertools::Itertools;
-use tabin_plugins::Status;
+use tabin_plugins::{CheckResult, Status};
 
 use crate::graphite::{FilteredGraphiteData, GraphiteData};
 
@@ -26,11 +26,12 @@ pub(crate) enum PointAssertion {
 impl Assertion {
     /// Check if any series *violates* the assertion
     ///
-    /// Returns the maximum violation status
+    /// The result has the assertion's failure status if any series violates
+    /// it, and is OK otherwise.
     ///
     /// `series_with_data` must contain only series that contain at least some
     /// data (`main::bail_if_no_data` must have been called first.)
-    pub fn check(&self, series_with_data: &[GraphiteData]) -> Status {
+    pub fn check(&self, series_with_data: &[GraphiteData]) -> CheckResult {
         let &Assertion {
             operator: ref op,
             op_is_negated,
@@ -47,7 +48,7 @@ impl Assertion {
             PointAssertion::Ratio(error_ratio) => series_with_data
                 .iter()
                 .map(|series| FilteredGraphiteData {
-                    original: &series,
+                    original: series,
                     points: series.invalid_points(&comparator),
                 })
                 .filter(|invalid| {
@@ -67,11 +68,11 @@ impl Assertion {
                 .collect::<Vec<FilteredGraphiteData>>(),
             PointAssertion::Recent(count) => series_with_data
                 .iter()
-                .map(|ref series| FilteredGraphiteData {
-                    original: &series,
+                .map(|series| FilteredGraphiteData {
+                    original: series,
                     points: series.last_invalid_points(count, &comparator),
                 })
-                .filter(|ref invalid| !invalid.is_empty())
+                .filter(|invalid| !invalid.is_empty())
                 .collect::<Vec<FilteredGraphiteData>>(),
         };
 
@@ -81,64 +82,55 @@ impl Assertion {
             ""
         };
         if !with_invalid.is_empty() {
-            match error_condition {
+            let mut lines = vec![];
+            let summary = match error_condition {
                 PointAssertion::Ratio(ratio) => {
+                    for series in &with_invalid {
+                        let prefix = if with_invalid.len() == 1 {
+                            ""
+                        } else {
+                            "       -> "
+                        };
+                        lines.push(format!(
+                            "{}{} has {} points ({:.1}%) that are{} {} {}: {}",
+                            prefix,
+                            series.original.target,
+                            series.points.len(),
+                            series.percent_matched(),
+                            nostr,
+                            op,
+                            threshold,
+                            series.points.iter().map(|gv| format!("{}", gv)).join(", ")
+                        ));
+                    }
                     if series_with_data.len() == with_invalid.len() {
                         if with_invalid.len() == 1 {
-                            print!("{}: ", status)
+                            lines.remove(0)
                         } else if ratio == 0.0 {
-                            println!(
-                                "{}: All {} matched paths have invalid datapoints:",
-                                status,
+                            format!(
+                                "All {} matched paths have invalid datapoints:",
                                 with_invalid.len()
                             )
                         } else {
-                            println!(
-                                "{}: All {} matched paths have at least {:.0}% invalid datapoints:",
-                                status,
+                            format!(
+                                "All {} matched paths have at least {:.0}% invalid datapoints:",
                                 with_invalid.len(),
                                 ratio * 100.0
                             )
                         }
                     } else {
-                        println!(
-                            "{}: Of {} paths with data, {} have at least {:.1}% invalid datapoints:",
-                            status,
+                        format!(
+                            "Of {} paths with data, {} have at least {:.1}% invalid datapoints:",
                             series_with_data.len(),
                             with_invalid.len(),
                             ratio * 100.0
-                        );
-                    }
-                    for series in &with_invalid {
-                        let prefix = if with_invalid.len() == 1 {
-                            ""
-                        } else {
-                            "       ->"
-                        };
-                        println!(
-                            "{} {} has {} points ({:.1}%) that are{} {} {}: {}",
-                            prefix,
-                            series.original.target,
-                            series.points.len(),
-                            series.percent_matched(),
-                            nostr,
-                            op,
-                            threshold,
-                            series.points.iter().map(|gv| format!("{}", gv)).join(", ")
-                        );
+                        )
                     }
                 }
                 PointAssertion::Recent(count) => {
-                    println!(
-                        "{}: Of {} paths with data, {} have the last {} points invalid:",
-                        status,
-                        series_with_data.len(),
-                        with_invalid.len(),
-                        count
-                    );
                     for series in &with_invalid {
                         let descriptor = if count == 1 { "point is" } else { "points are" };
-                        println!(
+                        lines.push(format!(
                             "       -> {} last {} {}{} {} {}: {}",
                             series.original.target,
                             count,
@@ -147,49 +139,54 @@ impl Assertion {
                             op,
                             threshold,
                             series.points.iter().map(|gv| format!("{}", gv)).join(", ")
-                        );
+                        ));
                     }
+                    format!(
+                        "Of {} paths with data, {} have the last {} points invalid:",
+                        series_with_data.len(),
+                        with_invalid.len(),
+                        count
+                    )
                 }
-            }
-            Status::Critical
+            };
+            let mut result = CheckResult::new(status, summary);
+            result.long_output = lines;
+            result
         } else {
-            match error_condition {
+            let summary = match error_condition {
                 PointAssertion::Ratio(percent) => {
-                    let amount;
-                    if percent == 0.0 {
-                        amount = "any".to_owned()
+                    let amount = if percent == 0.0 {
+                        "any".to_owned()
                     } else {
-                        amount = format!("at least {:.1}% of", percent * 100.0)
-                    }
-                    println!(
-                        "OK: Found {} paths with data, none had {} datapoints{} {} {:.2}.",
+                        format!("at least {:.1}% of", percent * 100.0)
+                    };
+                    format!(
+                        "Found {} paths with data, none had {} datapoints{} {} {:.2}.",
                         series_with_data.len(),
                         amount,
                         nostr,
                         op,
                         threshold
-                    );
+                    )
                 }
-                PointAssertion::Recent(count) => {
-                    println!(
-                        "OK: Found {} paths with data, none had their last {} datapoints{} {} \
-                         {}.",
-                        series_with_data.len(),
-                        count,
-                        nostr,
-                        op,
-                        threshold
-                    );
-                }
-            }
+                PointAssertion::Recent(count) => format!(
+                    "Found {} paths with data, none had their last {} datapoints{} {} {}.",
+                    series_with_data.len(),
+                    count,
+                    nostr,
+                    op,
+                    threshold
+                ),
+            };
+            let mut result = CheckResult::new(Status::Ok, summary);
             for series in series_with_data.iter() {
-                println!(
+                result.add_line(format!(
                     "    -> {}: {}",
                     series.target,
                     series.points.iter().map(|gv| format!("{}", gv)).join(", ")
-                )
+                ));
             }
-            Status::Ok
+            result
         }
     }
 }
@@ -374,7 +371,7 @@ fn parse_assertion(assertion: &str) -> Result<Assertion, ParseError> {
         op_is_negated: negated,
         threshold: threshold.expect("No threshold found in predicate"),
         point_assertion: point_assertion.expect("No point ratio found in predicate"),
-        series_ratio: series_ratio,
+        series_ratio,
         failure_status: status.expect("Needed to start with an exit status"),
     })
 }
@@ -397,7 +394,7 @@ where
         ratio = Ok(Ratio(1.0))
     } else if word == "at" {
         let mut rat = None;
-        while let Some(word) = it.next() {
+        for word in it.by_ref() {
             if word == "least" {
                 // 'at least'
             } else if word.find('%') == Some(word.len() - 1) {
@@ -428,7 +425,7 @@ where
         ratio = Ok(Ratio(rat.expect("Couldn't find ratio for blah") / 100f64))
     } else if word == "most" {
         match it.next() {
-            Some(word) if word == "recent" => {
+            Some("recent") => {
                 // yay
             }
             Some(word) => {
@@ -445,7 +442,7 @@ where
             }
         }
         match it.next() {
-            Some(word) if word == "point" => return Ok(Recent(1)),
+            Some("point") => return Ok(Recent(1)),
             Some(word) => {
                 return Err(ParseError::SyntaxError(format!(
                     "Expected 'most recent point' found \
@@ -543,7 +540,8 @@ mod test {
             series_ratio: 0.0,
             failure_status: Status::Critical,
         }
-        .check(&valid_data_from_json_two_sets());
+        .check(&valid_data_from_json_two_sets())
+        .status;
         if let Status::Critical = result {
             // expected
         } else {
@@ -561,7 +559,8 @@ mod test {
             series_ratio: 0.0,
             failure_status: Status::Critical,
         }
-        .check(&valid_data_from_json_two_sets());
+        .check(&valid_data_from_json_two_sets())
+        .status;
         if let Status::Ok = result {
             // expected
         } else {
@@ -572,8 +571,8 @@ mod test {
     #[test]
     fn parse_assertion_requires_a_starting_status() {
         let result = parse_assertion("any point is not < 100");
-        if let &Err(ref e) = &result {
-            if let &ParseError::NoStatusSpecifier(_) = e {
+        if let Err(ref e) = result {
+            if let ParseError::NoStatusSpecifier(_) = e {
                 // expected
             } else {
                 panic!("Unexpected result: {:?}", result)
@@ -620,7 +619,7 @@ mod test {
     fn parse_assertion_finds_per_point_description2_and_correctly_alerts() {
         let assertion = parse_assertion("critical if any point is not >= 5.5").unwrap();
         let graphite_data = deser(json_one_point_is_below_5_5());
-        let result = assertion.check(&graphite_data);
+        let result = assertion.check(&graphite_data).status;
         if let Status::Critical = result {
             // expected
         } else {
@@ -664,7 +663,7 @@ mod test {
         assert_eq!(assertion.point_assertion, Ratio(1.0));
 
         let graphite_data = deser(json_all_points_above_5());
-        let result = assertion.check(&graphite_data);
+        let result = assertion.check(&graphite_data).status;
         assert_eq!(result, Status::Critical);
     }
 
@@ -674,7 +673,7 @@ mod test {
         assert_eq!(assertion.point_assertion, Ratio(1.0));
 
         let graphite_data = deser(json_80p_of_points_are_below_6());
-        let result = assertion.check(&graphite_data);
+        let result = assertion.check(&graphite_data).status;
         assert_eq!(result, Status::Ok);
     }
 
@@ -719,13 +718,13 @@ mod test {
     #[test]
     fn most_recent_is_non_empty_works() {
         let assertion = parse_assertion("critical if most recent point is > 5").unwrap();
-        let graphite_data = deser(&json_last_point_is_5());
-        let result = assertion.check(&graphite_data);
+        let graphite_data = deser(json_last_point_is_5());
+        let result = assertion.check(&graphite_data).status;
         assert_eq!(result, Status::Ok);
 
         let assertion = parse_assertion("critical if most recent point is > 4").unwrap();
         let graphite_data = deser(json_last_point_is_5());
-        let result = assertion.check(&graphite_data);
+        let result = assertion.check(&graphite_data).status;
         assert_eq!(result, Status::Critical);
     }
 
@@ -733,12 +732,12 @@ mod test {
     fn most_recent_is_empty_works() {
         let assertion = parse_assertion("critical if most recent point is > 5").unwrap();
         let graphite_data = deser(json_last_existing_point_is_5());
-        let result = assertion.check(&graphite_data);
+        let result = assertion.check(&graphite_data).status;
         assert_eq!(result, Status::Ok);
 
         let assertion = parse_assertion("critical if most recent point is > 4").unwrap();
         let graphite_data = deser(json_last_existing_point_is_5());
-        let result = assertion.check(&graphite_data);
+        let result = assertion.check(&graphite_data).status;
         assert_eq!(result, Status::Critical);
     }
 
@@ -747,7 +746,7 @@ mod test {
         let assertion = parse_assertion("critical if most recent point is == 4").unwrap();
         let graphite_data = deser(json_last_point_is_5());
 
-        let result = assertion.check(&graphite_data);
+        let result = assertion.check(&graphite_data).status;
         assert_eq!(result, Status::Ok);
     }
 
@@ -767,7 +766,7 @@ mod test {
         let assertion =
             parse_assertion("critical if at least 80% of of points are not >= 5.5").unwrap();
         let graphite_data = deser(json_80p_of_points_are_below_6());
-        let result = assertion.check(&graphite_data);
+        let result = assertion.check(&graphite_data).status;
         assert_eq!(result, Status::Critical);
     }
 
@@ -775,7 +774,7 @@ mod test {
     fn parse_some_series_positive_assertion_and_correctly_allows() {
         let assertion = parse_assertion("critical if at least 79% of of points are < 6").unwrap();
         let graphite_data = deser(json_80p_of_points_are_below_6());
-        let result = assertion.check(&graphite_data);
+        let result = assertion.check(&graphite_data).status;
         assert_eq!(result, Status::Critical);
     }
 
@@ -783,7 +782,7 @@ mod test {
     fn parse_some_series_positive_assertion_and_correctly_allows_all_points() {
         let assertion = parse_assertion("critical if at least 79% of of points are < 6").unwrap();
         let graphite_data = deser(json_80p_of_points_are_below_6());
-        let result = assertion.check(&graphite_data);
+        let result = assertion.check(&graphite_data).status;
         assert_eq!(result, Status::Critical);
     }
 
@@ -821,7 +820,7 @@ mod test {
     fn null_points_do_not_count_towards_percent() {
         let assertion = parse_assertion("critical if at least 61% of points are == 0").unwrap();
         let graphite_data = deser(json_all_points_are_0_and_40p_of_points_are_null());
-        let result = assertion.check(&graphite_data);
+        let result = assertion.check(&graphite_data).status;
         assert_eq!(result, Status::Critical);
     }
 
@@ -829,7 +828,7 @@ mod test {
     fn null_points_count_towards_percent_crit() {
         let assertion = parse_assertion("critical if at least 60% of points are == 0").unwrap();
         let graphite_data = deser(json_all_points_are_0_and_40p_of_points_are_null());
-        let result = assertion.check(&graphite_data);
+        let result = assertion.check(&graphite_data).status;
         assert_eq!(result, Status::Critical);
     }
 }
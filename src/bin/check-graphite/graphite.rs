@@ -14,9 +14,8 @@ use chrono::naive::serde::ts_seconds::deserialize as from_ts_seconds;
 use chrono::naive::NaiveDateTime;
 use reqwest::{self, blocking, Error as ReqwestError};
 use serde::Deserialize;
-use serde_json;
 
-use tabin_plugins::Status;
+use tabin_plugins::watchdog;
 
 /// The result of `fetch_data`
 pub struct GraphiteResponse {
@@ -28,13 +27,8 @@ impl GraphiteResponse {
     /// Mutate self to only contain series that have at least one existing
     /// datapoint
     pub fn filter_to_series_with_data(&mut self) {
-        self.result.retain(|gd| {
-            gd.points
-                .iter()
-                .filter(|point| !point.val.is_none())
-                .count()
-                > 0
-        })
+        self.result
+            .retain(|gd| gd.points.iter().filter(|point| point.val.is_some()).count() > 0)
     }
 }
 
@@ -50,27 +44,25 @@ pub struct GraphiteData {
 
 impl GraphiteData {
     /// References to the points that exist and do not satisfy the comparator
-    // comparator is a box closure, which is not allows in map_or
-    pub(crate) fn invalid_points(&self, comparator: &Box<dyn Fn(f64) -> bool>) -> Vec<&DataPoint> {
+    pub(crate) fn invalid_points(&self, comparator: &dyn Fn(f64) -> bool) -> Vec<&DataPoint> {
         self.points
             .iter()
-            .filter(|p| p.val.map_or(false, |v| comparator(v)))
+            .filter(|p| p.val.is_some_and(comparator))
             .collect()
     }
 
     /// Get only invalid points from the end of the list
-    // comparator is a box closure, which is not allows in map_or
     pub(crate) fn last_invalid_points(
         &self,
         n: usize,
-        comparator: &Box<dyn Fn(f64) -> bool>,
+        comparator: &dyn Fn(f64) -> bool,
     ) -> Vec<&DataPoint> {
         self.points
             .iter()
             .rev()
             .filter(|p| p.val.is_some())
             .take(n)
-            .filter(|p| p.val.map_or(false, |v| comparator(v)))
+            .filter(|p| p.val.is_some_and(comparator))
             .collect()
     }
 }
@@ -172,74 +164,84 @@ impl DoubleEndedIterator for GraphiteIterator {
 }
 
 pub enum GraphiteError {
-    HttpError(ReqwestError),
-    JsonError(String),
-    IoError(String),
+    Http(ReqwestError),
+    Json(String),
+    Io(String),
 }
 
 impl GraphiteError {
     pub fn short_display(&self) -> String {
         match *self {
-            GraphiteError::HttpError(ref e) => e.to_string(),
-            GraphiteError::JsonError(_) => "Error parsing json".to_owned(),
-            GraphiteError::IoError(_) => "Error reading stream from graphite".to_owned(),
+            GraphiteError::Http(ref e) => e.to_string(),
+            GraphiteError::Json(_) => "Error parsing json".to_owned(),
+            GraphiteError::Io(_) => "Error reading stream from graphite".to_owned(),
         }
     }
 }
 
 impl From<ReqwestError> for GraphiteError {
     fn from(e: ReqwestError) -> Self {
-        GraphiteError::HttpError(e)
+        GraphiteError::Http(e)
     }
 }
 
 impl From<io::Error> for GraphiteError {
     fn from(e: io::Error) -> Self {
-        GraphiteError::IoError(e.to_string())
+        GraphiteError::Io(e.to_string())
     }
 }
 
 impl fmt::Display for GraphiteError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            GraphiteError::HttpError(ref e) => e.fmt(f),
-            GraphiteError::JsonError(ref e) => write!(f, "{}", e),
-            GraphiteError::IoError(ref e) => write!(f, "{}", e),
+            GraphiteError::Http(ref e) => e.fmt(f),
+            GraphiteError::Json(ref e) => write!(f, "{}", e),
+            GraphiteError::Io(ref e) => write!(f, "{}", e),
         }
     }
 }
 
 /// Load data from graphite
 ///
-/// Retry until success or exit the script
+/// Retry until success or exit the script, each attempt gives up after
+/// `timeout`
 pub fn fetch_data(
     url: &str,
     target: &str,
     window: i64,
     start_at: i64,
     retries: u8,
-    graphite_error: &Status,
-    print_url: bool,
+    timeout: Duration,
 ) -> Result<GraphiteResponse, String> {
     let mut attempts = 0;
     let mut retry_sleep = 2000;
     loop {
-        match get_graphite(url, target, window, start_at, print_url, graphite_error) {
+        watchdog::phase(format!("querying graphite at {}", url));
+        match get_graphite(url, target, window, start_at, timeout) {
             Ok(s) => {
                 return Ok(s);
             }
             Err(e) => {
-                print!("Error for {}: {}. ", url, e.short_display());
                 if attempts < retries {
-                    println!("Retrying in {}s.", retry_sleep / 1000);
+                    eprintln!(
+                        "Error for {}: {}. Retrying in {}s.",
+                        url,
+                        e.short_display(),
+                        retry_sleep / 1000
+                    );
                     attempts += 1;
+                    watchdog::phase(format!("waiting to retry graphite at {}", url));
                     sleep(Duration::from_millis(retry_sleep));
                     retry_sleep *= 2;
                     continue;
                 } else {
-                    println!("\nFull error: {}", e);
-                    println!("Giving up after {} attempts.", retries + 1);
-                    graphite_error.exit();
+                    return Err(format!(
+                        "Error for {}: {}. Giving up after {} attempts.\nFull error: {}",
+                        url,
+                        e.short_display(),
+                        retries + 1,
+                        e
+                    ));
                 }
             }
         }
@@ -255,20 +257,16 @@ fn get_graphite(
     target: &str,
     window: i64,
     start_at: i64,
-    print_url: bool,
-    graphite_error: &Status,
+    timeout: Duration,
 ) -> Result<GraphiteResponse, GraphiteError> {
     let full_path = format!(
         "{}/render?target={}&format=json&from=-{}min&until=-{}min",
         url, target, window, start_at
     );
     let c = blocking::Client::builder()
-        .timeout(Duration::from_secs(10))
+        .timeout(timeout)
         .build()
         .unwrap();
-    if print_url {
-        println!("INFO: querying {}", full_path);
-    }
     let mut result = c.get(&full_path).send()?;
     let mut s = String::new();
     result.read_to_string(&mut s)?;
@@ -279,19 +277,15 @@ fn get_graphite(
         }),
         Err(e) => {
             if e.is_syntax() || e.is_data() {
-                Err(GraphiteError::JsonError(format!(
-                    "{}: Graphite returned invalid json:\n\
+                Err(GraphiteError::Json(format!(
+                    "Graphite returned invalid json:\n\
                      {}\n=========================\n\
                      The full url queried was: {}",
-                    graphite_error,
                     s,
                     result.url()
                 )))
             } else {
-                Err(GraphiteError::JsonError(format!(
-                    "{}: {}",
-                    graphite_error, e
-                )))
+                Err(GraphiteError::Json(e.to_string()))
             }
         }
     }
@@ -299,6 +293,8 @@ fn get_graphite(
 
 #[cfg(test)]
 mod test {
+    use chrono::DateTime;
+
     use super::*;
 
     use crate::test::deser;
@@ -335,14 +331,14 @@ mod test {
                 GraphiteData {
                     points: vec![DataPoint {
                         val: None,
-                        time: NaiveDateTime::from_timestamp(10, 0),
+                        time: DateTime::from_timestamp(10, 0).unwrap().naive_utc(),
                     },],
                     target: "test.path.null-data".into(),
                 },
                 GraphiteData {
                     points: vec![DataPoint {
                         val: Some(1.0),
-                        time: NaiveDateTime::from_timestamp(50, 0),
+                        time: DateTime::from_timestamp(50, 0).unwrap().naive_utc(),
                     },],
                     target: "test.path.some-data".into(),
                 },
@@ -1,9 +1,10 @@
 #[macro_use]
 extern crate lazy_static;
 
-use std::cmp::max;
+use std::time::{Duration, Instant};
 
-use tabin_plugins::Status;
+use tabin_plugins::perfdata::PerfData;
+use tabin_plugins::{CheckResult, Status};
 
 use crate::args::Args;
 use crate::graphite::{fetch_data, GraphiteResponse};
@@ -12,70 +13,106 @@ mod args;
 mod assertions;
 mod graphite;
 
+/// How long to wait for graphite if there is no `--timeout`
+const DEFAULT_HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[cfg_attr(test, allow(dead_code))]
 fn main() {
     let args = Args::parse();
+    let started = Instant::now();
+    args.common.init();
     let mut data = match fetch_data(
         &args.url,
         &args.path,
         args.window,
         args.start_at,
         args.retries,
-        &args.graphite_error,
-        args.print_url,
+        args.common.timeout().unwrap_or(DEFAULT_HTTP_TIMEOUT),
     ) {
         Ok(data) => data,
-        Err(e) => {
-            println!("{}", e);
-            args.graphite_error.exit();
-        }
+        Err(e) => args
+            .common
+            .exit(CheckResult::new(args.graphite_error, e), started),
     };
 
-    bail_if_no_data(&mut data, &args.path, args.no_data);
+    if let Some(result) = bail_if_no_data(&mut data, &args.path, args.no_data) {
+        args.common.exit(result, started);
+    }
 
-    let mut status = Status::Ok;
-    for assertion in args.assertions {
-        status = max(status, assertion.check(&data.result));
+    let mut result = CheckResult::new(Status::Ok, "no assertions to check");
+    for (i, assertion) in args.assertions.iter().enumerate() {
+        let checked = assertion.check(&data.result);
+        if i == 0 {
+            result = checked;
+        } else {
+            result.merge(checked);
+        }
+    }
+    if args.print_url {
+        result.add_line(format!("INFO: queried {}", data.url));
     }
-    status.exit();
+    result.perfdata = perf_data(&data);
+    args.common.exit(result, started);
+}
+
+/// The most recent non-null value of every series
+fn perf_data(response: &GraphiteResponse) -> Vec<PerfData> {
+    response
+        .result
+        .iter()
+        .filter_map(|series| {
+            series
+                .points
+                .iter()
+                .rev()
+                .find_map(|point| point.val)
+                .map(|val| PerfData::new(series.target.clone(), val))
+        })
+        .collect()
 }
 
 /// Check if we have any graphite data
 ///
-/// If there is nothing to do, exit
-fn bail_if_no_data(response: &mut GraphiteResponse, path: &str, no_data_status: Status) {
-    let mut bail = false;
+/// If there is nothing to do, returns the result to exit with
+fn bail_if_no_data(
+    response: &mut GraphiteResponse,
+    path: &str,
+    no_data_status: Status,
+) -> Option<CheckResult> {
     if response.result.is_empty() {
-        println!(
-            "{}: Graphite returned no matching series for pattern '{}'",
-            no_data_status, path
+        let mut result = CheckResult::new(
+            no_data_status,
+            format!(
+                "Graphite returned no matching series for pattern '{}'",
+                path
+            ),
         );
-        bail = true;
+        result.add_line(format!("INFO: Full query: {}", response.url));
+        return Some(result);
     }
     let original_len = response.result.len();
     response.filter_to_series_with_data();
     if response.result.is_empty() {
-        println!(
-            "{}: Graphite found {} series but returned only null datapoints for them",
-            no_data_status, original_len
+        let mut result = CheckResult::new(
+            no_data_status,
+            format!(
+                "Graphite found {} series but returned only null datapoints for them",
+                original_len
+            ),
         );
-        bail = true;
-    }
-
-    if bail {
-        println!("INFO: Full query: {}", response.url);
-        no_data_status.exit();
+        result.add_line(format!("INFO: Full query: {}", response.url));
+        return Some(result);
     }
+    None
 }
 
 #[cfg(test)]
 mod test {
     // A couple helper methods for tests in other modules
 
+    use crate::graphite::{DataPoint, GraphiteData, GraphiteResponse};
     use chrono::naive::NaiveDateTime;
-    use serde_json;
-
-    use crate::graphite::{DataPoint, GraphiteData};
+    use chrono::DateTime;
 
     pub(crate) fn deser(s: &str) -> Vec<GraphiteData> {
         let result = serde_json::from_str(s);
@@ -83,7 +120,18 @@ mod test {
     }
 
     fn dt(t: i64) -> NaiveDateTime {
-        NaiveDateTime::from_timestamp(t, 0)
+        DateTime::from_timestamp(t, 0).unwrap().naive_utc()
+    }
+
+    #[test]
+    fn perf_data_uses_most_recent_point() {
+        let response = GraphiteResponse {
+            result: deser(r#"[{"datapoints": [[1, 10], [2, 20], [null, 30]], "target": "a.b"}]"#),
+            url: "https://blah".parse().unwrap(),
+        };
+        let perf = super::perf_data(&response);
+        assert_eq!(perf.len(), 1);
+        assert_eq!(perf[0].to_string(), "a.b=2");
     }
 
     pub(crate) fn valid_data_from_json_two_sets() -> Vec<GraphiteData> {
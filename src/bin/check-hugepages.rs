@@ -0,0 +1,338 @@
+//! Check hugepage pools and transparent hugepage settings
+
+use std::time::Instant;
+
+use serde::Deserialize;
+use structopt::StructOpt;
+
+use tabin_plugins::cli::{CommonArgs, Roots, Thresholds};
+use tabin_plugins::context::Context;
+use tabin_plugins::perfdata::{PerfData, Unit};
+use tabin_plugins::procfs::MemInfo;
+use tabin_plugins::sys::kernel::mm::{HugePagePool, TransparentHugePages};
+use tabin_plugins::watchdog;
+use tabin_plugins::{CheckResult, Status, Threshold};
+
+/// Check preallocated hugepages and transparent hugepages
+///
+/// Thresholds are the number of hugepages in a pool that are free and not
+/// reserved, or percentages of the pool with --percent. They are nagios
+/// ranges, so use e.g. '-w 100:' to warn when fewer than 100 pages are
+/// available. By default this goes critical when none are.
+///
+/// Every pool with pages allocated is checked, or only the one selected with
+/// --size. If no pool has pages and a threshold was given, the pool of the
+/// default size is checked, so a host that has lost its hugepages alerts.
+///
+/// --thp alerts if transparent hugepages are not set to the given mode, which
+/// many databases require to be 'never'.
+#[derive(Deserialize, StructOpt, Debug)]
+#[structopt(
+    name = "check-hugepages (part of tabin-plugins)",
+    setting = structopt::clap::AppSettings::ColoredHelp,
+)]
+struct Args {
+    #[structopt(flatten)]
+    thresholds: Thresholds,
+    #[structopt(
+        long = "percent",
+        help = "Thresholds are percentages of the pool instead of page counts"
+    )]
+    percent: bool,
+    #[structopt(
+        long = "size",
+        value_name = "KB",
+        help = "Only check the pool of this page size in KB, e.g. 2048 or 1048576"
+    )]
+    size: Option<usize>,
+    #[structopt(
+        long = "thp",
+        value_name = "MODE",
+        possible_values = &["always", "madvise", "never"],
+        help = "The mode transparent hugepages must be set to"
+    )]
+    thp: Option<String>,
+    #[structopt(
+        long = "thp-status",
+        value_name = "STATUS",
+        default_value = "warning",
+        possible_values = &Status::str_values(),
+        help = "The status if transparent hugepages are in the wrong mode"
+    )]
+    thp_status: Status,
+    #[structopt(flatten)]
+    roots: Roots,
+    #[structopt(flatten)]
+    common: CommonArgs,
+}
+
+impl Args {
+    fn crit(&self) -> Threshold {
+        self.thresholds
+            .crit
+            .unwrap_or_else(|| Threshold::below(1.0))
+    }
+}
+
+#[cfg_attr(test, allow(dead_code))]
+fn main() {
+    let args = Args::from_args();
+    let started = Instant::now();
+    args.common.init();
+    let context = args.roots.context();
+    watchdog::phase("reading hugepage pools");
+    let pools = load_pools(&context).unwrap_or_else(|e| args.common.fail(e, started));
+    let default_kb = MemInfo::load_in(&context)
+        .ok()
+        .and_then(|mem| mem.huge_pages())
+        .map(|pool| pool.size_kb);
+    let mut result = match select_pools(&args, &pools, default_kb) {
+        Ok(selected) => check_pools(&args, &selected),
+        Err(e) => args.common.fail(e, started),
+    };
+    if let Some(ref mode) = args.thp {
+        watchdog::phase("reading transparent hugepage settings");
+        let thp = TransparentHugePages::load_in(&context).unwrap_or_else(|e| {
+            args.common.fail(
+                format!("Unable to read transparent hugepage settings: {}", e),
+                started,
+            )
+        });
+        result.merge(check_thp(mode, args.thp_status, &thp));
+    }
+    args.common.exit(result, started);
+}
+
+/// Every hugepage pool, from /sys or, if that isn't mounted, the default
+/// size from /proc/meminfo
+fn load_pools(context: &Context) -> Result<Vec<HugePagePool>, String> {
+    match HugePagePool::load_all_in(context) {
+        Ok(pools) => Ok(pools),
+        Err(sys_err) => match MemInfo::load_in(context) {
+            Ok(mem) => Ok(mem.huge_pages().into_iter().collect()),
+            Err(e) => Err(format!(
+                "Unable to read hugepages: {}, or meminfo: {}",
+                sys_err, e
+            )),
+        },
+    }
+}
+
+/// The pools to check, see the help text
+///
+/// `default_kb` is the default size from /proc/meminfo, if it could be read.
+fn select_pools(
+    args: &Args,
+    pools: &[HugePagePool],
+    default_kb: Option<usize>,
+) -> Result<Vec<HugePagePool>, String> {
+    if let Some(size) = args.size {
+        return match pools.iter().find(|pool| pool.size_kb == size) {
+            Some(pool) => Ok(vec![*pool]),
+            None => Err(format!(
+                "No {}kB hugepages, sizes are: {}",
+                size,
+                pools
+                    .iter()
+                    .map(HugePagePool::size_name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        };
+    }
+    let allocated = pools
+        .iter()
+        .filter(|pool| pool.total > 0)
+        .cloned()
+        .collect::<Vec<_>>();
+    if allocated.is_empty() && (args.thresholds.warn.is_some() || args.thresholds.crit.is_some()) {
+        // The default size varies, e.g. arm64 has smaller pools than its
+        // 2MB default, so the smallest is only a guess
+        let default = pools
+            .iter()
+            .find(|pool| Some(pool.size_kb) == default_kb)
+            .or_else(|| pools.first());
+        Ok(default.cloned().into_iter().collect())
+    } else {
+        Ok(allocated)
+    }
+}
+
+/// Compare the available pages in each pool against the thresholds
+fn check_pools(args: &Args, pools: &[HugePagePool]) -> CheckResult {
+    let mut results = pools.iter().map(|pool| check_pool(args, pool));
+    let mut result = match results.next() {
+        Some(result) => result,
+        None => return CheckResult::new(Status::Ok, "no hugepages allocated"),
+    };
+    for other in results {
+        result.merge(other);
+    }
+    result
+}
+
+fn check_pool(args: &Args, pool: &HugePagePool) -> CheckResult {
+    let available = pool.available();
+    let (value, shown) = if args.percent {
+        let percent = if pool.total == 0 {
+            0.0
+        } else {
+            available as f64 / pool.total as f64 * 100.0
+        };
+        (percent, format!("{:.1}%", percent))
+    } else {
+        (available as f64, available.to_string())
+    };
+    let summary = format!(
+        "{} of {} {} hugepages available",
+        shown,
+        pool.total,
+        pool.size_name()
+    );
+    let detail = format!("({} free, {} reserved)", pool.free, pool.reserved);
+    let crit = args.crit();
+    let mut result = match args.thresholds.warn {
+        _ if crit.is_violated(value) => CheckResult::new(
+            Status::Critical,
+            format!("{} {} {}", summary, crit.describe(), detail),
+        ),
+        Some(warn) if warn.is_violated(value) => CheckResult::new(
+            Status::Warning,
+            format!("{} {} {}", summary, warn.describe(), detail),
+        ),
+        _ => CheckResult::new(Status::Ok, format!("{} {}", summary, detail)),
+    };
+    let label = |name| format!("hugepages_{}_{}", pool.size_name(), name);
+    let mut perf = if args.percent {
+        PerfData::new(label("available"), value)
+            .unit(Unit::Percent)
+            .max(100.0)
+    } else {
+        PerfData::new(label("available"), value).max(pool.total as f64)
+    };
+    perf = perf.min(0.0).crit(crit);
+    if let Some(warn) = args.thresholds.warn {
+        perf = perf.warn(warn);
+    }
+    result.add_perf(perf);
+    result.add_perf(PerfData::new(label("total"), pool.total as f64).min(0.0));
+    result.add_perf(PerfData::new(label("surplus"), pool.surplus as f64).min(0.0));
+    result
+}
+
+/// Check that transparent hugepages are enabled in `mode`
+fn check_thp(mode: &str, status: Status, thp: &TransparentHugePages) -> CheckResult {
+    if thp.enabled == mode {
+        CheckResult::new(
+            Status::Ok,
+            format!("transparent hugepages are {}", thp.enabled),
+        )
+    } else {
+        CheckResult::new(
+            status,
+            format!(
+                "transparent hugepages are {}, expected {}",
+                thp.enabled, mode
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use structopt::StructOpt;
+
+    use super::{check_pools, check_thp, select_pools, Args};
+    use tabin_plugins::sys::kernel::mm::{HugePagePool, TransparentHugePages};
+    use tabin_plugins::Status;
+
+    fn pool(size_kb: usize, total: u64, free: u64, reserved: u64) -> HugePagePool {
+        HugePagePool {
+            size_kb,
+            total,
+            free,
+            reserved,
+            surplus: 0,
+        }
+    }
+
+    #[test]
+    fn alerts_on_available_pages() {
+        let args = Args::from_iter(["arg0", "-w", "10:"].iter());
+        let result = check_pools(&args, &[pool(2048, 64, 16, 8)]);
+        assert_eq!(result.status, Status::Warning);
+        assert_eq!(
+            result.summary,
+            "8 of 64 2048kB hugepages available < 10 (16 free, 8 reserved)"
+        );
+        assert_eq!(
+            result.perfdata[0].to_string(),
+            "hugepages_2048kB_available=8;10:;1:;0;64"
+        );
+
+        let result = check_pools(&args, &[pool(2048, 64, 8, 8)]);
+        assert_eq!(result.status, Status::Critical);
+
+        let args = Args::from_iter(["arg0", "--percent", "-c", "20:"].iter());
+        let result = check_pools(&args, &[pool(2048, 64, 32, 0), pool(1_048_576, 4, 0, 0)]);
+        assert_eq!(result.status, Status::Critical);
+        assert_eq!(
+            result.summary,
+            "0.0% of 4 1048576kB hugepages available < 20 (0 free, 0 reserved)"
+        );
+        assert_eq!(
+            result.long_output[0],
+            "50.0% of 64 2048kB hugepages available (32 free, 0 reserved)"
+        );
+    }
+
+    #[test]
+    fn selects_pools() {
+        let pools = [pool(2048, 0, 0, 0), pool(1_048_576, 4, 4, 0)];
+        let args = Args::from_iter(["arg0"].iter());
+        assert_eq!(select_pools(&args, &pools, Some(2048)).unwrap(), [pools[1]]);
+        let args = Args::from_iter(["arg0", "--size", "2048"].iter());
+        assert_eq!(select_pools(&args, &pools, Some(2048)).unwrap(), [pools[0]]);
+        let args = Args::from_iter(["arg0", "--size", "4"].iter());
+        assert_eq!(
+            select_pools(&args, &pools, Some(2048)).unwrap_err(),
+            "No 4kB hugepages, sizes are: 2048kB, 1048576kB"
+        );
+
+        let pools = [pool(2048, 0, 0, 0)];
+        let args = Args::from_iter(["arg0"].iter());
+        assert!(select_pools(&args, &pools, Some(2048)).unwrap().is_empty());
+        assert_eq!(check_pools(&args, &[]).summary, "no hugepages allocated");
+        let args = Args::from_iter(["arg0", "-c", "100:"].iter());
+        assert_eq!(select_pools(&args, &pools, Some(2048)).unwrap(), pools);
+
+        // arm64 with 4k pages defaults to 2MB, but has smaller pools
+        let pools = [
+            pool(64, 0, 0, 0),
+            pool(2048, 0, 0, 0),
+            pool(32768, 0, 0, 0),
+            pool(1_048_576, 0, 0, 0),
+        ];
+        assert_eq!(select_pools(&args, &pools, Some(2048)).unwrap(), [pools[1]]);
+        // without meminfo the smallest is the best guess
+        assert_eq!(select_pools(&args, &pools, None).unwrap(), [pools[0]]);
+    }
+
+    #[test]
+    fn alerts_on_thp_mode() {
+        let thp = TransparentHugePages {
+            enabled: "always".into(),
+            defrag: "madvise".into(),
+        };
+        let result = check_thp("never", Status::Warning, &thp);
+        assert_eq!(result.status, Status::Warning);
+        assert_eq!(
+            result.summary,
+            "transparent hugepages are always, expected never"
+        );
+        assert_eq!(
+            check_thp("always", Status::Warning, &thp).status,
+            Status::Ok
+        );
+    }
+}
@@ -4,11 +4,19 @@
 //! due to IO or CPU constraints. The numbers used to check are the load averaged
 //! over 1, 5 and 15 minutes, respectively
 
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Instant;
+
 use serde::Deserialize;
 use structopt::StructOpt;
 
+use tabin_plugins::cli::{CommonArgs, Roots};
+use tabin_plugins::perfdata::PerfData;
 use tabin_plugins::procfs::{Calculations, LoadAvg};
-use tabin_plugins::Status;
+use tabin_plugins::watchdog;
+use tabin_plugins::{CheckResult, Status, Threshold};
 
 /// Check the load average of the system
 ///
@@ -24,32 +32,97 @@ struct Args {
     #[structopt(
         short = "w",
         long = "warn",
-        help = "Averages to warn at",
+        help = "Averages to warn at, as nagios ranges",
         default_value = "5,3.5,2.5"
     )]
-    warn: LoadAvg,
+    warn: LoadThresholds,
     #[structopt(
         short = "c",
         long = "crit",
-        help = "Averages to go critical at",
+        help = "Averages to go critical at, as nagios ranges",
         default_value = "10,5,3"
     )]
-    crit: LoadAvg,
+    crit: LoadThresholds,
     #[structopt(
         long = "per-cpu",
         help = "Divide the load average by the number of processors on the \
                 system."
     )]
     per_cpu: bool,
-    #[structopt(
-        short = "v",
-        long = "verbose",
-        help = "print info even if everything is okay"
-    )]
-    verbose: bool,
+    #[structopt(flatten)]
+    roots: Roots,
+    #[structopt(flatten)]
+    common: CommonArgs,
+}
+
+/// One threshold for each of the 1, 5 and 15 minute load averages
+///
+/// Parsed from three comma or space separated nagios ranges, or a single range
+/// that applies to all of them.
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "String")]
+struct LoadThresholds {
+    one: Threshold,
+    five: Threshold,
+    fifteen: Threshold,
+}
+
+impl LoadThresholds {
+    /// The names of the load averages that violate their threshold, along
+    /// with a description of the threshold
+    fn violations(&self, actual: &LoadAvg) -> Vec<String> {
+        [
+            ("load1", &self.one, actual.one),
+            ("load5", &self.five, actual.five),
+            ("load15", &self.fifteen, actual.fifteen),
+        ]
+        .iter()
+        .filter(|(_, threshold, value)| threshold.is_violated(*value))
+        .map(|(name, threshold, _)| format!("{} {}", name, threshold.describe()))
+        .collect()
+    }
+}
+
+impl FromStr for LoadThresholds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LoadThresholds, String> {
+        let ranges = s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|range| !range.is_empty())
+            .map(|range| range.parse::<Threshold>().map_err(|e| e.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        match ranges[..] {
+            [all] => Ok(LoadThresholds {
+                one: all,
+                five: all,
+                fifteen: all,
+            }),
+            [one, five, fifteen] => Ok(LoadThresholds { one, five, fifteen }),
+            _ => Err(format!(
+                "Expected one or three load average thresholds, got {:?}",
+                s
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for LoadThresholds {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<LoadThresholds, String> {
+        s.parse()
+    }
 }
 
-fn do_check(args: &Args, actual: LoadAvg, num_cpus: usize, per_cpu: bool) -> Status {
+impl fmt::Display for LoadThresholds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{},{}", self.one, self.five, self.fifteen)
+    }
+}
+
+fn do_check(args: &Args, actual: LoadAvg, num_cpus: usize, per_cpu: bool) -> CheckResult {
+    let perf = perf_data(args, &actual, num_cpus);
     let actual = actual / num_cpus;
     let cpu_str = if per_cpu && num_cpus > 1 {
         format!(" (divided by {} cpus)", num_cpus)
@@ -57,42 +130,92 @@ fn do_check(args: &Args, actual: LoadAvg, num_cpus: usize, per_cpu: bool) -> Sta
         String::new()
     };
 
-    if actual > args.crit {
-        println!(
-            "[check-load] CRITICAL: load average{} is {} (> {})",
-            cpu_str, actual, args.crit
-        );
-        Status::Critical
-    } else if actual > args.warn {
-        println!(
-            "[check-load] WARNING: load average{} is {} (> {})",
-            cpu_str, actual, args.warn
-        );
-        Status::Warning
+    let crit = args.crit.violations(&actual);
+    let warn = args.warn.violations(&actual);
+    let mut result = if !crit.is_empty() {
+        CheckResult::new(
+            Status::Critical,
+            format!(
+                "load average{} is {} ({})",
+                cpu_str,
+                actual,
+                crit.join(", ")
+            ),
+        )
+    } else if !warn.is_empty() {
+        CheckResult::new(
+            Status::Warning,
+            format!(
+                "load average{} is {} ({})",
+                cpu_str,
+                actual,
+                warn.join(", ")
+            ),
+        )
     } else {
-        if args.verbose {
-            println!(
-                "[check-load] OK: load average{} is {} (< {})",
+        CheckResult::new(
+            Status::Ok,
+            format!(
+                "load average{} is {} (warning at {})",
                 cpu_str, actual, args.warn
-            );
-        }
-        Status::Ok
+            ),
+        )
+    };
+    if args.common.verbose > 0 {
+        result.add_line(format!(
+            "INFO [check-load]: warning at {}, critical at {}",
+            args.warn, args.crit
+        ));
     }
+    result.perfdata = perf;
+    result
+}
+
+/// The load averages, divided by `num_cpus`, along with their thresholds
+fn perf_data(args: &Args, actual: &LoadAvg, num_cpus: usize) -> Vec<PerfData> {
+    let cpus = num_cpus as f64;
+    vec![
+        PerfData::new("load1", actual.one / cpus)
+            .warn(args.warn.one)
+            .crit(args.crit.one)
+            .min(0.0),
+        PerfData::new("load5", actual.five / cpus)
+            .warn(args.warn.five)
+            .crit(args.crit.five)
+            .min(0.0),
+        PerfData::new("load15", actual.fifteen / cpus)
+            .warn(args.warn.fifteen)
+            .crit(args.crit.fifteen)
+            .min(0.0),
+    ]
 }
 
 #[cfg_attr(test, allow(dead_code))]
 fn main() {
     let args = Args::from_args();
+    let started = Instant::now();
+    args.common.init();
+    let context = args.roots.context();
 
+    watchdog::phase("reading load average");
     let num_cpus = if args.per_cpu {
-        let cpus = Calculations::load_per_cpu().unwrap();
-        cpus.len()
+        match Calculations::load_per_cpu_in(&context) {
+            Ok(cpus) => cpus.len(),
+            Err(e) => args
+                .common
+                .fail(format!("Unable to count cpus: {}", e), started),
+        }
     } else {
         1
     };
 
-    let status = do_check(&args, LoadAvg::load().unwrap(), num_cpus, args.per_cpu);
-    status.exit();
+    let result = match LoadAvg::load_in(&context) {
+        Ok(load) => do_check(&args, load, num_cpus, args.per_cpu),
+        Err(e) => args
+            .common
+            .error(format!("Unable to read load average: {}", e)),
+    };
+    args.common.exit(result, started);
 }
 
 #[cfg(test)]
@@ -100,13 +223,15 @@ mod test {
     use structopt::StructOpt;
     use tabin_plugins::Status;
 
-    use super::{do_check, Args};
+    use super::{do_check, perf_data, Args};
 
+    #[allow(clippy::useless_conversion)]
     fn build_args(argv: Vec<&str>) -> Args {
         Args::from_iter(argv.into_iter())
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn flags() {
         let args = build_args(vec!["check-load", "--per-cpu"]);
         assert_eq!(args.per_cpu, true);
@@ -119,64 +244,87 @@ mod test {
     fn one_core_statuses() {
         let args = build_args(vec!["check-load", "-c", "1,2,3"]);
         assert_eq!(
-            do_check(&args, "2 1 1".parse().unwrap(), 1, args.per_cpu),
+            do_check(&args, "2 1 1".parse().unwrap(), 1, args.per_cpu).status,
             Status::Critical
         );
 
         let args = build_args(vec!["check-load", "-w", "1,2,3"]);
         assert_eq!(
-            do_check(&args, "1.1 1 1".parse().unwrap(), 1, args.per_cpu),
+            do_check(&args, "1.1 1 1".parse().unwrap(), 1, args.per_cpu).status,
             Status::Warning
         );
 
         let args = build_args(vec!["check-load"]);
         assert_eq!(
-            do_check(&args, "2 1 1".parse().unwrap(), 1, args.per_cpu),
+            do_check(&args, "2 1 1".parse().unwrap(), 1, args.per_cpu).status,
             Status::Ok
         );
 
         let args = build_args(vec!["check-load"]);
         assert_eq!(
-            do_check(&args, "12 1 1".parse().unwrap(), 1, args.per_cpu),
+            do_check(&args, "12 1 1".parse().unwrap(), 1, args.per_cpu).status,
             Status::Critical
         );
 
         let args = build_args(vec!["check-load", "-c", "2,3,4", "-w", "1,2,3"]);
         assert_eq!(
-            do_check(&args, ".5 .5 .5".parse().unwrap(), 1, args.per_cpu),
+            do_check(&args, ".5 .5 .5".parse().unwrap(), 1, args.per_cpu).status,
             Status::Ok
         );
     }
 
+    #[test]
+    fn accepts_ranges() {
+        let args = build_args(vec!["check-load", "-w", "@0:0.1", "-c", "~:20"]);
+        let result = do_check(&args, "0.08 1 1".parse().unwrap(), 1, args.per_cpu);
+        assert_eq!(result.status, Status::Warning);
+        assert_eq!(
+            result.summary,
+            "load average is 0.1 1.0 1.0 (load1 inside 0:0.1)"
+        );
+        assert!(Args::from_iter_safe(vec!["check-load", "-w", "1,2"]).is_err());
+    }
+
+    #[test]
+    fn perf_data_is_per_cpu() {
+        let args = build_args(vec!["check-load", "--per-cpu", "-w", "1,2,3"]);
+        let perf = perf_data(&args, &"4 2 1".parse().unwrap(), 2);
+        let rendered: Vec<_> = perf.iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            rendered,
+            ["load1=2;1;10;0", "load5=1;2;5;0", "load15=0.5;3;3;0"]
+        );
+    }
+
     #[test]
     fn multi_cpu_statuses() {
         let args = build_args(vec!["check-load", "-w", "7,2,2", "-v", "--per-cpu"]);
         assert_eq!(
-            do_check(&args, "12 1 1".parse().unwrap(), 2, args.per_cpu),
+            do_check(&args, "12 1 1".parse().unwrap(), 2, args.per_cpu).status,
             Status::Ok
         );
 
         let args = build_args(vec!["check-load", "-w", "5,2,2", "-v", "--per-cpu"]);
         assert_eq!(
-            do_check(&args, "12 1 1".parse().unwrap(), 2, args.per_cpu),
+            do_check(&args, "12 1 1".parse().unwrap(), 2, args.per_cpu).status,
             Status::Warning
         );
 
         let args = build_args(vec!["check-load", "-v", "--per-cpu"]);
         assert_eq!(
-            do_check(&args, "21 1 1".parse().unwrap(), 2, args.per_cpu),
+            do_check(&args, "21 1 1".parse().unwrap(), 2, args.per_cpu).status,
             Status::Critical
         );
 
         let args = build_args(vec!["check-load", "-v", "--per-cpu", "-c", "6,4,4"]);
         assert_eq!(
-            do_check(&args, "13 1 1".parse().unwrap(), 2, args.per_cpu),
+            do_check(&args, "13 1 1".parse().unwrap(), 2, args.per_cpu).status,
             Status::Critical
         );
 
         let args = build_args(vec!["check-load", "-v", "--per-cpu", "-c", "2,1,1"]);
         assert_eq!(
-            do_check(&args, "13 1 1".parse().unwrap(), 4, args.per_cpu),
+            do_check(&args, "13 1 1".parse().unwrap(), 4, args.per_cpu).status,
             Status::Critical
         );
     }
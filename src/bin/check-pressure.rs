@@ -0,0 +1,446 @@
+//! Check pressure stall information, for the whole system or a container
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Instant;
+
+use serde::Deserialize;
+use structopt::StructOpt;
+
+use tabin_plugins::check::worst_of;
+use tabin_plugins::cli::{CGroupSelector, CommonArgs, Roots};
+use tabin_plugins::perfdata::{PerfData, Unit};
+use tabin_plugins::procfs::{Pressure, PressureResource, Stalls};
+use tabin_plugins::sys::fs::cgroup::CGroup;
+use tabin_plugins::watchdog;
+use tabin_plugins::{CheckResult, Context, Status, Threshold};
+
+/// Check pressure stall information (PSI)
+///
+/// PSI is the percent of time in which tasks were stalled waiting for cpu,
+/// memory or io, averaged over the last 10, 60 and 300 seconds. Unlike the
+/// load average it measures how much work is being delayed, and it is
+/// tracked per resource and per cgroup. It needs Linux 4.20 or later.
+///
+/// By default this checks the whole system, from /proc/pressure. With
+/// --container it checks the cgroup it is running in, and from the host
+/// --cgroup, --pid or --container-id select a container, with --children
+/// checking every container under e.g. a slice. Containers need cgroup v2.
+#[derive(Deserialize, StructOpt, Debug)]
+#[structopt(
+    name = "check-pressure (part of tabin-plugins)",
+    setting = structopt::clap::AppSettings::ColoredHelp,
+    after_help = "About thresholds:
+
+    Thresholds are percents of time stalled, given as one nagios range for
+    all three averages or three comma separated ranges for the 10, 60 and 300
+    second averages. '--warn' and '--crit' apply to every resource unless it
+    has its own, e.g. '--warn-memory 10,5,2'.
+
+    By default the 'some' line is checked: the time in which at least one
+    task was stalled. With --full the 'full' line is checked instead: the
+    time in which every task was stalled at once, so no work got done at all.
+    Linux before 5.13 doesn't report 'full' for cpu, which is skipped.
+"
+)]
+struct Args {
+    #[structopt(
+        long = "resource",
+        name = "RESOURCE",
+        possible_values = &PressureResource::str_values(),
+        number_of_values = 1,
+        help = "Only check these resources, may be given more than once \
+                [default: all of them]"
+    )]
+    resources: Vec<PressureResource>,
+    #[structopt(long = "full", help = "Check the 'full' line instead of 'some'")]
+    full: bool,
+    #[structopt(
+        short = "w",
+        long = "warn",
+        help = "Percents stalled to warn at, as nagios ranges",
+        default_value = "40,20,10"
+    )]
+    warn: WindowThresholds,
+    #[structopt(
+        short = "c",
+        long = "crit",
+        help = "Percents stalled to go critical at, as nagios ranges",
+        default_value = "80,40,20"
+    )]
+    crit: WindowThresholds,
+    #[structopt(
+        long = "warn-cpu",
+        value_name = "RANGES",
+        help = "Percents of cpu stalled to warn at, instead of --warn"
+    )]
+    warn_cpu: Option<WindowThresholds>,
+    #[structopt(
+        long = "crit-cpu",
+        value_name = "RANGES",
+        help = "Percents of cpu stalled to go critical at, instead of --crit"
+    )]
+    crit_cpu: Option<WindowThresholds>,
+    #[structopt(
+        long = "warn-memory",
+        value_name = "RANGES",
+        help = "Percents of memory stalled to warn at, instead of --warn"
+    )]
+    warn_memory: Option<WindowThresholds>,
+    #[structopt(
+        long = "crit-memory",
+        value_name = "RANGES",
+        help = "Percents of memory stalled to go critical at, instead of --crit"
+    )]
+    crit_memory: Option<WindowThresholds>,
+    #[structopt(
+        long = "warn-io",
+        value_name = "RANGES",
+        help = "Percents of io stalled to warn at, instead of --warn"
+    )]
+    warn_io: Option<WindowThresholds>,
+    #[structopt(
+        long = "crit-io",
+        value_name = "RANGES",
+        help = "Percents of io stalled to go critical at, instead of --crit"
+    )]
+    crit_io: Option<WindowThresholds>,
+    #[structopt(
+        long = "container",
+        help = "Check the cgroup this is running in instead of the whole system"
+    )]
+    container: bool,
+    #[structopt(flatten)]
+    selector: CGroupSelector,
+    #[structopt(flatten)]
+    roots: Roots,
+    #[structopt(flatten)]
+    common: CommonArgs,
+}
+
+impl Args {
+    fn resources(&self) -> Vec<PressureResource> {
+        if self.resources.is_empty() {
+            PressureResource::all().to_vec()
+        } else {
+            self.resources.clone()
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        if self.full {
+            "full"
+        } else {
+            "some"
+        }
+    }
+
+    /// The warning and critical thresholds for `resource`
+    fn thresholds(&self, resource: PressureResource) -> (&WindowThresholds, &WindowThresholds) {
+        let (warn, crit) = match resource {
+            PressureResource::Cpu => (&self.warn_cpu, &self.crit_cpu),
+            PressureResource::Memory => (&self.warn_memory, &self.crit_memory),
+            PressureResource::Io => (&self.warn_io, &self.crit_io),
+        };
+        (
+            warn.as_ref().unwrap_or(&self.warn),
+            crit.as_ref().unwrap_or(&self.crit),
+        )
+    }
+}
+
+/// One threshold for each of the 10, 60 and 300 second averages
+///
+/// Parsed from three comma or space separated nagios ranges, or a single range
+/// that applies to all of them.
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "String")]
+struct WindowThresholds {
+    avg10: Threshold,
+    avg60: Threshold,
+    avg300: Threshold,
+}
+
+impl WindowThresholds {
+    /// The windows of `stalls` that violate their threshold, described
+    fn violations(&self, prefix: &str, stalls: &Stalls) -> Vec<String> {
+        self.windows(stalls)
+            .iter()
+            .filter(|(_, threshold, value)| threshold.is_violated(*value))
+            .map(|(name, threshold, value)| {
+                format!("{} {} {:.1} {}", prefix, name, value, threshold.describe())
+            })
+            .collect()
+    }
+
+    fn windows(&self, stalls: &Stalls) -> [(&'static str, Threshold, f64); 3] {
+        [
+            ("avg10", self.avg10, stalls.avg10),
+            ("avg60", self.avg60, stalls.avg60),
+            ("avg300", self.avg300, stalls.avg300),
+        ]
+    }
+}
+
+impl FromStr for WindowThresholds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<WindowThresholds, String> {
+        let ranges = s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|range| !range.is_empty())
+            .map(|range| range.parse::<Threshold>().map_err(|e| e.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        match ranges[..] {
+            [all] => Ok(WindowThresholds {
+                avg10: all,
+                avg60: all,
+                avg300: all,
+            }),
+            [avg10, avg60, avg300] => Ok(WindowThresholds {
+                avg10,
+                avg60,
+                avg300,
+            }),
+            _ => Err(format!(
+                "Expected one or three pressure thresholds, got {:?}",
+                s
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for WindowThresholds {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<WindowThresholds, String> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for WindowThresholds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{},{}", self.avg10, self.avg60, self.avg300)
+    }
+}
+
+fn main() {
+    let args: Args = Args::from_args();
+    let started = Instant::now();
+    args.common.init();
+    let context = args.roots.context();
+
+    let result = if args.container || args.selector.is_selected() {
+        watchdog::phase("finding the cgroup to check");
+        let cgroup = args.selector.cgroup(&context).unwrap_or_else(|e| {
+            args.common.fail(
+                format!("Unable to find the cgroup to check: {}", e),
+                started,
+            )
+        });
+        if args.selector.children {
+            let children = cgroup.children().unwrap_or_else(|e| {
+                args.common
+                    .fail(format!("Unable to list child cgroups: {}", e), started)
+            });
+            let checked = children
+                .iter()
+                .map(|child| {
+                    let result = match load(&args, &context, Some(child)) {
+                        Ok(pressures) => check(&args, "cgroup", &pressures),
+                        Err(e) => args.common.error(e),
+                    };
+                    (child.name(), result)
+                })
+                .collect::<Vec<_>>();
+            worst_of(
+                &format!("child cgroups of {}", cgroup.path().display()),
+                checked,
+                args.selector.show_children,
+            )
+        } else {
+            match load(&args, &context, Some(&cgroup)) {
+                Ok(pressures) => check(&args, "cgroup", &pressures),
+                Err(e) => args.common.fail(e, started),
+            }
+        }
+    } else {
+        match load(&args, &context, None) {
+            Ok(pressures) => check(&args, "system", &pressures),
+            Err(e) => args.common.fail(e, started),
+        }
+    };
+    args.common.exit(result, started);
+}
+
+/// Read the pressure of each resource being checked, from `cgroup` or
+/// system-wide
+fn load(
+    args: &Args,
+    context: &Context,
+    cgroup: Option<&CGroup>,
+) -> Result<Vec<(PressureResource, Pressure)>, String> {
+    args.resources()
+        .into_iter()
+        .map(|resource| {
+            watchdog::phase(format!("reading {} pressure", resource));
+            let pressure = match cgroup {
+                Some(cgroup) => cgroup.pressure(resource).map_err(|e| e.to_string()),
+                None => Pressure::load_in(resource, context).map_err(|e| e.to_string()),
+            };
+            pressure
+                .map(|pressure| (resource, pressure))
+                .map_err(|e| format!("Unable to read {} pressure: {}", resource, e))
+        })
+        .collect()
+}
+
+/// Compare the `some` or `full` stalls of each resource against its
+/// thresholds
+fn check(args: &Args, what: &str, pressures: &[(PressureResource, Pressure)]) -> CheckResult {
+    let kind = args.kind();
+    let mut crit_violations = vec![];
+    let mut warn_violations = vec![];
+    let mut current = vec![];
+    let mut skipped = vec![];
+    let mut perfdata = vec![];
+    for &(resource, ref pressure) in pressures {
+        let stalls = if args.full {
+            match pressure.full {
+                Some(ref full) => full,
+                None => {
+                    skipped.push(resource);
+                    continue;
+                }
+            }
+        } else {
+            &pressure.some
+        };
+        let (warn, crit) = args.thresholds(resource);
+        let prefix = format!("{} {}", resource, kind);
+        crit_violations.extend(crit.violations(&prefix, stalls));
+        warn_violations.extend(warn.violations(&prefix, stalls));
+        current.push(format!(
+            "{} {:.1}/{:.1}/{:.1}",
+            resource, stalls.avg10, stalls.avg60, stalls.avg300
+        ));
+        for ((name, warn, value), (_, crit, _)) in
+            warn.windows(stalls).iter().zip(crit.windows(stalls).iter())
+        {
+            perfdata.push(
+                PerfData::new(format!("{}_{}_{}", resource, kind, name), *value)
+                    .unit(Unit::Percent)
+                    .warn(*warn)
+                    .crit(*crit)
+                    .min(0.0)
+                    .max(100.0),
+            );
+        }
+    }
+
+    let mut result = if !crit_violations.is_empty() {
+        CheckResult::new(
+            Status::Critical,
+            format!("{} pressure: {}", what, crit_violations.join(", ")),
+        )
+    } else if !warn_violations.is_empty() {
+        CheckResult::new(
+            Status::Warning,
+            format!("{} pressure: {}", what, warn_violations.join(", ")),
+        )
+    } else {
+        CheckResult::new(
+            Status::Ok,
+            format!(
+                "{} pressure is {} ({} avg10/avg60/avg300)",
+                what,
+                current.join(", "),
+                kind
+            ),
+        )
+    };
+    for resource in skipped {
+        result.add_line(format!("INFO: {} reports no full pressure", resource));
+    }
+    result.perfdata = perfdata;
+    result
+}
+
+#[cfg(test)]
+mod unit {
+    use std::time::Duration;
+
+    use structopt::StructOpt;
+    use tabin_plugins::procfs::{Pressure, PressureResource, Stalls};
+    use tabin_plugins::Status;
+
+    use super::{check, Args};
+
+    fn stalls(avg10: f64, avg60: f64, avg300: f64) -> Stalls {
+        Stalls {
+            avg10,
+            avg60,
+            avg300,
+            total: Duration::from_secs(1),
+        }
+    }
+
+    fn pressures() -> Vec<(PressureResource, Pressure)> {
+        vec![
+            (
+                PressureResource::Cpu,
+                Pressure {
+                    some: stalls(7.5, 5.0, 4.0),
+                    full: None,
+                },
+            ),
+            (
+                PressureResource::Memory,
+                Pressure {
+                    some: stalls(30.0, 20.0, 5.0),
+                    full: Some(stalls(15.0, 12.5, 2.0)),
+                },
+            ),
+        ]
+    }
+
+    #[test]
+    fn uses_thresholds_per_resource() {
+        let args: Args = Args::from_iter(["arg0"].iter());
+        let result = check(&args, "system", &pressures());
+        assert_eq!(result.status, Status::Ok);
+        assert_eq!(
+            result.summary,
+            "system pressure is cpu 7.5/5.0/4.0, memory 30.0/20.0/5.0 (some avg10/avg60/avg300)"
+        );
+        assert_eq!(
+            result.perfdata[3].to_string(),
+            "memory_some_avg10=30%;40;80;0;100"
+        );
+
+        let args: Args =
+            Args::from_iter(["arg0", "--warn-memory", "25,20,10", "--crit-cpu", "5"].iter());
+        let result = check(&args, "system", &pressures());
+        assert_eq!(result.status, Status::Critical);
+        assert_eq!(result.summary, "system pressure: cpu some avg10 7.5 > 5");
+        assert_eq!(
+            result.long_output,
+            Vec::<String>::new(),
+            "warnings are not repeated"
+        );
+    }
+
+    #[test]
+    fn checks_full_stalls() {
+        let args: Args = Args::from_iter(["arg0", "--full", "-w", "10"].iter());
+        let result = check(&args, "cgroup", &pressures());
+        assert_eq!(result.status, Status::Warning);
+        assert_eq!(
+            result.summary,
+            "cgroup pressure: memory full avg10 15.0 > 10, memory full avg60 12.5 > 10"
+        );
+        assert_eq!(result.long_output, ["INFO: cpu reports no full pressure"]);
+        assert!(Args::from_iter_safe(["arg0", "--warn", "1,2"].iter()).is_err());
+    }
+}
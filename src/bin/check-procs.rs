@@ -1,25 +1,36 @@
 //! Check running processes
 
-use std::collections::HashSet;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime};
 
-use log::LevelFilter::{Debug, Trace, Warn};
 use log::{debug, trace};
 use nix::sys::signal::{kill, Signal as NixSignal};
-use nix::unistd::{getpid, getppid, Pid};
+use nix::unistd::{getpid, getppid, Pid, User};
 use regex::Regex;
 use structopt::StructOpt;
 
-use tabin_plugins::procfs::pid::{Process, State};
-use tabin_plugins::procfs::{LoadProcsError, ProcFsError, ProcMap, RunningProcs};
-use tabin_plugins::Status;
-
-const LOG_VAR: &str = "TABIN_LOG";
+use tabin_plugins::cli::{CommonArgs, Roots, Thresholds};
+use tabin_plugins::context::Context;
+use tabin_plugins::linux::PAGESIZE;
+use tabin_plugins::perfdata::{PerfData, Unit};
+use tabin_plugins::procfs::pid::{Process, Stat, State};
+use tabin_plugins::procfs::{
+    boot_time_in, Calculations, LoadProcsError, ProcFsError, ProcMap, ProcessTree, RunningProcs,
+};
+use tabin_plugins::watchdog;
+use tabin_plugins::{CheckResult, Status, Threshold};
 
 /// Check that an expected number of processes are running.
 ///
-/// Optionally, kill unwanted processes.
+/// Optionally, kill unwanted processes. Init, this check and the processes
+/// that started it are never killed, and neither are processes that exited
+/// and had their pid reused after they were matched.
 #[derive(StructOpt, Debug)]
 #[structopt(
     name = "check-procs (part of tabin-plugins)",
@@ -30,6 +41,15 @@ const LOG_VAR: &str = "TABIN_LOG";
 
         check-procs --crit-under 2 nginx
 
+    Warn if there are fewer than four gunicorn workers, and go critical if
+    there are none:
+
+        check-procs --warn-under 4 --crit-under 1 'gunicorn: worker'
+
+    The same, using nagios range syntax:
+
+        check-procs --warn 4: --crit 1: 'gunicorn: worker'
+
     Ensure there are not more than 30 zombie proccesses on the system:
 
         check-procs --crit-over 30 --state zombie
@@ -43,7 +63,28 @@ const LOG_VAR: &str = "TABIN_LOG";
     Ensure that there are at least three (running or waiting) (cassandra or
     postgres) processes:
 
-        check-procs --crit-under 3 --state=running --state=waiting 'cassandra|postgres'"
+        check-procs --crit-under 3 --state=running --state=waiting 'cassandra|postgres'
+
+    Warn if fewer than four processes were started by the gunicorn master,
+    however deep they are:
+
+        check-procs --warn-under 4 --children-of 'gunicorn: master'
+
+    Ensure that no process owned by www-data has been running for more than a
+    day:
+
+        check-procs --crit-over 0 --user www-data --older-than 1d
+
+    Go critical if any postgres process has more than 2GB of resident memory,
+    and warn if any used more than 90% of a cpu over 10 seconds:
+
+        check-procs --crit-rss 2048 --warn-cpu 90 --sample 10 postgres
+
+    Stop runaway report jobs, giving them 30 seconds to exit before they are
+    sent SIGKILL, and killing nothing if the pattern matches more than 10.
+    Add --dry-run to see what would be killed first:
+
+        check-procs --crit-over 0 --kill-matching TERM --kill-grace 30 --max-kills 10 'run-report'"
 )]
 struct Args {
     #[structopt(help = "Regex that command and its arguments must match")]
@@ -60,6 +101,21 @@ struct Args {
         help = "Error if there are more than <M> procs matching <pattern>"
     )]
     crit_over: Option<usize>,
+    #[structopt(
+        long = "warn-under",
+        value_name = "N",
+        help = "Warn if there are fewer than <N> procs matching <pattern>"
+    )]
+    warn_under: Option<usize>,
+    #[structopt(
+        long = "warn-over",
+        value_name = "M",
+        help = "Warn if there are more than <M> procs matching <pattern>"
+    )]
+    warn_over: Option<usize>,
+    // -w/-c are ranges for the number of procs matching <pattern>
+    #[structopt(flatten)]
+    thresholds: Thresholds,
 
     #[structopt(
         long = "state",
@@ -69,6 +125,12 @@ struct Args {
     )]
     states: Vec<State>,
 
+    #[structopt(flatten)]
+    filters: Filters,
+
+    #[structopt(flatten)]
+    limits: Limits,
+
     #[structopt(
         long = "allow-unparseable-procs",
         help = "In combination with --crit-over M this will not alert if any \
@@ -81,9 +143,9 @@ struct Args {
         name = "SIGNAL",
         help = "If *any* processes match, then kill them with the provided signal \
                 which can be either an integer or a name like KILL or SIGTERM. \
-                This option does not affect the exit status, all matches are always \
-                killed, and if --crit-under/over are violated then then this will \
-                still exit critical."
+                This option does not affect the exit status, all matches are \
+                killed unless there are more than --max-kills, and if \
+                --crit-under/over are violated then then this will still exit critical."
     )]
     kill_matching: Option<Signal>,
 
@@ -96,9 +158,905 @@ struct Args {
     )]
     kill_matching_parents: Option<Signal>,
 
-    /// print debug logs, use multiple times to make it more verbose
-    #[structopt(short = "v", long = "verbose", parse(from_occurrences))]
-    verbose: u8,
+    #[structopt(
+        long = "dry-run",
+        help = "Report the processes that --kill-matching and \
+                --kill-parents-of-matching would kill, without sending any signals"
+    )]
+    dry_run: bool,
+
+    #[structopt(
+        long = "kill-grace",
+        value_name = "SECONDS",
+        help = "After sending the kill signal, wait up to this long for the \
+                processes to exit, and then send SIGKILL to any that haven't"
+    )]
+    kill_grace: Option<u64>,
+
+    #[structopt(
+        long = "max-kills",
+        value_name = "N",
+        help = "Don't kill anything if more than <N> processes would be killed, \
+                e.g. because the pattern matches more than intended"
+    )]
+    max_kills: Option<usize>,
+
+    #[structopt(flatten)]
+    roots: Roots,
+    #[structopt(flatten)]
+    common: CommonArgs,
+}
+
+impl Args {
+    /// The counts allowed by `--crit-under` and `--crit-over`
+    fn crit_range(&self) -> Option<Threshold> {
+        count_range(self.crit_under, self.crit_over)
+    }
+
+    /// The counts allowed by `--warn-under` and `--warn-over`
+    fn warn_range(&self) -> Option<Threshold> {
+        count_range(self.warn_under, self.warn_over)
+    }
+}
+
+/// A range from an `--*-under` and `--*-over` pair, if either was given
+fn count_range(under: Option<usize>, over: Option<usize>) -> Option<Threshold> {
+    if under.is_none() && over.is_none() {
+        return None;
+    }
+    Some(Threshold {
+        start: under.map_or(0.0, |under| under as f64),
+        end: over.map_or(f64::INFINITY, |over| over as f64),
+        inside: false,
+    })
+}
+
+/// The end of `threshold` that `count` is past, e.g. `< 2`, rather than the
+/// whole range
+fn describe_crossed(threshold: &Threshold, count: f64) -> String {
+    if threshold.inside {
+        threshold.describe()
+    } else if count < threshold.start {
+        format!("< {}", threshold.start)
+    } else {
+        format!("> {}", threshold.end)
+    }
+}
+
+/// `--user`, `--ppid`, `--older-than` and the other filters on a process,
+/// which must all pass as well as the pattern and states
+#[derive(StructOpt, Debug, Default, PartialEq)]
+struct Filters {
+    #[structopt(
+        long = "user",
+        value_name = "NAME",
+        conflicts_with = "uid",
+        help = "Only processes running as this user"
+    )]
+    user: Option<String>,
+    #[structopt(
+        long = "uid",
+        value_name = "UID",
+        help = "Only processes running as this user ID"
+    )]
+    uid: Option<u32>,
+    #[structopt(
+        long = "ppid",
+        value_name = "PID",
+        help = "Only processes whose parent is this process"
+    )]
+    ppid: Option<i32>,
+    #[structopt(
+        long = "children-of",
+        value_name = "PATTERN",
+        help = "Only processes descended from a process whose command and \
+                arguments match this regex, e.g. the workers of a server"
+    )]
+    children_of: Option<String>,
+    #[structopt(
+        long = "exe",
+        value_name = "PATH",
+        help = "Only processes running this executable, as linked to by \
+                /proc/<pid>/exe. Other users' processes can only be matched as root"
+    )]
+    exe: Option<PathBuf>,
+    #[structopt(
+        long = "comm",
+        value_name = "NAME",
+        help = "Only processes whose executable is named exactly this, as in \
+                /proc/<pid>/stat. The kernel truncates names to 15 characters"
+    )]
+    comm: Option<String>,
+    #[structopt(
+        long = "older-than",
+        value_name = "AGE",
+        help = "Only processes that started longer ago than this, in seconds or \
+                with a unit like 30s, 10m, 2h, 1d or 1w"
+    )]
+    older_than: Option<Age>,
+    #[structopt(
+        long = "younger-than",
+        value_name = "AGE",
+        help = "Only processes that started more recently than this"
+    )]
+    younger_than: Option<Age>,
+    #[structopt(
+        long = "min-rss",
+        value_name = "MB",
+        help = "Only processes with at least this much resident memory"
+    )]
+    min_rss: Option<u64>,
+    #[structopt(
+        long = "min-cpu",
+        value_name = "PERCENT",
+        help = "Only processes that have used at least this percent of a cpu \
+                since they started, like the %CPU column of ps"
+    )]
+    min_cpu: Option<f64>,
+}
+
+impl Filters {
+    fn is_empty(&self) -> bool {
+        *self == Filters::default()
+    }
+
+    fn needs_boot_time(&self) -> bool {
+        self.older_than.is_some() || self.younger_than.is_some() || self.min_cpu.is_some()
+    }
+
+    /// Whether processes must be loaded with their status, for their owner
+    fn needs_status(&self) -> bool {
+        self.user.is_some() || self.uid.is_some()
+    }
+
+    /// Resolve the user name and load the boot time, if the filters or the
+    /// caller need it
+    fn matcher<'a>(
+        &'a self,
+        context: &'a Context,
+        needs_boot_time: bool,
+    ) -> Result<Matcher<'a>, String> {
+        let uid = match self.user {
+            Some(ref name) => match User::from_name(name) {
+                Ok(Some(user)) => Some(user.uid.as_raw()),
+                Ok(None) => return Err(format!("No such user: {}", name)),
+                Err(e) => return Err(format!("Unable to look up user {}: {}", name, e)),
+            },
+            None => self.uid,
+        };
+        let boot_time = if needs_boot_time || self.needs_boot_time() {
+            boot_time_in(context).map_err(|e| format!("Unable to find the boot time: {}", e))?
+        } else {
+            SystemTime::UNIX_EPOCH
+        };
+        let children_of = match self.children_of {
+            Some(ref pattern) => Some(
+                Regex::new(pattern).map_err(|e| format!("Invalid --children-of pattern: {}", e))?,
+            ),
+            None => None,
+        };
+        Ok(Matcher {
+            filters: self,
+            uid,
+            children_of,
+            boot_time,
+            now: SystemTime::now(),
+            context,
+        })
+    }
+
+    /// Describe the filters for the summary, e.g. `owned by root older than 1d`
+    fn describe(&self) -> Vec<String> {
+        let mut descriptions = vec![];
+        if let Some(ref user) = self.user {
+            descriptions.push(format!("owned by {}", user));
+        }
+        if let Some(uid) = self.uid {
+            descriptions.push(format!("owned by uid {}", uid));
+        }
+        if let Some(ppid) = self.ppid {
+            descriptions.push(format!("with parent {}", ppid));
+        }
+        if let Some(ref pattern) = self.children_of {
+            descriptions.push(format!("descended from '{}'", pattern));
+        }
+        if let Some(ref exe) = self.exe {
+            descriptions.push(format!("running {}", exe.display()));
+        }
+        if let Some(ref comm) = self.comm {
+            descriptions.push(format!("named {}", comm));
+        }
+        if let Some(ref age) = self.older_than {
+            descriptions.push(format!("older than {}", age));
+        }
+        if let Some(ref age) = self.younger_than {
+            descriptions.push(format!("younger than {}", age));
+        }
+        if let Some(mb) = self.min_rss {
+            descriptions.push(format!("using at least {}MB of ram", mb));
+        }
+        if let Some(percent) = self.min_cpu {
+            descriptions.push(format!("using at least {}% cpu", percent));
+        }
+        descriptions
+    }
+}
+
+/// `Filters` with everything they need to check a process
+#[derive(Debug)]
+struct Matcher<'a> {
+    filters: &'a Filters,
+    uid: Option<u32>,
+    children_of: Option<Regex>,
+    boot_time: SystemTime,
+    now: SystemTime,
+    context: &'a Context,
+}
+
+impl<'a> Matcher<'a> {
+    fn matches(&self, process: &Process) -> bool {
+        let filters = self.filters;
+        let age = || self.age(process);
+        // processes whose status can't be read may be anyone's, so they
+        // never match a user
+        let owner = process.status.as_ref().map(|status| status.uid.effective);
+        self.uid.is_none_or(|uid| owner == Some(uid))
+            && filters
+                .ppid
+                .is_none_or(|ppid| process.stat.ppid.as_raw() == ppid)
+            && filters
+                .comm
+                .as_ref()
+                .is_none_or(|comm| process.stat.comm == *comm)
+            && filters.older_than.is_none_or(|Age(min)| age() > min)
+            && filters.younger_than.is_none_or(|Age(max)| age() < max)
+            && filters
+                .min_rss
+                .is_none_or(|mb| process.stat.rss * *PAGESIZE >= mb.saturating_mul(1024 * 1024))
+            && filters
+                .min_cpu
+                .is_none_or(|percent| lifetime_cpu_percent(process, age()) >= percent)
+            && filters
+                .exe
+                .as_ref()
+                .is_none_or(|exe| self.is_running(process, exe))
+    }
+
+    /// Whether `process` passes `--children-of`, which needs the processes
+    /// that started it
+    fn has_matching_ancestor(&self, process: &Process, tree: &ProcessTree) -> bool {
+        self.children_of.as_ref().is_none_or(|re| {
+            tree.ancestors(process.stat.pid)
+                .any(|ancestor| re.is_match(&ancestor.useful_cmdline()))
+        })
+    }
+
+    /// How long ago `process` started
+    fn age(&self, process: &Process) -> Duration {
+        self.now
+            .duration_since(process.stat.start_time(self.boot_time))
+            .unwrap_or_default()
+    }
+
+    /// Whether `process` is running `exe`, including an old copy of it that
+    /// has since been replaced, e.g. by a package upgrade
+    fn is_running(&self, process: &Process, exe: &Path) -> bool {
+        match process.exe_in(self.context) {
+            Ok(path) => {
+                let path = path.to_string_lossy();
+                let path = path.strip_suffix(" (deleted)").unwrap_or(&path);
+                Path::new(path) == exe
+            }
+            Err(e) => {
+                debug!("unable to read exe of {}: {}", process.stat.pid, e);
+                false
+            }
+        }
+    }
+}
+
+/// `--warn-rss`, `--crit-cpu` and the other limits that every matching
+/// process must stay within, checked as well as the number of processes
+#[derive(StructOpt, Debug)]
+struct Limits {
+    #[structopt(
+        long = "warn-rss",
+        value_name = "MB",
+        help = "Warn if any matching process has more resident memory than this, \
+                as a nagios range"
+    )]
+    warn_rss: Option<Threshold>,
+    #[structopt(
+        long = "crit-rss",
+        value_name = "MB",
+        help = "Go critical if any matching process has more resident memory \
+                than this, as a nagios range"
+    )]
+    crit_rss: Option<Threshold>,
+    #[structopt(
+        long = "warn-cpu",
+        value_name = "PERCENT",
+        help = "Warn if any matching process uses more than this percent of a \
+                cpu over --sample seconds, as a nagios range"
+    )]
+    warn_cpu: Option<Threshold>,
+    #[structopt(
+        long = "crit-cpu",
+        value_name = "PERCENT",
+        help = "Go critical if any matching process uses more than this percent \
+                of a cpu over --sample seconds, as a nagios range"
+    )]
+    crit_cpu: Option<Threshold>,
+    #[structopt(
+        long = "warn-threads",
+        value_name = "COUNT",
+        help = "Warn if any matching process has more threads than this, as a \
+                nagios range"
+    )]
+    warn_threads: Option<Threshold>,
+    #[structopt(
+        long = "crit-threads",
+        value_name = "COUNT",
+        help = "Go critical if any matching process has more threads than this, \
+                as a nagios range"
+    )]
+    crit_threads: Option<Threshold>,
+    #[structopt(
+        long = "warn-fds",
+        value_name = "COUNT",
+        help = "Warn if any matching process has more open file descriptors than \
+                this, as a nagios range. Other users' processes can only be \
+                counted as root"
+    )]
+    warn_fds: Option<Threshold>,
+    #[structopt(
+        long = "crit-fds",
+        value_name = "COUNT",
+        help = "Go critical if any matching process has more open file \
+                descriptors than this, as a nagios range"
+    )]
+    crit_fds: Option<Threshold>,
+    #[structopt(
+        long = "warn-age",
+        value_name = "AGE",
+        help = "Warn if any matching process started longer ago than this, e.g. 12h"
+    )]
+    warn_age: Option<Age>,
+    #[structopt(
+        long = "crit-age",
+        value_name = "AGE",
+        help = "Go critical if any matching process started longer ago than this"
+    )]
+    crit_age: Option<Age>,
+    #[structopt(
+        short = "s",
+        long = "sample",
+        name = "seconds",
+        default_value = "5",
+        help = "Seconds to measure cpu usage over, if a cpu limit is given"
+    )]
+    sample: u64,
+}
+
+impl Limits {
+    fn are_given(&self) -> bool {
+        self.warn_rss.is_some()
+            || self.crit_rss.is_some()
+            || self.checks_cpu()
+            || self.warn_threads.is_some()
+            || self.crit_threads.is_some()
+            || self.checks_fds()
+            || self.needs_boot_time()
+    }
+
+    fn checks_cpu(&self) -> bool {
+        self.warn_cpu.is_some() || self.crit_cpu.is_some()
+    }
+
+    fn checks_fds(&self) -> bool {
+        self.warn_fds.is_some() || self.crit_fds.is_some()
+    }
+
+    fn needs_boot_time(&self) -> bool {
+        self.warn_age.is_some() || self.crit_age.is_some()
+    }
+}
+
+/// One of the `Limits`, for a measurement of each process
+#[derive(Debug)]
+struct Limit {
+    /// What is measured, e.g. `rss`
+    name: &'static str,
+    /// The unit of the perfdata
+    unit: Unit,
+    /// Format a value for the output, e.g. `12.5MB`
+    show: fn(f64) -> String,
+    warn: Option<Threshold>,
+    crit: Option<Threshold>,
+}
+
+impl Limit {
+    /// Like `Threshold::describe`, but showing a bare number, the usual
+    /// case, in the same units as the values, e.g. `> 1d`
+    fn describe(&self, threshold: Threshold) -> String {
+        if !threshold.inside && threshold.start == 0.0 && threshold.end.is_finite() {
+            format!("> {}", (self.show)(threshold.end))
+        } else {
+            threshold.describe()
+        }
+    }
+
+    /// Check each process against the limit, listing those that exceed it
+    fn check(&self, measured: &[(&Pid, &Process, f64)]) -> CheckResult {
+        let exceeds = |threshold: Option<Threshold>| {
+            threshold.and_then(|threshold| {
+                let offenders = measured
+                    .iter()
+                    .filter(|&&(_, _, value)| threshold.is_violated(value))
+                    .collect::<Vec<_>>();
+                if offenders.is_empty() {
+                    None
+                } else {
+                    Some((threshold, offenders))
+                }
+            })
+        };
+        let (status, threshold, mut offenders) = match exceeds(self.crit) {
+            Some((crit, offenders)) => (Status::Critical, crit, offenders),
+            None => match exceeds(self.warn) {
+                Some((warn, offenders)) => (Status::Warning, warn, offenders),
+                None => {
+                    let threshold = self.warn.or(self.crit).expect("a limit was given");
+                    let mut result = CheckResult::new(
+                        Status::Ok,
+                        format!("no procs have {} {}", self.name, self.describe(threshold)),
+                    );
+                    self.add_perf(&mut result, measured);
+                    return result;
+                }
+            },
+        };
+        offenders.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(Ordering::Equal));
+        let mut result = CheckResult::new(
+            status,
+            format!(
+                "{} procs have {} {}",
+                offenders.len(),
+                self.name,
+                self.describe(threshold)
+            ),
+        );
+        for &&(pid, process, value) in offenders.iter().take(20) {
+            result.add_line(format!(
+                "[{:>5}] {} {}: {}",
+                pid,
+                self.name,
+                (self.show)(value),
+                process.useful_cmdline()
+            ));
+        }
+        if offenders.len() > 20 {
+            result.add_line(format!("And {} more...", offenders.len() - 20));
+        }
+        self.add_perf(&mut result, measured);
+        result
+    }
+
+    /// The largest value, with the thresholds
+    fn add_perf(&self, result: &mut CheckResult, measured: &[(&Pid, &Process, f64)]) {
+        let max = measured
+            .iter()
+            .map(|&(_, _, value)| value)
+            .fold(None, |max: Option<f64>, value| {
+                Some(max.map_or(value, |max| max.max(value)))
+            });
+        if let Some(max) = max {
+            let mut perf = PerfData::new(format!("max_{}", self.name.replace(' ', "_")), max)
+                .unit(self.unit)
+                .min(0.0);
+            if let Some(warn) = self.warn {
+                perf = perf.warn(warn);
+            }
+            if let Some(crit) = self.crit {
+                perf = perf.crit(crit);
+            }
+            result.add_perf(perf);
+        }
+    }
+}
+
+/// Merge `results` into the worst of them, by `Status::severity`, so that
+/// each summary is followed by its own details
+fn merge_worst_first(mut results: Vec<CheckResult>) -> CheckResult {
+    // stable, so the count comes first among results with the same status
+    results.sort_by_key(|result| Reverse(result.status.severity()));
+    let mut results = results.into_iter();
+    let mut merged = results.next().expect("the count is always checked");
+    for result in results {
+        merged.merge(result);
+    }
+    merged
+}
+
+/// Check each matching process against the `Limits` that were given
+///
+/// `cpu` is the percent of a cpu that each process used over the sample, if
+/// a cpu limit was given.
+fn check_limits(
+    args: &Args,
+    matches: &[(&Pid, &Process)],
+    matcher: &Matcher<'_>,
+    cpu: Option<&HashMap<Pid, f64>>,
+) -> Vec<CheckResult> {
+    let limits = &args.limits;
+    let mut results = vec![];
+    let mut check = |limit: Limit, measure: &dyn Fn(&Process) -> Option<f64>| {
+        if limit.warn.is_some() || limit.crit.is_some() {
+            let measured = matches
+                .iter()
+                .filter_map(|&(pid, process)| Some((pid, process, measure(process)?)))
+                .collect::<Vec<_>>();
+            results.push(limit.check(&measured));
+        }
+    };
+    let age = |age: Option<Age>| age.map(|Age(age)| Threshold::above(age.as_secs_f64()));
+
+    check(
+        Limit {
+            name: "rss",
+            unit: Unit::MegaBytes,
+            show: |mb| format!("{:.1}MB", mb),
+            warn: limits.warn_rss,
+            crit: limits.crit_rss,
+        },
+        &|process| Some((process.stat.rss * *PAGESIZE) as f64 / 1024.0 / 1024.0),
+    );
+    check(
+        Limit {
+            name: "cpu",
+            unit: Unit::Percent,
+            show: |percent| format!("{:.1}%", percent),
+            warn: limits.warn_cpu,
+            crit: limits.crit_cpu,
+        },
+        // processes that started or exited during the sample aren't measured
+        &|process| cpu.and_then(|cpu| cpu.get(&process.stat.pid).cloned()),
+    );
+    check(
+        Limit {
+            name: "threads",
+            unit: Unit::None,
+            show: |count| count.to_string(),
+            warn: limits.warn_threads,
+            crit: limits.crit_threads,
+        },
+        &|process| Some(process.stat.num_threads as f64),
+    );
+    let mut fds = HashMap::new();
+    let mut uncounted = None;
+    if limits.checks_fds() {
+        let mut errors = vec![];
+        for &(pid, process) in matches {
+            match process.open_fds_in(matcher.context) {
+                Ok(count) => {
+                    fds.insert(*pid, count);
+                }
+                Err(e) => errors.push((pid, process, e)),
+            }
+        }
+        if !errors.is_empty() {
+            let mut result = args.common.error(format!(
+                "unable to count the open fds of {} procs",
+                errors.len()
+            ));
+            for (pid, process, e) in errors.iter().take(20) {
+                result.add_line(format!("[{:>5}] {}: {}", pid, e, process.useful_cmdline()));
+            }
+            if errors.len() > 20 {
+                result.add_line(format!("And {} more...", errors.len() - 20));
+            }
+            uncounted = Some(result);
+        }
+    }
+    check(
+        Limit {
+            name: "open fds",
+            unit: Unit::None,
+            show: |count| count.to_string(),
+            warn: limits.warn_fds,
+            crit: limits.crit_fds,
+        },
+        &|process| fds.get(&process.stat.pid).map(|&count| count as f64),
+    );
+    check(
+        Limit {
+            name: "age",
+            unit: Unit::Seconds,
+            show: show_age,
+            warn: age(limits.warn_age),
+            crit: age(limits.crit_age),
+        },
+        &|process| Some(matcher.age(process).as_secs_f64()),
+    );
+    results.extend(uncounted);
+    results
+}
+
+/// The percent of one cpu that `process` has used since it started
+fn lifetime_cpu_percent(process: &Process, age: Duration) -> f64 {
+    if age.as_secs_f64() <= 0.0 {
+        return 0.0;
+    }
+    process.stat.cpu_time().duration().as_secs_f64() / age.as_secs_f64() * 100.0
+}
+
+/// Show an age in seconds in the largest unit it reaches, e.g. `2.5h`
+fn show_age(seconds: f64) -> String {
+    let (divisor, unit) = [(24.0 * 60.0 * 60.0, "d"), (60.0 * 60.0, "h"), (60.0, "m")]
+        .iter()
+        .cloned()
+        .find(|&(divisor, _)| seconds >= divisor)
+        .unwrap_or((1.0, "s"));
+    let value = seconds / divisor;
+    if value.fract() == 0.0 {
+        format!("{}{}", value, unit)
+    } else {
+        format!("{:.1}{}", value, unit)
+    }
+}
+
+/// How long ago a process started, parsed from seconds or e.g. `10m` or `1d`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Age(Duration);
+
+impl FromStr for Age {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Age, String> {
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let number: u64 = number
+            .parse()
+            .map_err(|_| format!("Expected an age like 30s, 10m, 2h or 1d, got {:?}", s))?;
+        let seconds = match unit {
+            "" | "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            "w" => 7 * 24 * 60 * 60,
+            _ => {
+                return Err(format!(
+                    "Unknown unit {:?} in age {:?}, expected s, m, h, d or w",
+                    unit, s
+                ))
+            }
+        };
+        let seconds = number
+            .checked_mul(seconds)
+            .ok_or_else(|| format!("{:?} is too long", s))?;
+        Ok(Age(Duration::from_secs(seconds)))
+    }
+}
+
+impl fmt::Display for Age {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = self.0.as_secs();
+        let (divisor, unit) = [
+            (7 * 24 * 60 * 60, "w"),
+            (24 * 60 * 60, "d"),
+            (60 * 60, "h"),
+            (60, "m"),
+        ]
+        .iter()
+        .cloned()
+        .find(|&(divisor, _)| seconds > 0 && seconds.is_multiple_of(divisor))
+        .unwrap_or((1, "s"));
+        write!(f, "{}{}", seconds / divisor, unit)
+    }
+}
+
+/// The processes that must never be killed: init, this check and the
+/// processes that started it
+#[derive(Debug)]
+struct Protected {
+    me: Pid,
+    ancestors: HashSet<Pid>,
+}
+
+impl Protected {
+    fn new(me: Pid, parent: Pid, procs: &ProcMap) -> Protected {
+        let mut ancestors = HashSet::new();
+        let mut pid = parent;
+        while pid.as_raw() > 1 && ancestors.insert(pid) {
+            pid = match procs.get(&pid) {
+                Some(process) => process.stat.ppid,
+                None => break,
+            };
+        }
+        Protected { me, ancestors }
+    }
+
+    /// Why `pid` must not be killed, if it mustn't
+    fn reason(&self, pid: Pid) -> Option<&'static str> {
+        if pid.as_raw() <= 0 {
+            // kill(2) treats these as process groups
+            Some("it is the kernel")
+        } else if pid.as_raw() == 1 {
+            Some("it is init")
+        } else if pid == self.me {
+            Some("it is this check")
+        } else if self.ancestors.contains(&pid) {
+            Some("it started this check")
+        } else {
+            None
+        }
+    }
+}
+
+/// Sends `signal` to processes, safely, see `Killer::kill`
+#[derive(Debug)]
+struct Killer<'a> {
+    signal: NixSignal,
+    dry_run: bool,
+    /// How long to wait before sending SIGKILL
+    grace: Option<Duration>,
+    max_kills: Option<usize>,
+    context: &'a Context,
+    protected: &'a Protected,
+}
+
+/// What happened to a process that was to be killed
+#[derive(Debug)]
+enum Outcome {
+    /// Not decided yet
+    Pending,
+    Protected(&'static str),
+    /// There were more than `--max-kills` processes
+    OverLimit,
+    DryRun,
+    /// It exited before it could be signalled
+    Exited,
+    /// It exited and a new process has its pid
+    Reused,
+    Failed(NixSignal, nix::Error),
+    /// It was sent the signal, and may still be running
+    Signalled,
+    /// It was sent the signal and exited within the grace period
+    ExitedAfterSignal,
+    /// It was still running after the grace period, and was sent SIGKILL
+    Escalated(Duration),
+}
+
+/// Whether a process is still the one that was matched
+#[derive(Debug, PartialEq)]
+enum Liveness {
+    Running,
+    Exited,
+    Reused,
+}
+
+impl<'a> Killer<'a> {
+    /// Kill `targets`, and describe what happened to each
+    ///
+    /// Protected processes are skipped, nothing is killed if there are more
+    /// than `max_kills` targets, and each process is checked to still be the
+    /// one that was matched, by its start time, before it is signalled.
+    fn kill(&self, what: &str, targets: &[(&Pid, &Process)]) -> Vec<String> {
+        let mut outcomes = targets
+            .iter()
+            .map(|&(&pid, process)| {
+                let outcome = match self.protected.reason(pid) {
+                    Some(reason) => Outcome::Protected(reason),
+                    None => match self.liveness(pid, process) {
+                        Liveness::Exited => Outcome::Exited,
+                        Liveness::Reused => Outcome::Reused,
+                        Liveness::Running => Outcome::Pending,
+                    },
+                };
+                (pid, process, outcome)
+            })
+            .collect::<Vec<_>>();
+        let count = outcomes
+            .iter()
+            .filter(|(_, _, outcome)| matches!(outcome, Outcome::Pending))
+            .count();
+        let over_limit = self.max_kills.is_some_and(|max| count > max);
+        let header = match self.max_kills {
+            Some(max) if over_limit => format!(
+                "INFO: Not killing {} {}, more than --max-kills {}:",
+                count, what, max
+            ),
+            _ if self.dry_run => {
+                format!("INFO: Would send {} to {} {}:", self.signal, count, what)
+            }
+            _ => format!("INFO: Sending {} to {} {}:", self.signal, count, what),
+        };
+
+        for (pid, _, outcome) in &mut outcomes {
+            if let Outcome::Pending = outcome {
+                *outcome = if over_limit {
+                    Outcome::OverLimit
+                } else if self.dry_run {
+                    Outcome::DryRun
+                } else {
+                    match kill(*pid, self.signal) {
+                        Ok(()) => Outcome::Signalled,
+                        Err(e) => Outcome::Failed(self.signal, e),
+                    }
+                };
+            }
+        }
+
+        let signalled = |outcome: &Outcome| matches!(outcome, Outcome::Signalled);
+        if let Some(grace) = self.grace {
+            if self.signal != NixSignal::SIGKILL && outcomes.iter().any(|o| signalled(&o.2)) {
+                watchdog::phase(format!(
+                    "waiting {}s for killed processes to exit",
+                    grace.as_secs_f64()
+                ));
+                let deadline = Instant::now() + grace;
+                while Instant::now() < deadline
+                    && outcomes.iter().any(|(pid, process, outcome)| {
+                        signalled(outcome) && self.liveness(*pid, process) == Liveness::Running
+                    })
+                {
+                    sleep(Duration::from_millis(100));
+                }
+                for (pid, process, outcome) in &mut outcomes {
+                    if signalled(outcome) {
+                        *outcome = match self.liveness(*pid, process) {
+                            Liveness::Running => match kill(*pid, NixSignal::SIGKILL) {
+                                Ok(()) => Outcome::Escalated(grace),
+                                Err(e) => Outcome::Failed(NixSignal::SIGKILL, e),
+                            },
+                            _ => Outcome::ExitedAfterSignal,
+                        };
+                    }
+                }
+            }
+        }
+
+        let mut lines = vec![header];
+        for (pid, process, outcome) in outcomes {
+            lines.push(format!(
+                "[{:>5}] {}: {}",
+                pid,
+                self.describe(&outcome),
+                process.useful_cmdline()
+            ));
+        }
+        lines
+    }
+
+    /// Whether `pid` is still `process`, which it isn't if it has exited or
+    /// its pid has been reused
+    fn liveness(&self, pid: Pid, process: &Process) -> Liveness {
+        match Stat::from_pid_in(pid, self.context) {
+            Ok(ref stat) if stat.starttime != process.stat.starttime => Liveness::Reused,
+            // zombies have exited, but are waiting for their parent to notice
+            Ok(ref stat) if stat.state == State::Zombie => Liveness::Exited,
+            Ok(_) => Liveness::Running,
+            Err(_) => Liveness::Exited,
+        }
+    }
+
+    fn describe(&self, outcome: &Outcome) -> String {
+        match *outcome {
+            Outcome::Pending => "not killed".to_owned(),
+            Outcome::Protected(reason) => format!("not killed, {}", reason),
+            Outcome::OverLimit => "not killed, too many matches".to_owned(),
+            Outcome::DryRun => format!("would send {}", self.signal),
+            Outcome::Exited => "not killed, it already exited".to_owned(),
+            Outcome::Reused => "not killed, it exited and its pid was reused".to_owned(),
+            Outcome::Failed(signal, e) => format!("failed to send {}: {}", signal, e),
+            Outcome::Signalled => format!("sent {}", self.signal),
+            Outcome::ExitedAfterSignal => format!("exited after {}", self.signal),
+            Outcome::Escalated(grace) => format!(
+                "still running {}s after {}, sent SIGKILL",
+                grace.as_secs_f64(),
+                self.signal
+            ),
+        }
+    }
 }
 
 /// Our own signal wrapper so that we can implement a forgiving FromStr for `nix::sys::Signal`
@@ -137,108 +1095,184 @@ impl FromStr for Signal {
     }
 }
 
-fn parse_args() -> Args {
+fn parse_args(started: Instant) -> Args {
     let args = Args::from_args();
-    if args.pattern.is_none() && args.states.is_empty() {
-        println!("At least one of a pattern or some states are required for this to do anything");
-        Status::Critical.exit();
+    if args.pattern.is_none() && args.states.is_empty() && args.filters.is_empty() {
+        args.common.fail(
+            "At least one of a pattern, some states or a filter are required for this to do anything",
+            started,
+        );
     }
-    if let (None, None) = (args.crit_under, args.crit_over) {
-        println!("At least one of --crit-under or --crit-over must be provided");
-        Status::Critical.exit();
+    if args.crit_range().is_none()
+        && args.warn_range().is_none()
+        && args.thresholds.crit.is_none()
+        && args.thresholds.warn.is_none()
+        && !args.limits.are_given()
+    {
+        args.common.fail(
+            "At least one of --crit-under, --crit-over, --warn-under, --warn-over, \
+             --crit, --warn or a per-process limit like --crit-rss must be provided",
+            started,
+        );
     }
     args
 }
 
 fn main() {
-    let args = parse_args();
-    env_logger::Builder::from_env(LOG_VAR)
-        .filter_level(match args.verbose {
-            0 => Warn,
-            1 => Debug,
-            _ => Trace,
-        })
-        .init();
+    let started = Instant::now();
+    let args = parse_args(started);
+    args.common.init();
 
-    let should_die = if let Some(_) = args.crit_over {
+    let should_die = if args.crit_over.is_some()
+        || args.warn_over.is_some()
+        || args.thresholds.crit.is_some()
+        || args.thresholds.warn.is_some()
+    {
         !args.allow_unparseable_procs
     } else {
         false
     };
-    let procs = load_procs(should_die);
-    let re_ = args.pattern.as_ref().map(|s| s.to_string());
-    let re = || re_.as_ref().map(|s| &**s).unwrap_or("<ANYTHING>");
-
-    let matches = filter_procs(&args.pattern, &args.states, &procs.0);
+    let context = args.roots.context();
+    let matcher = args
+        .filters
+        .matcher(&context, args.limits.needs_boot_time())
+        .unwrap_or_else(|e| args.common.fail(e, started));
+    let start_cpus = if args.limits.checks_cpu() {
+        Some(load_cpus(&args, started))
+    } else {
+        None
+    };
+    let procs = load_procs(should_die, &args, started);
+    let mut matches = filter_procs(&args.pattern, &args.states, &procs.0);
+    matches.retain(|&(_, process)| matcher.matches(process));
+    if matcher.children_of.is_some() {
+        let tree = procs.tree();
+        matches.retain(|&(_, process)| matcher.has_matching_ancestor(process, &tree));
+    }
+    let mut results = vec![check(&args, &matches)];
+    if args.limits.are_given() {
+        let cpu = start_cpus.map(|start_cpus| sample_cpu(&args, &start_cpus, &procs, started));
+        results.extend(check_limits(&args, &matches, &matcher, cpu.as_ref()));
+    }
+    let mut result = merge_worst_first(results);
 
-    let mut status = Status::Ok;
-    if let Some(crit_over) = args.crit_over {
-        if matches.len() > crit_over {
-            status = Status::Critical;
-        }
+    let protected = Protected::new(getpid(), getppid(), &procs.0);
+    let killer = |signal| Killer {
+        signal,
+        dry_run: args.dry_run,
+        grace: args.kill_grace.map(Duration::from_secs),
+        max_kills: args.max_kills,
+        context: &context,
+        protected: &protected,
     };
-    if let Some(crit_under) = args.crit_under {
-        if matches.len() < crit_under {
-            status = Status::Critical;
+    if let Some(Signal(signal)) = args.kill_matching {
+        watchdog::phase("killing matching processes");
+        for line in killer(signal).kill("matching processes", &matches) {
+            result.add_line(line);
         }
     }
+    if let Some(Signal(signal)) = args.kill_matching_parents {
+        watchdog::phase("killing parents of matching processes");
+        let mut seen = HashSet::new();
+        let parents = matches
+            .iter()
+            .map(|&(_, process)| process.stat.ppid)
+            .filter(|ppid| seen.insert(*ppid))
+            .filter_map(|ppid| procs.0.get_key_value(&ppid))
+            .collect::<Vec<_>>();
+        for line in killer(signal).kill("parents of matching processes", &parents) {
+            result.add_line(line);
+        }
+    }
+    args.common.exit(result, started);
+}
 
-    print!("{}: there are {} procs ", status, matches.len());
-    if args.pattern.is_some() {
-        print!("that match '{}' ", re());
+/// Compare the number of matching processes against the thresholds
+fn check(args: &Args, matches: &[(&Pid, &Process)]) -> CheckResult {
+    let count = matches.len() as f64;
+    let crossed = |thresholds: &[Option<Threshold>]| {
+        thresholds
+            .iter()
+            .flatten()
+            .find(|threshold| threshold.is_violated(count))
+            .map(|threshold| describe_crossed(threshold, count))
+    };
+    let (status, reason) = if let Some(bound) = crossed(&[args.crit_range(), args.thresholds.crit])
+    {
+        (Status::Critical, Some(format!("critical: {}", bound)))
+    } else if let Some(bound) = crossed(&[args.warn_range(), args.thresholds.warn]) {
+        (Status::Warning, Some(format!("warning: {}", bound)))
+    } else {
+        (Status::Ok, None)
+    };
+
+    let mut summary = format!("there are {} procs", matches.len());
+    if let Some(ref re) = args.pattern {
+        summary.push_str(&format!(" that match '{}'", re));
     }
     if !args.states.is_empty() {
-        print!("with any state in {:?} ", args.states);
+        summary.push_str(&format!(" with any state in {:?}", args.states));
     }
-    let prefix = if status == Status::Critical {
-        "not "
-    } else {
-        ""
-    };
-    match (args.crit_over, args.crit_under) {
-        (Some(over), Some(under)) => println!("({}between {} and {})", prefix, over, under),
-        (Some(crit_over), None) => println!("({}less or equal to {})", prefix, crit_over),
-        (None, Some(crit_under)) => println!("({}greater or equal to {})", prefix, crit_under),
-        (None, None) => unreachable!("unexpected got no over under"),
+    for description in args.filters.describe() {
+        summary.push(' ');
+        summary.push_str(&description);
     }
+    if let Some(reason) = reason {
+        summary.push_str(&format!(" ({})", reason));
+    }
+    let mut result = CheckResult::new(status, summary);
 
-    if matches.len() > 0 {
-        println!("INFO: Matching processes:");
+    if !matches.is_empty() {
+        result.add_line("INFO: Matching processes:");
         for process in matches.iter().take(20) {
-            println!("[{:>5}] {}", process.0, process.1.useful_cmdline());
+            result.add_line(format!("[{:>5}] {}", process.0, process.1.useful_cmdline()));
         }
         if matches.len() > 20 {
-            println!("And {} more...", matches.len() - 20)
+            result.add_line(format!("And {} more...", matches.len() - 20));
         }
     }
 
-    if args.kill_matching.is_some() {
-        let signal = args.kill_matching.unwrap().0;
-        let errors: Vec<_> = matches
-            .iter()
-            .map(|&(pid, _)| kill(*pid, signal))
-            .filter(|result| result.is_err())
-            .collect();
-        if !errors.is_empty() {
-            println!("INFO: There were {} errors killing processes", errors.len());
-        }
+    let mut perf = PerfData::new("procs", count).min(0.0);
+    if let Some(warn) = args.thresholds.warn.or_else(|| args.warn_range()) {
+        perf = perf.warn(warn);
     }
-    if args.kill_matching_parents.is_some() {
-        let signal = args.kill_matching_parents.unwrap().0;
-        let mut parents = HashSet::new();
-        let errors: Vec<_> = matches
-            .iter()
-            .map(|&(_, process)| process.stat.ppid)
-            .filter(|ppid| parents.insert(*ppid))
-            .map(|ppid| kill(ppid, signal))
-            .filter(|result| result.is_err())
-            .collect();
-        if !errors.is_empty() {
-            println!("INFO: There were {} errors killing processes", errors.len());
-        }
+    if let Some(crit) = args.thresholds.crit.or_else(|| args.crit_range()) {
+        perf = perf.crit(crit);
     }
+    result.add_perf(perf);
+    result
+}
 
-    status.exit();
+fn load_cpus(args: &Args, started: Instant) -> Vec<Calculations> {
+    Calculations::load_per_cpu_in(&args.roots.context()).unwrap_or_else(|e| {
+        args.common
+            .fail(format!("Unable to read cpu usage: {}", e), started)
+    })
+}
+
+/// The percent of a cpu that each of `start_procs` used over `--sample`
+/// seconds
+fn sample_cpu(
+    args: &Args,
+    start_cpus: &[Calculations],
+    start_procs: &RunningProcs,
+    started: Instant,
+) -> HashMap<Pid, f64> {
+    watchdog::phase(format!("sampling cpu usage for {}s", args.limits.sample));
+    sleep(Duration::from_secs(args.limits.sample));
+    let end_cpus = load_cpus(args, started);
+    let end_procs = load_procs(false, args, started);
+    let (start, end) = match (start_cpus.first(), end_cpus.first()) {
+        (Some(start), Some(end)) => (start, end),
+        _ => args
+            .common
+            .fail("Unable to read cpu usage: no cpus in /proc/stat", started),
+    };
+    end_procs
+        .percent_cpu_util_since(start_procs, end.total() - start.total())
+        .iter()
+        .map(|usage| (usage.process.stat.pid, usage.total))
+        .collect()
 }
 
 /// Load currently running procs, and die if there is a surprising error
@@ -246,8 +1280,16 @@ fn main() {
 /// Normally if this can load *any* processes it returns what it can find, and
 /// prints errors for procs that can't be parsed. But if `die_on_any_errors` is
 /// true it dies if it cannot parse a *single* process.
-fn load_procs(die_on_any_errors: bool) -> RunningProcs {
-    match RunningProcs::currently_running() {
+fn load_procs(die_on_any_errors: bool, args: &Args, started: Instant) -> RunningProcs {
+    let common = &args.common;
+    watchdog::phase("loading processes");
+    let context = args.roots.context();
+    let loaded = if args.filters.needs_status() {
+        RunningProcs::currently_running_with_status_in(&context)
+    } else {
+        RunningProcs::currently_running_in(&context)
+    };
+    match loaded {
         Ok(procs) => procs,
         Err(ProcFsError::LoadProcsError(LoadProcsError { procs, errors })) => {
             let mut saw_real_error = false;
@@ -257,19 +1299,16 @@ fn load_procs(die_on_any_errors: bool) -> RunningProcs {
                     ProcFsError::Io(_) => {}
                     err => {
                         saw_real_error = true;
-                        println!("WARN: Unexpected error loading some processes: {}", err);
+                        eprintln!("WARN: Unexpected error loading some processes: {}", err);
                     }
                 }
             }
             if die_on_any_errors && saw_real_error {
-                Status::Critical.exit();
+                common.fail("unable to load some processes", started);
             }
             procs
         }
-        Err(err) => {
-            println!("ERROR: unable to load processes: {}", err);
-            Status::Critical.exit();
-        }
+        Err(err) => common.fail(format!("unable to load processes: {}", err), started),
     }
 }
 
@@ -311,7 +1350,8 @@ fn filter_procs<'m>(
 mod unit {
     use structopt::StructOpt;
 
-    use tabin_plugins::procfs::pid::Process;
+    use tabin_plugins::linux::{Jiffies, USER_HZ};
+    use tabin_plugins::procfs::pid::{Process, ProcessStatus};
 
     use super::*;
 
@@ -401,6 +1441,437 @@ mod unit {
         assert_eq!(filtered.len(), 0);
     }
 
+    #[test]
+    fn check_reports_count() {
+        let args = Args::from_iter(["c-p", "java", "--crit-under=2"].iter());
+        let mut procs = vec![Process::default(); 2];
+        procs[1].cmdline.raw.push("java".into());
+        let proc_map = vec_to_procmap(procs);
+        let matches = filter_procs(&args.pattern, &args.states, &proc_map);
+        let result = check(&args, &matches);
+        assert_eq!(result.status, Status::Critical);
+        assert_eq!(
+            result.summary,
+            "there are 1 procs that match 'java' (critical: < 2)"
+        );
+        assert_eq!(result.perfdata[0].to_string(), "procs=1;;2:;0");
+    }
+
+    #[test]
+    fn check_reports_the_bound_crossed() {
+        let args = Args::from_iter(
+            [
+                "c-p",
+                "java",
+                "--warn-under=3",
+                "--crit-under=1",
+                "--warn-over=4",
+                "--crit-over=6",
+            ]
+            .iter(),
+        );
+        let statuses = [0, 2, 3, 5, 7]
+            .iter()
+            .map(|&count| {
+                let mut procs = vec![Process::default(); count];
+                for process in &mut procs {
+                    process.cmdline.raw.push("java".into());
+                }
+                let proc_map = vec_to_procmap(procs);
+                let matches = filter_procs(&args.pattern, &args.states, &proc_map);
+                let result = check(&args, &matches);
+                (result.status, result.summary)
+            })
+            .collect::<Vec<_>>();
+        let summary =
+            |count, reason: &str| format!("there are {} procs that match 'java'{}", count, reason);
+        assert_eq!(
+            statuses,
+            [
+                (Status::Critical, summary(0, " (critical: < 1)")),
+                (Status::Warning, summary(2, " (warning: < 3)")),
+                (Status::Ok, summary(3, "")),
+                (Status::Warning, summary(5, " (warning: > 4)")),
+                (Status::Critical, summary(7, " (critical: > 6)")),
+            ]
+        );
+
+        let proc_map = vec_to_procmap(vec![Process::default()]);
+        let matches = filter_procs(&None, &[], &proc_map);
+        let result = check(&args, &matches);
+        assert_eq!(result.perfdata[0].to_string(), "procs=1;3:4;1:6;0");
+
+        let args = Args::from_iter(["c-p", "java", "-c", "2:5"].iter());
+        let result = check(&args, &[]);
+        assert_eq!(
+            result.summary,
+            "there are 0 procs that match 'java' (critical: < 2)"
+        );
+    }
+
+    #[test]
+    fn check_accepts_ranges() {
+        let args = Args::from_iter(["c-p", "java", "-w", "2:", "-c", "1:"].iter());
+        let mut procs = vec![Process::default(); 2];
+        procs[1].cmdline.raw.push("java".into());
+        let proc_map = vec_to_procmap(procs);
+        let matches = filter_procs(&args.pattern, &args.states, &proc_map);
+        let result = check(&args, &matches);
+        assert_eq!(result.status, Status::Warning);
+        assert_eq!(
+            result.summary,
+            "there are 1 procs that match 'java' (warning: < 2)"
+        );
+        assert_eq!(result.perfdata[0].to_string(), "procs=1;2:;1:;0");
+    }
+
+    #[test]
+    fn filters_by_owner_parent_and_name() {
+        let args = Args::from_iter(["c-p", "--crit-over=0", "--uid=33", "--ppid=1"].iter());
+        let mut procs = vec![Process::default(); 4];
+        for process in &mut procs[1..] {
+            process.stat.ppid = Pid::from_raw(1);
+            process.status = Some(ProcessStatus::default());
+        }
+        procs[2].status.as_mut().unwrap().uid.effective = 33;
+        procs[3].stat.comm = "nginx".into();
+        procs[3].status.as_mut().unwrap().uid.effective = 33;
+        let context = Context::default();
+        let matcher = args.filters.matcher(&context, false).unwrap();
+        let matched = procs
+            .iter()
+            .map(|process| matcher.matches(process))
+            .collect::<Vec<_>>();
+        assert_eq!(matched, [false, false, true, true]);
+
+        let args = Args::from_iter(["c-p", "--crit-over=0", "--uid=33", "--comm=nginx"].iter());
+        let matcher = args.filters.matcher(&context, false).unwrap();
+        assert!(!matcher.matches(&procs[2]));
+        assert!(matcher.matches(&procs[3]));
+        let result = check(&args, &[(&Pid::from_raw(3), &procs[3])]);
+        assert_eq!(
+            result.summary,
+            "there are 1 procs owned by uid 33 named nginx (critical: > 0)"
+        );
+    }
+
+    #[test]
+    fn filters_by_ancestor() {
+        let mut procs = vec![Process::default(); 5];
+        procs[1].cmdline.raw = vec!["gunicorn: master".into()];
+        for (pid, ppid) in [(1, 0), (2, 1), (3, 2), (4, 0)] {
+            procs[pid].stat.pid = Pid::from_raw(pid as i32);
+            procs[pid].stat.ppid = Pid::from_raw(ppid);
+        }
+        let tree = ProcessTree::new(&procs);
+        let context = Context::default();
+        let args = Args::from_iter(["c-p", "--crit-under=1", "--children-of=gunicorn"].iter());
+        let matcher = args.filters.matcher(&context, false).unwrap();
+        let descended = procs
+            .iter()
+            .filter(|process| matcher.has_matching_ancestor(process, &tree))
+            .map(|process| process.stat.pid.as_raw())
+            .collect::<Vec<_>>();
+        assert_eq!(descended, [2, 3]);
+        assert_eq!(args.filters.describe(), ["descended from 'gunicorn'"]);
+
+        let args = Args::from_iter(["c-p", "--crit-under=1", "--children-of=["].iter());
+        let err = args.filters.matcher(&context, false).unwrap_err();
+        assert!(
+            err.starts_with("Invalid --children-of pattern: "),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn filters_by_age_and_usage() {
+        let args =
+            Args::from_iter(["c-p", "--crit-over=0", "--older-than=1h", "--min-cpu=50"].iter());
+        let context = Context::default();
+        let mut matcher = args.filters.matcher(&context, false).unwrap();
+        matcher.boot_time = SystemTime::UNIX_EPOCH;
+        matcher.now = SystemTime::UNIX_EPOCH + Duration::from_secs(2 * 60 * 60);
+        let mut process = Process::default();
+        // started at boot, and used an hour of cpu in two
+        process.stat.utime = Jiffies::new(60 * 60 * *USER_HZ);
+        assert!(matcher.matches(&process));
+        process.stat.utime = Jiffies::new(0);
+        assert!(!matcher.matches(&process));
+
+        let args = Args::from_iter(["c-p", "--younger-than=90m", "--min-rss=1"].iter());
+        let mut matcher = args.filters.matcher(&context, false).unwrap();
+        matcher.boot_time = SystemTime::UNIX_EPOCH;
+        matcher.now = SystemTime::UNIX_EPOCH + Duration::from_secs(2 * 60 * 60);
+        process.stat.rss = 1024 * 1024 / *PAGESIZE;
+        assert!(!matcher.matches(&process));
+        process.stat.starttime = 60 * 60 * *USER_HZ;
+        assert!(matcher.matches(&process));
+        process.stat.rss -= 1;
+        assert!(!matcher.matches(&process));
+
+        // more MB than fit in a u64 of bytes matches nothing, without overflowing
+        let args = Args::from_iter(["c-p", "--min-rss=18446744073709551615"].iter());
+        let matcher = args.filters.matcher(&context, false).unwrap();
+        assert!(!matcher.matches(&process));
+    }
+
+    #[test]
+    fn filters_by_exe() {
+        let context = Context::default()
+            .with_proc_root(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/proc"));
+        let args = Args::from_iter(["c-p", "--crit-under=1", "--exe=/usr/sbin/nginx"].iter());
+        let matcher = args.filters.matcher(&context, false).unwrap();
+        let mut process = Process::default();
+        process.stat.pid = Pid::from_raw(42);
+        assert!(matcher.matches(&process));
+        process.stat.pid = Pid::from_raw(1);
+        assert!(!matcher.matches(&process));
+    }
+
+    #[test]
+    fn checks_per_process_limits() {
+        let args = Args::from_iter(
+            [
+                "c-p",
+                "java",
+                "--warn-rss=1",
+                "--crit-rss=100",
+                "--crit-threads=10",
+                "--warn-cpu=50",
+                "--warn-age=1h",
+            ]
+            .iter(),
+        );
+        let mut procs = vec![Process::default(); 3];
+        for (i, process) in procs.iter_mut().enumerate() {
+            process.stat.pid = Pid::from_raw(i as i32 + 1);
+            process.stat.num_threads = 1;
+            process.cmdline.raw.push(format!("java {}", i));
+        }
+        procs[0].stat.rss = 2 * 1024 * 1024 / *PAGESIZE;
+        procs[1].stat.num_threads = 20;
+        procs[2].stat.starttime = 90 * 60 * *USER_HZ;
+        let pids = procs.iter().map(|p| p.stat.pid).collect::<Vec<_>>();
+        let matches = pids.iter().zip(&procs).collect::<Vec<_>>();
+        let context = Context::default();
+        let mut matcher = args.filters.matcher(&context, false).unwrap();
+        matcher.boot_time = SystemTime::UNIX_EPOCH;
+        matcher.now = SystemTime::UNIX_EPOCH + Duration::from_secs(2 * 60 * 60);
+        let cpu = vec![(pids[0], 75.5)].into_iter().collect();
+
+        let results = check_limits(&args, &matches, &matcher, Some(&cpu));
+        let summaries = results
+            .iter()
+            .map(|result| (result.status, result.summary.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summaries,
+            [
+                (Status::Warning, "1 procs have rss > 1.0MB"),
+                (Status::Warning, "1 procs have cpu > 50.0%"),
+                (Status::Critical, "1 procs have threads > 10"),
+                (Status::Warning, "2 procs have age > 1h"),
+            ]
+        );
+        assert_eq!(results[0].long_output, ["[    1] rss 2.0MB: java 0"]);
+        assert_eq!(results[1].long_output, ["[    1] cpu 75.5%: java 0"]);
+        assert_eq!(results[2].perfdata[0].to_string(), "max_threads=20;;10;0");
+        assert_eq!(
+            results[3].long_output,
+            ["[    1] age 2h: java 0", "[    2] age 2h: java 1"]
+        );
+
+        let result = merge_worst_first(results);
+        assert_eq!(result.status, Status::Critical);
+        assert_eq!(result.summary, "1 procs have threads > 10");
+        assert_eq!(
+            result.long_output[..3],
+            [
+                "[    2] threads 20: java 1",
+                "1 procs have rss > 1.0MB",
+                "[    1] rss 2.0MB: java 0"
+            ]
+        );
+
+        let args = Args::from_iter(["c-p", "java", "--crit-rss=100"].iter());
+        let results = check_limits(&args, &matches, &matcher, None);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].status, Status::Ok);
+        assert_eq!(results[0].summary, "no procs have rss > 100.0MB");
+    }
+
+    #[test]
+    fn reports_procs_whose_fds_cant_be_counted() {
+        let args = Args::from_iter(["c-p", "--crit-fds=100"].iter());
+        let context = Context::default()
+            .with_proc_root(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/proc"));
+        let matcher = args.filters.matcher(&context, false).unwrap();
+        // the fixture for pid 1 has no fd dir
+        let mut procs = vec![Process::default(); 2];
+        for (process, pid) in procs.iter_mut().zip(&[1, 42]) {
+            process.stat.pid = Pid::from_raw(*pid);
+            process.cmdline.raw.push(format!("daemon {}", pid));
+        }
+        let pids = procs.iter().map(|p| p.stat.pid).collect::<Vec<_>>();
+        let matches = pids.iter().zip(&procs).collect::<Vec<_>>();
+
+        let results = check_limits(&args, &matches, &matcher, None);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].status, Status::Ok);
+        let uncounted = &results[1];
+        assert_eq!(uncounted.status, Status::Unknown);
+        assert_eq!(uncounted.summary, "unable to count the open fds of 1 procs");
+        assert_eq!(uncounted.long_output.len(), 1);
+        assert!(uncounted.long_output[0].starts_with("[    1] "));
+        assert!(uncounted.long_output[0].ends_with(": daemon 1"));
+
+        let result = merge_worst_first(results);
+        assert_eq!(result.status, Status::Unknown);
+    }
+
+    fn killer<'a>(context: &'a Context, protected: &'a Protected, extra: &[&str]) -> Killer<'a> {
+        let mut argv = vec!["c-p", "nginx", "--kill-matching", "TERM"];
+        argv.extend(extra);
+        let args = Args::from_iter(argv.iter());
+        Killer {
+            signal: NixSignal::SIGTERM,
+            dry_run: args.dry_run,
+            grace: args.kill_grace.map(Duration::from_secs),
+            max_kills: args.max_kills,
+            context,
+            protected,
+        }
+    }
+
+    #[test]
+    fn kills_only_the_processes_that_were_matched() {
+        let context = Context::default()
+            .with_proc_root(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/proc"));
+        let protected = Protected::new(Pid::from_raw(7), Pid::from_raw(6), &ProcMap::new());
+        let mut nginx = Process::default();
+        nginx.stat.starttime = 500;
+        nginx.cmdline.raw = vec!["nginx".into()];
+        let mut reused = nginx.clone();
+        reused.stat.starttime = 400;
+        let (pids, init) = ([42, 43, 1, 7, 6].map(Pid::from_raw), Process::default());
+        let targets = [
+            (&pids[0], &nginx),
+            (&pids[0], &reused),
+            (&pids[1], &nginx),
+            (&pids[2], &init),
+            (&pids[3], &nginx),
+            (&pids[4], &nginx),
+        ];
+
+        let lines =
+            killer(&context, &protected, &["--dry-run"]).kill("matching processes", &targets);
+        assert_eq!(
+            lines,
+            [
+                "INFO: Would send SIGTERM to 1 matching processes:",
+                "[   42] would send SIGTERM: nginx",
+                "[   42] not killed, it exited and its pid was reused: nginx",
+                "[   43] not killed, it already exited: nginx",
+                "[    1] not killed, it is init: init",
+                "[    7] not killed, it is this check: nginx",
+                "[    6] not killed, it started this check: nginx",
+            ]
+        );
+
+        let lines =
+            killer(&context, &protected, &["--max-kills=0"]).kill("matching processes", &targets);
+        assert_eq!(
+            lines[..3],
+            [
+                "INFO: Not killing 1 matching processes, more than --max-kills 0:",
+                "[   42] not killed, too many matches: nginx",
+                "[   42] not killed, it exited and its pid was reused: nginx",
+            ]
+        );
+    }
+
+    #[test]
+    fn protects_the_processes_that_started_this_check() {
+        let mut procs = vec![Process::default(); 5];
+        procs[4].stat.ppid = Pid::from_raw(3);
+        procs[3].stat.ppid = Pid::from_raw(1);
+        let protected = Protected::new(Pid::from_raw(5), Pid::from_raw(4), &vec_to_procmap(procs));
+        assert_eq!(
+            protected.reason(Pid::from_raw(3)),
+            Some("it started this check")
+        );
+        assert_eq!(protected.reason(Pid::from_raw(1)), Some("it is init"));
+        assert_eq!(protected.reason(Pid::from_raw(2)), None);
+        assert_eq!(
+            protected.reason(Pid::from_raw(-1)),
+            Some("it is the kernel")
+        );
+    }
+
+    #[test]
+    fn escalates_to_sigkill_after_the_grace_period() {
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "trap '' TERM; while :; do sleep 0.1; done"])
+            .spawn()
+            .unwrap();
+        let pid = Pid::from_raw(child.id() as i32);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !ProcessStatus::from_pid(pid).is_ok_and(|s| s.sig_ign.contains(NixSignal::SIGTERM)) {
+            assert!(Instant::now() < deadline, "the child never ignored SIGTERM");
+            sleep(Duration::from_millis(10));
+        }
+        let process = Process::from_pid(pid).unwrap();
+        let (context, protected) = (
+            Context::default(),
+            Protected::new(getpid(), getppid(), &ProcMap::new()),
+        );
+        let lines = killer(&context, &protected, &["--kill-grace=1"])
+            .kill("matching processes", &[(&pid, &process)]);
+        child.wait().unwrap();
+        assert_eq!(
+            lines[1],
+            format!(
+                "[{:>5}] still running 1s after SIGTERM, sent SIGKILL: {}",
+                pid,
+                process.useful_cmdline()
+            )
+        );
+    }
+
+    #[test]
+    fn merges_unknown_results_before_ok_ones() {
+        let count = CheckResult::new(Status::Ok, "there are 2 procs");
+        let mut unreadable = CheckResult::new(Status::Unknown, "unable to count fds");
+        unreadable.add_line("[   42] permission denied");
+        let result = merge_worst_first(vec![count, unreadable]);
+        assert_eq!(result.status, Status::Unknown);
+        assert_eq!(result.summary, "unable to count fds");
+        assert_eq!(
+            result.long_output,
+            ["[   42] permission denied", "there are 2 procs"]
+        );
+    }
+
+    #[test]
+    fn parses_ages() {
+        assert_eq!("90".parse(), Ok(Age(Duration::from_secs(90))));
+        assert_eq!("10m".parse(), Ok(Age(Duration::from_secs(600))));
+        assert_eq!("1d".parse::<Age>().unwrap().to_string(), "1d");
+        assert_eq!("120m".parse::<Age>().unwrap().to_string(), "2h");
+        assert_eq!("90s".parse::<Age>().unwrap().to_string(), "90s");
+        assert!("1y".parse::<Age>().is_err());
+        assert_eq!(show_age(90.0 * 60.0), "1.5h");
+        assert_eq!(show_age(2.0 * 24.0 * 60.0 * 60.0), "2d");
+        assert_eq!(show_age(59.0), "59s");
+        assert!("d".parse::<Age>().is_err());
+        assert_eq!(
+            "99999999999999999w".parse::<Age>(),
+            Err("\"99999999999999999w\" is too long".to_string())
+        );
+        assert!(Args::from_iter_safe(["c-p", "--older-than=99999999999999999w"].iter()).is_err());
+    }
+
     fn regex(re: &str) -> Option<Regex> {
         Some(Regex::new(re).unwrap())
     }
@@ -1,49 +1,98 @@
+use std::time::Instant;
+
 use serde::Deserialize;
 use structopt::StructOpt;
 
-use tabin_plugins::linux::pages_to_human_size;
+use tabin_plugins::cli::{CommonArgs, RamHogs, Roots, Thresholds};
+use tabin_plugins::linux::bytes_to_human_size;
+use tabin_plugins::perfdata::{PerfData, Unit};
 use tabin_plugins::procfs::{LoadProcsError, MemInfo, ProcFsError, RunningProcs};
-use tabin_plugins::Status;
+use tabin_plugins::watchdog;
+use tabin_plugins::Context;
+use tabin_plugins::{CheckResult, Status, Threshold};
+
+const DEFAULT_WARN: f64 = 85.0;
+const DEFAULT_CRIT: f64 = 95.0;
 
 /// Check the ram usage of the current computer
+///
+/// Thresholds are percentages of ram used, and default to warning above 85
+/// and critical above 95.
+///
+/// The other thresholds are only checked if they are given. --warn-commit and
+/// --crit-commit are percentages of the commit limit that has been allocated
+/// (Committed_AS / CommitLimit), which can go over 100 if the kernel allows
+/// overcommitting. --warn-dirty and --crit-dirty are percentages of ram that
+/// is dirty or being written back to disk, and --warn-slab and --crit-slab
+/// are percentages of ram in kernel slab that can't be reclaimed.
 #[derive(Deserialize, StructOpt, Debug)]
 #[structopt(
     name = "check-ram (part of tabin-plugins)",
     setting = structopt::clap::AppSettings::ColoredHelp,
 )]
 struct Args {
+    #[structopt(flatten)]
+    thresholds: Thresholds,
     #[structopt(
-        short = "w",
-        long = "warn",
-        help = "Percent to warn at",
-        default_value = "85"
+        long = "warn-commit",
+        value_name = "RANGE",
+        help = "Percent of the commit limit allocated to warn at"
     )]
-    warn: f64,
+    warn_commit: Option<Threshold>,
     #[structopt(
-        short = "c",
-        long = "crit",
-        help = "Percent to go critical at",
-        default_value = "95"
+        long = "crit-commit",
+        value_name = "RANGE",
+        help = "Percent of the commit limit allocated to go critical at"
     )]
-    crit: f64,
-
+    crit_commit: Option<Threshold>,
     #[structopt(
-        long = "show-hogs",
-        name = "count",
-        help = "Show <count> most ram-intensive processes in this computer.",
-        default_value = "0"
+        long = "warn-dirty",
+        value_name = "RANGE",
+        help = "Percent of ram dirty or under writeback to warn at"
     )]
-    show_hogs: usize,
+    warn_dirty: Option<Threshold>,
+    #[structopt(
+        long = "crit-dirty",
+        value_name = "RANGE",
+        help = "Percent of ram dirty or under writeback to go critical at"
+    )]
+    crit_dirty: Option<Threshold>,
+    #[structopt(
+        long = "warn-slab",
+        value_name = "RANGE",
+        help = "Percent of ram in unreclaimable slab to warn at"
+    )]
+    warn_slab: Option<Threshold>,
+    #[structopt(
+        long = "crit-slab",
+        value_name = "RANGE",
+        help = "Percent of ram in unreclaimable slab to go critical at"
+    )]
+    crit_slab: Option<Threshold>,
+    #[structopt(flatten)]
+    hogs: RamHogs,
+    #[structopt(flatten)]
+    roots: Roots,
+    #[structopt(flatten)]
+    common: CommonArgs,
 }
 
 #[cfg_attr(test, allow(dead_code))]
 fn main() {
     let args = Args::from_args();
-    let mem = MemInfo::load();
-    let status = compare_status(args.crit, args.warn, &mem);
-    if args.show_hogs > 0 {
+    let started = Instant::now();
+    args.common.init();
+    let context = args.roots.context();
+    watchdog::phase("reading memory usage");
+    let mem = MemInfo::load_in(&context).unwrap_or_else(|e| {
+        args.common
+            .fail(format!("Unable to read memory usage: {}", e), started)
+    });
+    let mut result = check(&args, &mem);
+    if args.hogs.show_hogs > 0 {
         let mut load_errors = None;
-        let per_proc = match RunningProcs::currently_running() {
+        watchdog::phase("loading processes");
+        let per_proc = match RunningProcs::currently_running_in(&context) {
             Ok(procs) => procs,
             Err(ProcFsError::LoadProcsError(LoadProcsError { procs, errors })) => {
                 load_errors = Some(errors);
@@ -54,24 +103,11 @@ fn main() {
                 RunningProcs::empty()
             }
         };
-
-        let mut procs = per_proc.0.values().collect::<Vec<_>>();
-        procs.sort_by(|l, r| r.stat.rss.cmp(&l.stat.rss));
-        println!(
-            "INFO [check-ram]: {} processes running, top {} ram hogs:",
-            procs.len(),
-            args.show_hogs
-        );
-        for process in procs.iter().take(args.show_hogs) {
-            let system_kb = mem.total.unwrap();
-            let percent = process.percent_ram(system_kb * 1024);
-            println!(
-                "[{:>6}]{:>5.1}% {:>6}: {}",
-                process.stat.pid,
-                percent,
-                pages_to_human_size(process.stat.rss),
-                process.useful_cmdline()
-            );
+        if let Some(total_kb) = mem.total {
+            watchdog::phase("measuring processes");
+            for line in ram_hogs(&per_proc, total_kb * 1024, &args.hogs, &context) {
+                result.add_line(line);
+            }
         }
         if let Some(errors) = load_errors {
             for err in errors {
@@ -79,46 +115,378 @@ fn main() {
             }
         }
     };
-    status.exit();
+    args.common.exit(result, started);
 }
 
-fn compare_status(crit: f64, warn: f64, mem: &MemInfo) -> Status {
-    match mem.percent_used() {
-        Ok(percent) => {
-            if percent > crit {
-                println!("CRITICAL [check-ram]: {:.1}% > {}%", percent, crit);
-                Status::Critical
-            } else if percent > warn {
-                println!("WARNING [check-ram]: {:.1}% > {}%", percent, warn);
-                Status::Warning
-            } else {
-                println!("OK [check-ram]: {:.1}% < {}%", percent, warn);
-                Status::Ok
-            }
+/// Check the ram used and any kernel memory thresholds that were given
+///
+/// If the ram used can't be worked out the result is UNKNOWN, or
+/// `--status-on-error`, unless a kernel memory check is worse.
+fn check(args: &Args, mem: &MemInfo) -> CheckResult {
+    let crit = args.thresholds.crit_or(DEFAULT_CRIT);
+    let warn = args.thresholds.warn_or(DEFAULT_WARN);
+    let mut result = match mem.percent_used() {
+        Ok(percent) => compare_status(crit, warn, percent),
+        Err(e) => args
+            .common
+            .error(format!("Unable to read memory usage: {}", e)),
+    };
+    for other in check_kernel_memory(args, mem) {
+        result.merge(other);
+    }
+    result
+}
+
+/// Check the commit, dirty and slab thresholds that were given
+///
+/// A metric whose fields are missing from /proc/meminfo gets the
+/// `--status-on-error` status, without stopping the others.
+fn check_kernel_memory(args: &Args, mem: &MemInfo) -> Vec<CheckResult> {
+    let mut results = vec![];
+    let missing = |field| format!("Unable to find {} in /proc/meminfo", field);
+    let mut check = |given: bool, metric: &dyn Fn() -> Result<CheckResult, String>| {
+        if given {
+            results.push(metric().unwrap_or_else(|e| args.common.error(e)));
         }
-        Err(e) => {
-            println!("UNKNOWN [check-ram]: UNEXPECTED ERROR {:?}", e);
-            Status::Unknown
+    };
+    check(
+        args.warn_commit.is_some() || args.crit_commit.is_some(),
+        &|| {
+            let percent = mem
+                .percent_committed()
+                .ok_or_else(|| missing("Committed_AS or CommitLimit"))?;
+            let detail = format!(
+                "({} of {})",
+                kb_to_human_size(mem.kb("Committed_AS").unwrap_or(0)),
+                kb_to_human_size(mem.kb("CommitLimit").unwrap_or(0))
+            );
+            Ok(check_percent(
+                "commit",
+                "committed",
+                percent,
+                &detail,
+                (args.warn_commit, args.crit_commit),
+            ))
+        },
+    );
+    check(
+        args.warn_dirty.is_some() || args.crit_dirty.is_some(),
+        &|| {
+            let kb = match (mem.kb("Dirty"), mem.kb("Writeback")) {
+                (Some(dirty), Some(writeback)) => dirty + writeback,
+                _ => return Err(missing("Dirty or Writeback")),
+            };
+            let percent = mem
+                .percent_of_total(kb)
+                .ok_or_else(|| missing("MemTotal"))?;
+            let detail = format!("({} of ram)", kb_to_human_size(kb));
+            Ok(check_percent(
+                "dirty",
+                "dirty or writeback",
+                percent,
+                &detail,
+                (args.warn_dirty, args.crit_dirty),
+            ))
+        },
+    );
+    check(
+        args.warn_slab.is_some() || args.crit_slab.is_some(),
+        &|| {
+            let kb = mem.kb("SUnreclaim").ok_or_else(|| missing("SUnreclaim"))?;
+            let percent = mem
+                .percent_of_total(kb)
+                .ok_or_else(|| missing("MemTotal"))?;
+            let detail = format!("({} of ram)", kb_to_human_size(kb));
+            Ok(check_percent(
+                "slab_unreclaimable",
+                "unreclaimable slab",
+                percent,
+                &detail,
+                (args.warn_slab, args.crit_slab),
+            ))
+        },
+    );
+    results
+}
+
+/// Compare a percentage against optional warning and critical thresholds
+fn check_percent(
+    label: &str,
+    name: &str,
+    percent: f64,
+    detail: &str,
+    (warn, crit): (Option<Threshold>, Option<Threshold>),
+) -> CheckResult {
+    let violated = |status, threshold: Threshold| {
+        CheckResult::new(
+            status,
+            format!(
+                "{} {:.1}% {} {}",
+                name,
+                percent,
+                threshold.describe(),
+                detail
+            ),
+        )
+    };
+    let mut result = match (warn, crit) {
+        (_, Some(crit)) if crit.is_violated(percent) => violated(Status::Critical, crit),
+        (Some(warn), _) if warn.is_violated(percent) => violated(Status::Warning, warn),
+        _ => CheckResult::new(Status::Ok, format!("{} {:.1}% {}", name, percent, detail)),
+    };
+    let mut perf = PerfData::new(label, percent).unit(Unit::Percent).min(0.0);
+    if let Some(warn) = warn {
+        perf = perf.warn(warn);
+    }
+    if let Some(crit) = crit {
+        perf = perf.crit(crit);
+    }
+    result.add_perf(perf);
+    result
+}
+
+fn kb_to_human_size(kb: usize) -> String {
+    bytes_to_human_size(kb as u64 * 1024)
+}
+
+/// Describe the processes using the most ram
+fn ram_hogs(
+    per_proc: &RunningProcs,
+    total_bytes: usize,
+    hogs: &RamHogs,
+    context: &Context,
+) -> Vec<String> {
+    let ranking = hogs.hogs_by.rank_in(per_proc.0.values(), context);
+    let percent = |bytes| bytes as f64 / total_bytes as f64 * 100.0;
+    let mut lines = vec![];
+    if hogs.hogs_per_tree {
+        let trees = ranking.by_tree(&per_proc.tree());
+        lines.push(format!(
+            "INFO [check-ram]: {} processes running in {} trees, top {} ram hogs by {}:",
+            per_proc.len(),
+            trees.len(),
+            hogs.show_hogs,
+            hogs.hogs_by
+        ));
+        for &(process, count, bytes) in trees.iter().take(hogs.show_hogs) {
+            lines.push(format!(
+                "[{:>6}]{:>5.1}% {:>6}: {} ({} processes)",
+                process.stat.pid,
+                percent(bytes),
+                bytes_to_human_size(bytes),
+                process.useful_cmdline(),
+                count
+            ));
+        }
+    } else {
+        lines.push(format!(
+            "INFO [check-ram]: {} processes running, top {} ram hogs by {}:",
+            per_proc.len(),
+            hogs.show_hogs,
+            hogs.hogs_by
+        ));
+        for &(process, bytes) in ranking.measured.iter().take(hogs.show_hogs) {
+            lines.push(format!(
+                "[{:>6}]{:>5.1}% {:>6}: {}",
+                process.stat.pid,
+                percent(bytes),
+                bytes_to_human_size(bytes),
+                process.useful_cmdline()
+            ));
         }
     }
+    if let Some((process, err)) = ranking.errors.first() {
+        eprintln!(
+            "Unable to measure the {} of {} processes, e.g. {}: {}",
+            hogs.hogs_by,
+            ranking.errors.len(),
+            process.stat.pid,
+            err
+        );
+    }
+    lines
+}
+
+fn perf_data(crit: Threshold, warn: Threshold, percent: f64) -> PerfData {
+    PerfData::new("ram_used", percent)
+        .unit(Unit::Percent)
+        .warn(warn)
+        .crit(crit)
+        .min(0.0)
+        .max(100.0)
+}
+
+/// Compare the percent of ram used, from `MemInfo::percent_used`
+fn compare_status(crit: Threshold, warn: Threshold, percent: f64) -> CheckResult {
+    let mut result = if crit.is_violated(percent) {
+        CheckResult::new(
+            Status::Critical,
+            format!("ram used {:.1}% {}", percent, crit.describe()),
+        )
+    } else if warn.is_violated(percent) {
+        CheckResult::new(
+            Status::Warning,
+            format!("ram used {:.1}% {}", percent, warn.describe()),
+        )
+    } else {
+        CheckResult::new(
+            Status::Ok,
+            format!("ram used {:.1}% {}", percent, warn.describe_ok()),
+        )
+    };
+    result.add_perf(perf_data(crit, warn, percent));
+    result
 }
 
 #[cfg(test)]
 mod test {
-    use super::compare_status;
-    use tabin_plugins::procfs::MemInfo;
-    use tabin_plugins::Status;
+    use structopt::StructOpt;
+
+    use super::{check, check_kernel_memory, compare_status, perf_data, ram_hogs, Args};
+    use tabin_plugins::procfs::{MemInfo, RunningProcs};
+    use tabin_plugins::{Context, Status, Threshold};
 
     #[test]
     fn alerts_when_told_to() {
         let mem = MemInfo {
             total: Some(100),
             available: Some(15), // 15% free means 85% used
-            free: None,
-            cached: None,
+            ..MemInfo::default()
+        };
+        let crit_threshold = Threshold::above(80.0);
+
+        let percent = mem.percent_used().unwrap();
+        let result = compare_status(crit_threshold, Threshold::above(25.0), percent);
+        assert_eq!(result.status, Status::Critical);
+        assert_eq!(result.summary, "ram used 85.0% > 80");
+    }
+
+    #[test]
+    fn alerts_on_ranges() {
+        let mem = MemInfo {
+            total: Some(100),
+            available: Some(95),
+            ..MemInfo::default()
+        };
+        let percent = mem.percent_used().unwrap();
+        let result = compare_status(Threshold::above(95.0), "10:".parse().unwrap(), percent);
+        assert_eq!(result.status, Status::Warning);
+        assert_eq!(result.summary, "ram used 5.0% < 10");
+    }
+
+    #[test]
+    fn reports_percent_used() {
+        let mem = MemInfo {
+            total: Some(100),
+            available: Some(15),
+            ..MemInfo::default()
         };
-        let crit_threshold = 80.0;
+        let percent = mem.percent_used().unwrap();
+        let perf = perf_data(Threshold::above(95.0), Threshold::above(85.0), percent);
+        assert_eq!(perf.to_string(), "ram_used=85%;85;95;0;100");
+    }
+
+    #[test]
+    fn alerts_on_kernel_memory() {
+        let mem: MemInfo = concat!(
+            "MemTotal: 1000 kB\n",
+            "Dirty: 30 kB\n",
+            "Writeback: 20 kB\n",
+            "SUnreclaim: 100 kB\n",
+            "CommitLimit: 800 kB\n",
+            "Committed_AS: 1000 kB\n",
+        )
+        .parse()
+        .unwrap();
+        let args = Args::from_iter(["arg0"].iter());
+        assert!(check_kernel_memory(&args, &mem).is_empty());
+
+        let args = Args::from_iter(
+            [
+                "arg0",
+                "--crit-commit",
+                "120",
+                "--warn-dirty",
+                "10",
+                "--warn-slab",
+                "5",
+                "--crit-slab",
+                "20",
+            ]
+            .iter(),
+        );
+        let results = check_kernel_memory(&args, &mem);
+        assert_eq!(results[0].status, Status::Critical);
+        assert_eq!(
+            results[0].summary,
+            "committed 125.0% > 120 (1000.0K of 800.0K)"
+        );
+        assert_eq!(results[0].perfdata[0].to_string(), "commit=125%;;120;0");
+        assert_eq!(results[1].status, Status::Ok);
+        assert_eq!(results[1].summary, "dirty or writeback 5.0% (50.0K of ram)");
+        assert_eq!(results[2].status, Status::Warning);
+        assert_eq!(
+            results[2].summary,
+            "unreclaimable slab 10.0% > 5 (100.0K of ram)"
+        );
+
+        let args = Args::from_iter(["arg0", "--warn-slab", "5", "--warn-dirty", "10"].iter());
+        let mem: MemInfo = "MemTotal: 1000 kB\nDirty: 30 kB\nWriteback: 20 kB\n"
+            .parse()
+            .unwrap();
+        let results = check_kernel_memory(&args, &mem);
+        assert_eq!(results[0].status, Status::Ok);
+        assert_eq!(results[1].status, Status::Unknown);
+        assert_eq!(
+            results[1].summary,
+            "Unable to find SUnreclaim in /proc/meminfo"
+        );
+    }
 
-        assert_eq!(compare_status(crit_threshold, 25.0, &mem), Status::Critical);
+    #[test]
+    fn unknown_ram_usage_is_not_hidden_by_kernel_memory() {
+        let mem: MemInfo = "MemTotal: 1000 kB\nCommitLimit: 800 kB\nCommitted_AS: 400 kB\n"
+            .parse()
+            .unwrap();
+        let args = Args::from_iter(["arg0", "--warn-commit", "80"].iter());
+        let result = check(&args, &mem);
+        assert_eq!(result.status, Status::Unknown);
+        assert!(
+            result.summary.starts_with("Unable to read memory usage: "),
+            "{}",
+            result.summary
+        );
+        assert_eq!(result.long_output.len(), 1);
+
+        let args = Args::from_iter(["arg0", "--crit-commit", "40"].iter());
+        assert_eq!(check(&args, &mem).status, Status::Critical);
+    }
+
+    #[test]
+    fn missing_kernel_memory_keeps_the_ram_used() {
+        let mem: MemInfo = "MemTotal: 1000 kB\nMemAvailable: 100 kB\n".parse().unwrap();
+        let args = Args::from_iter(["arg0", "--warn-slab", "5"].iter());
+        let result = check(&args, &mem);
+        assert_eq!(result.status, Status::Unknown);
+        assert_eq!(result.summary, "Unable to find SUnreclaim in /proc/meminfo");
+        assert_eq!(result.long_output, ["ram used 90.0% > 85"]);
+        assert_eq!(result.perfdata[0].to_string(), "ram_used=90%;85;95;0;100");
+    }
+
+    #[test]
+    fn shows_hogs_per_tree() {
+        let context = Context::default()
+            .with_proc_root(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/proc"));
+        let procs = RunningProcs::currently_running_in(&context).unwrap();
+        let args =
+            Args::from_iter(["arg0", "--show-hogs=1", "--hogs-by=pss", "--hogs-per-tree"].iter());
+        let lines = ram_hogs(&procs, 30_000 * 1024, &args.hogs, &context);
+        assert_eq!(
+            lines,
+            [
+                "INFO [check-ram]: 2 processes running in 2 trees, top 1 ram hogs by pss:",
+                "[    42] 10.0%   2.9M: nginx: master process (1 processes)",
+            ]
+        );
     }
 }
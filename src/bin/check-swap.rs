@@ -0,0 +1,272 @@
+//! Check swap usage and swapping activity
+
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use structopt::StructOpt;
+
+use tabin_plugins::cli::{CommonArgs, Roots, Thresholds};
+use tabin_plugins::linux::bytes_to_human_size;
+use tabin_plugins::perfdata::{PerfData, Unit};
+use tabin_plugins::procfs::{MemInfo, Swap, VmStat};
+use tabin_plugins::watchdog;
+use tabin_plugins::{CheckResult, Status, Threshold};
+
+const DEFAULT_WARN: f64 = 50.0;
+const DEFAULT_CRIT: f64 = 80.0;
+
+/// Check the swap usage of the current computer
+///
+/// Thresholds are percentages of swap used, and default to warning above 50
+/// and critical above 80. A computer without swap is always OK.
+///
+/// Filling swap is often harmless, constantly moving pages in and out of it
+/// is not. --warn-rate and --crit-rate alert on the pages swapped in and out
+/// per second, sampled from /proc/vmstat over --sample seconds.
+#[derive(Deserialize, StructOpt, Debug)]
+#[structopt(
+    name = "check-swap (part of tabin-plugins)",
+    setting = structopt::clap::AppSettings::ColoredHelp,
+)]
+struct Args {
+    #[structopt(flatten)]
+    thresholds: Thresholds,
+    #[structopt(
+        long = "warn-rate",
+        value_name = "RANGE",
+        help = "Pages swapped in plus pages swapped out per second to warn \
+                at, as a nagios range"
+    )]
+    warn_rate: Option<Threshold>,
+    #[structopt(
+        long = "crit-rate",
+        value_name = "RANGE",
+        help = "Pages swapped in plus pages swapped out per second to go \
+                critical at, as a nagios range"
+    )]
+    crit_rate: Option<Threshold>,
+    #[structopt(
+        short = "s",
+        long = "sample",
+        name = "seconds",
+        help = "Seconds to measure the swap rate over, if a rate threshold \
+                is given",
+        default_value = "5"
+    )]
+    sample: u64,
+    #[structopt(flatten)]
+    roots: Roots,
+    #[structopt(flatten)]
+    common: CommonArgs,
+}
+
+impl Args {
+    fn checks_rate(&self) -> bool {
+        self.warn_rate.is_some() || self.crit_rate.is_some()
+    }
+}
+
+#[cfg_attr(test, allow(dead_code))]
+fn main() {
+    let args = Args::from_args();
+    let started = Instant::now();
+    args.common.init();
+    let context = args.roots.context();
+    watchdog::phase("reading swap usage");
+    let mem = MemInfo::load_in(&context).unwrap_or_else(|e| {
+        args.common
+            .fail(format!("Unable to read swap usage: {}", e), started)
+    });
+    let mut result = check_used(&args, &mem);
+
+    if args.checks_rate() {
+        let load_vmstat = || {
+            VmStat::load_in(&context).unwrap_or_else(|e| {
+                args.common
+                    .fail(format!("Unable to read swap activity: {}", e), started)
+            })
+        };
+        let start = load_vmstat();
+        watchdog::phase(format!("sampling swap activity for {}s", args.sample));
+        let sample = Duration::from_secs(args.sample);
+        sleep(sample);
+        let swapped = load_vmstat().since(&start);
+        result.merge(check_rate(&args, &swapped, sample));
+    }
+
+    // The devices are only informational, so don't fail if they can't be read
+    watchdog::phase("reading swap devices");
+    match Swap::load_all_in(&context) {
+        Ok(swaps) => {
+            for line in describe_swaps(&swaps) {
+                result.add_line(line);
+            }
+        }
+        Err(e) => eprintln!("Unable to read swap devices: {}", e),
+    }
+    args.common.exit(result, started);
+}
+
+/// Compare the percent of swap used against the thresholds
+fn check_used(args: &Args, mem: &MemInfo) -> CheckResult {
+    let percent = match mem.percent_swap_used() {
+        Some(percent) => percent,
+        None if mem.swap_total == Some(0) => {
+            return CheckResult::new(Status::Ok, "no swap configured")
+        }
+        None => return args.common.error("Unable to find SwapTotal or SwapFree"),
+    };
+    let warn = args.thresholds.warn_or(DEFAULT_WARN);
+    let crit = args.thresholds.crit_or(DEFAULT_CRIT);
+    let mut result = if crit.is_violated(percent) {
+        CheckResult::new(
+            Status::Critical,
+            format!("swap used {:.1}% {}", percent, crit.describe()),
+        )
+    } else if warn.is_violated(percent) {
+        CheckResult::new(
+            Status::Warning,
+            format!("swap used {:.1}% {}", percent, warn.describe()),
+        )
+    } else {
+        CheckResult::new(
+            Status::Ok,
+            format!("swap used {:.1}% {}", percent, warn.describe_ok()),
+        )
+    };
+    result.add_perf(
+        PerfData::new("swap_used", percent)
+            .unit(Unit::Percent)
+            .warn(warn)
+            .crit(crit)
+            .min(0.0)
+            .max(100.0),
+    );
+    result
+}
+
+/// Compare the pages swapped per second over `sample` against the rate
+/// thresholds
+fn check_rate(args: &Args, swapped: &VmStat, sample: Duration) -> CheckResult {
+    let seconds = sample.as_secs_f64().max(1e-3);
+    let swap_in = swapped.pswpin as f64 / seconds;
+    let swap_out = swapped.pswpout as f64 / seconds;
+    let rate = swap_in + swap_out;
+    let summary = format!(
+        "swapping {:.1} pages/s ({:.1} in, {:.1} out)",
+        rate, swap_in, swap_out
+    );
+    let mut result = match (args.crit_rate, args.warn_rate) {
+        (Some(crit), _) if crit.is_violated(rate) => {
+            CheckResult::new(Status::Critical, format!("{} {}", summary, crit.describe()))
+        }
+        (_, Some(warn)) if warn.is_violated(rate) => {
+            CheckResult::new(Status::Warning, format!("{} {}", summary, warn.describe()))
+        }
+        _ => CheckResult::new(Status::Ok, summary),
+    };
+    result.add_perf(PerfData::new("swap_in", swap_in).min(0.0));
+    result.add_perf(PerfData::new("swap_out", swap_out).min(0.0));
+    result
+}
+
+/// Describe how full each swap device is
+fn describe_swaps(swaps: &[Swap]) -> Vec<String> {
+    if swaps.is_empty() {
+        return vec![];
+    }
+    let mut lines = vec![format!("INFO [check-swap]: {} swap areas:", swaps.len())];
+    for swap in swaps {
+        lines.push(format!(
+            "{:>5.1}% of {:>8} ({}, priority {}): {}",
+            swap.percent_used(),
+            bytes_to_human_size(swap.size as u64 * 1024),
+            swap.kind,
+            swap.priority,
+            swap.filename
+        ));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use structopt::StructOpt;
+
+    use super::{check_rate, check_used, describe_swaps, Args};
+    use tabin_plugins::procfs::{MemInfo, Swap, VmStat};
+    use tabin_plugins::Status;
+
+    fn swap(total: usize, free: usize) -> MemInfo {
+        MemInfo {
+            swap_total: Some(total),
+            swap_free: Some(free),
+            ..MemInfo::default()
+        }
+    }
+
+    #[test]
+    fn alerts_on_percent_used() {
+        let args = Args::from_iter(["arg0"].iter());
+        let result = check_used(&args, &swap(100, 40));
+        assert_eq!(result.status, Status::Warning);
+        assert_eq!(result.summary, "swap used 60.0% > 50");
+        assert_eq!(result.perfdata[0].to_string(), "swap_used=60%;50;80;0;100");
+
+        let args = Args::from_iter(["arg0", "-c", "50"].iter());
+        assert_eq!(check_used(&args, &swap(100, 40)).status, Status::Critical);
+        assert_eq!(check_used(&args, &swap(100, 90)).status, Status::Ok);
+    }
+
+    #[test]
+    fn no_swap_is_ok() {
+        let args = Args::from_iter(["arg0"].iter());
+        let result = check_used(&args, &swap(0, 0));
+        assert_eq!(result.status, Status::Ok);
+        assert_eq!(result.summary, "no swap configured");
+        assert!(result.perfdata.is_empty());
+
+        let result = check_used(&args, &MemInfo::default());
+        assert_eq!(result.status, Status::Unknown);
+    }
+
+    #[test]
+    fn alerts_on_swap_rate() {
+        let args = Args::from_iter(["arg0", "--warn-rate", "10", "--crit-rate", "100"].iter());
+        let swapped = VmStat {
+            pswpin: 50,
+            pswpout: 25,
+            pgmajfault: 0,
+        };
+        let result = check_rate(&args, &swapped, Duration::from_secs(5));
+        assert_eq!(result.status, Status::Warning);
+        assert_eq!(
+            result.summary,
+            "swapping 15.0 pages/s (10.0 in, 5.0 out) > 10"
+        );
+        assert_eq!(result.perfdata[0].to_string(), "swap_in=10;;;0");
+
+        let result = check_rate(&args, &swapped, Duration::from_millis(500));
+        assert_eq!(result.status, Status::Critical);
+        let result = check_rate(&args, &VmStat::default(), Duration::from_secs(5));
+        assert_eq!(result.status, Status::Ok);
+    }
+
+    #[test]
+    fn describes_swaps() {
+        let lines = describe_swaps(&[Swap {
+            filename: "/dev/sda2".into(),
+            kind: "partition".into(),
+            size: 4 * 1024 * 1024,
+            used: 1024 * 1024,
+            priority: -2,
+        }]);
+        assert_eq!(lines[0], "INFO [check-swap]: 1 swap areas:");
+        assert!(lines[1].starts_with(" 25.0% of "), "{}", lines[1]);
+        assert!(lines[1].ends_with("(partition, priority -2): /dev/sda2"));
+        assert!(describe_swaps(&[]).is_empty());
+    }
+}
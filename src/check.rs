@@ -0,0 +1,270 @@
+//! The result of running a check
+//!
+//! Checks should build up a `CheckResult` and hand it back to `main`, which
+//! is the only place that needs to know how to print it and exit.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::mem;
+use std::thread;
+use std::time::Duration;
+
+use crate::output::Format;
+use crate::perfdata::{self, PerfData};
+use crate::watchdog;
+use crate::Status;
+
+/// Everything a check has to say about the state of the system
+///
+/// The `Display` impl renders the result in the classic nagios format:
+///
+/// ```text
+/// STATUS: summary | perfdata
+/// long output line 1
+/// long output line 2
+/// ```
+///
+/// ```
+/// use tabin_plugins::{CheckResult, PerfData, Status};
+///
+/// let mut result = CheckResult::new(Status::Warning, "load average is 6.0 (> 5.0)");
+/// result.add_line("(divided by 2 cpus)");
+/// result.add_perf(PerfData::new("load1", 6.0).warn(5.0));
+/// assert_eq!(
+///     result.to_string(),
+///     "WARNING: load average is 6.0 (> 5.0) | load1=6;5\n(divided by 2 cpus)\n"
+/// );
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct CheckResult {
+    /// The overall status of the check
+    pub status: Status,
+    /// A single line describing the status
+    pub summary: String,
+    /// Additional details, one entry per line
+    pub long_output: Vec<String>,
+    /// Numbers for graphing
+    pub perfdata: Vec<PerfData>,
+    /// Arbitrary extra information about the check, not shown in nagios output
+    pub metadata: BTreeMap<String, String>,
+    /// How long the check took to run, if it was timed
+    pub duration: Option<Duration>,
+}
+
+impl CheckResult {
+    pub fn new<S: Into<String>>(status: Status, summary: S) -> CheckResult {
+        CheckResult {
+            status,
+            summary: summary.into(),
+            long_output: Vec::new(),
+            perfdata: Vec::new(),
+            metadata: BTreeMap::new(),
+            duration: None,
+        }
+    }
+
+    /// Add a line of long output
+    pub fn add_line<S: Into<String>>(&mut self, line: S) {
+        self.long_output.push(line.into());
+    }
+
+    pub fn add_perf(&mut self, perf: PerfData) {
+        self.perfdata.push(perf);
+    }
+
+    pub fn add_meta<K: Into<String>, V: ToString>(&mut self, key: K, value: V) {
+        self.metadata.insert(key.into(), value.to_string());
+    }
+
+    /// Fold another result into this one
+    ///
+    /// The status becomes the worse of the two statuses, by
+    /// `Status::severity`. Whichever result has the worse status keeps its
+    /// summary, the other summary is added to the long output. Long output,
+    /// perfdata and metadata from `other` are appended.
+    pub fn merge(&mut self, mut other: CheckResult) {
+        if other.status.severity() > self.status.severity() {
+            self.status = other.status;
+            mem::swap(&mut self.summary, &mut other.summary);
+        }
+        self.long_output.push(other.summary);
+        self.long_output.append(&mut other.long_output);
+        self.perfdata.append(&mut other.perfdata);
+        self.metadata.append(&mut other.metadata);
+        self.duration = self.duration.max(other.duration);
+    }
+
+    /// Print the result in nagios format and exit with the appropriate status code
+    pub fn exit(&self) -> ! {
+        self.exit_as(Format::Nagios)
+    }
+
+    /// Print the result in the given format and exit with the appropriate status code
+    ///
+    /// If the watchdog has already timed out it is left to print its result
+    /// instead.
+    pub fn exit_as(&self, format: Format) -> ! {
+        if !watchdog::finish() {
+            loop {
+                thread::park();
+            }
+        }
+        print!("{}", format.render(self));
+        self.status.exit()
+    }
+}
+
+/// Combine the results of checking several things of the same kind, e.g.
+/// every child of a cgroup
+///
+/// Each result is named, and `results` should be sorted from most to least
+/// interesting, e.g. by usage. The combined status is the worst status by
+/// `Status::severity`, the summary names the worst result, and the first
+/// `show` results, worst first, are listed in the long output with their
+/// perfdata prefixed by their name.
+///
+/// ```
+/// use tabin_plugins::check::worst_of;
+/// use tabin_plugins::{CheckResult, Status};
+///
+/// let result = worst_of(
+///     "cgroups",
+///     vec![
+///         ("a".into(), CheckResult::new(Status::Ok, "using 50%")),
+///         ("b".into(), CheckResult::new(Status::Warning, "using 40% (> 30)")),
+///     ],
+///     1,
+/// );
+/// assert_eq!(result.status, Status::Warning);
+/// assert_eq!(result.summary, "2 cgroups, worst is b: using 40% (> 30)");
+/// assert_eq!(result.long_output, ["WARNING b: using 40% (> 30)"]);
+/// ```
+pub fn worst_of(what: &str, mut results: Vec<(String, CheckResult)>, show: usize) -> CheckResult {
+    // stable, so results with the same status keep the caller's order
+    results.sort_by_key(|(_, result)| std::cmp::Reverse(result.status.severity()));
+    let mut combined = match results.first() {
+        Some((name, worst)) => CheckResult::new(
+            worst.status,
+            format!(
+                "{} {}, worst is {}: {}",
+                results.len(),
+                what,
+                name,
+                worst.summary
+            ),
+        ),
+        None => return CheckResult::new(Status::Ok, format!("no {}", what)),
+    };
+    for (name, result) in results.into_iter().take(show) {
+        combined.add_line(format!("{} {}: {}", result.status, name, result.summary));
+        for mut perf in result.perfdata {
+            perf.label = format!("{}_{}", name, perf.label);
+            combined.add_perf(perf);
+        }
+    }
+    combined
+}
+
+impl fmt::Display for CheckResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.status, self.summary)?;
+        if !self.perfdata.is_empty() {
+            write!(f, " | {}", perfdata::render(&self.perfdata))?;
+        }
+        writeln!(f)?;
+        for line in &self.long_output {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use super::*;
+
+    #[test]
+    fn renders_summary_only() {
+        let result = CheckResult::new(Status::Ok, "all good");
+        assert_eq!(result.to_string(), "OK: all good\n");
+    }
+
+    #[test]
+    fn merge_keeps_worst_summary() {
+        let mut result = CheckResult::new(Status::Ok, "cpu0 fine");
+        result.add_perf(PerfData::new("cpu0", 1.0));
+        let mut other = CheckResult::new(Status::Critical, "cpu1 busy");
+        other.add_line("cpu1 details");
+        other.add_perf(PerfData::new("cpu1", 99.0));
+        result.merge(other);
+
+        assert_eq!(result.status, Status::Critical);
+        assert_eq!(result.summary, "cpu1 busy");
+        assert_eq!(result.long_output, ["cpu0 fine", "cpu1 details"]);
+        assert_eq!(result.perfdata.len(), 2);
+
+        result.merge(CheckResult::new(Status::Warning, "cpu2 warm"));
+        assert_eq!(result.status, Status::Critical);
+        assert_eq!(result.summary, "cpu1 busy");
+        assert_eq!(result.long_output.last().unwrap(), "cpu2 warm");
+    }
+
+    #[test]
+    fn merge_keeps_unknown_over_ok() {
+        let mut result = CheckResult::new(Status::Unknown, "unable to read meminfo");
+        result.merge(CheckResult::new(Status::Ok, "commit 10%"));
+        assert_eq!(result.status, Status::Unknown);
+        assert_eq!(result.summary, "unable to read meminfo");
+        assert_eq!(result.long_output, ["commit 10%"]);
+
+        let mut result = CheckResult::new(Status::Ok, "commit 10%");
+        result.merge(CheckResult::new(Status::Unknown, "unable to read meminfo"));
+        assert_eq!(result.status, Status::Unknown);
+        result.merge(CheckResult::new(Status::Critical, "dirty 90%"));
+        assert_eq!(result.status, Status::Critical);
+        result.merge(CheckResult::new(Status::Warning, "slab 20%"));
+        assert_eq!(result.status, Status::Critical);
+    }
+
+    #[test]
+    fn worst_of_prefixes_perfdata() {
+        let mut busy = CheckResult::new(Status::Critical, "busy");
+        busy.add_perf(PerfData::new("cpu", 99.0));
+        let mut idle = CheckResult::new(Status::Ok, "idle");
+        idle.add_perf(PerfData::new("cpu", 1.0));
+        let result = worst_of(
+            "cgroups",
+            vec![("idle".into(), idle), ("busy".into(), busy)],
+            5,
+        );
+        assert_eq!(result.status, Status::Critical);
+        assert_eq!(result.long_output, ["CRITICAL busy: busy", "OK idle: idle"]);
+        let labels: Vec<_> = result.perfdata.iter().map(|p| p.label.as_str()).collect();
+        assert_eq!(labels, ["busy_cpu", "idle_cpu"]);
+
+        assert_eq!(worst_of("cgroups", vec![], 5).summary, "no cgroups");
+    }
+
+    #[test]
+    fn worst_of_ranks_unknown_children_above_ok() {
+        // callers sort unreadable children last, as they have no usage
+        let result = worst_of(
+            "cgroups",
+            vec![
+                ("a".into(), CheckResult::new(Status::Ok, "using 50%")),
+                (
+                    "b".into(),
+                    CheckResult::new(Status::Warning, "using 40% (> 30)"),
+                ),
+                (
+                    "c".into(),
+                    CheckResult::new(Status::Unknown, "unable to read"),
+                ),
+            ],
+            1,
+        );
+        assert_eq!(result.status, Status::Unknown);
+        assert_eq!(result.summary, "3 cgroups, worst is c: unable to read");
+        assert_eq!(result.long_output, ["UNKNOWN c: unable to read"]);
+    }
+}
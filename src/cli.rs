@@ -0,0 +1,453 @@
+//! Command-line arguments shared by every check
+//!
+//! Scripts flatten these into their own `StructOpt` args so that flags like
+//! `--warn`, `--format` and `--verbose` mean the same thing everywhere:
+//!
+//! ```
+//! use structopt::StructOpt;
+//! use tabin_plugins::cli::{CommonArgs, Thresholds};
+//!
+//! #[derive(StructOpt, Debug)]
+//! struct Args {
+//!     #[structopt(flatten)]
+//!     thresholds: Thresholds,
+//!     #[structopt(flatten)]
+//!     common: CommonArgs,
+//! }
+//!
+//! let args = Args::from_iter(&["check-thing", "-w", "10:", "--format", "json"]);
+//! assert!(args.thresholds.warn_or(5.0).is_violated(7.0));
+//! ```
+//!
+//! The structs here must keep to one-paragraph doc comments, otherwise
+//! structopt replaces the description in each script's `--help` with theirs.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use log::LevelFilter;
+use serde::Deserialize;
+use structopt::StructOpt;
+
+use crate::check::CheckResult;
+use crate::context::Context;
+use crate::output::Format;
+use crate::procfs::pid::RamMeasure;
+use crate::sys::fs::cgroup::CGroup;
+use crate::sys::SysFsError;
+use crate::threshold::Threshold;
+use crate::watchdog;
+use crate::Status;
+
+/// The environment variable that configures logging, e.g. `TABIN_LOG=debug`
+pub const LOG_VAR: &str = "TABIN_LOG";
+
+/// Parse `--timeout`, which must be a positive number of seconds that fits
+/// in a `Duration`
+fn parse_timeout(s: &str) -> Result<f64, String> {
+    let seconds = s
+        .parse::<f64>()
+        .map_err(|e| format!("{:?} is not a number: {}", s, e))?;
+    match Duration::try_from_secs_f64(seconds) {
+        Ok(timeout) if timeout > Duration::from_secs(0) => Ok(seconds),
+        _ => Err(format!("{} is not a positive number of seconds", s)),
+    }
+}
+
+/// Flags that every script accepts
+#[derive(StructOpt, Deserialize, Debug, PartialEq)]
+pub struct CommonArgs {
+    #[structopt(
+        short = "v",
+        long = "verbose",
+        parse(from_occurrences),
+        help = "Print more details and debug logs, use twice for trace logs. \
+                Logging can also be configured with TABIN_LOG"
+    )]
+    pub verbose: u8,
+    #[structopt(
+        long = "format",
+        default_value = "nagios",
+        possible_values = &Format::str_values(),
+        help = "How to print the result"
+    )]
+    pub format: Format,
+    #[structopt(
+        long = "timeout",
+        name = "TIMEOUT",
+        value_name = "SECONDS",
+        parse(try_from_str = parse_timeout),
+        help = "Give up if the check takes longer than this"
+    )]
+    pub timeout: Option<f64>,
+    #[structopt(
+        long = "timeout-status",
+        name = "TIMEOUT_STATUS",
+        value_name = "STATUS",
+        default_value = "unknown",
+        possible_values = &Status::str_values(),
+        help = "The status to exit with if --timeout is reached"
+    )]
+    pub timeout_status: Status,
+    #[structopt(
+        long = "status-on-error",
+        name = "ERROR_STATUS",
+        value_name = "STATUS",
+        default_value = "unknown",
+        possible_values = &Status::str_values(),
+        help = "The status to exit with if the check itself fails, e.g. because \
+                something could not be read"
+    )]
+    pub status_on_error: Status,
+}
+
+impl CommonArgs {
+    /// Configure logging and start the `--timeout` watchdog
+    pub fn init(&self) {
+        self.init_logging();
+        if let Some(timeout) = self.timeout() {
+            watchdog::install(timeout, self.timeout_status, self.format);
+        }
+    }
+
+    /// Configure logging from `TABIN_LOG`, or from `-v`/`-vv` if given
+    pub fn init_logging(&self) {
+        let mut builder = env_logger::Builder::from_env(LOG_VAR);
+        match self.verbose {
+            0 => {}
+            1 => {
+                builder.filter_level(LevelFilter::Debug);
+            }
+            _ => {
+                builder.filter_level(LevelFilter::Trace);
+            }
+        }
+        builder.init();
+    }
+
+    /// The `--timeout`, if one was given and is a valid duration
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+    }
+
+    /// A result for when the check itself could not be run
+    pub fn error<S: Into<String>>(&self, summary: S) -> CheckResult {
+        CheckResult::new(self.status_on_error, summary)
+    }
+
+    /// Exit with the `--status-on-error` status, for when the check itself
+    /// could not be run
+    pub fn fail<S: Into<String>>(&self, summary: S, started: Instant) -> ! {
+        self.exit(self.error(summary), started)
+    }
+
+    /// Print `result` in the requested format and exit
+    ///
+    /// `started` is when the check started running, and is used to report
+    /// how long it took.
+    pub fn exit(&self, mut result: CheckResult, started: Instant) -> ! {
+        result.duration = Some(started.elapsed());
+        result.exit_as(self.format)
+    }
+}
+
+impl Default for CommonArgs {
+    fn default() -> CommonArgs {
+        CommonArgs {
+            verbose: 0,
+            format: Format::Nagios,
+            timeout: None,
+            timeout_status: Status::Unknown,
+            status_on_error: Status::Unknown,
+        }
+    }
+}
+
+/// The `--warn` and `--crit` ranges for checks that compare a single value
+#[derive(StructOpt, Deserialize, Debug, PartialEq, Default)]
+pub struct Thresholds {
+    #[structopt(
+        short = "w",
+        long = "warn",
+        name = "WARN_RANGE",
+        help = "Warn if the value is outside this nagios range, e.g. 80 or 10:"
+    )]
+    pub warn: Option<Threshold>,
+    #[structopt(
+        short = "c",
+        long = "crit",
+        name = "CRIT_RANGE",
+        help = "Go critical if the value is outside this nagios range"
+    )]
+    pub crit: Option<Threshold>,
+}
+
+// Defaults differ between scripts, so they are left as `None` and filled in
+// with `warn_or`/`crit_or`.
+impl Thresholds {
+    /// The warning range, or alert above `default` if none was given
+    pub fn warn_or(&self, default: f64) -> Threshold {
+        self.warn.unwrap_or_else(|| Threshold::above(default))
+    }
+
+    /// The critical range, or alert above `default` if none was given
+    pub fn crit_or(&self, default: f64) -> Threshold {
+        self.crit.unwrap_or_else(|| Threshold::above(default))
+    }
+}
+
+/// `--show-hogs`, for checks that can list the processes using the most of
+/// whatever they measure
+#[derive(StructOpt, Deserialize, Debug, PartialEq, Default)]
+pub struct Hogs {
+    #[structopt(
+        long = "show-hogs",
+        name = "COUNT",
+        default_value = "0",
+        help = "Show the COUNT processes using the most of the resource being checked"
+    )]
+    pub show_hogs: usize,
+}
+
+/// `--show-hogs`, `--hogs-by` and `--hogs-per-tree`, for checks of ram usage
+#[derive(StructOpt, Deserialize, Debug, PartialEq)]
+pub struct RamHogs {
+    #[structopt(
+        long = "show-hogs",
+        name = "COUNT",
+        default_value = "0",
+        help = "Show the COUNT processes using the most ram"
+    )]
+    pub show_hogs: usize,
+    #[structopt(
+        long = "hogs-by",
+        name = "MEASURE",
+        default_value = "rss",
+        possible_values = &RamMeasure::str_values(),
+        help = "How to measure the ram of --show-hogs. rss counts shared memory \
+                in full for every process, pss splits it between them, uss \
+                leaves it out. pss and uss need root to see other users' \
+                processes."
+    )]
+    pub hogs_by: RamMeasure,
+    #[structopt(
+        long = "hogs-per-tree",
+        help = "Add up the ram of --show-hogs per process tree, counting each \
+                process towards its highest ancestor that init didn't start, \
+                e.g. a service with all of its workers"
+    )]
+    pub hogs_per_tree: bool,
+}
+
+/// `--proc-root` and `--sys-root`, for checks that read from `/proc` or `/sys`
+#[derive(StructOpt, Deserialize, Debug, PartialEq, Default)]
+pub struct Roots {
+    #[structopt(
+        long = "proc-root",
+        name = "PROC_ROOT",
+        value_name = "DIR",
+        parse(from_os_str),
+        help = "Read the proc filesystem from DIR instead of /proc, e.g. the host's \
+                /proc mounted into a container"
+    )]
+    pub proc_root: Option<PathBuf>,
+    #[structopt(
+        long = "sys-root",
+        name = "SYS_ROOT",
+        value_name = "DIR",
+        parse(from_os_str),
+        help = "Read the sys filesystem from DIR instead of /sys"
+    )]
+    pub sys_root: Option<PathBuf>,
+}
+
+impl Roots {
+    /// The `Context` to load everything from
+    ///
+    /// The flags take precedence over `TABIN_PROC_ROOT` and `TABIN_SYS_ROOT`.
+    pub fn context(&self) -> Context {
+        let mut context = Context::from_env();
+        if let Some(ref root) = self.proc_root {
+            context.proc_root = root.clone();
+        }
+        if let Some(ref root) = self.sys_root {
+            context.sys_root = root.clone();
+        }
+        context
+    }
+}
+
+/// `--cgroup`, `--pid`, `--container-id` and `--children`, for cgroup checks
+#[derive(StructOpt, Deserialize, Debug, PartialEq, Default)]
+pub struct CGroupSelector {
+    #[structopt(
+        long = "cgroup",
+        name = "CGROUP",
+        value_name = "PATH",
+        conflicts_with_all = &["PID", "CONTAINER_ID"],
+        help = "Check the cgroup at PATH in the cgroup hierarchy, as shown in \
+                /proc/<pid>/cgroup, e.g. /system.slice/docker.service"
+    )]
+    pub cgroup: Option<String>,
+    #[structopt(
+        long = "pid",
+        name = "PID",
+        conflicts_with = "CONTAINER_ID",
+        help = "Check the cgroup that PID is in"
+    )]
+    pub pid: Option<i32>,
+    #[structopt(
+        long = "container-id",
+        name = "CONTAINER_ID",
+        value_name = "ID",
+        help = "Check the cgroup of the container with this ID, or a unique prefix of it"
+    )]
+    pub container_id: Option<String>,
+    #[structopt(
+        long = "children",
+        help = "Check every child of the selected cgroup, e.g. every container in a \
+                slice, and report the worst"
+    )]
+    pub children: bool,
+    #[structopt(
+        long = "show-children",
+        name = "CHILD_COUNT",
+        value_name = "COUNT",
+        default_value = "5",
+        help = "With --children, list the COUNT children using the most"
+    )]
+    pub show_children: usize,
+}
+
+impl CGroupSelector {
+    /// Whether a cgroup other than our own was selected
+    pub fn is_selected(&self) -> bool {
+        self.cgroup.is_some() || self.pid.is_some() || self.container_id.is_some()
+    }
+
+    /// Find the selected cgroup, or the one the check is running in if none
+    /// was selected
+    pub fn cgroup(&self, context: &Context) -> Result<CGroup, SysFsError> {
+        if let Some(ref path) = self.cgroup {
+            CGroup::at_path_in(path, context)
+        } else if let Some(pid) = self.pid {
+            CGroup::for_pid_in(pid, context)
+        } else if let Some(ref id) = self.container_id {
+            CGroup::find_container_in(id, context)
+        } else {
+            CGroup::current_in(context)
+        }
+    }
+
+    /// The cgroups to check: the selected cgroup, or its children if
+    /// `--children` was given
+    pub fn targets(&self, context: &Context) -> Result<Vec<CGroup>, SysFsError> {
+        let cgroup = self.cgroup(context)?;
+        if self.children {
+            cgroup.children()
+        } else {
+            Ok(vec![cgroup])
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use super::*;
+
+    #[derive(StructOpt, Debug)]
+    struct Args {
+        #[structopt(flatten)]
+        thresholds: Thresholds,
+        #[structopt(flatten)]
+        hogs: Hogs,
+        #[structopt(flatten)]
+        roots: Roots,
+        #[structopt(flatten)]
+        selector: CGroupSelector,
+        #[structopt(flatten)]
+        common: CommonArgs,
+    }
+
+    #[test]
+    fn defaults() {
+        let args = Args::from_iter(&["check"]);
+        assert_eq!(args.common, CommonArgs::default());
+        assert_eq!(args.hogs.show_hogs, 0);
+        assert_eq!(args.thresholds.warn_or(80.0), Threshold::above(80.0));
+    }
+
+    #[test]
+    fn parses_everything() {
+        let args = Args::from_iter(&[
+            "check",
+            "-vv",
+            "-w",
+            "10:",
+            "-c",
+            "@0:5",
+            "--format=human",
+            "--timeout=2.5",
+            "--timeout-status=critical",
+            "--status-on-error=critical",
+            "--show-hogs=3",
+            "--proc-root=/host/proc",
+        ]);
+        assert_eq!(args.thresholds.warn_or(80.0), Threshold::below(10.0));
+        assert!(args.thresholds.crit_or(90.0).inside);
+        assert_eq!(args.hogs.show_hogs, 3);
+        assert_eq!(args.roots.context().proc_root, PathBuf::from("/host/proc"));
+        assert_eq!(args.common.verbose, 2);
+        assert_eq!(args.common.format, Format::Human);
+        assert_eq!(args.common.timeout(), Some(Duration::from_millis(2500)));
+        assert_eq!(args.common.timeout_status, Status::Critical);
+        assert_eq!(args.common.error("oops").status, Status::Critical);
+    }
+
+    #[test]
+    fn rejects_invalid_timeouts() {
+        for timeout in &["-1", "0", "nan", "inf", "1e30", "soon"] {
+            let flag = format!("--timeout={}", timeout);
+            let err = Args::from_iter_safe(&["check", &flag]).unwrap_err();
+            assert!(err.message.contains("--timeout"), "{}", err.message);
+        }
+        let args = Args::from_iter(&["check", "--timeout=0.5"]);
+        assert_eq!(args.common.timeout(), Some(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn parses_ram_hogs() {
+        #[derive(StructOpt, Debug)]
+        struct RamArgs {
+            #[structopt(flatten)]
+            hogs: RamHogs,
+        }
+        let args = RamArgs::from_iter(&["check"]);
+        assert_eq!(args.hogs.hogs_by, RamMeasure::Rss);
+        let args = RamArgs::from_iter(&["check", "--show-hogs=5", "--hogs-by=pss"]);
+        assert_eq!(
+            (args.hogs.show_hogs, args.hogs.hogs_by),
+            (5, RamMeasure::Pss)
+        );
+        assert!(!args.hogs.hogs_per_tree);
+        assert!(RamArgs::from_iter_safe(&["check", "--hogs-by=vss"]).is_err());
+    }
+
+    #[test]
+    fn selects_cgroups() {
+        let args = Args::from_iter(&["check"]);
+        assert!(!args.selector.is_selected());
+        let args = Args::from_iter(&["check", "--container-id=abc", "--children"]);
+        assert!(args.selector.is_selected());
+        assert!(args.selector.children);
+        assert!(Args::from_iter_safe(&["check", "--pid=1", "--cgroup=/"]).is_err());
+
+        let context = crate::context::fixtures_v2();
+        let args = Args::from_iter(&["check", "--cgroup=/"]);
+        assert_eq!(
+            args.selector.cgroup(&context).unwrap(),
+            CGroup::detect_in(&context)
+        );
+    }
+}
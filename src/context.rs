@@ -0,0 +1,103 @@
+//! Where to find the pseudo-filesystems that checks read from
+//!
+//! Everything in `procfs` and `sys` reads from `/proc` and `/sys` by default.
+//! A `Context` points them somewhere else instead, e.g. at the host's `/proc`
+//! bind-mounted into a container, or at a fixture tree in tests:
+//!
+//! ```
+//! use tabin_plugins::Context;
+//!
+//! let host = Context::default().with_proc_root("/host/proc");
+//! assert_eq!(host.proc_path("loadavg").to_str(), Some("/host/proc/loadavg"));
+//! assert_eq!(host.sys_path("fs/cgroup").to_str(), Some("/sys/fs/cgroup"));
+//! ```
+//!
+//! Functions that don't take a `Context` use `Context::from_env`, so the
+//! `TABIN_PROC_ROOT` and `TABIN_SYS_ROOT` environment variables apply to them.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// The environment variable that overrides where `/proc` is
+pub const PROC_ROOT_VAR: &str = "TABIN_PROC_ROOT";
+/// The environment variable that overrides where `/sys` is
+pub const SYS_ROOT_VAR: &str = "TABIN_SYS_ROOT";
+
+/// The roots of the proc and sys filesystems
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Context {
+    /// Where `/proc` is mounted
+    pub proc_root: PathBuf,
+    /// Where `/sys` is mounted
+    pub sys_root: PathBuf,
+}
+
+impl Context {
+    /// A context reading from `proc_root` and `sys_root`
+    pub fn new<P: Into<PathBuf>, S: Into<PathBuf>>(proc_root: P, sys_root: S) -> Context {
+        Context {
+            proc_root: proc_root.into(),
+            sys_root: sys_root.into(),
+        }
+    }
+
+    /// `/proc` and `/sys`, unless overridden by `TABIN_PROC_ROOT` or
+    /// `TABIN_SYS_ROOT`
+    pub fn from_env() -> Context {
+        let mut context = Context::default();
+        if let Some(root) = env::var_os(PROC_ROOT_VAR).filter(|root| !root.is_empty()) {
+            context.proc_root = root.into();
+        }
+        if let Some(root) = env::var_os(SYS_ROOT_VAR).filter(|root| !root.is_empty()) {
+            context.sys_root = root.into();
+        }
+        context
+    }
+
+    /// This context, but reading `/proc` from `proc_root`
+    pub fn with_proc_root<P: Into<PathBuf>>(self, proc_root: P) -> Context {
+        Context {
+            proc_root: proc_root.into(),
+            ..self
+        }
+    }
+
+    /// This context, but reading `/sys` from `sys_root`
+    pub fn with_sys_root<S: Into<PathBuf>>(self, sys_root: S) -> Context {
+        Context {
+            sys_root: sys_root.into(),
+            ..self
+        }
+    }
+
+    /// The path to `/proc/<relative>`
+    pub fn proc_path<P: AsRef<Path>>(&self, relative: P) -> PathBuf {
+        self.proc_root.join(relative)
+    }
+
+    /// The path to `/sys/<relative>`
+    pub fn sys_path<P: AsRef<Path>>(&self, relative: P) -> PathBuf {
+        self.sys_root.join(relative)
+    }
+}
+
+impl Default for Context {
+    /// The real `/proc` and `/sys`, ignoring the environment
+    fn default() -> Context {
+        Context::new("/proc", "/sys")
+    }
+}
+
+/// The context that tests in this crate read from, in `tests/fixtures`
+#[cfg(test)]
+pub(crate) fn fixtures() -> Context {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    Context::new(root.join("proc"), root.join("sys"))
+}
+
+/// Like `fixtures`, but with a cgroup v2 hierarchy in `/sys`
+#[cfg(test)]
+pub(crate) fn fixtures_v2() -> Context {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    Context::new(root.join("proc"), root.join("sys-v2"))
+}
@@ -1,8 +1,16 @@
 //! Utitilty for writing nagios-style check scripts/plugins
 //!
-//! There are three things:
+//! There are a few things:
 //!
 //! * The `Status` enum for representing health status
+//! * The `CheckResult` struct, which is what every check produces
+//! * The `PerfData` struct for reporting nagios performance data
+//! * The `Threshold` struct, which parses and checks nagios ranges like `10:20`
+//! * The `cli` module, with command-line flags that every check shares
+//! * The `watchdog` module, which gives up on checks that hang
+//! * The `output::Format` enum, for rendering a `CheckResult` as nagios text,
+//!   json, a sensu check result, or a table for humans
+//! * The `Context` struct, which says where to find `/proc` and `/sys`
 //! * The `procfs` module, which contains rusty representations of some files
 //!   from /proc
 //! * A few scripts in the bin directory, which contain actual
@@ -10,9 +18,6 @@
 //!
 //! TODOs include
 //!
-//! * Nice logging, including some standard formats -- json and human would be
-//!   nice
-//! * Some way of easily standardizing command-line args
 //! * Much of the code is hideous, and should not be
 
 #![deny(unused_imports, dead_code, missing_debug_implementations)]
@@ -23,10 +28,22 @@ use std::str::FromStr;
 
 use serde::Deserialize;
 
+pub mod check;
+pub mod cli;
+pub mod context;
 pub mod linux;
+pub mod output;
+pub mod perfdata;
 pub mod procfs;
 pub mod scripts;
 pub mod sys;
+pub mod threshold;
+pub mod watchdog;
+
+pub use crate::check::CheckResult;
+pub use crate::context::Context;
+pub use crate::perfdata::PerfData;
+pub use crate::threshold::Threshold;
 
 /// All errors are TabinErrors
 #[derive(Debug)]
@@ -37,8 +54,8 @@ pub enum TabinError {
 
 impl fmt::Display for TabinError {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        match self {
-            &TabinError::UnknownValue(ref msg) => write!(f, "Unknown Value: {}", msg),
+        match *self {
+            TabinError::UnknownValue(ref msg) => write!(f, "Unknown Value: {}", msg),
         }
     }
 }
@@ -63,12 +80,32 @@ pub enum Status {
 impl Status {
     /// Exit with a return code that indicates the state of the system
     pub fn exit(&self) -> ! {
+        process::exit(self.code())
+    }
+
+    /// The exit code that nagios expects for this status
+    pub fn code(&self) -> i32 {
+        use self::Status::*;
+        match *self {
+            Ok => 0,
+            Warning => 1,
+            Critical => 2,
+            Unknown => 3,
+        }
+    }
+
+    /// How bad the status is, for picking the worst of several
+    ///
+    /// The derived `Ord` puts `Unknown` first, but a check that couldn't
+    /// measure something is worse than one that found it fine, so this
+    /// ranks `Ok < Warning < Unknown < Critical`.
+    pub fn severity(&self) -> u8 {
         use self::Status::*;
         match *self {
-            Ok => process::exit(0),
-            Warning => process::exit(1),
-            Critical => process::exit(2),
-            Unknown => process::exit(3),
+            Ok => 0,
+            Warning => 1,
+            Unknown => 2,
+            Critical => 3,
         }
     }
 
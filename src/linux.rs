@@ -30,7 +30,7 @@ pub fn bytes_to_human_size(bytes: u64) -> String {
     let mut reductions = 0;
     while reductions < sizes.len() - 1 {
         if bytes > 1000.0 {
-            bytes = bytes / 1024.0;
+            bytes /= 1024.0;
             reductions += 1;
         } else {
             break;
@@ -67,6 +67,11 @@ impl UserHz {
         UserHz(val)
     }
 
+    /// The raw number of `USER_HZ` ticks
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+
     pub fn duration(&self) -> Duration {
         Duration::from_millis(self.0 * 1000)
     }
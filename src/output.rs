@@ -0,0 +1,275 @@
+//! Rendering `CheckResult`s for different consumers
+//!
+//! The same check can be run under nagios, under sensu, by a script that
+//! wants to parse the result, or by a person at a terminal. Every script
+//! accepts a `--format` option that picks one of the `Format`s here.
+
+use std::env;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::check::CheckResult;
+use crate::perfdata::PerfData;
+use crate::threshold::Threshold;
+use crate::{Status, TabinError, TabinResult};
+
+/// How a `CheckResult` should be printed
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Deserialize)]
+pub enum Format {
+    /// `STATUS: summary | perfdata` followed by long output
+    #[default]
+    Nagios,
+    /// A JSON document with the status, message, metrics and timing
+    Json,
+    /// A sensu check result, suitable for the sensu client socket
+    Sensu,
+    /// A colorized summary and table of metrics, for interactive use
+    Human,
+}
+
+impl Format {
+    /// The legal values for `from_str`
+    pub fn str_values() -> [&'static str; 4] {
+        ["nagios", "json", "sensu", "human"]
+    }
+
+    /// Render `result` in this format
+    pub fn render(self, result: &CheckResult) -> String {
+        match self {
+            Format::Nagios => result.to_string(),
+            Format::Json => format!("{}\n", json(result)),
+            Format::Sensu => format!("{}\n", sensu(result)),
+            Format::Human => human(result, stdout_is_tty()),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = TabinError;
+
+    fn from_str(s: &str) -> TabinResult<Format> {
+        match s {
+            "nagios" => Ok(Format::Nagios),
+            "json" => Ok(Format::Json),
+            "sensu" => Ok(Format::Sensu),
+            "human" => Ok(Format::Human),
+            _ => Err(TabinError::UnknownValue(format!(
+                "Unexpected output format: {}",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Format::Nagios => "nagios",
+            Format::Json => "json",
+            Format::Sensu => "sensu",
+            Format::Human => "human",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The name of the running script, e.g. `check-load`
+fn check_name() -> String {
+    env::args()
+        .next()
+        .as_ref()
+        .and_then(|arg0| Path::new(arg0).file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "tabin-plugins".to_owned())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn metric(perf: &PerfData) -> Value {
+    json!({
+        "label": perf.label,
+        "value": perf.value,
+        "unit": perf.unit.to_string(),
+        "warn": perf.warn.map(|t| t.to_string()),
+        "crit": perf.crit.map(|t| t.to_string()),
+        "min": perf.min,
+        "max": perf.max,
+    })
+}
+
+fn json(result: &CheckResult) -> Value {
+    json!({
+        "name": check_name(),
+        "status": result.status.to_string(),
+        "exit_code": result.status.code(),
+        "message": result.summary,
+        "details": result.long_output,
+        "metrics": result.perfdata.iter().map(metric).collect::<Vec<_>>(),
+        "metadata": result.metadata,
+        "timing": {
+            "executed": now(),
+            "duration": result.duration.map(|d| d.as_secs_f64()),
+        },
+    })
+}
+
+/// See https://docs.sensu.io/sensu-core/latest/reference/checks/#check-result-specification
+fn sensu(result: &CheckResult) -> Value {
+    let mut check = json!({
+        "name": check_name(),
+        "status": result.status.code(),
+        "output": result.to_string(),
+        "executed": now(),
+    });
+    if let Some(duration) = result.duration {
+        check["duration"] = json!(duration.as_secs_f64());
+    }
+    check
+}
+
+fn stdout_is_tty() -> bool {
+    unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 }
+}
+
+fn colorize(status: Status, color: bool) -> String {
+    if !color {
+        return status.to_string();
+    }
+    let code = match status {
+        Status::Ok => "32",
+        Status::Warning => "33",
+        Status::Critical => "31",
+        Status::Unknown => "35",
+    };
+    format!("\x1b[1;{}m{}\x1b[0m", code, status)
+}
+
+fn optional(val: Option<f64>) -> String {
+    val.map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn range(threshold: Option<Threshold>) -> String {
+    threshold.map(|t| t.to_string()).unwrap_or_default()
+}
+
+fn human(result: &CheckResult, color: bool) -> String {
+    let mut out = format!(
+        "{} {}: {}\n",
+        check_name(),
+        colorize(result.status, color),
+        result.summary
+    );
+    for line in &result.long_output {
+        out.push_str(&format!("    {}\n", line));
+    }
+    if !result.perfdata.is_empty() {
+        let rows = result
+            .perfdata
+            .iter()
+            .map(|perf| {
+                [
+                    perf.label.clone(),
+                    format!("{}{}", perf.value, perf.unit),
+                    range(perf.warn),
+                    range(perf.crit),
+                    optional(perf.min),
+                    optional(perf.max),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let header = ["Metric", "Value", "Warn", "Crit", "Min", "Max"];
+        let mut widths = header.map(str::len);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        out.push('\n');
+        let header = header.map(str::to_owned);
+        for row in Some(&header).into_iter().chain(&rows) {
+            let cells = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect::<Vec<_>>();
+            out.push_str(cells.join("  ").trim_end());
+            out.push('\n');
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod unit {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn result() -> CheckResult {
+        let mut result = CheckResult::new(Status::Warning, "load is high");
+        result.add_line("more info");
+        result.add_perf(PerfData::new("load1", 6.0).warn(5.0));
+        result
+    }
+
+    #[test]
+    fn parses_formats() {
+        for name in &Format::str_values() {
+            let format: Format = name.parse().unwrap();
+            assert_eq!(format.to_string(), *name);
+        }
+        assert!("xml".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn renders_json() {
+        let mut result = result();
+        result.duration = Some(Duration::from_millis(1500));
+        let rendered: Value = serde_json::from_str(&Format::Json.render(&result)).unwrap();
+        assert_eq!(rendered["status"], "WARNING");
+        assert_eq!(rendered["exit_code"], 1);
+        assert_eq!(rendered["message"], "load is high");
+        assert_eq!(rendered["details"], json!(["more info"]));
+        assert_eq!(rendered["metrics"][0]["label"], "load1");
+        assert_eq!(rendered["metrics"][0]["warn"], "5");
+        assert_eq!(rendered["metrics"][0]["crit"], Value::Null);
+        assert_eq!(rendered["timing"]["duration"], 1.5);
+    }
+
+    #[test]
+    fn renders_sensu() {
+        let rendered: Value = serde_json::from_str(&Format::Sensu.render(&result())).unwrap();
+        assert_eq!(rendered["status"], 1);
+        assert_eq!(
+            rendered["output"],
+            "WARNING: load is high | load1=6;5\nmore info\n"
+        );
+        assert!(rendered.get("duration").is_none());
+    }
+
+    #[test]
+    fn renders_human_table() {
+        let rendered = human(&result(), false);
+        let lines = rendered.lines().skip(1).collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "    more info",
+                "",
+                "Metric  Value  Warn  Crit  Min  Max",
+                "load1   6      5",
+            ]
+        );
+        assert!(human(&result(), true).contains("\x1b[1;33mWARNING\x1b[0m"));
+    }
+}
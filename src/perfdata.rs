@@ -0,0 +1,253 @@
+//! Nagios-style performance data
+//!
+//! Performance data is the `| label=value;warn;crit;min;max` suffix that
+//! nagios-compatible monitoring systems parse out of check output to draw
+//! graphs. See the [nagios plugin guidelines][] for the full format.
+//!
+//! Perfdata is normally attached to a `CheckResult`, which takes care of
+//! rendering it.
+//!
+//! [nagios plugin guidelines]: https://nagios-plugins.org/doc/guidelines.html#AEN200
+
+use std::fmt;
+
+use crate::threshold::Threshold;
+
+/// The unit of measurement of a `PerfData` value
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Unit {
+    /// A plain number, e.g. a count of processes or a load average
+    None,
+    Seconds,
+    Milliseconds,
+    Microseconds,
+    Percent,
+    Bytes,
+    KiloBytes,
+    MegaBytes,
+    GigaBytes,
+    TeraBytes,
+    /// A continuously increasing counter, e.g. bytes transmitted
+    Counter,
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Unit::*;
+        let uom = match *self {
+            None => "",
+            Seconds => "s",
+            Milliseconds => "ms",
+            Microseconds => "us",
+            Percent => "%",
+            Bytes => "B",
+            KiloBytes => "KB",
+            MegaBytes => "MB",
+            GigaBytes => "GB",
+            TeraBytes => "TB",
+            Counter => "c",
+        };
+        f.write_str(uom)
+    }
+}
+
+/// A single performance data point
+///
+/// ```
+/// use tabin_plugins::perfdata::{PerfData, Unit};
+///
+/// let perf = PerfData::new("ram used", 45.25)
+///     .unit(Unit::Percent)
+///     .warn(85.0)
+///     .crit(95.0)
+///     .min(0.0)
+///     .max(100.0);
+/// assert_eq!(perf.to_string(), "'ram used'=45.25%;85;95;0;100");
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct PerfData {
+    pub label: String,
+    pub value: f64,
+    pub unit: Unit,
+    pub warn: Option<Threshold>,
+    pub crit: Option<Threshold>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl PerfData {
+    /// Create a new unitless data point without any thresholds
+    pub fn new<L: Into<String>>(label: L, value: f64) -> PerfData {
+        PerfData {
+            label: label.into(),
+            value,
+            unit: Unit::None,
+            warn: None,
+            crit: None,
+            min: None,
+            max: None,
+        }
+    }
+
+    pub fn unit(mut self, unit: Unit) -> PerfData {
+        self.unit = unit;
+        self
+    }
+
+    /// Set the warning range, a plain number means "warn above this"
+    pub fn warn<T: Into<Threshold>>(mut self, warn: T) -> PerfData {
+        self.warn = Some(warn.into());
+        self
+    }
+
+    /// Set the critical range, a plain number means "critical above this"
+    pub fn crit<T: Into<Threshold>>(mut self, crit: T) -> PerfData {
+        self.crit = Some(crit.into());
+        self
+    }
+
+    pub fn min(mut self, min: f64) -> PerfData {
+        self.min = Some(min);
+        self
+    }
+
+    pub fn max(mut self, max: f64) -> PerfData {
+        self.max = Some(max);
+        self
+    }
+
+    /// The label as it should appear in nagios output
+    ///
+    /// `=` and `|` are not allowed anywhere in a label so they are replaced
+    /// with underscores. Labels containing spaces or single quotes are
+    /// wrapped in single quotes, with any single quotes inside doubled.
+    fn quoted_label(&self) -> String {
+        let label: String = self
+            .label
+            .chars()
+            .map(|c| match c {
+                '=' | '|' => '_',
+                '\n' | '\r' => ' ',
+                c => c,
+            })
+            .collect();
+        if label.contains(|c: char| c.is_whitespace() || c == '\'') {
+            format!("'{}'", label.replace('\'', "''"))
+        } else {
+            label
+        }
+    }
+}
+
+/// Format a number without trailing zeros
+fn number(val: f64) -> String {
+    let s = format!("{:.3}", val);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" {
+        "0".to_owned()
+    } else {
+        s.to_owned()
+    }
+}
+
+fn optional(val: Option<f64>) -> String {
+    val.map(number).unwrap_or_default()
+}
+
+impl fmt::Display for PerfData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let range =
+            |threshold: Option<Threshold>| threshold.map(|t| t.to_string()).unwrap_or_default();
+        let fields = [
+            range(self.warn),
+            range(self.crit),
+            optional(self.min),
+            optional(self.max),
+        ];
+        // Trailing unfilled fields may be dropped
+        let used = fields
+            .iter()
+            .rposition(|field| !field.is_empty())
+            .map_or(0, |i| i + 1);
+        write!(
+            f,
+            "{}={}{}",
+            self.quoted_label(),
+            number(self.value),
+            self.unit
+        )?;
+        for field in &fields[..used] {
+            write!(f, ";{}", field)?;
+        }
+        Ok(())
+    }
+}
+
+/// Render a collection of data points as a single space-separated string
+pub fn render(perfdata: &[PerfData]) -> String {
+    perfdata
+        .iter()
+        .map(|perf| perf.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod unit {
+    use super::*;
+
+    #[test]
+    fn renders_value_only() {
+        assert_eq!(PerfData::new("procs", 5.0).to_string(), "procs=5");
+    }
+
+    #[test]
+    fn drops_trailing_empty_fields_only() {
+        let perf = PerfData::new("load1", 0.5).crit(10.0);
+        assert_eq!(perf.to_string(), "load1=0.5;;10");
+
+        let perf = PerfData::new("load1", 0.5).max(4.0);
+        assert_eq!(perf.to_string(), "load1=0.5;;;;4");
+    }
+
+    #[test]
+    fn rounds_values() {
+        let perf = PerfData::new("cpu", 0.1 + 0.2).unit(Unit::Percent);
+        assert_eq!(perf.to_string(), "cpu=0.3%");
+        let perf = PerfData::new("cpu", 12.34567).unit(Unit::Percent);
+        assert_eq!(perf.to_string(), "cpu=12.346%");
+        let perf = PerfData::new("cpu", -0.0001);
+        assert_eq!(perf.to_string(), "cpu=0");
+    }
+
+    #[test]
+    fn quotes_labels() {
+        assert_eq!(PerfData::new("/var/lib", 1.0).to_string(), "/var/lib=1");
+        assert_eq!(PerfData::new("my disk", 1.0).to_string(), "'my disk'=1");
+        assert_eq!(PerfData::new("it's", 1.0).to_string(), "'it''s'=1");
+        assert_eq!(PerfData::new("a=b|c", 1.0).to_string(), "a_b_c=1");
+    }
+
+    #[test]
+    fn renders_ranges() {
+        let perf = PerfData::new("free", 5.0)
+            .warn("10:".parse::<Threshold>().unwrap())
+            .crit("@0:5".parse::<Threshold>().unwrap());
+        assert_eq!(perf.to_string(), "free=5;10:;@5");
+    }
+
+    #[test]
+    fn renders_units() {
+        let perf = PerfData::new("rss", 1024.0).unit(Unit::Bytes).min(0.0);
+        assert_eq!(perf.to_string(), "rss=1024B;;;0");
+        let perf = PerfData::new("packets", 7.0).unit(Unit::Counter);
+        assert_eq!(perf.to_string(), "packets=7c");
+    }
+
+    #[test]
+    fn renders_many() {
+        let perf = [PerfData::new("a", 1.0), PerfData::new("b", 2.0)];
+        assert_eq!(render(&perf), "a=1 b=2");
+        assert_eq!(render(&[]), "");
+    }
+}
@@ -3,25 +3,38 @@
 //! Each file gets a struct to represent its data, with an associated `load`
 //! function.
 
-use std::collections::{hash_map, HashMap};
+use std::collections::{hash_map, BTreeMap, HashMap};
 use std::fmt;
 use std::fs::{self, File};
 use std::io::{self, Read};
 use std::num;
 use std::ops::{Div, Sub};
+use std::path::{Path, PathBuf};
 use std::result::Result as StdResult;
 use std::slice;
 use std::str::{FromStr, Split};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use derive_more::From;
 use nix::unistd::Pid;
 use regex::Regex;
 use serde::Deserialize;
 
+use crate::context::Context;
 use crate::linux::Jiffies;
 use crate::procfs::pid::ProcessCpuUsage;
+use crate::sys::kernel::mm::HugePagePool;
 
 pub mod pid;
+mod pressure;
+mod swaps;
+mod tree;
+mod vmstat;
+
+pub use self::pressure::{Pressure, PressureResource, Stalls};
+pub use self::swaps::Swap;
+pub use self::tree::{Ancestors, Descendants, ProcessTree, SubtreeTotals};
+pub use self::vmstat::VmStat;
 
 /// ProcFs errors
 ///
@@ -42,23 +55,134 @@ pub enum ProcFsError {
     ParseStatError(ParseStatError),
     /// Happens when we get an invalid process state
     ParseStateError(ParseStateError),
+    /// A specific file could not be read
+    ReadFile(ReadFileError),
+    /// A field in a file was missing or could not be parsed
+    InvalidField(FieldError),
 }
 
 impl fmt::Display for ProcFsError {
     fn fmt(&self, f: &mut fmt::Formatter) -> StdResult<(), fmt::Error> {
         use self::ProcFsError::*;
-        match self {
-            &Io(ref e) => write!(f, "{}", e),
-            &InsufficientData(ref e) => write!(f, "{}", e),
-            &InvalidFloat(ref e) => write!(f, "{}", e),
-            &InvalidInt(ref e) => write!(f, "{}", e),
-            &LoadProcsError(ref e) => write!(f, "{}", e),
-            &ParseStatError(ref e) => write!(f, "{}", e),
-            &ParseStateError(ref e) => write!(f, "{}", e),
+        match *self {
+            Io(ref e) => write!(f, "{}", e),
+            InsufficientData(ref e) => write!(f, "{}", e),
+            InvalidFloat(ref e) => write!(f, "{}", e),
+            InvalidInt(ref e) => write!(f, "{}", e),
+            LoadProcsError(ref e) => write!(f, "{}", e),
+            ParseStatError(ref e) => write!(f, "{}", e),
+            ParseStateError(ref e) => write!(f, "{}", e),
+            ReadFile(ref e) => write!(f, "{}", e),
+            InvalidField(ref e) => write!(f, "{}", e),
         }
     }
 }
 
+/// An error reading a file in procfs or sysfs
+#[derive(Debug)]
+pub struct ReadFileError {
+    /// The file we tried to read
+    pub path: PathBuf,
+    /// Why we couldn't
+    pub error: io::Error,
+}
+
+impl fmt::Display for ReadFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> StdResult<(), fmt::Error> {
+        write!(f, "unable to read {}: {}", self.path.display(), self.error)
+    }
+}
+
+/// A field in a procfs or sysfs file that was missing or could not be parsed
+#[derive(Debug, PartialEq, Eq)]
+pub struct FieldError {
+    /// The file the field should be in
+    pub path: PathBuf,
+    /// The line the field is on, starting from 1, if we got that far
+    pub line: Option<usize>,
+    /// The name of the field
+    pub field: String,
+    /// What was wrong with it
+    pub reason: String,
+}
+
+impl FieldError {
+    pub(crate) fn new<F: Into<String>, R: fmt::Display>(
+        path: &Path,
+        line: Option<usize>,
+        field: F,
+        reason: R,
+    ) -> FieldError {
+        FieldError {
+            path: path.to_owned(),
+            line,
+            field: field.into(),
+            reason: reason.to_string(),
+        }
+    }
+
+    /// A field that we needed but was not in the file at all
+    pub(crate) fn missing<F: Into<String>>(path: &Path, field: F) -> FieldError {
+        FieldError::new(path, None, field, "missing")
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> StdResult<(), fmt::Error> {
+        write!(f, "{}", self.path.display())?;
+        if let Some(line) = self.line {
+            write!(f, " line {}", line)?;
+        }
+        write!(f, ": invalid {}: {}", self.field, self.reason)
+    }
+}
+
+/// Read a whole file, remembering its path if that fails
+pub(crate) fn read_file(path: &Path) -> StdResult<String, ReadFileError> {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut fh| fh.read_to_string(&mut contents))
+        .map_err(|error| ReadFileError {
+            path: path.to_owned(),
+            error,
+        })?;
+    Ok(contents)
+}
+
+/// Split a file made of `Name: value` lines, like `/proc/<pid>/status`, into
+/// the trimmed values and the lines they are on
+pub(crate) fn parse_colon_fields(contents: &str) -> HashMap<&str, (usize, &str)> {
+    contents
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let colon = line.find(':')?;
+            Some((&line[..colon], (i + 1, line[colon + 1..].trim())))
+        })
+        .collect()
+}
+
+/// Parse a size like `1234 kB` from `parse_colon_fields`, returning KB
+pub(crate) fn parse_kb(
+    fields: &HashMap<&str, (usize, &str)>,
+    path: &Path,
+    name: &str,
+) -> StdResult<Option<usize>, FieldError> {
+    let (line, value) = match fields.get(name) {
+        Some(&field) => field,
+        None => return Ok(None),
+    };
+    let number = value.strip_suffix("kB").unwrap_or(value).trim();
+    number.parse().map(Some).map_err(|e| {
+        FieldError::new(
+            path,
+            Some(line),
+            name,
+            format!("{:?} is not a number: {}", value, e),
+        )
+    })
+}
+
 #[derive(Debug)]
 pub struct LoadProcsError {
     /// The data we successfully loaded
@@ -69,9 +193,9 @@ pub struct LoadProcsError {
 
 impl fmt::Display for LoadProcsError {
     fn fmt(&self, f: &mut fmt::Formatter) -> StdResult<(), fmt::Error> {
-        write!(
+        writeln!(
             f,
-            "loaded {} processes correctly, but got {} errors:\n",
+            "loaded {} processes correctly, but got {} errors:",
             self.procs.len(),
             self.errors.len()
         )?;
@@ -147,6 +271,11 @@ impl<'a> ProcessCpuUsages<'a> {
         self.0.len()
     }
 
+    /// If we didn't load any processes
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     /// Get an iterator over the underlying Vec.
     pub fn iter(&'a self) -> slice::Iter<'a, ProcessCpuUsage<'a>> {
         self.0.iter()
@@ -163,10 +292,25 @@ pub struct RunningProcs(pub ProcMap);
 impl RunningProcs {
     /// Load the currently running processes from /proc/[pid]/*
     pub fn currently_running() -> Result<RunningProcs> {
+        RunningProcs::currently_running_in(&Context::from_env())
+    }
+
+    /// Load the running processes from `<proc_root>/[pid]/*`
+    pub fn currently_running_in(context: &Context) -> Result<RunningProcs> {
+        RunningProcs::load_in(context, false)
+    }
+
+    /// Load the running processes with their status files, see
+    /// `Process::from_pid_with_status_in`
+    pub fn currently_running_with_status_in(context: &Context) -> Result<RunningProcs> {
+        RunningProcs::load_in(context, true)
+    }
+
+    fn load_in(context: &Context, with_status: bool) -> Result<RunningProcs> {
         let mut procs = ProcMap::new();
         let mut errors = vec![];
         let is_digit = Regex::new(r"^[0-9]+$").unwrap();
-        for entry in fs::read_dir("/proc")? {
+        for entry in fs::read_dir(&context.proc_root)? {
             if entry.is_err() {
                 continue;
             }
@@ -179,7 +323,12 @@ impl RunningProcs {
                             // we only care about the pid files
                             return;
                         }
-                        match pid::Process::from_pid(fname) {
+                        let loaded = if with_status {
+                            pid::Process::from_pid_with_status_in(fname, context)
+                        } else {
+                            pid::Process::from_pid_in(fname, context)
+                        };
+                        match loaded {
                             Ok(prc) => {
                                 procs.insert(prc.stat.pid, prc);
                             }
@@ -204,7 +353,12 @@ impl RunningProcs {
         RunningProcs(HashMap::new())
     }
 
-    fn iter(&self) -> hash_map::Iter<Pid, pid::Process> {
+    /// The parent and child relationships between the processes
+    pub fn tree(&self) -> ProcessTree<'_> {
+        ProcessTree::new(self.0.values())
+    }
+
+    fn iter(&self) -> hash_map::Iter<'_, Pid, pid::Process> {
         self.0.iter()
     }
 
@@ -213,6 +367,9 @@ impl RunningProcs {
     ///
     /// The value for `total_cpu` should probably be the result of subtracting
     /// two `Calculations::total()`s from each other.
+    ///
+    /// Processes that exited in between, including ones whose pid was reused
+    /// by a new process, are left out.
     pub fn percent_cpu_util_since<'a>(
         &'a self,
         start: &'a RunningProcs,
@@ -221,7 +378,10 @@ impl RunningProcs {
         let me = &self.0;
         let mut usages = Vec::new();
         for (_start_pid, start_process) in start.iter() {
-            if let Some(end_process) = me.get(&start_process.stat.pid) {
+            let end_process = me
+                .get(&start_process.stat.pid)
+                .filter(|end| end.stat.starttime == start_process.stat.starttime);
+            if let Some(end_process) = end_process {
                 usages.push(end_process.cpu_utilization_since(start_process, total_cpu));
             }
         }
@@ -231,6 +391,10 @@ impl RunningProcs {
     pub fn len(&self) -> usize {
         self.0.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
 }
 
 // ////////////////////////////////////////////////////////////////////////////
@@ -340,17 +504,19 @@ pub struct Calculations {
 
 impl Calculations {
     /// Read /proc/stat and return its contents as a string
-    fn read_procstat() -> Result<String> {
-        let mut fh = File::open("/proc/stat")?;
-        let mut contents = String::new();
-        fh.read_to_string(&mut contents)?;
-        Ok(contents)
+    fn read_procstat(context: &Context) -> Result<String> {
+        Ok(read_file(&context.proc_path("stat"))?)
     }
 
     /// Build a new `Calculations` for *total* CPU jiffies from the /proc/stat
     /// pseudofile. See `load_per_cpu` for per-cpu metrics.
     pub fn load() -> Result<Calculations> {
-        let contents = Self::read_procstat()?;
+        Self::load_in(&Context::from_env())
+    }
+
+    /// Like `load`, but reading `<proc_root>/stat`
+    pub fn load_in(context: &Context) -> Result<Calculations> {
+        let contents = Self::read_procstat(context)?;
         Self::from_str(&contents)
     }
 
@@ -358,7 +524,12 @@ impl Calculations {
     ///
     /// This does not include the `total` line, use `load()` for that.
     pub fn load_per_cpu() -> Result<Vec<Calculations>> {
-        let contents = Self::read_procstat().unwrap();
+        Self::load_per_cpu_in(&Context::from_env())
+    }
+
+    /// Like `load_per_cpu`, but reading `<proc_root>/stat`
+    pub fn load_per_cpu_in(context: &Context) -> Result<Vec<Calculations>> {
+        let contents = Self::read_procstat(context)?;
         Calculations::per_cpu(&contents)
     }
 
@@ -374,14 +545,25 @@ impl Calculations {
 
     /// Convert a single line from /proc/stat
     fn from_line(line: &str) -> Result<Calculations> {
-        assert!(line.starts_with("cpu"));
+        if !line.starts_with("cpu") {
+            return Err(ProcFsError::InsufficientData(format!(
+                "expected a cpu line from /proc/stat, got {:?}",
+                line
+            )));
+        }
 
         let usages = line
             .split(' ')
             .skip(1)
-            .filter(|part| part.len() > 0)
+            .filter(|part| !part.is_empty())
             .map(|part| part.parse())
             .collect::<StdResult<Vec<u64>, _>>()?;
+        if usages.len() < 9 {
+            return Err(ProcFsError::InsufficientData(format!(
+                "expected at least 9 fields in cpu line from /proc/stat, got {:?}",
+                line
+            )));
+        }
         Ok(Calculations {
             user: Jiffies::new(usages[0]),
             nice: Jiffies::new(usages[1]),
@@ -400,7 +582,7 @@ impl Calculations {
     ///
     /// This includes all processes in user space, kernel space, and time
     /// stolen by other VMs.
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     pub fn active(&self) -> Jiffies {
         self.user + self.nice + // user processes
             self.system + self.irq + self.softirq + // kernel and interrupts
@@ -461,12 +643,12 @@ impl FromStr for Calculations {
     /// Parse the entire /proc/stat file into a single `Calculations` object
     /// for total CPU
     fn from_str(contents: &str) -> Result<Calculations> {
-        let mut calcs = contents
-            .lines()
-            .take(1)
-            .map(Self::from_line)
-            .collect::<StdResult<Vec<_>, _>>()?;
-        Ok(calcs.remove(0))
+        match contents.lines().next() {
+            Some(line) => Self::from_line(line),
+            None => Err(ProcFsError::InsufficientData(
+                "/proc/stat is empty".to_owned(),
+            )),
+        }
     }
 }
 
@@ -491,7 +673,7 @@ impl Sub for Calculations {
     }
 }
 
-impl<'a> Sub for &'a Calculations {
+impl Sub for &Calculations {
     type Output = Calculations;
     fn sub(self, rhs: Self) -> Calculations {
         Calculations {
@@ -535,34 +717,147 @@ impl fmt::Display for Calculations {
     }
 }
 
+/// When the computer booted, from the `btime` line of `/proc/stat`
+pub fn boot_time() -> Result<SystemTime> {
+    boot_time_in(&Context::from_env())
+}
+
+/// Like `boot_time`, but reading `<proc_root>/stat`
+pub fn boot_time_in(context: &Context) -> Result<SystemTime> {
+    let path = context.proc_path("stat");
+    parse_boot_time(&read_file(&path)?, &path)
+}
+
+fn parse_boot_time(contents: &str, path: &Path) -> Result<SystemTime> {
+    let (line, seconds) = contents
+        .lines()
+        .enumerate()
+        .find_map(|(i, line)| Some((i + 1, line.strip_prefix("btime ")?.trim())))
+        .ok_or_else(|| FieldError::missing(path, "btime"))?;
+    let seconds = seconds.parse().map_err(|e| {
+        FieldError::new(
+            path,
+            Some(line),
+            "btime",
+            format!("{:?} is not a number: {}", seconds, e),
+        )
+    })?;
+    Ok(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
 // ////////////////////////////////////////////////////////////////////////////
 // Memory
 
 /// A struct that represents overall memory usage on the system.
 ///
-/// All values are in KB.
-#[derive(PartialEq, Eq, Debug)]
+/// All values are in KB. The fields here are the ones that the checks have
+/// always used, every line of the file is in `fields`.
+#[derive(PartialEq, Eq, Debug, Default)]
 pub struct MemInfo {
     pub total: Option<usize>,
     pub available: Option<usize>,
     pub free: Option<usize>,
     pub cached: Option<usize>,
+    pub swap_total: Option<usize>,
+    pub swap_free: Option<usize>,
+    /// Memory that was swapped out and back in, and is still in the swap
+    /// file, so it can be swapped out again without writing to disk
+    pub swap_cached: Option<usize>,
+    /// Every field in the file, by name, e.g. `Dirty` or `HugePages_Total`
+    pub fields: BTreeMap<String, MemInfoValue>,
+}
+
+/// A value from /proc/meminfo
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MemInfoValue {
+    /// A size in KB, from a line ending in `kB`
+    Kb(usize),
+    /// A count without a unit, e.g. the number of `HugePages_Free`
+    Count(usize),
+}
+
+impl MemInfoValue {
+    /// The number, whatever its unit
+    pub fn value(self) -> usize {
+        match self {
+            MemInfoValue::Kb(value) | MemInfoValue::Count(value) => value,
+        }
+    }
 }
 
 impl MemInfo {
     /// Read the data from /proc/meminfo into a `MemInfo`
-    pub fn load() -> MemInfo {
-        let contents = match File::open("/proc/meminfo") {
-            Ok(ref mut content) => {
-                let mut s = String::new();
-                let _ = content.read_to_string(&mut s);
-                s
-            }
-            Err(e) => panic!("Unable to open /proc/meminfo: {:?}", e),
-        };
+    pub fn load() -> Result<MemInfo> {
+        MemInfo::load_in(&Context::from_env())
+    }
 
-        MemInfo::from_str(&contents).unwrap()
+    /// Read the data from `<proc_root>/meminfo` into a `MemInfo`
+    pub fn load_in(context: &Context) -> Result<MemInfo> {
+        let path = context.proc_path("meminfo");
+        let contents = read_file(&path)?;
+        MemInfo::parse(&contents, &path)
     }
+
+    /// Parse the contents of `path`, which should look like /proc/meminfo
+    fn parse(meminfo: &str, path: &Path) -> Result<MemInfo> {
+        let mut info = MemInfo::default();
+        for (i, line) in meminfo.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (name, value) = match line.find(':') {
+                Some(colon) => (&line[..colon], &line[colon + 1..]),
+                None => {
+                    return Err(FieldError::new(
+                        path,
+                        Some(i + 1),
+                        line.trim(),
+                        "expected a line like `Name: 1234 kB`",
+                    )
+                    .into())
+                }
+            };
+            let mut parts = value.split_whitespace();
+            let number = parts.next().unwrap_or("");
+            let number = number.parse().map_err(|e| {
+                FieldError::new(
+                    path,
+                    Some(i + 1),
+                    name,
+                    format!("{:?} is not a number: {}", number, e),
+                )
+            })?;
+            let value = match parts.next() {
+                Some("kB") => MemInfoValue::Kb(number),
+                None => MemInfoValue::Count(number),
+                Some(unit) => {
+                    return Err(FieldError::new(
+                        path,
+                        Some(i + 1),
+                        name,
+                        format!("unexpected unit {:?}", unit),
+                    )
+                    .into())
+                }
+            };
+            let field = match name {
+                "MemTotal" => Some(&mut info.total),
+                "MemAvailable" => Some(&mut info.available),
+                "MemFree" => Some(&mut info.free),
+                "Cached" => Some(&mut info.cached),
+                "SwapTotal" => Some(&mut info.swap_total),
+                "SwapFree" => Some(&mut info.swap_free),
+                "SwapCached" => Some(&mut info.swap_cached),
+                _ => None,
+            };
+            if let Some(field) = field {
+                *field = Some(value.value());
+            }
+            info.fields.insert(name.to_owned(), value);
+        }
+        Ok(info)
+    }
+
     /// Try to figure out how much memory is being used
     ///
     /// Since linux kernel 3.16 this just performs
@@ -597,11 +892,82 @@ impl MemInfo {
         }
     }
 
+    /// Any field from the file, e.g. `HugePages_Total`
+    pub fn get(&self, name: &str) -> Option<MemInfoValue> {
+        self.fields.get(name).cloned()
+    }
+
+    /// A field from the file that is a size in KB, e.g. `Dirty`
+    pub fn kb(&self, name: &str) -> Option<usize> {
+        match self.get(name) {
+            Some(MemInfoValue::Kb(kb)) => Some(kb),
+            _ => None,
+        }
+    }
+
+    /// The pool of hugepages of the default size, from `HugePages_Total`,
+    /// `HugePages_Free`, `HugePages_Rsvd`, `HugePages_Surp` and
+    /// `Hugepagesize`
+    ///
+    /// `None` if the kernel doesn't support hugepages. Pools of other sizes
+    /// are only in `/sys/kernel/mm/hugepages`.
+    pub fn huge_pages(&self) -> Option<HugePagePool> {
+        let count = |name| match self.get(name) {
+            Some(MemInfoValue::Count(count)) => Some(count as u64),
+            _ => None,
+        };
+        Some(HugePagePool {
+            size_kb: self.kb("Hugepagesize")?,
+            total: count("HugePages_Total")?,
+            free: count("HugePages_Free")?,
+            reserved: count("HugePages_Rsvd")?,
+            surplus: count("HugePages_Surp")?,
+        })
+    }
+
+    /// The percent of the commit limit that has been allocated
+    ///
+    /// This is `Committed_AS / CommitLimit`. It can be over 100 unless the
+    /// kernel is configured to refuse to overcommit memory, in which case
+    /// allocations fail when it reaches 100.
+    pub fn percent_committed(&self) -> Option<f64> {
+        match (self.kb("Committed_AS"), self.kb("CommitLimit")) {
+            (Some(committed), Some(limit)) if limit > 0 => {
+                Some(committed as f64 / limit as f64 * 100.0)
+            }
+            _ => None,
+        }
+    }
+
+    /// The percent of `MemTotal` that a size in KB is
+    pub fn percent_of_total(&self, kb: usize) -> Option<f64> {
+        match self.total {
+            Some(total) if total > 0 => Some(kb as f64 / total as f64 * 100.0),
+            _ => None,
+        }
+    }
+
     /// The inverse of `MemInfo::percent_free`
     pub fn percent_used(&self) -> Result<f64> {
         let free = self.percent_free()?;
         Ok(100f64 - free)
     }
+
+    /// Swap in use, in KB
+    pub fn swap_used(&self) -> Option<usize> {
+        match (self.swap_total, self.swap_free) {
+            (Some(total), Some(free)) => Some(total.saturating_sub(free)),
+            _ => None,
+        }
+    }
+
+    /// The percent of swap in use, `None` if there is no swap
+    pub fn percent_swap_used(&self) -> Option<f64> {
+        match (self.swap_total, self.swap_used()) {
+            (Some(total), Some(used)) if total > 0 => Some(used as f64 / total as f64 * 100.0),
+            _ => None,
+        }
+    }
 }
 
 impl FromStr for MemInfo {
@@ -610,80 +976,7 @@ impl FromStr for MemInfo {
     /// Convert the contents of a string like /proc/meminfo into a MemInfo
     /// object
     fn from_str(meminfo: &str) -> Result<Self> {
-        enum Currently {
-            Total,
-            Free,
-            Available,
-            Cached,
-            Unknown,
-            None,
-        };
-
-        let mut word = String::new();
-        let mut info = MemInfo {
-            total: None,
-            free: None,
-            available: None,
-            cached: None,
-        };
-        let mut amount: usize;
-        let mut currently = Currently::None;
-
-        for chr in meminfo.chars() {
-            match chr {
-                c if 'A' <= c && c <= 'z' => {
-                    word.push(chr);
-                }
-                ':' => {
-                    match &word[..] {
-                        "MemTotal" => currently = Currently::Total,
-                        "MemAvailable" => currently = Currently::Available,
-                        "MemFree" => currently = Currently::Free,
-                        "Cached" => currently = Currently::Cached,
-                        _ => currently = Currently::Unknown,
-                    }
-                    word.clear();
-                }
-                x if '0' <= x && x <= '9' => {
-                    word.push(chr);
-                }
-                ' ' | '\n' => {
-                    if word.is_empty() {
-                        continue;
-                    }
-                    if word == "kB" {
-                        word.clear();
-                        continue;
-                    }
-
-                    amount = match word.parse() {
-                        Ok(amount) => amount,
-                        Err(e) => panic!(r#"Unable to parse number from "{}": {:?}"#, word, e),
-                    };
-                    word.clear();
-
-                    match currently {
-                        Currently::Total => info.total = Some(amount),
-                        Currently::Free => info.free = Some(amount),
-                        Currently::Available => info.available = Some(amount),
-                        Currently::Cached => info.cached = Some(amount),
-                        Currently::Unknown => {
-                            // don't care
-                        }
-                        Currently::None => panic!(
-                            "Unexpectedly parsed a number before figuring out where I am: \
-                             {}",
-                            amount
-                        ),
-                    }
-                }
-                _ => {
-                    // Don't care about other chars
-                }
-            }
-        }
-
-        Ok(info)
+        MemInfo::parse(meminfo, Path::new("/proc/meminfo"))
     }
 }
 
@@ -701,9 +994,12 @@ pub struct LoadAvg {
 impl LoadAvg {
     /// Load from the /proc/loadavg file
     pub fn load() -> Result<LoadAvg> {
-        let mut fh = File::open("/proc/loadavg")?;
-        let mut contents = String::new();
-        fh.read_to_string(&mut contents)?;
+        LoadAvg::load_in(&Context::from_env())
+    }
+
+    /// Load from `<proc_root>/loadavg`
+    pub fn load_in(context: &Context) -> Result<LoadAvg> {
+        let contents = read_file(&context.proc_path("loadavg"))?;
         Self::from_str(&contents)
     }
 }
@@ -731,6 +1027,12 @@ impl FromStr for LoadAvg {
             .take(3)
             .map(|load| load.parse())
             .collect::<StdResult<Vec<f64>, _>>()?;
+        if fields.len() < 3 {
+            return Err(ProcFsError::InsufficientData(format!(
+                "expected three load averages, got {:?}",
+                contents
+            )));
+        }
         Ok(LoadAvg {
             one: fields[0],
             five: fields[1],
@@ -778,24 +1080,18 @@ fn mount_from_line(line: &str) -> Result<Mount> {
         vfstype: next(&mut parts)?,
         mntops: parts
             .next()
-            .map_or(
-                Err(InsufficientData("Missing mnt ops from mount".to_owned())),
-                Ok,
-            )?
+            .ok_or_else(|| InsufficientData("Missing mnt ops from mount".to_owned()))?
             .split(',')
             .map(|part| part.to_owned())
             .collect::<Vec<_>>(),
-        freq: parts.next().map(|v| v.parse().unwrap()),
-        passno: parts.next().map(|v| v.parse().unwrap()),
+        freq: parts.next().map(|v| v.parse()).transpose()?,
+        passno: parts.next().map(|v| v.parse()).transpose()?,
     })
 }
 
 impl Mount {
-    fn read_mounts() -> Result<String> {
-        let mut fh = File::open("/proc/mounts")?;
-        let mut contents = String::new();
-        fh.read_to_string(&mut contents)?;
-        Ok(contents)
+    fn read_mounts(context: &Context) -> Result<String> {
+        Ok(read_file(&context.proc_path("mounts"))?)
     }
 
     fn parse_str(mounts: &str) -> Result<Vec<Mount>> {
@@ -805,8 +1101,14 @@ impl Mount {
             .collect::<Result<Vec<_>>>()
     }
 
+    /// Load every mount point from /proc/mounts
     pub fn load_all() -> Result<Vec<Mount>> {
-        let mounts = Mount::read_mounts()?;
+        Mount::load_all_in(&Context::from_env())
+    }
+
+    /// Load every mount point from `<proc_root>/mounts`
+    pub fn load_all_in(context: &Context) -> Result<Vec<Mount>> {
+        let mounts = Mount::read_mounts(context)?;
         Mount::parse_str(&mounts)
     }
 }
@@ -823,6 +1125,7 @@ mod unit {
     use crate::linux::Jiffies;
 
     #[test]
+    #[allow(clippy::deprecated_cfg_attr)]
     #[cfg_attr(rustfmt, rustfmt_skip)]
     fn can_parse_stat_for_system() {
         let c = Calculations::from_str(
@@ -848,6 +1151,7 @@ btime 143
     }
 
     #[test]
+    #[allow(clippy::deprecated_cfg_attr)]
     #[cfg_attr(rustfmt, rustfmt_skip)]
     fn can_parse_multiple_cpus() {
         let c = Calculations::per_cpu(
@@ -906,25 +1210,108 @@ btime 143
         stat.parse::<pid::Stat>().unwrap();
     }
 
+    #[test]
+    fn cpu_util_skips_reused_pids() {
+        let procs = |starttime, utime| {
+            let mut process = pid::Process::default();
+            process.stat.pid = Pid::from_raw(5);
+            process.stat.starttime = starttime;
+            process.stat.utime = Jiffies::new(utime);
+            RunningProcs(vec![(process.stat.pid, process)].into_iter().collect())
+        };
+        let start = procs(100, 50);
+        let same = procs(100, 60);
+        let usages = same.percent_cpu_util_since(&start, Jiffies::new(100));
+        assert_eq!(
+            usages.iter().map(|usage| usage.total).collect::<Vec<_>>(),
+            [10.0]
+        );
+        let reused = procs(200, 0);
+        let usages = reused.percent_cpu_util_since(&start, Jiffies::new(100));
+        assert!(usages.is_empty());
+    }
+
+    #[test]
+    fn parse_boot_time_errors() {
+        let path = Path::new("/proc/stat");
+        let err = parse_boot_time("cpu  1 2 3\nctxt 310\n", path).unwrap_err();
+        assert_eq!(err.to_string(), "/proc/stat: invalid btime: missing");
+        let err = parse_boot_time("cpu  1 2 3\nbtime soon\n", path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "/proc/stat line 2: invalid btime: \"soon\" is not a number: \
+             invalid digit found in string"
+        );
+    }
+
     #[test]
     fn parse_meminfo() {
+        let mem = MemInfo::from_str(concat!(
+            "Useless: 898\n",
+            "MemTotal: 500\n",
+            "MemAvailable: 20\n",
+            "MemFree: 280\n",
+            "Meaningless: 777\n",
+            "Cached: 200\n",
+            "SwapCached: 10\n",
+            "SwapTotal: 400\n",
+            "SwapFree: 300\n",
+        ))
+        .unwrap();
         assert_eq!(
-            MemInfo::from_str(concat!(
-                "Useless: 898\n",
-                "MemTotal: 500\n",
-                "MemAvailable: 20\n",
-                "MemFree: 280\n",
-                "Meaningless: 777\n",
-                "Cached: 200\n"
-            ))
-            .unwrap(),
-            MemInfo {
-                total: Some(500),
-                available: Some(20),
-                free: Some(280),
-                cached: Some(200),
-            }
-        )
+            (mem.total, mem.available, mem.free, mem.cached),
+            (Some(500), Some(20), Some(280), Some(200))
+        );
+        assert_eq!(
+            (mem.swap_total, mem.swap_free, mem.swap_cached),
+            (Some(400), Some(300), Some(10))
+        );
+        assert_eq!(mem.get("Meaningless"), Some(MemInfoValue::Count(777)));
+        assert_eq!(mem.fields.len(), 9);
+    }
+
+    #[test]
+    fn parse_meminfo_units() {
+        let mem = MemInfo::from_str(concat!(
+            "MemTotal:        1000 kB\n",
+            "Dirty:             50 kB\n",
+            "CommitLimit:      800 kB\n",
+            "Committed_AS:    1000 kB\n",
+            "HugePages_Total:    4\n",
+        ))
+        .unwrap();
+        assert_eq!(mem.total, Some(1000));
+        assert_eq!(mem.kb("Dirty"), Some(50));
+        assert_eq!(mem.get("HugePages_Total"), Some(MemInfoValue::Count(4)));
+        assert_eq!(mem.kb("HugePages_Total"), None);
+        assert_eq!(mem.percent_committed(), Some(125.0));
+        assert_eq!(mem.percent_of_total(50), Some(5.0));
+        assert_eq!(MemInfo::default().percent_committed(), None);
+
+        let err = MemInfo::from_str("MemTotal: 500 MB\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "/proc/meminfo line 1: invalid MemTotal: unexpected unit \"MB\""
+        );
+    }
+
+    #[test]
+    fn meminfo_errors_have_context() {
+        let err = MemInfo::from_str("MemTotal: 500 kB\nMemFree: lots kB\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "/proc/meminfo line 2: invalid MemFree: \"lots\" is not a number: \
+             invalid digit found in string"
+        );
+        assert!(MemInfo::from_str("MemTotal 500 kB\n").is_err());
+    }
+
+    #[test]
+    fn bad_proc_lines_are_errors() {
+        assert!(Calculations::from_str("cpu 1 2 3\n").is_err());
+        assert!(Calculations::from_str("").is_err());
+        assert!(LoadAvg::from_str("1.0 2.0").is_err());
+        assert!(mount_from_line("/dev/sda1 / ext4 rw x 0").is_err());
     }
 
     #[test]
@@ -932,20 +1319,36 @@ btime 143
         let mem = MemInfo {
             total: Some(100),
             available: Some(30),
-            free: None,
-            cached: None,
+            ..MemInfo::default()
         };
         assert_eq!(mem.percent_free().unwrap(), 30.0);
 
         let mem = MemInfo {
             total: Some(100),
-            available: None,
             free: Some(25),
             cached: Some(20),
+            ..MemInfo::default()
         };
         assert_eq!(mem.percent_free().unwrap(), 45.0);
     }
 
+    #[test]
+    fn meminfo_percent_swap_used() {
+        let mem = MemInfo {
+            swap_total: Some(400),
+            swap_free: Some(300),
+            ..MemInfo::default()
+        };
+        assert_eq!(mem.swap_used(), Some(100));
+        assert_eq!(mem.percent_swap_used(), Some(25.0));
+        let mem = MemInfo {
+            swap_total: Some(0),
+            swap_free: Some(0),
+            ..MemInfo::default()
+        };
+        assert_eq!(mem.percent_swap_used(), None);
+    }
+
     #[test]
     fn loadavg_can_parse_space_str() {
         let avg = LoadAvg::from_str("0.1 1.5 21 5/23 938").unwrap();
@@ -987,6 +1390,7 @@ btime 143
     }
 
     #[test]
+    #[allow(clippy::needless_borrow)]
     fn mount_from_line_works() {
         let line = "none /data/docker/aufs/mnt/b6e1b aufs \
                     rw,relatime,si=5c1d022653bfa828,dio,dirperm1 0 0";
@@ -1015,6 +1419,7 @@ btime 143
     }
 
     #[test]
+    #[allow(clippy::needless_borrow)]
     fn mount_all_works() {
         let mount_config = "none /data/docker/aufs/mnt/b6e1b aufs \
                             rw,relatime,si=5c1d022653bfa828,dio,dirperm1 0 0\nsome / ext4 \
@@ -1084,6 +1489,7 @@ mod integration {
     use super::{LoadAvg, MemInfo, Mount, RunningProcs};
 
     #[test]
+    #[allow(clippy::len_zero)]
     fn can_read_all_procs() {
         let procs = RunningProcs::currently_running().unwrap();
         assert!(procs.0.len() > 0);
@@ -1091,7 +1497,7 @@ mod integration {
 
     #[test]
     fn meminfo_can_load() {
-        let info = MemInfo::load();
+        let info = MemInfo::load().unwrap();
         assert_eq!(
             info.percent_free().unwrap() + info.percent_used().unwrap(),
             100.0
@@ -1108,3 +1514,61 @@ mod integration {
         Mount::load_all().unwrap();
     }
 }
+
+#[cfg(test)]
+mod fixture_tree {
+    use nix::unistd::Pid;
+
+    use std::path::Path;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{boot_time_in, Calculations, LoadAvg, MemInfo, Mount, ProcFsError, RunningProcs};
+    use crate::context::{fixtures, Context};
+    use crate::linux::Jiffies;
+
+    #[test]
+    fn loads_procs_from_root() {
+        let procs = RunningProcs::currently_running_in(&fixtures()).unwrap();
+        assert_eq!(procs.len(), 2);
+        let nginx = &procs.0[&Pid::from_raw(42)];
+        assert_eq!(nginx.stat.ppid, Pid::from_raw(1));
+        assert_eq!(nginx.useful_cmdline(), "nginx: master process");
+        assert_eq!(nginx.stat.comm, "nginx");
+    }
+
+    #[test]
+    fn loads_cpus_from_root() {
+        let total = Calculations::load_in(&fixtures()).unwrap();
+        assert_eq!(total.user, Jiffies::new(400));
+        let per_cpu = Calculations::load_per_cpu_in(&fixtures()).unwrap();
+        assert_eq!(per_cpu.len(), 2);
+        let booted = boot_time_in(&fixtures()).unwrap();
+        assert_eq!(booted, UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+    }
+
+    #[test]
+    fn loads_files_from_root() {
+        let mem = MemInfo::load_in(&fixtures()).unwrap();
+        assert_eq!(mem.percent_used().unwrap(), 75.0);
+        assert_eq!(mem.kb("SUnreclaim"), Some(400_000));
+        assert_eq!(mem.percent_committed(), Some(112.5));
+        let huge = mem.huge_pages().unwrap();
+        assert_eq!((huge.size_kb, huge.total, huge.free), (2048, 64, 16));
+        let load = LoadAvg::load_in(&fixtures()).unwrap();
+        assert_eq!(load.one, 1.5);
+        let mounts = Mount::load_all_in(&fixtures()).unwrap();
+        assert_eq!(mounts[0].file, "/");
+        assert_eq!(mounts.len(), 3);
+    }
+
+    #[test]
+    fn reports_missing_files() {
+        let context = Context::default().with_proc_root("/nonexistent");
+        match MemInfo::load_in(&context) {
+            Err(ProcFsError::ReadFile(e)) => {
+                assert_eq!(e.path, Path::new("/nonexistent/meminfo"))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
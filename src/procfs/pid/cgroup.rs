@@ -0,0 +1,88 @@
+use std::fmt;
+use std::path::Path;
+
+use crate::context::Context;
+use crate::procfs::{read_file, FieldError, Result};
+
+/// The cgroups a process is in, from `/proc/<pid>/cgroup`
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct CGroupMembership {
+    pub entries: Vec<CGroupEntry>,
+}
+
+/// One line of `/proc/<pid>/cgroup`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CGroupEntry {
+    /// The hierarchy ID, always 0 for cgroup v2
+    pub hierarchy_id: u32,
+    /// The controllers bound to the hierarchy, empty for cgroup v2
+    pub controllers: Vec<String>,
+    /// The path of the cgroup, relative to the root of the hierarchy as seen
+    /// from the reading process's cgroup namespace
+    pub path: String,
+}
+
+impl CGroupMembership {
+    pub fn from_pid<P: fmt::Display>(pid: P) -> Result<CGroupMembership> {
+        CGroupMembership::from_pid_in(pid, &Context::from_env())
+    }
+
+    /// Load `<proc_root>/<pid>/cgroup`, `pid` can be `self`
+    pub fn from_pid_in<P: fmt::Display>(pid: P, context: &Context) -> Result<CGroupMembership> {
+        let path = context.proc_path(format!("{}/cgroup", pid));
+        CGroupMembership::parse(&read_file(&path)?, &path)
+    }
+
+    pub(crate) fn parse(contents: &str, path: &Path) -> Result<CGroupMembership> {
+        let mut entries = vec![];
+        for (i, line) in contents.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let mut parts = line.splitn(3, ':');
+            let (id, controllers, cgroup) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(id), Some(controllers), Some(cgroup)) => (id, controllers, cgroup),
+                _ => {
+                    return Err(FieldError::new(
+                        path,
+                        Some(i + 1),
+                        line,
+                        "expected `hierarchy-ID:controller-list:cgroup-path`",
+                    )
+                    .into())
+                }
+            };
+            entries.push(CGroupEntry {
+                hierarchy_id: id.parse().map_err(|e| {
+                    FieldError::new(
+                        path,
+                        Some(i + 1),
+                        "hierarchy-ID",
+                        format!("{:?}: {}", id, e),
+                    )
+                })?,
+                controllers: controllers
+                    .split(',')
+                    .filter(|c| !c.is_empty())
+                    .map(String::from)
+                    .collect(),
+                path: cgroup.to_owned(),
+            });
+        }
+        Ok(CGroupMembership { entries })
+    }
+
+    /// The cgroup v2 entry, if the process is in the unified hierarchy
+    pub fn unified(&self) -> Option<&CGroupEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.hierarchy_id == 0 && entry.controllers.is_empty())
+    }
+
+    /// The cgroup v1 entry for `controller`, e.g. `memory`
+    pub fn controller(&self, controller: &str) -> Option<&CGroupEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.controllers.iter().any(|c| c == controller))
+    }
+}
@@ -2,6 +2,7 @@ use std::fmt;
 use std::fs::File;
 use std::io::Read;
 
+use crate::context::Context;
 use crate::procfs::Result;
 
 /// The visibule command line for a process
@@ -15,8 +16,12 @@ pub struct CmdLine {
 
 impl CmdLine {
     pub fn from_pid<P: fmt::Display>(pid: P) -> Result<CmdLine> {
-        let path_str = format!("/proc/{}/cmdline", pid);
-        let mut f = File::open(&path_str)?;
+        CmdLine::from_pid_in(pid, &Context::from_env())
+    }
+
+    /// Load `<proc_root>/<pid>/cmdline`
+    pub fn from_pid_in<P: fmt::Display>(pid: P, context: &Context) -> Result<CmdLine> {
+        let mut f = File::open(context.proc_path(format!("{}/cmdline", pid)))?;
         let mut s = String::new();
         f.read_to_string(&mut s)?;
         Ok(CmdLine {
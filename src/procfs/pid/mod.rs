@@ -3,16 +3,32 @@
 //! The `Process` struct can load everything about a running process, and
 //! provides some aggregate data about them.
 
+mod cgroup;
 mod cmd_line;
+mod mount_info;
+mod smaps_rollup;
 mod stat;
+mod status;
 
+use std::collections::HashMap;
 use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
 
+use serde::Deserialize;
+
+use crate::context::Context;
 use crate::linux::{Jiffies, Ratio, PAGESIZE};
-use crate::procfs::Result;
+use crate::procfs::{ProcFsError, ProcessTree, ReadFileError, Result};
 
+pub use self::cgroup::{CGroupEntry, CGroupMembership};
 pub use self::cmd_line::CmdLine;
+pub(crate) use self::mount_info::unescape;
+pub use self::mount_info::MountInfo;
+pub use self::smaps_rollup::SmapsRollup;
 pub use self::stat::{Stat, State};
+pub use self::status::{Ids, ProcessStatus, Seccomp, SignalMask};
 
 /// Information about a running process
 #[derive(Clone, PartialEq, Eq, Debug, Default)]
@@ -21,16 +37,77 @@ pub struct Process {
     pub stat: Stat,
     /// The command line, as revealed by the /proc fs
     pub cmdline: CmdLine,
+    /// The owner, threads, capabilities and more, only loaded by
+    /// `from_pid_with_status_in`, and `None` if `/proc/<pid>/status` could not
+    /// be read
+    pub status: Option<ProcessStatus>,
 }
 
 impl Process {
     pub fn from_pid<P: fmt::Display + Copy>(p: P) -> Result<Process> {
+        Process::from_pid_in(p, &Context::from_env())
+    }
+
+    /// Load a process from `<proc_root>/<pid>`
+    ///
+    /// Only the stat and cmdline files are read, which must be readable.
+    pub fn from_pid_in<P: fmt::Display + Copy>(p: P, context: &Context) -> Result<Process> {
+        Ok(Process {
+            stat: Stat::from_pid_in(p, context)?,
+            cmdline: CmdLine::from_pid_in(p, context)?,
+            status: None,
+        })
+    }
+
+    pub fn from_pid_with_status<P: fmt::Display + Copy>(p: P) -> Result<Process> {
+        Process::from_pid_with_status_in(p, &Context::from_env())
+    }
+
+    /// Load a process like `from_pid_in`, and its status file too
+    ///
+    /// The status file is optional, it is slow to parse for every process so
+    /// only callers that need the owner or the like should ask for it.
+    pub fn from_pid_with_status_in<P: fmt::Display + Copy>(
+        p: P,
+        context: &Context,
+    ) -> Result<Process> {
         Ok(Process {
-            stat: Stat::from_pid(p)?,
-            cmdline: CmdLine::from_pid(p)?,
+            status: ProcessStatus::from_pid_in(p, context).ok(),
+            ..Process::from_pid_in(p, context)?
         })
     }
 
+    /// The path to the executable that is running
+    pub fn exe(&self) -> Result<PathBuf> {
+        self.exe_in(&Context::from_env())
+    }
+
+    /// Read the `<proc_root>/<pid>/exe` link
+    ///
+    /// Reading another user's processes needs root. If the executable was
+    /// deleted or replaced since the process started the kernel appends
+    /// ` (deleted)` to the path.
+    pub fn exe_in(&self, context: &Context) -> Result<PathBuf> {
+        let path = context.proc_path(format!("{}/exe", self.stat.pid));
+        fs::read_link(&path).map_err(|error| ReadFileError { path, error }.into())
+    }
+
+    /// The number of open file descriptors
+    pub fn open_fds(&self) -> Result<usize> {
+        self.open_fds_in(&Context::from_env())
+    }
+
+    /// Count the entries in `<proc_root>/<pid>/fd`, which needs root for
+    /// other users' processes
+    pub fn open_fds_in(&self, context: &Context) -> Result<usize> {
+        let path = context.proc_path(format!("{}/fd", self.stat.pid));
+        let entries = fs::read_dir(&path).map_err(|error| ReadFileError {
+            path: path.clone(),
+            error,
+        })?;
+        Ok(entries.filter(|entry| entry.is_ok()).count())
+    }
+
     pub fn useful_cmdline(&self) -> String {
         let cmd = self.cmdline.display();
         if cmd.is_empty() {
@@ -75,7 +152,7 @@ impl Process {
                 .duration()
                 .ratio(&total_cpu.duration());
         ProcessCpuUsage {
-            process: &start_process,
+            process: start_process,
             upercent: user,
             spercent: sys,
             total: user + sys,
@@ -83,6 +160,120 @@ impl Process {
     }
 }
 
+/// How to measure the ram that a process uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RamMeasure {
+    /// Resident set size, which counts memory shared with other processes
+    /// in full for each of them
+    Rss,
+    /// Proportional set size, which divides shared memory between the
+    /// processes sharing it
+    Pss,
+    /// Unique set size, only the memory that no other process shares
+    Uss,
+}
+
+impl RamMeasure {
+    /// The legal values for `from_str`
+    pub fn str_values() -> [&'static str; 3] {
+        ["rss", "pss", "uss"]
+    }
+
+    /// The bytes of ram that `process` uses by this measure
+    ///
+    /// PSS and USS read `<proc_root>/<pid>/smaps_rollup`, which needs Linux
+    /// 4.14, and needs root for other users' processes.
+    pub fn bytes_in(self, process: &Process, context: &Context) -> Result<u64> {
+        let kb = match self {
+            RamMeasure::Rss => return Ok(pages_to_bytes(process.stat.rss)),
+            // kernel threads have no memory, and newer kernels refuse to
+            // read their smaps_rollup
+            _ if process.stat.is_kernel_thread() => return Ok(0),
+            RamMeasure::Pss => SmapsRollup::from_pid_in(process.stat.pid, context)?.pss,
+            RamMeasure::Uss => SmapsRollup::from_pid_in(process.stat.pid, context)?.uss(),
+        };
+        Ok(kb as u64 * 1024)
+    }
+
+    /// Measure each of `procs`, largest first
+    ///
+    /// Processes that can't be measured, usually because they exited or
+    /// belong to another user, are returned separately with the error.
+    pub fn rank_in<'a, I>(self, procs: I, context: &Context) -> RamRanking<'a>
+    where
+        I: IntoIterator<Item = &'a Process>,
+    {
+        let mut ranking = RamRanking::default();
+        for process in procs {
+            match self.bytes_in(process, context) {
+                Ok(bytes) => ranking.measured.push((process, bytes)),
+                Err(e) => ranking.errors.push((process, e)),
+            }
+        }
+        ranking
+            .measured
+            .sort_by_key(|&(_, bytes)| std::cmp::Reverse(bytes));
+        ranking
+    }
+}
+
+impl FromStr for RamMeasure {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<RamMeasure, String> {
+        match s {
+            "rss" => Ok(RamMeasure::Rss),
+            "pss" => Ok(RamMeasure::Pss),
+            "uss" => Ok(RamMeasure::Uss),
+            _ => Err(format!("Unexpected ram measure: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for RamMeasure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            RamMeasure::Rss => "rss",
+            RamMeasure::Pss => "pss",
+            RamMeasure::Uss => "uss",
+        })
+    }
+}
+
+/// Processes sorted by `RamMeasure::rank_in`
+#[derive(Debug, Default)]
+pub struct RamRanking<'a> {
+    /// Processes and the bytes they use, largest first
+    pub measured: Vec<(&'a Process, u64)>,
+    /// Processes that couldn't be measured, and why
+    pub errors: Vec<(&'a Process, ProcFsError)>,
+}
+
+impl<'a> RamRanking<'a> {
+    /// Add up the measured processes by `ProcessTree::top_level`, largest
+    /// first
+    ///
+    /// Each entry is the top level process, the number of measured processes
+    /// in its tree and their bytes.
+    pub fn by_tree(&self, tree: &ProcessTree<'a>) -> Vec<(&'a Process, usize, u64)> {
+        let mut trees = HashMap::<_, (&Process, usize, u64)>::new();
+        for &(process, bytes) in &self.measured {
+            let top = tree.top_level(process.stat.pid);
+            let entry = trees
+                .entry(top)
+                .or_insert((tree.get(top).unwrap_or(process), 0, 0));
+            entry.1 += 1;
+            entry.2 += bytes;
+        }
+        let mut trees = trees.into_values().collect::<Vec<_>>();
+        trees.sort_by_key(|&(process, _, bytes)| {
+            (std::cmp::Reverse(bytes), process.stat.pid.as_raw())
+        });
+        trees
+    }
+}
+
 /// Represent the percent CPU utilization of a specific process over a specific
 /// time period
 ///
@@ -102,3 +293,104 @@ pub struct ProcessCpuUsage<'a> {
 fn pages_to_bytes(pages: u64) -> u64 {
     pages * (*PAGESIZE)
 }
+
+#[cfg(test)]
+mod unit {
+    use std::path::Path;
+
+    use nix::unistd::Pid;
+
+    use super::{Process, RamMeasure, RamRanking};
+    use crate::context::fixtures;
+    use crate::procfs::{ProcessTree, RunningProcs};
+
+    #[test]
+    fn ranks_processes_by_ram() {
+        let context = fixtures();
+        let procs = RunningProcs::currently_running_in(&context).unwrap();
+        let ranking = RamMeasure::Pss.rank_in(procs.0.values(), &context);
+        let pss = ranking
+            .measured
+            .iter()
+            .map(|(process, bytes)| (process.stat.pid.as_raw(), *bytes))
+            .collect::<Vec<_>>();
+        assert_eq!(pss, [(42, 3000 * 1024), (1, 900 * 1024)]);
+        assert!(ranking.errors.is_empty());
+        let ranking = RamMeasure::Uss.rank_in(procs.0.values(), &context);
+        assert_eq!(ranking.measured[1].1, 800 * 1024);
+        assert_eq!("uss".parse(), Ok(RamMeasure::Uss));
+    }
+
+    #[test]
+    fn ranks_process_trees_by_ram() {
+        let context = fixtures();
+        let procs = RunningProcs::currently_running_in(&context).unwrap();
+        let ranking = RamMeasure::Pss.rank_in(procs.0.values(), &context);
+        let trees = ranking
+            .by_tree(&procs.tree())
+            .iter()
+            .map(|&(process, count, bytes)| (process.stat.pid.as_raw(), count, bytes))
+            .collect::<Vec<_>>();
+        // init's children are their own trees
+        assert_eq!(trees, [(42, 1, 3000 * 1024), (1, 1, 900 * 1024)]);
+
+        let mut worker = procs.0[&Pid::from_raw(42)].clone();
+        worker.stat.pid = Pid::from_raw(43);
+        worker.stat.ppid = Pid::from_raw(42);
+        let ranking = RamRanking {
+            measured: vec![(&procs.0[&Pid::from_raw(42)], 100), (&worker, 50)],
+            errors: vec![],
+        };
+        let with_worker = procs.0.values().chain(Some(&worker));
+        let trees = ranking.by_tree(&ProcessTree::new(with_worker));
+        assert_eq!(trees.len(), 1);
+        assert_eq!(
+            (trees[0].0.stat.pid.as_raw(), trees[0].1, trees[0].2),
+            (42, 2, 150)
+        );
+    }
+
+    #[test]
+    fn loads_status_on_request() {
+        let context = fixtures();
+        let process = Process::from_pid_in(1, &context).unwrap();
+        assert_eq!(process.status, None);
+        let process = Process::from_pid_with_status_in(1, &context).unwrap();
+        assert_eq!(process.status.unwrap().uid.real, 0);
+
+        let procs = RunningProcs::currently_running_with_status_in(&context).unwrap();
+        assert_eq!(
+            procs.0[&Pid::from_raw(42)]
+                .status
+                .as_ref()
+                .unwrap()
+                .uid
+                .real,
+            33
+        );
+        let procs = RunningProcs::currently_running_in(&context).unwrap();
+        assert!(procs.0.values().all(|process| process.status.is_none()));
+    }
+
+    #[test]
+    fn reads_exe_link() {
+        let context = fixtures();
+        let nginx = Process::from_pid_in(42, &context).unwrap();
+        assert_eq!(
+            nginx.exe_in(&context).unwrap(),
+            Path::new("/usr/sbin/nginx")
+        );
+        let init = Process::from_pid_in(1, &context).unwrap();
+        let err = init.exe_in(&context).unwrap_err();
+        assert!(err.to_string().contains("1/exe"), "{}", err);
+    }
+
+    #[test]
+    fn counts_open_fds() {
+        let context = fixtures();
+        let nginx = Process::from_pid_in(42, &context).unwrap();
+        assert_eq!(nginx.open_fds_in(&context).unwrap(), 3);
+        let init = Process::from_pid_in(1, &context).unwrap();
+        assert!(init.open_fds_in(&context).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt;
+use std::path::Path;
+
+use crate::context::Context;
+use crate::procfs::{read_file, FieldError, Result};
+
+/// A line from `/proc/<pid>/mountinfo`
+///
+/// Unlike `/proc/mounts` this says which part of the mounted filesystem is
+/// visible at the mount point, which is how cgroup directories are found.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MountInfo {
+    pub mount_id: u32,
+    pub parent_id: u32,
+    /// The directory in the filesystem that is mounted at `mount_point`
+    pub root: String,
+    pub mount_point: String,
+    pub fs_type: String,
+    pub source: String,
+    /// Filesystem specific options, e.g. the controllers of a cgroup v1
+    /// hierarchy
+    pub super_options: Vec<String>,
+}
+
+impl MountInfo {
+    pub fn from_pid<P: fmt::Display>(pid: P) -> Result<Vec<MountInfo>> {
+        MountInfo::from_pid_in(pid, &Context::from_env())
+    }
+
+    /// Load `<proc_root>/<pid>/mountinfo`, `pid` can be `self`
+    pub fn from_pid_in<P: fmt::Display>(pid: P, context: &Context) -> Result<Vec<MountInfo>> {
+        let path = context.proc_path(format!("{}/mountinfo", pid));
+        MountInfo::parse_all(&read_file(&path)?, &path)
+    }
+
+    pub(crate) fn parse_all(contents: &str, path: &Path) -> Result<Vec<MountInfo>> {
+        contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty())
+            .map(|(i, line)| MountInfo::parse(line, path, i + 1))
+            .collect()
+    }
+
+    fn parse(line: &str, path: &Path, lineno: usize) -> Result<MountInfo> {
+        let invalid =
+            |field: &str, reason: &str| FieldError::new(path, Some(lineno), field, reason);
+        let mut parts = line.split(' ');
+        let mut next = |field: &str| parts.next().ok_or_else(|| invalid(field, "missing"));
+        let id = |field: &str, value: &str| {
+            value.parse().map_err(|e| {
+                FieldError::new(path, Some(lineno), field, format!("{:?}: {}", value, e))
+            })
+        };
+        let mount_id = id("mount ID", next("mount ID")?)?;
+        let parent_id = id("parent ID", next("parent ID")?)?;
+        next("major:minor")?;
+        let root = unescape(next("root")?);
+        let mount_point = unescape(next("mount point")?);
+        next("mount options")?;
+        // optional fields are terminated by a single hyphen
+        while next("separator")? != "-" {}
+        let fs_type = next("filesystem type")?.to_owned();
+        let source = unescape(next("mount source")?);
+        let super_options = next("super options")?
+            .split(',')
+            .map(String::from)
+            .collect();
+        Ok(MountInfo {
+            mount_id,
+            parent_id,
+            root,
+            mount_point,
+            fs_type,
+            source,
+            super_options,
+        })
+    }
+}
+
+/// Undo the octal escaping of spaces, tabs, newlines and backslashes
+pub(crate) fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 4 <= bytes.len() {
+            let octal = std::str::from_utf8(&bytes[i + 1..i + 4]).unwrap_or("");
+            if let Ok(byte) = u8::from_str_radix(octal, 8) {
+                out.push(byte);
+                i += 4;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod unit {
+    use std::path::Path;
+
+    use super::{unescape, MountInfo};
+
+    #[test]
+    fn parses_mountinfo() {
+        let mounts = MountInfo::parse_all(
+            concat!(
+                "36 35 98:0 /mnt1 /mnt\\0402 rw,noatime master:1 - ext3 /dev/root rw,errors=continue\n",
+                "30 25 0:27 /docker/abc /sys/fs/cgroup/memory rw,nosuid - cgroup cgroup rw,memory\n",
+            ),
+            Path::new("mountinfo"),
+        )
+        .unwrap();
+        assert_eq!(mounts[0].root, "/mnt1");
+        assert_eq!(mounts[0].mount_point, "/mnt 2");
+        assert_eq!(mounts[0].fs_type, "ext3");
+        assert_eq!(mounts[1].parent_id, 25);
+        assert_eq!(mounts[1].root, "/docker/abc");
+        assert_eq!(mounts[1].super_options, ["rw", "memory"]);
+
+        let err =
+            MountInfo::parse_all("36 35 98:0 / /mnt rw\n", Path::new("mountinfo")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "mountinfo line 1: invalid separator: missing"
+        );
+    }
+
+    #[test]
+    fn unescapes_octal() {
+        assert_eq!(unescape("a\\040b\\134"), "a b\\");
+        assert_eq!(unescape("trailing\\04"), "trailing\\04");
+    }
+}
@@ -0,0 +1,119 @@
+use std::fmt;
+use std::path::Path;
+
+use crate::context::Context;
+use crate::procfs::{parse_colon_fields, parse_kb, read_file, FieldError, Result};
+
+/// The memory a process uses, summed over its mappings, from
+/// `/proc/<pid>/smaps_rollup`
+///
+/// All values are in KB. Unlike `Stat::rss` this can tell memory shared with
+/// other processes, e.g. the children of a forking server, from memory that
+/// is only used by this one. Needs Linux 4.14, and reading another user's
+/// processes needs root.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct SmapsRollup {
+    /// Resident memory, the same as `Stat::rss`
+    pub rss: usize,
+    /// Proportional set size: private memory plus each shared page divided
+    /// by the number of processes sharing it
+    pub pss: usize,
+    pub shared_clean: usize,
+    pub shared_dirty: usize,
+    pub private_clean: usize,
+    pub private_dirty: usize,
+    /// Anonymous memory that has been swapped out
+    pub swap: usize,
+}
+
+impl SmapsRollup {
+    pub fn from_pid<P: fmt::Display>(pid: P) -> Result<SmapsRollup> {
+        SmapsRollup::from_pid_in(pid, &Context::from_env())
+    }
+
+    /// Load `<proc_root>/<pid>/smaps_rollup`, `pid` can be `self`
+    pub fn from_pid_in<P: fmt::Display>(pid: P, context: &Context) -> Result<SmapsRollup> {
+        let path = context.proc_path(format!("{}/smaps_rollup", pid));
+        SmapsRollup::parse(&read_file(&path)?, &path)
+    }
+
+    pub(crate) fn parse(contents: &str, path: &Path) -> Result<SmapsRollup> {
+        // kernel threads have no memory, and no lines at all
+        if contents.trim().is_empty() {
+            return Ok(SmapsRollup::default());
+        }
+        let fields = parse_colon_fields(contents);
+        let kb =
+            |name| parse_kb(&fields, path, name)?.ok_or_else(|| FieldError::missing(path, name));
+        Ok(SmapsRollup {
+            rss: kb("Rss")?,
+            pss: kb("Pss")?,
+            shared_clean: kb("Shared_Clean")?,
+            shared_dirty: kb("Shared_Dirty")?,
+            private_clean: kb("Private_Clean")?,
+            private_dirty: kb("Private_Dirty")?,
+            swap: kb("Swap")?,
+        })
+    }
+
+    /// Unique set size: the memory that only this process uses, and that
+    /// would be freed if it exited
+    pub fn uss(&self) -> usize {
+        self.private_clean + self.private_dirty
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use std::path::Path;
+
+    use super::SmapsRollup;
+    use crate::context::fixtures;
+
+    #[test]
+    fn parses_smaps_rollup() {
+        let path = Path::new("/proc/1/smaps_rollup");
+        let rollup = SmapsRollup::parse(
+            concat!(
+                "557cebf6c000-7ffdc36dc000 ---p 00000000 00:00 0      [rollup]\n",
+                "Rss:                1248 kB\n",
+                "Pss:                 446 kB\n",
+                "Pss_Anon:            104 kB\n",
+                "Shared_Clean:       1084 kB\n",
+                "Shared_Dirty:          0 kB\n",
+                "Private_Clean:        60 kB\n",
+                "Private_Dirty:       104 kB\n",
+                "Swap:                  8 kB\n",
+            ),
+            path,
+        )
+        .unwrap();
+        assert_eq!(rollup.pss, 446);
+        assert_eq!(rollup.uss(), 164);
+        assert_eq!(rollup.swap, 8);
+        assert_eq!(
+            SmapsRollup::parse("", path).unwrap(),
+            SmapsRollup::default()
+        );
+
+        let err = SmapsRollup::parse("Rss: 12 kB\n", path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "/proc/1/smaps_rollup: invalid Pss: missing"
+        );
+        let err = SmapsRollup::parse("Rss: lots kB\n", path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "/proc/1/smaps_rollup line 1: invalid Rss: \"lots kB\" is not a number: \
+             invalid digit found in string"
+        );
+    }
+
+    #[test]
+    fn loads_from_fixtures() {
+        let rollup = SmapsRollup::from_pid_in(42, &fixtures()).unwrap();
+        assert_eq!(rollup.rss, 8000);
+        assert_eq!(rollup.pss, 3000);
+        assert_eq!(rollup.uss(), 3000);
+    }
+}
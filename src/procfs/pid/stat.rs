@@ -2,14 +2,19 @@ use std::fmt;
 use std::fs::File;
 use std::io::Read;
 use std::str::FromStr;
+use std::time::SystemTime;
 
 use nix::unistd::Pid;
 use scan_fmt::scan_fmt_some;
 use serde::Deserialize;
 
+use crate::context::Context;
 use crate::linux::Jiffies;
 use crate::procfs::{ParseStatError, ParseStateError, ProcFsError, Result};
 
+/// `flags` bit for kernel threads, from `include/linux/sched.h`
+const PF_KTHREAD: u32 = 0x0020_0000;
+
 /// The status of a `Process`
 ///
 /// This represents much of the information in `/proc/[pid]/stat`, and is
@@ -46,14 +51,37 @@ pub struct Stat {
 
 impl Stat {
     pub fn from_pid<P: fmt::Display>(pid: P) -> Result<Stat> {
-        let path_str = format!("/proc/{}/stat", pid);
-        let mut f = File::open(&path_str)?;
+        Stat::from_pid_in(pid, &Context::from_env())
+    }
+
+    /// Load `<proc_root>/<pid>/stat`
+    pub fn from_pid_in<P: fmt::Display>(pid: P, context: &Context) -> Result<Stat> {
+        let mut f = File::open(context.proc_path(format!("{}/stat", pid)))?;
         let mut s = String::new();
         f.read_to_string(&mut s)?;
         s.parse()
     }
 }
 
+impl Stat {
+    /// Whether this is a kernel thread, like `kthreadd` or `kworker/0:1`
+    pub fn is_kernel_thread(&self) -> bool {
+        self.flags & PF_KTHREAD != 0
+    }
+
+    /// When the process started, given when the computer booted
+    ///
+    /// `starttime` is in clock ticks since boot, see `procfs::boot_time`.
+    pub fn start_time(&self, boot_time: SystemTime) -> SystemTime {
+        boot_time + Jiffies::new(self.starttime).duration()
+    }
+
+    /// The cpu time the process has used in user and kernel mode
+    pub fn cpu_time(&self) -> Jiffies {
+        self.utime + self.stime
+    }
+}
+
 impl Default for Stat {
     fn default() -> Stat {
         Stat {
@@ -89,12 +117,12 @@ fn field<T>(val: Option<T>, field_name: &'static str, row: &str, position: u8) -
         Some(v) => Ok(v),
         None => {
             let row = row.to_string();
-            return Err(ParseStatError {
+            Err(ParseStatError {
                 line: row,
                 field_name,
                 position,
             }
-            .into());
+            .into())
         }
     }
 }
@@ -103,9 +131,22 @@ impl FromStr for Stat {
     type Err = ProcFsError;
     /// Parse the results of /proc/[pid]/stat into a `Stat`
     fn from_str(s: &str) -> Result<Stat> {
+        // comm can contain spaces and parens, so everything up to the last
+        // paren is split off before handing the rest to scan_fmt
+        let (open, close) = match (s.find('('), s.rfind(')')) {
+            (Some(open), Some(close)) if open < close => (open, close),
+            _ => {
+                return Err(ParseStatError {
+                    line: s.to_string(),
+                    field_name: "comm",
+                    position: 1,
+                }
+                .into())
+            }
+        };
+        let pid = s[..open].trim().parse().ok();
+        let comm = Some(s[open + 1..close].to_owned());
         let (
-            pid,
-            comm,
             state,
             ppid,
             pgrp,
@@ -128,29 +169,27 @@ impl FromStr for Stat {
             vsize,
             rss,
         ) = scan_fmt_some!(
-            s,
-            "{d} {/\\((.*)\\) /}{} {} {} {} {} {} {d} {d} {d} {d} {d} {d} {d} {} {} \
+            &s[close + 1..],
+            " {} {} {} {} {} {} {d} {d} {d} {d} {d} {d} {d} {} {} \
              {} {} {} 0 {d} {d} {}",
-            i32,    // pid
-            String, // comm
-            State,  // state
-            i32,    // ppid
-            i32,    // pgrp
-            i32,    // session
-            i32,    // tty_nr
-            i32,    // tpgid
-            u32,    // flags
-            u64,    // minflt
-            u64,    // cminflt
-            u64,    // majflt
-            u64,    // cmajflt
-            u64,    // utime
-            u64,    // stime
-            i64,    // cutime (children usertime)
-            i64,    // cstime
-            i64,    // priority
-            i64,    // nice
-            i64,    // num_threads
+            State, // state
+            i32,   // ppid
+            i32,   // pgrp
+            i32,   // session
+            i32,   // tty_nr
+            i32,   // tpgid
+            u32,   // flags
+            u64,   // minflt
+            u64,   // cminflt
+            u64,   // majflt
+            u64,   // cmajflt
+            u64,   // utime
+            u64,   // stime
+            i64,   // cutime (children usertime)
+            i64,   // cstime
+            i64,   // priority
+            i64,   // nice
+            i64,   // num_threads
             // itrealvalue (always 0)
             u64, // starttime FIXME: should be long long int
             u64, // vsize
@@ -246,6 +285,8 @@ impl FromStr for State {
 #[cfg(test)]
 mod test {
     mod cpu_stat {
+        use std::time::UNIX_EPOCH;
+
         use super::super::*;
 
         #[test]
@@ -270,5 +311,27 @@ mod test {
                 });
             }
         }
+
+        #[test]
+        fn parses_comm_with_spaces_and_parens() {
+            let stat: Stat = "529 ((sd-pam) x) S 885 885 885 0 -1 107793 24 0 0 0 7 \
+                              3 0 0 20 0 1 0 250 111111111 647 18848888888888888888"
+                .parse()
+                .unwrap();
+            assert_eq!(stat.pid, Pid::from_raw(529));
+            assert_eq!(stat.comm, "(sd-pam) x");
+            assert_eq!(stat.state, State::Sleeping);
+            assert_eq!(stat.cpu_time(), Jiffies::new(10));
+            assert_eq!(
+                stat.start_time(UNIX_EPOCH),
+                UNIX_EPOCH + Jiffies::new(250).duration()
+            );
+
+            let err = "529 sd-pam S 885".parse::<Stat>().unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "unable to parse 'comm' at position 1 from line '529 sd-pam S 885'"
+            );
+        }
     }
 }
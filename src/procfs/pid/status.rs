@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use nix::sys::signal::Signal;
+
+use crate::context::Context;
+use crate::procfs::{parse_colon_fields, parse_kb, read_file, FieldError, Result};
+
+/// Human readable information about a process, from `/proc/<pid>/status`
+///
+/// Sizes are in KB, and are `None` for kernel threads, which have no memory
+/// of their own.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ProcessStatus {
+    /// The filename of the executable, the same as `Stat::comm`
+    pub name: String,
+    /// The users that the process runs as
+    pub uid: Ids,
+    /// The groups that the process runs as
+    pub gid: Ids,
+    /// The number of threads in the process
+    pub threads: u64,
+    /// The most virtual memory the process has ever had
+    pub vm_peak: Option<usize>,
+    /// Resident memory, the sum of the `rss_*` fields
+    pub vm_rss: Option<usize>,
+    /// Resident anonymous memory
+    pub rss_anon: Option<usize>,
+    /// Resident file mappings
+    pub rss_file: Option<usize>,
+    /// Resident shared memory, including tmpfs
+    pub rss_shmem: Option<usize>,
+    /// Anonymous memory that has been swapped out
+    pub vm_swap: Option<usize>,
+    /// Times the process gave up the cpu, e.g. to wait for IO
+    pub voluntary_ctxt_switches: u64,
+    /// Times the process was preempted because its time slice ran out
+    pub nonvoluntary_ctxt_switches: u64,
+    /// Signals that are blocked
+    pub sig_blk: SignalMask,
+    /// Signals that are ignored
+    pub sig_ign: SignalMask,
+    /// The effective capabilities, a bit mask of `CAP_*` numbers
+    pub cap_eff: u64,
+    /// `None` if the kernel was built without seccomp
+    pub seccomp: Option<Seccomp>,
+}
+
+/// The real, effective, saved set and filesystem IDs from the `Uid` or `Gid`
+/// line
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Ids {
+    /// The user or group that started the process
+    pub real: u32,
+    /// The ID that is used for permission checks, which differs from `real`
+    /// for setuid programs
+    pub effective: u32,
+    pub saved: u32,
+    pub filesystem: u32,
+}
+
+/// A set of signals from a line like `SigIgn: 0000000000001000`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct SignalMask(pub u64);
+
+impl SignalMask {
+    /// Whether `signal` is in the set
+    pub fn contains(self, signal: Signal) -> bool {
+        let bit = signal as i32 - 1;
+        self.0 & (1 << bit) != 0
+    }
+}
+
+/// How a process is restricted by seccomp
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Seccomp {
+    /// Not restricted
+    Disabled,
+    /// Only read, write, exit and sigreturn are allowed
+    Strict,
+    /// Syscalls are checked by a BPF filter, as Docker and systemd set up
+    Filter,
+}
+
+impl ProcessStatus {
+    pub fn from_pid<P: fmt::Display>(pid: P) -> Result<ProcessStatus> {
+        ProcessStatus::from_pid_in(pid, &Context::from_env())
+    }
+
+    /// Load `<proc_root>/<pid>/status`, `pid` can be `self`
+    pub fn from_pid_in<P: fmt::Display>(pid: P, context: &Context) -> Result<ProcessStatus> {
+        let path = context.proc_path(format!("{}/status", pid));
+        ProcessStatus::parse(&read_file(&path)?, &path)
+    }
+
+    pub(crate) fn parse(contents: &str, path: &Path) -> Result<ProcessStatus> {
+        let fields = parse_colon_fields(contents);
+        let kb = |name| parse_kb(&fields, path, name);
+        let parsed = Parser {
+            fields: &fields,
+            path,
+        };
+        Ok(ProcessStatus {
+            name: parsed.get("Name")?.to_owned(),
+            uid: parsed.ids("Uid")?,
+            gid: parsed.ids("Gid")?,
+            threads: parsed.number("Threads")?,
+            vm_peak: kb("VmPeak")?,
+            vm_rss: kb("VmRSS")?,
+            rss_anon: kb("RssAnon")?,
+            rss_file: kb("RssFile")?,
+            rss_shmem: kb("RssShmem")?,
+            vm_swap: kb("VmSwap")?,
+            voluntary_ctxt_switches: parsed.number("voluntary_ctxt_switches")?,
+            nonvoluntary_ctxt_switches: parsed.number("nonvoluntary_ctxt_switches")?,
+            sig_blk: SignalMask(parsed.hex("SigBlk")?),
+            sig_ign: SignalMask(parsed.hex("SigIgn")?),
+            cap_eff: parsed.hex("CapEff")?,
+            seccomp: match fields.get("Seccomp").map(|&(_, mode)| mode) {
+                None => None,
+                Some("0") => Some(Seccomp::Disabled),
+                Some("1") => Some(Seccomp::Strict),
+                Some("2") => Some(Seccomp::Filter),
+                Some(_) => return Err(parsed.invalid("Seccomp", "expected 0, 1 or 2").into()),
+            },
+        })
+    }
+}
+
+/// Typed access to the result of `parse_colon_fields`
+struct Parser<'a> {
+    fields: &'a HashMap<&'a str, (usize, &'a str)>,
+    path: &'a Path,
+}
+
+impl<'a> Parser<'a> {
+    fn get(&self, name: &str) -> std::result::Result<&'a str, FieldError> {
+        self.fields
+            .get(name)
+            .map(|&(_, value)| value)
+            .ok_or_else(|| FieldError::missing(self.path, name))
+    }
+
+    fn invalid<R: fmt::Display>(&self, name: &str, reason: R) -> FieldError {
+        let line = self.fields.get(name).map(|&(line, _)| line);
+        FieldError::new(self.path, line, name, reason)
+    }
+
+    fn number(&self, name: &str) -> std::result::Result<u64, FieldError> {
+        let value = self.get(name)?;
+        value
+            .parse()
+            .map_err(|e| self.invalid(name, format!("{:?} is not a number: {}", value, e)))
+    }
+
+    fn hex(&self, name: &str) -> std::result::Result<u64, FieldError> {
+        let value = self.get(name)?;
+        u64::from_str_radix(value, 16)
+            .map_err(|e| self.invalid(name, format!("{:?} is not a hex mask: {}", value, e)))
+    }
+
+    fn ids(&self, name: &str) -> std::result::Result<Ids, FieldError> {
+        let value = self.get(name)?;
+        let ids = value
+            .split_whitespace()
+            .map(|id| id.parse())
+            .collect::<std::result::Result<Vec<u32>, _>>()
+            .map_err(|e| self.invalid(name, format!("{:?}: {}", value, e)))?;
+        match ids[..] {
+            [real, effective, saved, filesystem] => Ok(Ids {
+                real,
+                effective,
+                saved,
+                filesystem,
+            }),
+            _ => Err(self.invalid(name, format!("expected four IDs, got {:?}", value))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use std::path::Path;
+
+    use nix::sys::signal::Signal;
+
+    use super::{Ids, ProcessStatus, Seccomp, SignalMask};
+    use crate::context::fixtures;
+
+    const STATUS: &str = concat!(
+        "Name:\tcat\n",
+        "State:\tR (running)\n",
+        "Uid:\t1000\t0\t0\t0\n",
+        "Gid:\t100\t100\t100\t100\n",
+        "VmPeak:\t    2640 kB\n",
+        "VmRSS:\t    1300 kB\n",
+        "RssAnon:\t     104 kB\n",
+        "VmSwap:\t       0 kB\n",
+        "Threads:\t3\n",
+        "SigBlk:\t0000000000000000\n",
+        "SigIgn:\t0000000000001000\n",
+        "CapEff:\t000001ffffffffff\n",
+        "Seccomp:\t2\n",
+        "voluntary_ctxt_switches:\t10\n",
+        "nonvoluntary_ctxt_switches:\t2\n",
+    );
+
+    #[test]
+    fn parses_status() {
+        let path = Path::new("/proc/1/status");
+        let status = ProcessStatus::parse(STATUS, path).unwrap();
+        assert_eq!(status.name, "cat");
+        assert_eq!(
+            status.uid,
+            Ids {
+                real: 1000,
+                effective: 0,
+                saved: 0,
+                filesystem: 0
+            }
+        );
+        assert_eq!(status.gid.effective, 100);
+        assert_eq!(status.threads, 3);
+        assert_eq!(status.vm_peak, Some(2640));
+        assert_eq!(status.vm_rss, Some(1300));
+        assert_eq!(status.rss_file, None);
+        assert_eq!(status.nonvoluntary_ctxt_switches, 2);
+        assert!(status.sig_ign.contains(Signal::SIGPIPE));
+        assert!(!status.sig_ign.contains(Signal::SIGTERM));
+        assert_eq!(status.sig_blk, SignalMask(0));
+        assert_eq!(status.cap_eff, 0x1ff_ffff_ffff);
+        assert_eq!(status.seccomp, Some(Seccomp::Filter));
+
+        let kthread = ProcessStatus::parse(&STATUS.replace("VmRSS", "Other"), path).unwrap();
+        assert_eq!(kthread.vm_rss, None);
+        let old = ProcessStatus::parse(&STATUS.replace("Seccomp", "Other"), path).unwrap();
+        assert_eq!(old.seccomp, None);
+    }
+
+    #[test]
+    fn status_errors_have_context() {
+        let path = Path::new("/proc/1/status");
+        let err = ProcessStatus::parse(&STATUS.replace("Name", "Other"), path).unwrap_err();
+        assert_eq!(err.to_string(), "/proc/1/status: invalid Name: missing");
+        let err = ProcessStatus::parse(&STATUS.replace("\t0\t0\t0\n", "\t0\n"), path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "/proc/1/status line 3: invalid Uid: expected four IDs, got \"1000\\t0\""
+        );
+        let err = ProcessStatus::parse(&STATUS.replace("0000000000001000", "x"), path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "/proc/1/status line 11: invalid SigIgn: \"x\" is not a hex mask: \
+             invalid digit found in string"
+        );
+    }
+
+    #[test]
+    fn loads_from_fixtures() {
+        let status = ProcessStatus::from_pid_in(42, &fixtures()).unwrap();
+        assert_eq!(status.name, "nginx");
+        assert_eq!(status.vm_rss, Some(8000));
+        assert_eq!(status.uid.real, 33);
+        assert_eq!(status.threads, 4);
+        assert_eq!(status.seccomp, Some(Seccomp::Filter));
+    }
+}
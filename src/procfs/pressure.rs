@@ -0,0 +1,196 @@
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::context::Context;
+use crate::procfs::{read_file, FieldError, Result};
+
+/// A resource that the kernel tracks pressure stalls for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PressureResource {
+    Cpu,
+    Memory,
+    Io,
+}
+
+impl PressureResource {
+    /// Every resource, in the order the kernel documents them
+    pub fn all() -> [PressureResource; 3] {
+        [
+            PressureResource::Cpu,
+            PressureResource::Memory,
+            PressureResource::Io,
+        ]
+    }
+
+    /// The legal values for `from_str`
+    pub fn str_values() -> [&'static str; 3] {
+        ["cpu", "memory", "io"]
+    }
+}
+
+impl FromStr for PressureResource {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<PressureResource, String> {
+        match s {
+            "cpu" => Ok(PressureResource::Cpu),
+            "memory" => Ok(PressureResource::Memory),
+            "io" => Ok(PressureResource::Io),
+            _ => Err(format!("Unexpected pressure resource: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for PressureResource {
+    /// The name of the resource's file, e.g. `memory` for
+    /// `/proc/pressure/memory` and `memory.pressure`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            PressureResource::Cpu => "cpu",
+            PressureResource::Memory => "memory",
+            PressureResource::Io => "io",
+        })
+    }
+}
+
+/// Pressure stall information for one resource
+///
+/// From `/proc/pressure/<resource>` for the whole system, or
+/// `<resource>.pressure` in a cgroup v2 directory. See
+/// https://www.kernel.org/doc/html/latest/accounting/psi.html
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pressure {
+    /// Time in which at least one task was stalled on the resource
+    pub some: Stalls,
+    /// Time in which every non-idle task was stalled at once, i.e. no work
+    /// got done. Linux before 5.13 doesn't report this for cpu.
+    pub full: Option<Stalls>,
+}
+
+/// One line of a pressure file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stalls {
+    /// The percent of the last 10 seconds that tasks were stalled
+    pub avg10: f64,
+    /// The percent of the last 60 seconds that tasks were stalled
+    pub avg60: f64,
+    /// The percent of the last 300 seconds that tasks were stalled
+    pub avg300: f64,
+    /// The total time that tasks have been stalled
+    pub total: Duration,
+}
+
+impl Pressure {
+    pub fn load(resource: PressureResource) -> Result<Pressure> {
+        Pressure::load_in(resource, &Context::from_env())
+    }
+
+    /// Load `<proc_root>/pressure/<resource>`
+    pub fn load_in(resource: PressureResource, context: &Context) -> Result<Pressure> {
+        let path = context.proc_path(format!("pressure/{}", resource));
+        Ok(Pressure::parse(&read_file(&path)?, &path)?)
+    }
+
+    pub(crate) fn parse(contents: &str, path: &Path) -> std::result::Result<Pressure, FieldError> {
+        let mut some = None;
+        let mut full = None;
+        for (i, line) in contents.lines().enumerate() {
+            let mut parts = line.split_whitespace();
+            match parts.next() {
+                Some("some") => some = Some(Stalls::parse(parts, path, i + 1)?),
+                Some("full") => full = Some(Stalls::parse(parts, path, i + 1)?),
+                _ => {}
+            }
+        }
+        Ok(Pressure {
+            some: some.ok_or_else(|| FieldError::missing(path, "some"))?,
+            full,
+        })
+    }
+}
+
+impl Stalls {
+    fn parse<'a, I>(fields: I, path: &Path, line: usize) -> std::result::Result<Stalls, FieldError>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let (mut avg10, mut avg60, mut avg300, mut total) = (None, None, None, None);
+        for field in fields {
+            let mut kv = field.splitn(2, '=');
+            let (name, value) = match (kv.next(), kv.next()) {
+                (Some(name), Some(value)) => (name, value),
+                _ => {
+                    return Err(FieldError::new(
+                        path,
+                        Some(line),
+                        field,
+                        "expected `name=value`",
+                    ))
+                }
+            };
+            let invalid = |e: &dyn fmt::Display| {
+                FieldError::new(path, Some(line), name, format!("{:?}: {}", value, e))
+            };
+            match name {
+                "avg10" => avg10 = Some(value.parse().map_err(|e| invalid(&e))?),
+                "avg60" => avg60 = Some(value.parse().map_err(|e| invalid(&e))?),
+                "avg300" => avg300 = Some(value.parse().map_err(|e| invalid(&e))?),
+                "total" => {
+                    total = Some(Duration::from_micros(
+                        value.parse().map_err(|e| invalid(&e))?,
+                    ))
+                }
+                _ => {}
+            }
+        }
+        let missing = |name| FieldError::new(path, Some(line), name, "missing");
+        Ok(Stalls {
+            avg10: avg10.ok_or_else(|| missing("avg10"))?,
+            avg60: avg60.ok_or_else(|| missing("avg60"))?,
+            avg300: avg300.ok_or_else(|| missing("avg300"))?,
+            total: total.ok_or_else(|| missing("total"))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use std::path::Path;
+    use std::time::Duration;
+
+    use super::{Pressure, PressureResource};
+    use crate::context::fixtures;
+
+    #[test]
+    fn parses_pressure() {
+        let path = Path::new("/proc/pressure/cpu");
+        let pressure = Pressure::parse(
+            "some avg10=7.48 avg60=5.05 avg300=4.05 total=152880319\n",
+            path,
+        )
+        .unwrap();
+        assert_eq!(pressure.some.avg10, 7.48);
+        assert_eq!(pressure.some.total, Duration::from_micros(152_880_319));
+        assert_eq!(pressure.full, None);
+
+        let err = Pressure::parse("some avg10=x avg60=0 avg300=0 total=0\n", path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "/proc/pressure/cpu line 1: invalid avg10: \"x\": invalid float literal"
+        );
+        let err = Pressure::parse("full avg10=0 avg60=0 avg300=0 total=0\n", path).unwrap_err();
+        assert_eq!(err.to_string(), "/proc/pressure/cpu: invalid some: missing");
+    }
+
+    #[test]
+    fn loads_from_fixtures() {
+        let memory = Pressure::load_in(PressureResource::Memory, &fixtures()).unwrap();
+        assert_eq!(memory.full.unwrap().avg60, 12.5);
+        assert_eq!("io".parse(), Ok(PressureResource::Io));
+    }
+}
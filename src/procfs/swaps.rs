@@ -0,0 +1,118 @@
+use std::fmt;
+use std::path::Path;
+
+use crate::context::Context;
+use crate::procfs::pid::unescape;
+use crate::procfs::{read_file, FieldError, Result};
+
+/// A swap device or file, from `/proc/swaps`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Swap {
+    pub filename: String,
+    /// `partition` or `file`
+    pub kind: String,
+    /// The size in KB
+    pub size: usize,
+    /// The KB in use
+    pub used: usize,
+    /// Higher priority swap is used first
+    pub priority: i32,
+}
+
+impl Swap {
+    /// Every active swap area, from /proc/swaps
+    pub fn load_all() -> Result<Vec<Swap>> {
+        Swap::load_all_in(&Context::from_env())
+    }
+
+    /// Like `load_all`, but reading `<proc_root>/swaps`
+    pub fn load_all_in(context: &Context) -> Result<Vec<Swap>> {
+        let path = context.proc_path("swaps");
+        Swap::parse_all(&read_file(&path)?, &path)
+    }
+
+    pub(crate) fn parse_all(contents: &str, path: &Path) -> Result<Vec<Swap>> {
+        contents
+            .lines()
+            .enumerate()
+            // the first line is a header
+            .skip(1)
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| Swap::parse(line, path, i + 1))
+            .collect()
+    }
+
+    fn parse(line: &str, path: &Path, lineno: usize) -> Result<Swap> {
+        let mut parts = line.split_whitespace();
+        let mut next = |field: &str| {
+            parts
+                .next()
+                .ok_or_else(|| FieldError::new(path, Some(lineno), field, "missing"))
+        };
+        let filename = unescape(next("Filename")?);
+        let kind = next("Type")?.to_owned();
+        let (size, used, priority) = (next("Size")?, next("Used")?, next("Priority")?);
+        let invalid = |field: &str, value: &str, e: &dyn fmt::Display| {
+            FieldError::new(
+                path,
+                Some(lineno),
+                field,
+                format!("{:?} is not a number: {}", value, e),
+            )
+        };
+        Ok(Swap {
+            filename,
+            kind,
+            size: size.parse().map_err(|e| invalid("Size", size, &e))?,
+            used: used.parse().map_err(|e| invalid("Used", used, &e))?,
+            priority: priority
+                .parse()
+                .map_err(|e| invalid("Priority", priority, &e))?,
+        })
+    }
+
+    /// The percent of this swap area in use
+    pub fn percent_used(&self) -> f64 {
+        if self.size == 0 {
+            0.0
+        } else {
+            self.used as f64 / self.size as f64 * 100.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use std::path::Path;
+
+    use super::Swap;
+    use crate::context::fixtures;
+
+    #[test]
+    fn parses_swaps() {
+        let swaps = Swap::parse_all(
+            concat!(
+                "Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority\n",
+                "/dev/sda2                               partition\t8388604\t\t0\t\t-2\n",
+                "/swap\\040file                           file\t\t1048572\t\t524286\t\t-3\n",
+            ),
+            Path::new("/proc/swaps"),
+        )
+        .unwrap();
+        assert_eq!(swaps.len(), 2);
+        assert_eq!(swaps[0].priority, -2);
+        assert_eq!(swaps[1].filename, "/swap file");
+        assert_eq!(swaps[1].percent_used(), 50.0);
+
+        let err =
+            Swap::parse_all("header\n/dev/sda2 partition 10\n", Path::new("swaps")).unwrap_err();
+        assert_eq!(err.to_string(), "swaps line 2: invalid Used: missing");
+    }
+
+    #[test]
+    fn loads_from_fixtures() {
+        let swaps = Swap::load_all_in(&fixtures()).unwrap();
+        assert_eq!(swaps[0].filename, "/dev/sda2");
+        assert_eq!(swaps[0].used, 1000000);
+    }
+}
@@ -0,0 +1,279 @@
+//! Processes as a tree of parents and children
+//!
+//! `/proc` only lists processes by pid, each `Stat` names its parent. A
+//! `ProcessTree` links them up so that checks can walk a process's
+//! descendants or ancestors, and add up what a whole subtree uses.
+
+use std::collections::{HashMap, HashSet};
+
+use nix::unistd::Pid;
+
+use crate::linux::{Jiffies, PAGESIZE};
+use crate::procfs::pid::Process;
+
+/// The parent and child relationships between processes, from `Stat::ppid`
+///
+/// A tree can be built from any set of processes, e.g. only those in a
+/// container. Processes whose parent isn't in the set are the roots.
+#[derive(Debug, Default)]
+pub struct ProcessTree<'a> {
+    procs: HashMap<Pid, &'a Process>,
+    children: HashMap<Pid, Vec<Pid>>,
+}
+
+/// The resources used by a process and all of its descendants
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubtreeTotals {
+    /// The number of processes, including the one at the top
+    pub processes: usize,
+    /// Resident memory in bytes, counting shared memory once per process
+    pub rss: u64,
+    /// User and system time since each process started
+    pub cpu_time: Jiffies,
+}
+
+impl<'a> ProcessTree<'a> {
+    pub fn new<I>(procs: I) -> ProcessTree<'a>
+    where
+        I: IntoIterator<Item = &'a Process>,
+    {
+        let procs = procs
+            .into_iter()
+            .map(|process| (process.stat.pid, process))
+            .collect::<HashMap<_, _>>();
+        let mut children = HashMap::<_, Vec<_>>::new();
+        for process in procs.values() {
+            if process.stat.ppid != process.stat.pid {
+                children
+                    .entry(process.stat.ppid)
+                    .or_default()
+                    .push(process.stat.pid);
+            }
+        }
+        for pids in children.values_mut() {
+            pids.sort_by_key(|pid| pid.as_raw());
+        }
+        ProcessTree { procs, children }
+    }
+
+    pub fn get(&self, pid: Pid) -> Option<&'a Process> {
+        self.procs.get(&pid).cloned()
+    }
+
+    /// The processes that `pid` started, lowest pid first
+    pub fn children(&self, pid: Pid) -> impl Iterator<Item = &'a Process> + '_ {
+        self.children
+            .get(&pid)
+            .into_iter()
+            .flatten()
+            .filter_map(move |child| self.get(*child))
+    }
+
+    /// The children of `pid`, their children and so on, each before its own
+    /// children
+    pub fn descendants(&self, pid: Pid) -> Descendants<'_, 'a> {
+        let mut stack = self.children.get(&pid).cloned().unwrap_or_default();
+        stack.reverse();
+        Descendants {
+            tree: self,
+            stack,
+            seen: Some(pid).into_iter().collect(),
+        }
+    }
+
+    /// The parent of `pid`, its parent and so on up to a root
+    pub fn ancestors(&self, pid: Pid) -> Ancestors<'_, 'a> {
+        Ancestors {
+            tree: self,
+            pid,
+            seen: Some(pid).into_iter().collect(),
+        }
+    }
+
+    /// The processes whose parent isn't in the tree, lowest pid first
+    pub fn roots(&self) -> Vec<&'a Process> {
+        let mut roots = self
+            .procs
+            .values()
+            .filter(|process| !self.procs.contains_key(&process.stat.ppid))
+            .cloned()
+            .collect::<Vec<_>>();
+        roots.sort_by_key(|process| process.stat.pid.as_raw());
+        roots
+    }
+
+    /// The highest ancestor of `pid` that init didn't start itself, or `pid`
+    /// if it has none
+    ///
+    /// This is usually the service or login session that a process belongs
+    /// to. Init's own children aren't grouped under it, nor are a container's
+    /// under the container's init if the tree holds only its processes.
+    pub fn top_level(&self, pid: Pid) -> Pid {
+        let mut top = pid;
+        for ancestor in self.ancestors(pid) {
+            if ancestor.stat.pid.as_raw() <= 1 {
+                break;
+            }
+            top = ancestor.stat.pid;
+        }
+        top
+    }
+
+    /// The resources used by `pid` and all of its descendants
+    pub fn totals(&self, pid: Pid) -> SubtreeTotals {
+        let mut totals = SubtreeTotals {
+            processes: 0,
+            rss: 0,
+            cpu_time: Jiffies::new(0),
+        };
+        for process in self.get(pid).into_iter().chain(self.descendants(pid)) {
+            totals.processes += 1;
+            totals.rss += process.stat.rss * *PAGESIZE;
+            totals.cpu_time = totals.cpu_time + process.stat.cpu_time();
+        }
+        totals
+    }
+
+    pub fn len(&self) -> usize {
+        self.procs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.procs.is_empty()
+    }
+}
+
+/// See `ProcessTree::descendants`
+#[derive(Debug)]
+pub struct Descendants<'t, 'a> {
+    tree: &'t ProcessTree<'a>,
+    stack: Vec<Pid>,
+    // a snapshot of /proc can have cycles if pids were reused while it was
+    // being read
+    seen: HashSet<Pid>,
+}
+
+impl<'t, 'a> Iterator for Descendants<'t, 'a> {
+    type Item = &'a Process;
+
+    fn next(&mut self) -> Option<&'a Process> {
+        while let Some(pid) = self.stack.pop() {
+            if !self.seen.insert(pid) {
+                continue;
+            }
+            if let Some(children) = self.tree.children.get(&pid) {
+                self.stack.extend(children.iter().rev());
+            }
+            if let Some(process) = self.tree.get(pid) {
+                return Some(process);
+            }
+        }
+        None
+    }
+}
+
+/// See `ProcessTree::ancestors`
+#[derive(Debug)]
+pub struct Ancestors<'t, 'a> {
+    tree: &'t ProcessTree<'a>,
+    pid: Pid,
+    seen: HashSet<Pid>,
+}
+
+impl<'t, 'a> Iterator for Ancestors<'t, 'a> {
+    type Item = &'a Process;
+
+    fn next(&mut self) -> Option<&'a Process> {
+        let ppid = self.tree.get(self.pid)?.stat.ppid;
+        let parent = self.tree.get(ppid)?;
+        if !self.seen.insert(ppid) {
+            return None;
+        }
+        self.pid = ppid;
+        Some(parent)
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use nix::unistd::Pid;
+
+    use super::{ProcessTree, SubtreeTotals};
+    use crate::linux::{Jiffies, PAGESIZE};
+    use crate::procfs::pid::Process;
+
+    /// init -> sshd -> bash -> {vim, make -> cc}, and init -> cron
+    fn procs() -> Vec<Process> {
+        [
+            (1, 0),
+            (10, 1),
+            (20, 10),
+            (30, 20),
+            (31, 20),
+            (40, 31),
+            (50, 1),
+        ]
+        .iter()
+        .map(|&(pid, ppid)| {
+            let mut process = Process::default();
+            process.stat.pid = Pid::from_raw(pid);
+            process.stat.ppid = Pid::from_raw(ppid);
+            process.stat.rss = 10;
+            process.stat.utime = Jiffies::new(3);
+            process.stat.stime = Jiffies::new(1);
+            process
+        })
+        .collect()
+    }
+
+    fn pids<'a, I: Iterator<Item = &'a Process>>(procs: I) -> Vec<i32> {
+        procs.map(|process| process.stat.pid.as_raw()).collect()
+    }
+
+    #[test]
+    fn walks_descendants_and_ancestors() {
+        let procs = procs();
+        let tree = ProcessTree::new(&procs);
+        let pid = Pid::from_raw;
+        assert_eq!(pids(tree.children(pid(1))), [10, 50]);
+        assert_eq!(pids(tree.descendants(pid(10))), [20, 30, 31, 40]);
+        assert_eq!(pids(tree.descendants(pid(40))), Vec::<i32>::new());
+        assert_eq!(pids(tree.ancestors(pid(40))), [31, 20, 10, 1]);
+        assert_eq!(pids(tree.ancestors(pid(1))), Vec::<i32>::new());
+        assert_eq!(pids(tree.roots().into_iter()), [1]);
+        assert_eq!(tree.top_level(pid(40)), pid(10));
+        assert_eq!(tree.top_level(pid(50)), pid(50));
+        assert_eq!(tree.top_level(pid(1)), pid(1));
+
+        // without init, its children are the roots
+        let tree = ProcessTree::new(&procs[1..]);
+        assert_eq!(pids(tree.roots().into_iter()), [10, 50]);
+        assert_eq!(tree.top_level(pid(40)), pid(10));
+    }
+
+    #[test]
+    fn survives_cycles() {
+        let mut procs = procs();
+        // sshd's pid was reused by a child of cc while /proc was being read
+        procs[1].stat.ppid = Pid::from_raw(40);
+        let tree = ProcessTree::new(&procs);
+        assert_eq!(pids(tree.ancestors(Pid::from_raw(30))), [20, 10, 40, 31]);
+        assert_eq!(pids(tree.descendants(Pid::from_raw(40))), [10, 20, 30, 31]);
+    }
+
+    #[test]
+    fn totals_subtrees() {
+        let procs = procs();
+        let tree = ProcessTree::new(&procs);
+        assert_eq!(
+            tree.totals(Pid::from_raw(20)),
+            SubtreeTotals {
+                processes: 4,
+                rss: 40 * *PAGESIZE,
+                cpu_time: Jiffies::new(16),
+            }
+        );
+        assert_eq!(tree.totals(Pid::from_raw(1)).processes, 7);
+        assert_eq!(tree.totals(Pid::from_raw(99)).processes, 0);
+    }
+}
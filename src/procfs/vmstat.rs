@@ -0,0 +1,102 @@
+use std::path::Path;
+
+use crate::context::Context;
+use crate::procfs::{read_file, FieldError, Result};
+
+/// Counters from `/proc/vmstat`, counted since boot
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct VmStat {
+    /// Pages swapped in
+    pub pswpin: u64,
+    /// Pages swapped out
+    pub pswpout: u64,
+    /// Page faults that had to read from disk
+    pub pgmajfault: u64,
+}
+
+impl VmStat {
+    /// Read /proc/vmstat
+    pub fn load() -> Result<VmStat> {
+        VmStat::load_in(&Context::from_env())
+    }
+
+    /// Like `load`, but reading `<proc_root>/vmstat`
+    pub fn load_in(context: &Context) -> Result<VmStat> {
+        let path = context.proc_path("vmstat");
+        VmStat::parse(&read_file(&path)?, &path)
+    }
+
+    pub(crate) fn parse(contents: &str, path: &Path) -> Result<VmStat> {
+        let (mut pswpin, mut pswpout, mut pgmajfault) = (None, None, None);
+        for (i, line) in contents.lines().enumerate() {
+            let mut parts = line.split_whitespace();
+            let (name, value) = match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) => (name, value),
+                _ => continue,
+            };
+            let field = match name {
+                "pswpin" => &mut pswpin,
+                "pswpout" => &mut pswpout,
+                "pgmajfault" => &mut pgmajfault,
+                _ => continue,
+            };
+            *field = Some(value.parse().map_err(|e| {
+                FieldError::new(
+                    path,
+                    Some(i + 1),
+                    name,
+                    format!("{:?} is not a number: {}", value, e),
+                )
+            })?);
+        }
+        let missing = |name| FieldError::missing(path, name);
+        Ok(VmStat {
+            pswpin: pswpin.ok_or_else(|| missing("pswpin"))?,
+            pswpout: pswpout.ok_or_else(|| missing("pswpout"))?,
+            pgmajfault: pgmajfault.ok_or_else(|| missing("pgmajfault"))?,
+        })
+    }
+
+    /// The counts between `earlier` and this
+    pub fn since(&self, earlier: &VmStat) -> VmStat {
+        VmStat {
+            pswpin: self.pswpin.saturating_sub(earlier.pswpin),
+            pswpout: self.pswpout.saturating_sub(earlier.pswpout),
+            pgmajfault: self.pgmajfault.saturating_sub(earlier.pgmajfault),
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use std::path::Path;
+
+    use super::VmStat;
+    use crate::context::fixtures;
+
+    #[test]
+    fn parses_vmstat() {
+        let path = Path::new("/proc/vmstat");
+        let stat = VmStat::parse(
+            "nr_free_pages 1\npswpin 10\npswpout 20\npgmajfault 3\n",
+            path,
+        )
+        .unwrap();
+        assert_eq!(stat.pswpout, 20);
+        let earlier = VmStat {
+            pswpin: 4,
+            pswpout: 20,
+            pgmajfault: 1,
+        };
+        assert_eq!(stat.since(&earlier).pswpin, 6);
+        assert_eq!(
+            VmStat::parse("pswpin 10\n", path).unwrap_err().to_string(),
+            "/proc/vmstat: invalid pswpout: missing"
+        );
+    }
+
+    #[test]
+    fn loads_from_fixtures() {
+        assert_eq!(VmStat::load_in(&fixtures()).unwrap().pswpin, 150);
+    }
+}
@@ -4,7 +4,10 @@
 //! - [check-cpu](#check-cpu)
 //! - [check-container-cpu](#check-container-cpu)
 //! - [check-load](#check-load)
+//! - [check-pressure](#check-pressure)
 //! - [check-ram](#check-ram)
+//! - [check-swap](#check-swap)
+//! - [check-hugepages](#check-hugepages)
 //! - [check-container-ram](#check-container-ram)
 //! - [check-procs](#check-procs)
 //! - [check-fs-writeable](#check-fs-writeable)
@@ -224,6 +227,75 @@
 //!
 //! ```
 //!
+//! # check-pressure
+//!
+//! Linux-only, needs Linux 4.20 or later, and cgroup v2 to check containers.
+//!
+//! ```plain
+//! $ check-pressure --help
+//! check-pressure (part of tabin-plugins) 0.3.1
+//! Check pressure stall information (PSI)
+//!
+//! PSI is the percent of time in which tasks were stalled waiting for cpu, memory or io, averaged over the last 10, 60 and
+//! 300 seconds. Unlike the load average it measures how much work is being delayed, and it is tracked per resource and per
+//! cgroup. It needs Linux 4.20 or later.
+//!
+//! By default this checks the whole system, from /proc/pressure. With --container it checks the cgroup it is running in,
+//! and from the host --cgroup, --pid or --container-id select a container, with --children checking every container under
+//! e.g. a slice. Containers need cgroup v2.
+//!
+//! USAGE:
+//!     check-pressure [FLAGS] [OPTIONS]
+//!
+//! FLAGS:
+//!         --children     Check every child of the selected cgroup, e.g. every container in a slice, and report the worst
+//!         --container    Check the cgroup this is running in instead of the whole system
+//!         --full         Check the 'full' line instead of 'some'
+//!     -h, --help         Prints help information
+//!     -V, --version      Prints version information
+//!     -v, --verbose      Print more details and debug logs, use twice for trace logs. Logging can also be configured with
+//!                        TABIN_LOG
+//!
+//! OPTIONS:
+//!         --cgroup <PATH>               Check the cgroup at PATH in the cgroup hierarchy, as shown in /proc/<pid>/cgroup,
+//!                                       e.g. /system.slice/docker.service
+//!         --show-children <COUNT>       With --children, list the COUNT children using the most [default: 5]
+//!         --container-id <ID>           Check the cgroup of the container with this ID, or a unique prefix of it
+//!         --status-on-error <STATUS>    The status to exit with if the check itself fails, e.g. because something could
+//!                                       not be read [default: unknown]  [possible values: ok, warning, critical, unknown]
+//!         --pid <PID>                   Check the cgroup that PID is in
+//!         --proc-root <DIR>             Read the proc filesystem from DIR instead of /proc, e.g. the host's /proc mounted
+//!                                       into a container
+//!         --resource <RESOURCE>...      Only check these resources, may be given more than once [default: all of them]
+//!                                       [possible values: cpu, memory, io]
+//!         --sys-root <DIR>              Read the sys filesystem from DIR instead of /sys
+//!         --timeout <SECONDS>           Give up if the check takes longer than this
+//!         --timeout-status <STATUS>     The status to exit with if --timeout is reached [default: unknown]  [possible
+//!                                       values: ok, warning, critical, unknown]
+//!     -c, --crit <crit>                 Percents stalled to go critical at, as nagios ranges [default: 80,40,20]
+//!         --crit-cpu <RANGES>           Percents of cpu stalled to go critical at, instead of --crit
+//!         --crit-io <RANGES>            Percents of io stalled to go critical at, instead of --crit
+//!         --crit-memory <RANGES>        Percents of memory stalled to go critical at, instead of --crit
+//!         --format <format>             How to print the result [default: nagios]  [possible values: nagios, json, sensu,
+//!                                       human]
+//!     -w, --warn <warn>                 Percents stalled to warn at, as nagios ranges [default: 40,20,10]
+//!         --warn-cpu <RANGES>           Percents of cpu stalled to warn at, instead of --warn
+//!         --warn-io <RANGES>            Percents of io stalled to warn at, instead of --warn
+//!         --warn-memory <RANGES>        Percents of memory stalled to warn at, instead of --warn
+//!
+//! About thresholds:
+//!
+//!     Thresholds are percents of time stalled, given as one nagios range for
+//!     all three averages or three comma separated ranges for the 10, 60 and 300
+//!     second averages. '--warn' and '--crit' apply to every resource unless it
+//!     has its own, e.g. '--warn-memory 10,5,2'.
+//!
+//!     By default the 'some' line is checked: the time in which at least one
+//!     task was stalled. With --full the 'full' line is checked instead: the
+//!     time in which every task was stalled at once, so no work got done at all.
+//!     Linux before 5.13 doesn't report 'full' for cpu, which is skipped.
+//! ```
+//!
 //! # check-ram
 //!
 //! Linux-only.
@@ -248,6 +320,97 @@
 //!
 //! ```
 //!
+//! # check-swap
+//!
+//! Linux-only.
+//!
+//! ```plain
+//! $ check-swap --help
+//! check-swap (part of tabin-plugins) 0.3.1
+//! Check the swap usage of the current computer
+//!
+//! Thresholds are percentages of swap used, and default to warning above 50 and critical above 80. A computer without swap
+//! is always OK.
+//!
+//! Filling swap is often harmless, constantly moving pages in and out of it is not. --warn-rate and --crit-rate alert on
+//! the pages swapped in and out per second, sampled from /proc/vmstat over --sample seconds.
+//!
+//! USAGE:
+//!     check-swap [FLAGS] [OPTIONS]
+//!
+//! FLAGS:
+//!     -h, --help       Prints help information
+//!     -V, --version    Prints version information
+//!     -v, --verbose    Print more details and debug logs, use twice for trace logs. Logging can also be configured with
+//!                      TABIN_LOG
+//!
+//! OPTIONS:
+//!     -c, --crit <CRIT_RANGE>           Go critical if the value is outside this nagios range
+//!         --status-on-error <STATUS>    The status to exit with if the check itself fails, e.g. because something could
+//!                                       not be read [default: unknown]  [possible values: ok, warning, critical, unknown]
+//!         --proc-root <DIR>             Read the proc filesystem from DIR instead of /proc, e.g. the host's /proc mounted
+//!                                       into a container
+//!         --sys-root <DIR>              Read the sys filesystem from DIR instead of /sys
+//!         --timeout <SECONDS>           Give up if the check takes longer than this
+//!         --timeout-status <STATUS>     The status to exit with if --timeout is reached [default: unknown]  [possible
+//!                                       values: ok, warning, critical, unknown]
+//!     -w, --warn <WARN_RANGE>           Warn if the value is outside this nagios range, e.g. 80 or 10:
+//!         --crit-rate <RANGE>           Pages swapped in plus pages swapped out per second to go critical at, as a nagios
+//!                                       range
+//!         --format <format>             How to print the result [default: nagios]  [possible values: nagios, json, sensu,
+//!                                       human]
+//!     -s, --sample <seconds>            Seconds to measure the swap rate over, if a rate threshold is given [default: 5]
+//!         --warn-rate <RANGE>           Pages swapped in plus pages swapped out per second to warn at, as a nagios range
+//! ```
+//!
+//! # check-hugepages
+//!
+//! Linux-only.
+//!
+//! ```plain
+//! $ check-hugepages --help
+//! check-hugepages (part of tabin-plugins) 0.3.1
+//! Check preallocated hugepages and transparent hugepages
+//!
+//! Thresholds are the number of hugepages in a pool that are free and not reserved, or percentages of the pool with
+//! --percent. They are nagios ranges, so use e.g. '-w 100:' to warn when fewer than 100 pages are available. By default
+//! this goes critical when none are.
+//!
+//! Every pool with pages allocated is checked, or only the one selected with --size. If no pool has pages and a threshold
+//! was given, the pool of the default size is checked, so a host that has lost its hugepages alerts.
+//!
+//! --thp alerts if transparent hugepages are not set to the given mode, which many databases require to be 'never'.
+//!
+//! USAGE:
+//!     check-hugepages [FLAGS] [OPTIONS]
+//!
+//! FLAGS:
+//!     -h, --help       Prints help information
+//!         --percent    Thresholds are percentages of the pool instead of page counts
+//!     -V, --version    Prints version information
+//!     -v, --verbose    Print more details and debug logs, use twice for trace logs. Logging can also be configured with
+//!                      TABIN_LOG
+//!
+//! OPTIONS:
+//!     -c, --crit <CRIT_RANGE>           Go critical if the value is outside this nagios range
+//!         --status-on-error <STATUS>    The status to exit with if the check itself fails, e.g. because something could
+//!                                       not be read [default: unknown]  [possible values: ok, warning, critical, unknown]
+//!         --proc-root <DIR>             Read the proc filesystem from DIR instead of /proc, e.g. the host's /proc mounted
+//!                                       into a container
+//!         --sys-root <DIR>              Read the sys filesystem from DIR instead of /sys
+//!         --timeout <SECONDS>           Give up if the check takes longer than this
+//!         --timeout-status <STATUS>     The status to exit with if --timeout is reached [default: unknown]  [possible
+//!                                       values: ok, warning, critical, unknown]
+//!     -w, --warn <WARN_RANGE>           Warn if the value is outside this nagios range, e.g. 80 or 10:
+//!         --format <format>             How to print the result [default: nagios]  [possible values: nagios, json, sensu,
+//!                                       human]
+//!         --size <KB>                   Only check the pool of this page size in KB, e.g. 2048 or 1048576
+//!         --thp <MODE>                  The mode transparent hugepages must be set to [possible values: always, madvise,
+//!                                       never]
+//!         --thp-status <STATUS>         The status if transparent hugepages are in the wrong mode [default: warning]
+//!                                       [possible values: ok, warning, critical, unknown]
+//! ```
+//!
 //! # check-container-ram
 //!
 //! Linux-only. Can only be run from inside a cgroup.
@@ -285,11 +448,11 @@
 //!
 //! ```plain
 //! $ check-procs --help
-//! check-procs (part of tabin-plugins) 0.3.0
-//! Brandon W Maister <quodlibetor@gmail.com>
+//! check-procs (part of tabin-plugins) 0.3.1
 //! Check that an expected number of processes are running.
 //!
-//! Optionally, kill unwanted processes.
+//! Optionally, kill unwanted processes. Init, this check and the processes that started it are never killed, and neither
+//! are processes that exited and had their pid reused after they were matched.
 //!
 //! USAGE:
 //!     check-procs [FLAGS] [OPTIONS] [--] [pattern]
@@ -297,23 +460,102 @@
 //! FLAGS:
 //!         --allow-unparseable-procs    In combination with --crit-over M this will not alert if any processes cannot be
 //!                                      parsed
+//!         --dry-run                    Report the processes that --kill-matching and --kill-parents-of-matching would
+//!                                      kill, without sending any signals
 //!     -h, --help                       Prints help information
 //!     -V, --version                    Prints version information
+//!     -v, --verbose                    Print more details and debug logs, use twice for trace logs. Logging can also be
+//!                                      configured with TABIN_LOG
 //!
 //! OPTIONS:
+//!     -c, --crit <CRIT_RANGE>                           Go critical if the value is outside this nagios range
+//!         --status-on-error <STATUS>
+//!             The status to exit with if the check itself fails, e.g. because something could not be read [default:
+//!             unknown]  [possible values: ok, warning, critical, unknown]
 //!         --crit-over <M>                               Error if there are more than <M> procs matching <pattern>
 //!         --crit-under <N>                              Error if there are fewer than <N> procs matching <pattern>
 //!         --kill-parents-of-matching <PARENT_SIGNAL>
 //!             If *any* processes match, then kill their parents with the provided signal which can be either an integer or
 //!             a name like KILL or SIGTERM. This has the same exit status behavior as kill-matching.
+//!         --proc-root <DIR>
+//!             Read the proc filesystem from DIR instead of /proc, e.g. the host's /proc mounted into a container
+//!
 //!         --kill-matching <SIGNAL>
 //!             If *any* processes match, then kill them with the provided signal which can be either an integer or a name
-//!             like KILL or SIGTERM. This option does not affect the exit status, all matches are always killed, and if
-//!             --crit-under/over are violated then then this will still exit critical.
+//!             like KILL or SIGTERM. This option does not affect the exit status, all matches are killed unless there are
+//!             more than --max-kills, and if --crit-under/over are violated then then this will still exit critical.
+//!         --sys-root <DIR>                              Read the sys filesystem from DIR instead of /sys
+//!         --timeout <SECONDS>                           Give up if the check takes longer than this
+//!         --timeout-status <STATUS>
+//!             The status to exit with if --timeout is reached [default: unknown]  [possible values: ok, warning, critical,
+//!             unknown]
+//!     -w, --warn <WARN_RANGE>                           Warn if the value is outside this nagios range, e.g. 80 or 10:
+//!         --children-of <PATTERN>
+//!             Only processes descended from a process whose command and arguments match this regex, e.g. the workers of a
+//!             server
+//!         --comm <NAME>
+//!             Only processes whose executable is named exactly this, as in /proc/<pid>/stat. The kernel truncates names to
+//!             15 characters
+//!         --crit-age <AGE>                              Go critical if any matching process started longer ago than this
+//!         --crit-cpu <PERCENT>
+//!             Go critical if any matching process uses more than this percent of a cpu over --sample seconds, as a nagios
+//!             range
+//!         --crit-fds <COUNT>
+//!             Go critical if any matching process has more open file descriptors than this, as a nagios range
+//!
+//!         --crit-rss <MB>
+//!             Go critical if any matching process has more resident memory than this, as a nagios range
+//!
+//!         --crit-threads <COUNT>
+//!             Go critical if any matching process has more threads than this, as a nagios range
+//!
+//!         --exe <PATH>
+//!             Only processes running this executable, as linked to by /proc/<pid>/exe. Other users' processes can only be
+//!             matched as root
+//!         --format <format>
+//!             How to print the result [default: nagios]  [possible values: nagios, json, sensu, human]
+//!
+//!         --kill-grace <SECONDS>
+//!             After sending the kill signal, wait up to this long for the processes to exit, and then send SIGKILL to any
+//!             that haven't
+//!         --max-kills <N>
+//!             Don't kill anything if more than <N> processes would be killed, e.g. because the pattern matches more than
+//!             intended
+//!         --min-cpu <PERCENT>
+//!             Only processes that have used at least this percent of a cpu since they started, like the %CPU column of ps
+//!
+//!         --min-rss <MB>                                Only processes with at least this much resident memory
+//!         --older-than <AGE>
+//!             Only processes that started longer ago than this, in seconds or with a unit like 30s, 10m, 2h, 1d or 1w
+//!
+//!         --ppid <PID>                                  Only processes whose parent is this process
+//!     -s, --sample <seconds>
+//!             Seconds to measure cpu usage over, if a cpu limit is given [default: 5]
+//!
 //!         --state <states>...
 //!             Filter to only processes in these states. If passed multiple times, processes matching any state are
 //!             included.
 //!             Choices: running sleeping uninterruptible-sleep waiting stopped zombie
+//!         --uid <UID>                                   Only processes running as this user ID
+//!         --user <NAME>                                 Only processes running as this user
+//!         --warn-age <AGE>
+//!             Warn if any matching process started longer ago than this, e.g. 12h
+//!
+//!         --warn-cpu <PERCENT>
+//!             Warn if any matching process uses more than this percent of a cpu over --sample seconds, as a nagios range
+//!
+//!         --warn-fds <COUNT>
+//!             Warn if any matching process has more open file descriptors than this, as a nagios range. Other users'
+//!             processes can only be counted as root
+//!         --warn-over <M>                               Warn if there are more than <M> procs matching <pattern>
+//!         --warn-rss <MB>
+//!             Warn if any matching process has more resident memory than this, as a nagios range
+//!
+//!         --warn-threads <COUNT>
+//!             Warn if any matching process has more threads than this, as a nagios range
+//!
+//!         --warn-under <N>                              Warn if there are fewer than <N> procs matching <pattern>
+//!         --younger-than <AGE>                          Only processes that started more recently than this
 //!
 //! ARGS:
 //!     <pattern>    Regex that command and its arguments must match
@@ -324,6 +566,15 @@
 //!
 //!         check-procs --crit-under 2 nginx
 //!
+//!     Warn if there are fewer than four gunicorn workers, and go critical if
+//!     there are none:
+//!
+//!         check-procs --warn-under 4 --crit-under 1 'gunicorn: worker'
+//!
+//!     The same, using nagios range syntax:
+//!
+//!         check-procs --warn 4: --crit 1: 'gunicorn: worker'
+//!
 //!     Ensure there are not more than 30 zombie proccesses on the system:
 //!
 //!         check-procs --crit-over 30 --state zombie
@@ -339,6 +590,27 @@
 //!
 //!         check-procs --crit-under 3 --state=running --state=waiting 'cassandra|postgres'
 //!
+//!     Warn if fewer than four processes were started by the gunicorn master,
+//!     however deep they are:
+//!
+//!         check-procs --warn-under 4 --children-of 'gunicorn: master'
+//!
+//!     Ensure that no process owned by www-data has been running for more than a
+//!     day:
+//!
+//!         check-procs --crit-over 0 --user www-data --older-than 1d
+//!
+//!     Go critical if any postgres process has more than 2GB of resident memory,
+//!     and warn if any used more than 90% of a cpu over 10 seconds:
+//!
+//!         check-procs --crit-rss 2048 --warn-cpu 90 --sample 10 postgres
+//!
+//!     Stop runaway report jobs, giving them 30 seconds to exit before they are
+//!     sent SIGKILL, and killing nothing if the pattern matches more than 10.
+//!     Add --dry-run to see what would be killed first:
+//!
+//!         check-procs --crit-over 0 --kill-matching TERM --kill-grace 30 --max-kills 10 'run-report'
+//!
 //! ```
 //!
 //! # check-fs-writeable
@@ -0,0 +1,1375 @@
+//! Interact with cgroups
+//!
+//! Linux has two cgroup hierarchies. In v1 every controller (`cpu`,
+//! `cpuacct`, `memory`, ...) is mounted in its own directory under
+//! `/sys/fs/cgroup`, and the `cpu`, `cpuacct` and `memory` modules read from
+//! those. In v2 (the "unified hierarchy") all controllers share one directory
+//! with different file names, which the `v2` module reads.
+//!
+//! `CGroup` detects which hierarchy is in use and provides the same API for
+//! both, which is what checks should use. `CGroup::current` finds the
+//! cgroup that this process is in from `/proc/self/cgroup`:
+//!
+//! ```no_run
+//! use tabin_plugins::sys::fs::cgroup::CGroup;
+//!
+//! let cgroup = CGroup::current().unwrap();
+//! println!("{:?}", cgroup.memory_usage());
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::context::Context;
+use crate::linux::{Ratio, UserHz, USER_HZ};
+use crate::procfs::pid::{CGroupEntry, CGroupMembership, MountInfo};
+use crate::procfs::{FieldError, Pressure, PressureResource, ReadFileError};
+use crate::sys::{read_file, SysFsError};
+
+pub mod v2;
+
+/// Which cgroup hierarchy a system uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    /// One directory per controller
+    V1,
+    /// One directory for all controllers
+    V2,
+}
+
+impl Version {
+    /// Detect the hierarchy mounted at `/sys/fs/cgroup`
+    pub fn detect() -> Version {
+        Version::detect_in(&Context::from_env())
+    }
+
+    /// Like `detect`, but looking in `<sys_root>/fs/cgroup`
+    ///
+    /// Systems that mount v2 at the root of `/sys/fs/cgroup` have a
+    /// `cgroup.controllers` file there. "Hybrid" systems that mount v2 at
+    /// `/sys/fs/cgroup/unified` use v1 for the controllers we read, so they
+    /// count as v1.
+    pub fn detect_in(context: &Context) -> Version {
+        if context.sys_path("fs/cgroup/cgroup.controllers").exists() {
+            Version::V2
+        } else {
+            Version::V1
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Version::V1 => f.write_str("v1"),
+            Version::V2 => f.write_str("v2"),
+        }
+    }
+}
+
+/// A cgroup in either hierarchy, with the directories to read it from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CGroup {
+    /// A cgroup in the v1 hierarchy, with one directory per controller
+    V1 {
+        cpu: PathBuf,
+        cpuacct: PathBuf,
+        memory: PathBuf,
+    },
+    /// A cgroup in the v2 hierarchy
+    V2 { path: PathBuf },
+}
+
+/// CPU time used by a cgroup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuUsage {
+    pub user: Duration,
+    pub system: Duration,
+}
+
+impl CpuUsage {
+    pub fn total(&self) -> Duration {
+        self.user + self.system
+    }
+}
+
+/// The CFS bandwidth limit of a cgroup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuQuota {
+    /// How much CPU time the cgroup may use every `period`, `None` if it is
+    /// unlimited
+    pub quota: Option<Duration>,
+    pub period: Duration,
+}
+
+impl CpuQuota {
+    /// The number of CPUs worth of time the quota allows, `None` if unlimited
+    pub fn cpus(&self) -> Option<f64> {
+        self.quota.map(|quota| quota.ratio(&self.period))
+    }
+}
+
+impl From<v2::CpuMax> for CpuQuota {
+    fn from(max: v2::CpuMax) -> CpuQuota {
+        CpuQuota {
+            quota: max.quota,
+            period: max.period,
+        }
+    }
+}
+
+/// How often a cgroup has hit its CPU quota, counted since it was created
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CpuThrottling {
+    /// The number of enforcement periods in which the cgroup ran
+    pub periods: u64,
+    /// The number of those periods in which it used up its quota
+    pub throttled_periods: u64,
+    /// The total time processes were throttled for
+    pub throttled_time: Duration,
+}
+
+impl CpuThrottling {
+    /// The throttling between `earlier` and this
+    pub fn since(&self, earlier: &CpuThrottling) -> CpuThrottling {
+        CpuThrottling {
+            periods: self.periods.saturating_sub(earlier.periods),
+            throttled_periods: self
+                .throttled_periods
+                .saturating_sub(earlier.throttled_periods),
+            throttled_time: self
+                .throttled_time
+                .checked_sub(earlier.throttled_time)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The percent of periods that were throttled, `None` if there were no
+    /// periods
+    pub fn percent_throttled(&self) -> Option<f64> {
+        if self.periods == 0 {
+            None
+        } else {
+            Some(100.0 * self.throttled_periods as f64 / self.periods as f64)
+        }
+    }
+}
+
+/// The parts of memory.stat that both hierarchies have, in bytes
+///
+/// In v1 these are the hierarchical `total_` fields when the kernel reports
+/// them, so that they include descendant cgroups like v2's always do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryStat {
+    /// Anonymous memory: `rss` in v1 and `anon` in v2
+    pub rss: usize,
+    /// Page cache: `cache` in v1 and `file` in v2
+    pub cache: usize,
+    /// Transparent huge pages, included in `rss`: `rss_huge` in v1 and
+    /// `anon_thp` in v2
+    pub rss_huge: usize,
+    /// tmpfs and shared memory, included in `cache`
+    ///
+    /// This and the fields below are `None` on v1 kernels that don't report
+    /// them.
+    pub shmem: Option<usize>,
+    /// Page cache mapped into processes: `mapped_file` in v1 and
+    /// `file_mapped` in v2
+    pub mapped_file: Option<usize>,
+    pub active_file: Option<usize>,
+    /// Page cache that will be reclaimed first
+    pub inactive_file: Option<usize>,
+}
+
+impl MemoryStat {
+    /// The working set of a cgroup using `usage` bytes
+    ///
+    /// This is how the kubelet and cAdvisor measure memory for eviction and
+    /// `kubectl top`: everything charged to the cgroup except inactive page
+    /// cache, which the kernel can drop cheaply. `None` if the kernel
+    /// doesn't report the inactive page cache.
+    pub fn working_set(&self, usage: usize) -> Option<usize> {
+        self.inactive_file
+            .map(|inactive_file| usage.saturating_sub(inactive_file))
+    }
+}
+
+/// Counts of memory limit events since a cgroup was created
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryEventCounts {
+    /// Times the cgroup hit its memory limit: `memory.failcnt` in v1 and
+    /// `max` in `memory.events` in v2
+    pub max: u64,
+    /// Processes killed by the OOM killer, `None` on v1 kernels older than
+    /// 4.13
+    pub oom_kill: Option<u64>,
+}
+
+impl CGroup {
+    /// The root cgroup of whichever hierarchy is mounted at `/sys/fs/cgroup`
+    ///
+    /// Inside a container with its own cgroup namespace this is the
+    /// container's cgroup.
+    pub fn detect() -> CGroup {
+        CGroup::detect_in(&Context::from_env())
+    }
+
+    /// Like `detect`, but looking in `<sys_root>/fs/cgroup`
+    pub fn detect_in(context: &Context) -> CGroup {
+        let root = context.sys_path("fs/cgroup");
+        match Version::detect_in(context) {
+            Version::V1 => CGroup::V1 {
+                cpu: root.join("cpu"),
+                cpuacct: root.join("cpuacct"),
+                memory: root.join("memory"),
+            },
+            Version::V2 => CGroup::V2 { path: root },
+        }
+    }
+
+    /// The cgroup that this process is in
+    pub fn current() -> Result<CGroup, SysFsError> {
+        CGroup::current_in(&Context::from_env())
+    }
+
+    /// Like `current`, but reading from `<proc_root>` and `<sys_root>`
+    pub fn current_in(context: &Context) -> Result<CGroup, SysFsError> {
+        CGroup::for_pid_in("self", context)
+    }
+
+    /// The cgroup that `pid` is in
+    ///
+    /// This reads `/proc/<pid>/cgroup` for the cgroup paths, and
+    /// `/proc/self/mountinfo` to find where each hierarchy is mounted, so it
+    /// works under systemd slices, nested cgroups and in containers with or
+    /// without their own cgroup namespace.
+    pub fn for_pid_in<P: fmt::Display>(pid: P, context: &Context) -> Result<CGroup, SysFsError> {
+        let membership = CGroupMembership::from_pid_in(&pid, context)?;
+        let mounts = MountInfo::from_pid_in("self", context)?;
+        let source = context.proc_path(format!("{}/cgroup", pid));
+        CGroup::resolve(
+            Version::detect_in(context),
+            &membership,
+            &mounts,
+            context,
+            &source,
+        )
+    }
+
+    /// The cgroup at `path` in the hierarchy, as it would appear in
+    /// `/proc/<pid>/cgroup`, e.g. `/system.slice/docker-<id>.scope`
+    pub fn at_path_in(path: &str, context: &Context) -> Result<CGroup, SysFsError> {
+        let path = format!("/{}", path.trim_start_matches('/'));
+        let entry = |hierarchy_id, controller: &str| CGroupEntry {
+            hierarchy_id,
+            controllers: if controller.is_empty() {
+                vec![]
+            } else {
+                vec![controller.to_owned()]
+            },
+            path: path.clone(),
+        };
+        let membership = CGroupMembership {
+            entries: vec![
+                entry(0, ""),
+                entry(1, "cpu"),
+                entry(2, "cpuacct"),
+                entry(3, "memory"),
+            ],
+        };
+        let mounts = MountInfo::from_pid_in("self", context)?;
+        CGroup::resolve(
+            Version::detect_in(context),
+            &membership,
+            &mounts,
+            context,
+            Path::new(&path),
+        )
+    }
+
+    /// The cgroup of the container with `id`
+    ///
+    /// This searches the hierarchy for a cgroup whose name contains `id`,
+    /// which covers how docker, containerd, cri-o and podman name their
+    /// cgroups (`/docker/<id>`, `docker-<id>.scope`, `cri-containerd-<id>.scope`,
+    /// `crio-<id>.scope`, `libpod-<id>.scope`), and works with a unique
+    /// prefix of the ID. The shallowest matches win, and it is an error if
+    /// there is more than one of those.
+    pub fn find_container_in(id: &str, context: &Context) -> Result<CGroup, SysFsError> {
+        let root = CGroup::at_path_in("/", context)?;
+        let mut level = vec![root];
+        while !level.is_empty() {
+            let mut matches = vec![];
+            let mut next = vec![];
+            for cgroup in level {
+                for child in cgroup.children()? {
+                    if child.name().contains(id) {
+                        matches.push(child);
+                    } else {
+                        next.push(child);
+                    }
+                }
+            }
+            match matches.len() {
+                0 => level = next,
+                1 => return Ok(matches.remove(0)),
+                _ => {
+                    return Err(SysFsError::CGroupNotFound(format!(
+                        "container id {} matches more than one cgroup: {}",
+                        id,
+                        matches
+                            .iter()
+                            .map(|cgroup| cgroup.path().display().to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )))
+                }
+            }
+        }
+        Err(SysFsError::CGroupNotFound(format!(
+            "no cgroup found for container id {}",
+            id
+        )))
+    }
+
+    /// Find the directories for `membership` in `mounts`
+    ///
+    /// `source` is the file `membership` came from, for errors.
+    fn resolve(
+        version: Version,
+        membership: &CGroupMembership,
+        mounts: &[MountInfo],
+        context: &Context,
+        source: &Path,
+    ) -> Result<CGroup, SysFsError> {
+        match version {
+            Version::V1 => {
+                let dir = |controller: &str| -> Result<PathBuf, SysFsError> {
+                    let entry = membership
+                        .controller(controller)
+                        .ok_or_else(|| FieldError::missing(source, controller))?;
+                    let mount = mounts
+                        .iter()
+                        .find(|m| {
+                            m.fs_type == "cgroup" && m.super_options.iter().any(|o| o == controller)
+                        })
+                        .ok_or_else(|| {
+                            FieldError::new(
+                                &context.proc_path("self/mountinfo"),
+                                None,
+                                controller,
+                                "no cgroup hierarchy is mounted for it",
+                            )
+                        })?;
+                    Ok(cgroup_dir(
+                        &sys_path(context, &mount.mount_point),
+                        &mount.root,
+                        entry,
+                    ))
+                };
+                Ok(CGroup::V1 {
+                    cpu: dir("cpu")?,
+                    cpuacct: dir("cpuacct")?,
+                    memory: dir("memory")?,
+                })
+            }
+            Version::V2 => {
+                let entry = membership
+                    .unified()
+                    .ok_or_else(|| FieldError::missing(source, "cgroup v2 hierarchy"))?;
+                let mount_point = context.sys_path("fs/cgroup");
+                // The unified hierarchy is always at /sys/fs/cgroup if we
+                // detected v2, we only need to know which part of it is there
+                let root = mounts
+                    .iter()
+                    .find(|m| {
+                        m.fs_type == "cgroup2" && sys_path(context, &m.mount_point) == mount_point
+                    })
+                    .map_or("/", |m| &m.root);
+                Ok(CGroup::V2 {
+                    path: cgroup_dir(&mount_point, root, entry),
+                })
+            }
+        }
+    }
+
+    pub fn version(&self) -> Version {
+        match *self {
+            CGroup::V1 { .. } => Version::V1,
+            CGroup::V2 { .. } => Version::V2,
+        }
+    }
+
+    /// The directory that identifies this cgroup, the `memory` directory in
+    /// v1
+    pub fn path(&self) -> &Path {
+        match *self {
+            CGroup::V1 { ref memory, .. } => memory,
+            CGroup::V2 { ref path } => path,
+        }
+    }
+
+    /// The last component of this cgroup's path
+    pub fn name(&self) -> String {
+        self.path()
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "/".to_owned())
+    }
+
+    /// The child cgroup called `name`
+    pub fn join(&self, name: &str) -> CGroup {
+        match *self {
+            CGroup::V1 {
+                ref cpu,
+                ref cpuacct,
+                ref memory,
+            } => CGroup::V1 {
+                cpu: cpu.join(name),
+                cpuacct: cpuacct.join(name),
+                memory: memory.join(name),
+            },
+            CGroup::V2 { ref path } => CGroup::V2 {
+                path: path.join(name),
+            },
+        }
+    }
+
+    /// The cgroups directly below this one, sorted by name
+    ///
+    /// In v1 these are the children in the memory hierarchy, which may be
+    /// missing from the others.
+    pub fn children(&self) -> Result<Vec<CGroup>, SysFsError> {
+        let dir = self.path();
+        let entries = fs::read_dir(dir).map_err(|error| ReadFileError {
+            path: dir.to_owned(),
+            error,
+        })?;
+        let mut names = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().map(|t| t.is_dir()).unwrap_or(false))
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        Ok(names.iter().map(|name| self.join(name)).collect())
+    }
+
+    /// The processes in this cgroup and all of its descendants
+    pub fn pids(&self) -> Result<Vec<i32>, SysFsError> {
+        let path = self.path().join("cgroup.procs");
+        let contents = read_file(&path)?;
+        let mut pids = contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                line.trim().parse().map_err(|e| {
+                    FieldError::new(&path, Some(i + 1), "pid", format!("{:?}: {}", line, e)).into()
+                })
+            })
+            .collect::<Result<Vec<i32>, SysFsError>>()?;
+        for child in self.children()? {
+            pids.extend(child.pids()?);
+        }
+        Ok(pids)
+    }
+
+    /// Total CPU time used by processes in this cgroup
+    ///
+    /// From `cpuacct.stat` in v1 and `cpu.stat` in v2.
+    pub fn cpu_usage(&self) -> Result<CpuUsage, SysFsError> {
+        match *self {
+            CGroup::V1 { ref cpuacct, .. } => {
+                let stat = cpuacct::Stat::load_at(cpuacct)?;
+                Ok(CpuUsage {
+                    user: user_hz_to_duration(stat.user),
+                    system: user_hz_to_duration(stat.system),
+                })
+            }
+            CGroup::V2 { ref path } => {
+                let stat = v2::CpuStat::load_at(path)?;
+                Ok(CpuUsage {
+                    user: stat.user,
+                    system: stat.system,
+                })
+            }
+        }
+    }
+
+    /// The relative share of CPU this cgroup gets when CPUs are contended
+    ///
+    /// This is `cpu.shares` in v1. v2 has `cpu.weight` instead, which is
+    /// converted to shares by inverting the conversion that runc and systemd
+    /// use, so a container started with 1024 shares reports roughly 1024.
+    pub fn cpu_shares(&self) -> Result<u64, SysFsError> {
+        match *self {
+            CGroup::V1 { ref cpu, .. } => cpu::shares_at(cpu).map(u64::from),
+            CGroup::V2 { ref path } => v2::cpu_weight_at(path).map(v2::weight_to_shares),
+        }
+    }
+
+    /// The CFS bandwidth limit
+    ///
+    /// From `cpu.cfs_quota_us` and `cpu.cfs_period_us` in v1 and `cpu.max` in
+    /// v2. The root cgroup has no limit, and in v2 doesn't have the file.
+    pub fn cpu_quota(&self) -> Result<CpuQuota, SysFsError> {
+        match *self {
+            CGroup::V1 { ref cpu, .. } => Ok(CpuQuota {
+                quota: cpu::cfs_quota_at(cpu)?,
+                period: cpu::cfs_period_at(cpu)?,
+            }),
+            CGroup::V2 { ref path } => match v2::CpuMax::load_at(path) {
+                Ok(max) => Ok(max.into()),
+                Err(SysFsError::ReadFile(ref e)) if e.error.kind() == io::ErrorKind::NotFound => {
+                    Ok(CpuQuota {
+                        quota: None,
+                        period: Duration::from_millis(100),
+                    })
+                }
+                Err(e) => Err(e),
+            },
+        }
+    }
+
+    /// How often the cgroup has been throttled for using up its quota
+    ///
+    /// From `cpu.stat` in either version. In v2 the counts are only there if
+    /// the cpu controller is enabled for the cgroup.
+    pub fn cpu_throttling(&self) -> Result<CpuThrottling, SysFsError> {
+        match *self {
+            CGroup::V1 { ref cpu, .. } => {
+                let stat = cpu::Stat::load_at(cpu)?;
+                Ok(CpuThrottling {
+                    periods: stat.nr_periods,
+                    throttled_periods: stat.nr_throttled,
+                    throttled_time: stat.throttled_time,
+                })
+            }
+            CGroup::V2 { ref path } => {
+                let stat = v2::CpuStat::load_at(path)?;
+                let source = path.join("cpu.stat");
+                let missing = |field| SysFsError::from(FieldError::missing(&source, field));
+                Ok(CpuThrottling {
+                    periods: stat.nr_periods.ok_or_else(|| missing("nr_periods"))?,
+                    throttled_periods: stat.nr_throttled.ok_or_else(|| missing("nr_throttled"))?,
+                    throttled_time: stat.throttled.ok_or_else(|| missing("throttled_usec"))?,
+                })
+            }
+        }
+    }
+
+    /// The memory limit in bytes, `None` if there is no limit
+    ///
+    /// v1 has no way to say "no limit", and reports a huge number instead,
+    /// which is returned as-is.
+    pub fn memory_limit(&self) -> Result<Option<usize>, SysFsError> {
+        match *self {
+            CGroup::V1 { ref memory, .. } => memory::limit_in_bytes_at(memory).map(Some),
+            CGroup::V2 { ref path } => v2::memory_max_at(path),
+        }
+    }
+
+    /// Memory currently charged to this cgroup in bytes, including page cache
+    pub fn memory_usage(&self) -> Result<usize, SysFsError> {
+        match *self {
+            CGroup::V1 { ref memory, .. } => memory::usage_in_bytes_at(memory),
+            CGroup::V2 { ref path } => v2::memory_current_at(path),
+        }
+    }
+
+    /// The working set of this cgroup, see `MemoryStat::working_set`
+    pub fn memory_working_set(&self) -> Result<usize, SysFsError> {
+        let usage = self.memory_usage()?;
+        self.memory_stat()?.working_set(usage).ok_or_else(|| {
+            let dir = match *self {
+                CGroup::V1 { ref memory, .. } => memory,
+                CGroup::V2 { ref path } => path,
+            };
+            FieldError::missing(&dir.join("memory.stat"), "inactive_file").into()
+        })
+    }
+
+    /// Pressure stall information for `resource` in this cgroup
+    ///
+    /// Only cgroup v2 has `<resource>.pressure` files, on Linux 4.20 and
+    /// later.
+    pub fn pressure(&self, resource: PressureResource) -> Result<Pressure, SysFsError> {
+        match *self {
+            CGroup::V1 { .. } => Err(SysFsError::UnsupportedVersion(Version::V1)),
+            CGroup::V2 { ref path } => {
+                let path = path.join(format!("{}.pressure", resource));
+                Ok(Pressure::parse(&read_file(&path)?, &path)?)
+            }
+        }
+    }
+
+    /// How often the cgroup has hit its memory limit and been OOM killed
+    ///
+    /// The root cgroup has no limit, and none of these files.
+    pub fn memory_events(&self) -> Result<MemoryEventCounts, SysFsError> {
+        match *self {
+            CGroup::V1 { ref memory, .. } => Ok(MemoryEventCounts {
+                max: memory::failcnt_at(memory)?,
+                oom_kill: memory::OomControl::load_at(memory)?.oom_kill,
+            }),
+            CGroup::V2 { ref path } => {
+                let events = v2::MemoryEvents::load_at(path)?;
+                Ok(MemoryEventCounts {
+                    max: events.max,
+                    oom_kill: Some(events.oom_kill),
+                })
+            }
+        }
+    }
+
+    /// The fields of memory.stat that both hierarchies report
+    pub fn memory_stat(&self) -> Result<MemoryStat, SysFsError> {
+        match *self {
+            CGroup::V1 { ref memory, .. } => {
+                let stat = memory::Stat::load_at(memory)?;
+                let counters = stat.hierarchical();
+                Ok(MemoryStat {
+                    rss: counters.rss,
+                    cache: counters.cache,
+                    rss_huge: counters.rss_huge,
+                    shmem: counters.shmem,
+                    mapped_file: counters.mapped_file,
+                    active_file: counters.active_file,
+                    inactive_file: counters.inactive_file,
+                })
+            }
+            CGroup::V2 { ref path } => {
+                let stat = v2::MemoryStat::load_at(path)?;
+                Ok(MemoryStat {
+                    rss: stat.anon,
+                    cache: stat.file,
+                    rss_huge: stat.anon_thp,
+                    shmem: Some(stat.shmem),
+                    mapped_file: Some(stat.file_mapped),
+                    active_file: Some(stat.active_file),
+                    inactive_file: Some(stat.inactive_file),
+                })
+            }
+        }
+    }
+}
+
+/// Translate a mount point under `/sys` into `<sys_root>`
+fn sys_path(context: &Context, mount_point: &str) -> PathBuf {
+    match Path::new(mount_point).strip_prefix("/sys") {
+        Ok(relative) => context.sys_root.join(relative),
+        Err(_) => PathBuf::from(mount_point),
+    }
+}
+
+/// The directory for the cgroup in `entry` in a hierarchy mounted at
+/// `mount_point` with `root` visible there
+///
+/// If the cgroup is not under the visible root, e.g. because we are in a
+/// container without its own cgroup namespace, the best guess is that the
+/// container's runtime mounted its own cgroup.
+fn cgroup_dir(mount_point: &Path, root: &str, entry: &CGroupEntry) -> PathBuf {
+    let relative = Path::new(&entry.path)
+        .strip_prefix(root)
+        .unwrap_or_else(|_| Path::new(""));
+    if relative.as_os_str().is_empty() {
+        mount_point.to_owned()
+    } else {
+        mount_point.join(relative)
+    }
+}
+
+fn user_hz_to_duration(hz: UserHz) -> Duration {
+    Duration::from_nanos(hz.value() * 1_000_000_000 / *USER_HZ)
+}
+
+pub mod cpu {
+    //! The CPU Controller CGroup hierarchy
+    //!
+    //! https://kernel.googlesource.com/pub/scm/linux/kernel/git/glommer/memcg/+/cpu_stat/Documentation/cgroups/cpu.txt
+    use std::path::Path;
+    use std::time::Duration;
+
+    use crate::context::Context;
+    use crate::sys::{keyed_field, parse_keyed, parse_value, read_file, SysFsError};
+
+    /// The number of CPU shares this cgroup has got
+    pub fn shares() -> Result<u32, SysFsError> {
+        shares_in(&Context::from_env())
+    }
+
+    /// Like `shares`, but reading from `<sys_root>/fs/cgroup`
+    pub fn shares_in(context: &Context) -> Result<u32, SysFsError> {
+        shares_at(context.sys_path("fs/cgroup/cpu"))
+    }
+
+    /// Like `shares`, but reading from the cgroup directory `dir`
+    pub fn shares_at<P: AsRef<Path>>(dir: P) -> Result<u32, SysFsError> {
+        let path = dir.as_ref().join("cpu.shares");
+        parse_value(&read_file(&path)?, &path)
+    }
+
+    /// Read `<sys_root>/fs/cgroup/cpu/cpu.cfs_quota_us`
+    pub fn cfs_quota_in(context: &Context) -> Result<Option<Duration>, SysFsError> {
+        cfs_quota_at(context.sys_path("fs/cgroup/cpu"))
+    }
+
+    /// The CPU time this cgroup may use every period, from the cgroup
+    /// directory `dir`, `None` if it is unlimited
+    pub fn cfs_quota_at<P: AsRef<Path>>(dir: P) -> Result<Option<Duration>, SysFsError> {
+        let path = dir.as_ref().join("cpu.cfs_quota_us");
+        let quota: i64 = parse_value(&read_file(&path)?, &path)?;
+        if quota < 0 {
+            Ok(None)
+        } else {
+            Ok(Some(Duration::from_micros(quota as u64)))
+        }
+    }
+
+    /// Read `<sys_root>/fs/cgroup/cpu/cpu.cfs_period_us`
+    pub fn cfs_period_in(context: &Context) -> Result<Duration, SysFsError> {
+        cfs_period_at(context.sys_path("fs/cgroup/cpu"))
+    }
+
+    /// The length of a CFS enforcement period, from the cgroup directory `dir`
+    pub fn cfs_period_at<P: AsRef<Path>>(dir: P) -> Result<Duration, SysFsError> {
+        let path = dir.as_ref().join("cpu.cfs_period_us");
+        parse_value(&read_file(&path)?, &path).map(Duration::from_micros)
+    }
+
+    /// Throttling statistics from cpu.stat
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Stat {
+        /// The number of enforcement periods that have elapsed
+        pub nr_periods: u64,
+        /// The number of periods in which the cgroup used up its quota
+        pub nr_throttled: u64,
+        /// The total time the cgroup spent throttled
+        pub throttled_time: Duration,
+    }
+
+    impl Stat {
+        /// Read `<sys_root>/fs/cgroup/cpu/cpu.stat`
+        pub fn load_in(context: &Context) -> Result<Stat, SysFsError> {
+            Stat::load_at(context.sys_path("fs/cgroup/cpu"))
+        }
+
+        /// Read `cpu.stat` from the cgroup directory `dir`
+        pub fn load_at<P: AsRef<Path>>(dir: P) -> Result<Stat, SysFsError> {
+            let path = dir.as_ref().join("cpu.stat");
+            let fields = parse_keyed(&read_file(&path)?, &path)?;
+            Ok(Stat {
+                nr_periods: keyed_field(&fields, &path, "nr_periods")?,
+                nr_throttled: keyed_field(&fields, &path, "nr_throttled")?,
+                throttled_time: Duration::from_nanos(keyed_field(
+                    &fields,
+                    &path,
+                    "throttled_time",
+                )?),
+            })
+        }
+    }
+}
+pub mod cpuacct {
+    //! The cpuacct directory for describing cgroups
+    //!
+    //! The most interesting file in here is cpuacct.stat which shows
+    //! the total cpu usage for all processes in this cgroup
+    //!
+    //! https://www.kernel.org/doc/Documentation/cgroups/cpuacct.txt
+    use std::path::Path;
+
+    use crate::context::Context;
+    use crate::linux::UserHz;
+    use crate::sys::{keyed_field, parse_keyed, read_file, SysFsError};
+
+    /// Similar to /proc/stat, but shows toatal CPU usage by the cgroup
+    #[derive(Debug)]
+    pub struct Stat {
+        pub user: UserHz,
+        pub system: UserHz,
+    }
+
+    impl Stat {
+        /// Create a new `Stat` with values for the cgroup fs
+        pub fn load() -> Result<Stat, SysFsError> {
+            Stat::load_in(&Context::from_env())
+        }
+
+        /// Like `load`, but reading from `<sys_root>/fs/cgroup`
+        pub fn load_in(context: &Context) -> Result<Stat, SysFsError> {
+            Stat::load_at(context.sys_path("fs/cgroup/cpuacct"))
+        }
+
+        /// Like `load`, but reading from the cgroup directory `dir`
+        pub fn load_at<P: AsRef<Path>>(dir: P) -> Result<Stat, SysFsError> {
+            let path = dir.as_ref().join("cpuacct.stat");
+            Stat::parse(&read_file(&path)?, &path)
+        }
+
+        pub(crate) fn parse(contents: &str, path: &Path) -> Result<Stat, SysFsError> {
+            let fields = parse_keyed(contents, path)?;
+            Ok(Stat {
+                user: UserHz::new(keyed_field(&fields, path, "user")?),
+                system: UserHz::new(keyed_field(&fields, path, "system")?),
+            })
+        }
+
+        pub fn total(&self) -> UserHz {
+            self.user + self.system
+        }
+    }
+}
+
+pub mod memory {
+    //! The memory directory for describing cgroups
+    //!
+    //! See the
+    //! [memory.txt](https://www.kernel.org/doc/Documentation/cgroups/memory.txt)
+    //! file.
+
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    use crate::context::Context;
+    use crate::sys::{keyed_field, parse_keyed, parse_value, read_file, SysFsError};
+
+    /// The memory limit for this cgroup
+    ///
+    /// If it's not set, it seems to be u64::max
+    pub fn limit_in_bytes() -> Result<usize, SysFsError> {
+        limit_in_bytes_in(&Context::from_env())
+    }
+
+    /// Like `limit_in_bytes`, but reading from `<sys_root>/fs/cgroup`
+    pub fn limit_in_bytes_in(context: &Context) -> Result<usize, SysFsError> {
+        limit_in_bytes_at(context.sys_path("fs/cgroup/memory"))
+    }
+
+    /// Like `limit_in_bytes`, but reading from the cgroup directory `dir`
+    pub fn limit_in_bytes_at<P: AsRef<Path>>(dir: P) -> Result<usize, SysFsError> {
+        let path = dir.as_ref().join("memory.limit_in_bytes");
+        parse_value(&read_file(&path)?, &path)
+    }
+
+    /// The memory currently used by this cgroup, including page cache
+    pub fn usage_in_bytes() -> Result<usize, SysFsError> {
+        usage_in_bytes_in(&Context::from_env())
+    }
+
+    /// Like `usage_in_bytes`, but reading from `<sys_root>/fs/cgroup`
+    pub fn usage_in_bytes_in(context: &Context) -> Result<usize, SysFsError> {
+        usage_in_bytes_at(context.sys_path("fs/cgroup/memory"))
+    }
+
+    /// Like `usage_in_bytes`, but reading from the cgroup directory `dir`
+    pub fn usage_in_bytes_at<P: AsRef<Path>>(dir: P) -> Result<usize, SysFsError> {
+        let path = dir.as_ref().join("memory.usage_in_bytes");
+        parse_value(&read_file(&path)?, &path)
+    }
+
+    /// The number of times this cgroup's memory usage has hit its limit
+    pub fn failcnt() -> Result<u64, SysFsError> {
+        failcnt_in(&Context::from_env())
+    }
+
+    /// Like `failcnt`, but reading from `<sys_root>/fs/cgroup`
+    pub fn failcnt_in(context: &Context) -> Result<u64, SysFsError> {
+        failcnt_at(context.sys_path("fs/cgroup/memory"))
+    }
+
+    /// Like `failcnt`, but reading from the cgroup directory `dir`
+    pub fn failcnt_at<P: AsRef<Path>>(dir: P) -> Result<u64, SysFsError> {
+        let path = dir.as_ref().join("memory.failcnt");
+        parse_value(&read_file(&path)?, &path)
+    }
+
+    /// The memory.oom_control file
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct OomControl {
+        /// Whether the OOM killer is disabled, in which case processes hang
+        /// instead of being killed when the cgroup runs out of memory
+        pub oom_kill_disable: bool,
+        /// Whether the cgroup is out of memory right now
+        pub under_oom: bool,
+        /// Processes killed by the OOM killer, only reported by Linux 4.13
+        /// and later
+        pub oom_kill: Option<u64>,
+    }
+
+    impl OomControl {
+        /// Read `memory.oom_control` for this cgroup
+        pub fn load() -> Result<OomControl, SysFsError> {
+            OomControl::load_in(&Context::from_env())
+        }
+
+        /// Like `load`, but reading from `<sys_root>/fs/cgroup`
+        pub fn load_in(context: &Context) -> Result<OomControl, SysFsError> {
+            OomControl::load_at(context.sys_path("fs/cgroup/memory"))
+        }
+
+        /// Like `load`, but reading from the cgroup directory `dir`
+        pub fn load_at<P: AsRef<Path>>(dir: P) -> Result<OomControl, SysFsError> {
+            let path = dir.as_ref().join("memory.oom_control");
+            OomControl::parse(&read_file(&path)?, &path)
+        }
+
+        pub(crate) fn parse(contents: &str, path: &Path) -> Result<OomControl, SysFsError> {
+            let fields = parse_keyed(contents, path)?;
+            Ok(OomControl {
+                oom_kill_disable: keyed_field(&fields, path, "oom_kill_disable")? != 0,
+                under_oom: keyed_field(&fields, path, "under_oom")? != 0,
+                oom_kill: fields.get("oom_kill").cloned(),
+            })
+        }
+    }
+
+    /// The cgroup v2 equivalent of `failcnt` and `OomControl`
+    pub use super::v2::MemoryEvents;
+
+    /// The memory.stat file
+    ///
+    /// All values are in bytes, contrary to the same file from procfs
+    /// which reports everything in pages
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Stat {
+        /// Memory charged to this cgroup itself
+        pub local: Counters,
+        /// Memory charged to this cgroup and all of its descendants, from
+        /// the `total_` fields, which are only present if hierarchical
+        /// accounting is enabled
+        pub total: Option<Counters>,
+        /// The smallest memory limit of this cgroup and its ancestors
+        pub hierarchical_memory_limit: Option<usize>,
+        /// The smallest memory plus swap limit of this cgroup and its
+        /// ancestors, only present if swap accounting is enabled
+        pub hierarchical_memsw_limit: Option<usize>,
+    }
+
+    /// The fields that memory.stat reports both for the cgroup itself and,
+    /// prefixed with `total_`, for its whole subtree
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Counters {
+        /// Memory used, including the filesystem page cache. This
+        /// number will never decrease unless memory presure gets
+        /// applied from outside the cgroup.
+        pub cache: usize,
+        /// Actual memory being used by the cgroup
+        pub rss: usize,
+        /// Only hugetables. This is included in rss
+        pub rss_huge: usize,
+        /// Number of bytes that have been swapped out to disk, only present
+        /// if swap accounting is enabled
+        pub swap: Option<usize>,
+        /// tmpfs and shared memory, included in cache
+        ///
+        /// This and the fields below are `None` if the kernel doesn't report
+        /// them, only the fields above are always there.
+        pub shmem: Option<usize>,
+        /// Page cache that is mapped into processes
+        pub mapped_file: Option<usize>,
+        /// Page cache waiting to be written back to disk
+        pub dirty: Option<usize>,
+        /// Page cache being written back to disk
+        pub writeback: Option<usize>,
+        /// Number of pages charged to the cgroup
+        pub pgpgin: Option<u64>,
+        /// Number of pages uncharged from the cgroup
+        pub pgpgout: Option<u64>,
+        /// Number of page faults
+        pub pgfault: Option<u64>,
+        /// Number of page faults that needed to read from disk
+        pub pgmajfault: Option<u64>,
+        pub inactive_anon: Option<usize>,
+        pub active_anon: Option<usize>,
+        /// Page cache that the kernel will reclaim first, which the kubelet
+        /// doesn't count as part of the working set
+        pub inactive_file: Option<usize>,
+        pub active_file: Option<usize>,
+        /// Memory that can't be reclaimed, e.g. because it is `mlock`ed
+        pub unevictable: Option<usize>,
+    }
+
+    impl Stat {
+        /// Read information from the filesystem and create a new `Stat`
+        pub fn load() -> Result<Stat, SysFsError> {
+            Stat::load_in(&Context::from_env())
+        }
+
+        /// Like `load`, but reading from `<sys_root>/fs/cgroup`
+        pub fn load_in(context: &Context) -> Result<Stat, SysFsError> {
+            Stat::load_at(context.sys_path("fs/cgroup/memory"))
+        }
+
+        /// Like `load`, but reading from the cgroup directory `dir`
+        pub fn load_at<P: AsRef<Path>>(dir: P) -> Result<Stat, SysFsError> {
+            let path = dir.as_ref().join("memory.stat");
+            Stat::parse(&read_file(&path)?, &path)
+        }
+
+        pub(crate) fn parse(contents: &str, path: &Path) -> Result<Stat, SysFsError> {
+            let fields = parse_keyed(contents, path)?;
+            let total = if fields.contains_key("total_rss") {
+                Some(Counters::from_fields(&fields, path, "total_")?)
+            } else {
+                None
+            };
+            let limit = |name| fields.get(name).map(|&v| v as usize);
+            Ok(Stat {
+                local: Counters::from_fields(&fields, path, "")?,
+                total,
+                hierarchical_memory_limit: limit("hierarchical_memory_limit"),
+                hierarchical_memsw_limit: limit("hierarchical_memsw_limit"),
+            })
+        }
+
+        /// The counters for the whole subtree if the kernel reports them,
+        /// otherwise for this cgroup alone
+        pub fn hierarchical(&self) -> &Counters {
+            self.total.as_ref().unwrap_or(&self.local)
+        }
+    }
+
+    impl Counters {
+        fn from_fields(
+            fields: &HashMap<String, u64>,
+            path: &Path,
+            prefix: &str,
+        ) -> Result<Counters, SysFsError> {
+            let bytes = |name: &str| {
+                keyed_field(fields, path, &format!("{}{}", prefix, name)).map(|v| v as usize)
+            };
+            // only these have been in memory.stat since cgroups were added
+            let count = |name: &str| fields.get(&format!("{}{}", prefix, name)).cloned();
+            let optional = |name: &str| count(name).map(|v| v as usize);
+            Ok(Counters {
+                cache: bytes("cache")?,
+                rss: bytes("rss")?,
+                rss_huge: bytes("rss_huge")?,
+                swap: optional("swap"),
+                shmem: optional("shmem"),
+                mapped_file: optional("mapped_file"),
+                dirty: optional("dirty"),
+                writeback: optional("writeback"),
+                pgpgin: count("pgpgin"),
+                pgpgout: count("pgpgout"),
+                pgfault: count("pgfault"),
+                pgmajfault: count("pgmajfault"),
+                inactive_anon: optional("inactive_anon"),
+                active_anon: optional("active_anon"),
+                inactive_file: optional("inactive_file"),
+                active_file: optional("active_file"),
+                unevictable: optional("unevictable"),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use std::path::Path;
+
+    use std::time::Duration;
+
+    use super::{
+        cpu, cpuacct, memory, CGroup, CpuThrottling, MemoryEventCounts, MemoryStat, Version,
+    };
+    use crate::context::{fixtures, fixtures_v2, Context};
+    use crate::linux::UserHz;
+    use crate::procfs::pid::{CGroupMembership, MountInfo};
+    use crate::procfs::PressureResource;
+    use crate::sys::SysFsError;
+
+    #[test]
+    fn loads_cgroups_from_root() {
+        assert_eq!(cpu::shares_in(&fixtures()).unwrap(), 2048);
+        let stat = cpuacct::Stat::load_in(&fixtures()).unwrap();
+        assert_eq!(stat.total(), UserHz::new(1500));
+        assert_eq!(
+            memory::limit_in_bytes_in(&fixtures()).unwrap(),
+            1024 * 1024 * 1024
+        );
+        assert_eq!(
+            memory::Stat::load_in(&fixtures()).unwrap().local.rss,
+            512 * 1024 * 1024
+        );
+    }
+
+    #[test]
+    fn detects_the_hierarchy() {
+        assert_eq!(Version::detect_in(&fixtures()), Version::V1);
+        assert_eq!(Version::detect_in(&fixtures_v2()), Version::V2);
+        assert_eq!(
+            CGroup::detect_in(&fixtures_v2()),
+            CGroup::V2 {
+                path: fixtures_v2().sys_path("fs/cgroup")
+            }
+        );
+    }
+
+    #[test]
+    fn reads_both_hierarchies_the_same_way() {
+        let v1 = CGroup::detect_in(&fixtures());
+        assert_eq!(v1.cpu_shares().unwrap(), 2048);
+        assert_eq!(v1.memory_limit().unwrap(), Some(1024 * 1024 * 1024));
+        assert_eq!(v1.memory_usage().unwrap(), 768 * 1024 * 1024);
+        assert_eq!(v1.memory_stat().unwrap().rss, 512 * 1024 * 1024);
+        let usage = v1.cpu_usage().unwrap();
+        assert_eq!(
+            usage.total().as_nanos() as u64,
+            1500 * 1_000_000_000 / *crate::linux::USER_HZ
+        );
+
+        let v2 = CGroup::detect_in(&fixtures_v2());
+        assert_eq!(v2.cpu_shares().unwrap(), 998);
+        assert_eq!(v2.memory_limit().unwrap(), None);
+        assert_eq!(v2.memory_usage().unwrap(), 768 * 1024 * 1024);
+        assert_eq!(
+            v2.memory_stat().unwrap(),
+            MemoryStat {
+                rss: 512 * 1024 * 1024,
+                cache: 256 * 1024 * 1024,
+                rss_huge: 0,
+                shmem: Some(0),
+                mapped_file: Some(1024 * 1024),
+                active_file: Some(128 * 1024 * 1024),
+                inactive_file: Some(128 * 1024 * 1024),
+            }
+        );
+        let usage = v2.cpu_usage().unwrap();
+        assert_eq!(usage.user, Duration::from_secs(12));
+        assert_eq!(usage.system, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn reads_pressure() {
+        let v2 = CGroup::at_path_in("system.slice/docker-abc123.scope", &fixtures_v2()).unwrap();
+        let pressure = v2.pressure(PressureResource::Memory).unwrap();
+        assert_eq!(pressure.some.avg10, 45.0);
+        assert_eq!(pressure.full.unwrap().avg300, 8.0);
+
+        let v1 = CGroup::detect_in(&fixtures());
+        let err = v1.pressure(PressureResource::Cpu).unwrap_err();
+        assert_eq!(err.to_string(), "not supported on cgroup v1");
+    }
+
+    #[test]
+    fn reads_memory_events() {
+        let v1 = CGroup::detect_in(&fixtures());
+        assert_eq!(
+            v1.memory_events().unwrap(),
+            MemoryEventCounts {
+                max: 12,
+                oom_kill: Some(2),
+            }
+        );
+        let v2 = CGroup::detect_in(&fixtures_v2());
+        assert_eq!(
+            v2.memory_events().unwrap(),
+            MemoryEventCounts {
+                max: 3,
+                oom_kill: Some(1),
+            }
+        );
+
+        let path = Path::new("memory.oom_control");
+        let old = memory::OomControl::parse("oom_kill_disable 1\nunder_oom 0\n", path).unwrap();
+        assert!(old.oom_kill_disable);
+        assert_eq!(old.oom_kill, None);
+        assert!(memory::OomControl::parse("under_oom 0\n", path).is_err());
+    }
+
+    #[test]
+    fn parses_hierarchical_memory_stats() {
+        let stat = memory::Stat::load_in(&fixtures()).unwrap();
+        assert_eq!(stat.local.inactive_file, Some(128 * 1024 * 1024));
+        assert_eq!(stat.local.swap, Some(0));
+        assert_eq!(stat.total.as_ref(), Some(&stat.local));
+        assert_eq!(stat.hierarchical_memory_limit, Some(1024 * 1024 * 1024));
+
+        // without hierarchical accounting or swap accounting
+        let local = concat!(
+            "cache 100\nrss 200\nrss_huge 0\nshmem 0\nmapped_file 0\ndirty 0\n",
+            "writeback 0\npgpgin 1\npgpgout 1\npgfault 1\npgmajfault 0\n",
+            "inactive_anon 0\nactive_anon 200\ninactive_file 60\nactive_file 40\n",
+            "unevictable 0\n",
+        );
+        let stat = memory::Stat::parse(local, Path::new("memory.stat")).unwrap();
+        assert_eq!(stat.total, None);
+        assert_eq!(stat.local.swap, None);
+        assert_eq!(stat.hierarchical().rss, 200);
+
+        // older kernels only have the counters that were there from the start
+        let minimal = "cache 100\nrss 200\nrss_huge 0\n";
+        let stat = memory::Stat::parse(minimal, Path::new("memory.stat")).unwrap();
+        assert_eq!(stat.local.rss, 200);
+        assert_eq!(stat.local.inactive_file, None);
+        assert_eq!(stat.local.pgfault, None);
+
+        let v1 = CGroup::detect_in(&fixtures());
+        assert_eq!(v1.memory_working_set().unwrap(), 640 * 1024 * 1024);
+    }
+
+    fn resolve(version: Version, cgroup: &str, mountinfo: &str) -> CGroup {
+        let source = Path::new("/proc/self/cgroup");
+        CGroup::resolve(
+            version,
+            &CGroupMembership::parse(cgroup, source).unwrap(),
+            &MountInfo::parse_all(mountinfo, Path::new("/proc/self/mountinfo")).unwrap(),
+            &Context::default(),
+            source,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn finds_the_current_cgroup() {
+        assert_eq!(
+            CGroup::current_in(&fixtures()).unwrap(),
+            CGroup::detect_in(&fixtures())
+        );
+        assert_eq!(
+            CGroup::current_in(&fixtures_v2()).unwrap(),
+            CGroup::detect_in(&fixtures_v2())
+        );
+    }
+
+    #[test]
+    fn resolves_nested_cgroups() {
+        // a systemd service on the host, with cpu and cpuacct co-mounted
+        let cgroup = resolve(
+            Version::V1,
+            concat!(
+                "7:memory:/system.slice/nginx.service\n",
+                "4:cpu,cpuacct:/system.slice/nginx.service\n",
+            ),
+            concat!(
+                "30 25 0:27 / /sys/fs/cgroup/memory rw - cgroup cgroup rw,memory\n",
+                "31 25 0:28 / /sys/fs/cgroup/cpu,cpuacct rw - cgroup cgroup rw,cpu,cpuacct\n",
+            ),
+        );
+        assert_eq!(
+            cgroup,
+            CGroup::V1 {
+                cpu: "/sys/fs/cgroup/cpu,cpuacct/system.slice/nginx.service".into(),
+                cpuacct: "/sys/fs/cgroup/cpu,cpuacct/system.slice/nginx.service".into(),
+                memory: "/sys/fs/cgroup/memory/system.slice/nginx.service".into(),
+            }
+        );
+
+        // a container without its own cgroup namespace, whose runtime
+        // mounted its cgroup at /sys/fs/cgroup
+        let cgroup = resolve(
+            Version::V2,
+            "0::/kubepods/pod1/abc\n",
+            "26 25 0:23 /kubepods/pod1/abc /sys/fs/cgroup rw - cgroup2 cgroup2 rw\n",
+        );
+        assert_eq!(
+            cgroup,
+            CGroup::V2 {
+                path: "/sys/fs/cgroup".into()
+            }
+        );
+
+        // the host's view of the same container
+        let cgroup = resolve(
+            Version::V2,
+            "0::/kubepods/pod1/abc\n",
+            "26 25 0:23 / /sys/fs/cgroup rw - cgroup2 cgroup2 rw\n",
+        );
+        assert_eq!(
+            cgroup,
+            CGroup::V2 {
+                path: "/sys/fs/cgroup/kubepods/pod1/abc".into()
+            }
+        );
+    }
+
+    #[test]
+    fn reads_quotas_and_throttling() {
+        let v1 = CGroup::detect_in(&fixtures());
+        let quota = v1.cpu_quota().unwrap();
+        assert_eq!(quota.cpus(), Some(1.5));
+        let throttling = v1.cpu_throttling().unwrap();
+        assert_eq!(throttling.throttled_time, Duration::from_millis(1500));
+
+        let v2 = CGroup::detect_in(&fixtures_v2());
+        assert_eq!(v2.cpu_quota().unwrap().cpus(), Some(2.0));
+        let later = v2.cpu_throttling().unwrap();
+        let earlier = CpuThrottling {
+            periods: 50,
+            throttled_periods: 15,
+            throttled_time: Duration::from_secs(1),
+        };
+        assert_eq!(later.since(&earlier).percent_throttled(), Some(10.0));
+        assert_eq!(later.since(&later).percent_throttled(), None);
+
+        let child = CGroup::at_path_in("system.slice/docker-abc123.scope", &fixtures_v2()).unwrap();
+        assert_eq!(child.cpu_quota().unwrap().quota, None);
+        assert!(child.cpu_throttling().is_err());
+        let root = CGroup::V2 {
+            path: fixtures_v2().sys_path("fs/cgroup/system.slice"),
+        };
+        assert_eq!(root.cpu_quota().unwrap().cpus(), None);
+    }
+
+    #[test]
+    fn finds_cgroups_by_path_and_container_id() {
+        let context = fixtures_v2();
+        let root = context.sys_path("fs/cgroup");
+        let abc = CGroup::V2 {
+            path: root.join("system.slice/docker-abc123.scope"),
+        };
+        assert_eq!(
+            CGroup::at_path_in("/system.slice/docker-abc123.scope", &context).unwrap(),
+            abc
+        );
+        assert_eq!(CGroup::find_container_in("abc", &context).unwrap(), abc);
+        assert!(CGroup::find_container_in("nope", &context).is_err());
+        // both containers are in a .scope
+        assert!(CGroup::find_container_in(".scope", &context).is_err());
+
+        let slice = CGroup::at_path_in("system.slice", &context).unwrap();
+        let names: Vec<_> = slice.children().unwrap().iter().map(CGroup::name).collect();
+        assert_eq!(names, ["docker-abc123.scope", "docker-def456.scope"]);
+        assert_eq!(CGroup::detect_in(&context).pids().unwrap(), [1, 42]);
+    }
+
+    #[test]
+    fn reports_missing_files() {
+        let context = Context::default().with_sys_root("/nonexistent");
+        let err = cpu::shares_in(&context).unwrap_err();
+        match err {
+            SysFsError::ReadFile(ref e) => {
+                assert_eq!(e.path, Path::new("/nonexistent/fs/cgroup/cpu/cpu.shares"))
+            }
+            _ => panic!("unexpected error: {:?}", err),
+        }
+        assert!(err
+            .to_string()
+            .starts_with("unable to read /nonexistent/fs/cgroup/cpu/cpu.shares: "));
+    }
+
+    #[test]
+    fn reports_invalid_fields() {
+        let path = Path::new("memory.stat");
+        let err = memory::Stat::parse("cache 10\nrss lots\n", path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "memory.stat line 2: invalid rss: \"lots\" is not a number: invalid digit found in string"
+        );
+
+        let err = memory::Stat::parse("cache 10\nrss 20\n", path).unwrap_err();
+        assert_eq!(err.to_string(), "memory.stat: invalid rss_huge: missing");
+
+        let path = Path::new("cpuacct.stat");
+        let err = cpuacct::Stat::parse("user 10\nsystem\n", path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cpuacct.stat line 2: invalid system: no value"
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! The cgroup v2 "unified" hierarchy
+//!
+//! Every controller's files live in the same directory, which is
+//! `/sys/fs/cgroup` itself for the root cgroup or inside a container with its
+//! own cgroup namespace.
+//!
+//! https://www.kernel.org/doc/Documentation/admin-guide/cgroup-v2.rst
+
+use std::path::Path;
+use std::time::Duration;
+
+use crate::context::Context;
+use crate::linux::Ratio;
+use crate::procfs::FieldError;
+use crate::sys::{keyed_field, parse_keyed, parse_max, parse_value, read_file, SysFsError};
+
+/// The bandwidth limit from `cpu.max`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuMax {
+    /// How much CPU time the cgroup may use every `period`, `None` if it is
+    /// unlimited
+    pub quota: Option<Duration>,
+    pub period: Duration,
+}
+
+impl CpuMax {
+    /// Read `<sys_root>/fs/cgroup/cpu.max`
+    pub fn load_in(context: &Context) -> Result<CpuMax, SysFsError> {
+        CpuMax::load_at(context.sys_path("fs/cgroup"))
+    }
+
+    /// Read `cpu.max` from the cgroup directory `dir`
+    pub fn load_at<P: AsRef<Path>>(dir: P) -> Result<CpuMax, SysFsError> {
+        let path = dir.as_ref().join("cpu.max");
+        CpuMax::parse(&read_file(&path)?, &path)
+    }
+
+    pub(crate) fn parse(contents: &str, path: &Path) -> Result<CpuMax, SysFsError> {
+        let mut parts = contents.split_whitespace();
+        let quota = parts
+            .next()
+            .ok_or_else(|| FieldError::missing(path, "quota"))?;
+        let period = parts
+            .next()
+            .ok_or_else(|| FieldError::missing(path, "period"))?;
+        let micros = |name: &str, value: &str| {
+            value
+                .parse()
+                .map(Duration::from_micros)
+                .map_err(|e| FieldError::new(path, Some(1), name, format!("{:?}: {}", value, e)))
+        };
+        Ok(CpuMax {
+            quota: match quota {
+                "max" => None,
+                quota => Some(micros("quota", quota)?),
+            },
+            period: micros("period", period)?,
+        })
+    }
+
+    /// The number of CPUs worth of time the quota allows, `None` if unlimited
+    pub fn cpus(&self) -> Option<f64> {
+        self.quota.map(|quota| quota.ratio(&self.period))
+    }
+}
+
+/// Read `<sys_root>/fs/cgroup/cpu.weight`
+pub fn cpu_weight_in(context: &Context) -> Result<u64, SysFsError> {
+    cpu_weight_at(context.sys_path("fs/cgroup"))
+}
+
+/// Read the relative CPU weight, between 1 and 10000, from the cgroup
+/// directory `dir`
+pub fn cpu_weight_at<P: AsRef<Path>>(dir: P) -> Result<u64, SysFsError> {
+    let path = dir.as_ref().join("cpu.weight");
+    parse_value(&read_file(&path)?, &path)
+}
+
+/// Convert a `cpu.weight` to the v1 `cpu.shares` it most likely came from
+///
+/// This inverts the conversion runc (and so Kubernetes) uses to set weights
+/// from shares. It is exact at the ends of the range, and within a few
+/// shares in between, e.g. 1024 shares become a weight of 39 which comes back
+/// as 998.
+pub fn weight_to_shares(weight: u64) -> u64 {
+    let weight = weight.clamp(1, 10_000);
+    2 + ((weight - 1) * 262_142 + 9_999 / 2) / 9_999
+}
+
+/// CPU time used by a cgroup, from `cpu.stat`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuStat {
+    pub usage: Duration,
+    pub user: Duration,
+    pub system: Duration,
+    /// The number of enforcement periods that have elapsed, only present if
+    /// the cpu controller is enabled
+    pub nr_periods: Option<u64>,
+    /// The number of periods in which the cgroup used up its quota
+    pub nr_throttled: Option<u64>,
+    /// The total time the cgroup spent throttled
+    pub throttled: Option<Duration>,
+}
+
+impl CpuStat {
+    /// Read `<sys_root>/fs/cgroup/cpu.stat`
+    pub fn load_in(context: &Context) -> Result<CpuStat, SysFsError> {
+        CpuStat::load_at(context.sys_path("fs/cgroup"))
+    }
+
+    /// Read `cpu.stat` from the cgroup directory `dir`
+    pub fn load_at<P: AsRef<Path>>(dir: P) -> Result<CpuStat, SysFsError> {
+        let path = dir.as_ref().join("cpu.stat");
+        CpuStat::parse(&read_file(&path)?, &path)
+    }
+
+    pub(crate) fn parse(contents: &str, path: &Path) -> Result<CpuStat, SysFsError> {
+        let fields = parse_keyed(contents, path)?;
+        let micros = |name| keyed_field(&fields, path, name).map(Duration::from_micros);
+        Ok(CpuStat {
+            usage: micros("usage_usec")?,
+            user: micros("user_usec")?,
+            system: micros("system_usec")?,
+            nr_periods: fields.get("nr_periods").cloned(),
+            nr_throttled: fields.get("nr_throttled").cloned(),
+            throttled: fields
+                .get("throttled_usec")
+                .map(|&usec| Duration::from_micros(usec)),
+        })
+    }
+}
+
+/// Read `<sys_root>/fs/cgroup/memory.max`
+pub fn memory_max_in(context: &Context) -> Result<Option<usize>, SysFsError> {
+    memory_max_at(context.sys_path("fs/cgroup"))
+}
+
+/// Read the memory limit in bytes from the cgroup directory `dir`, `None` if
+/// there is no limit
+pub fn memory_max_at<P: AsRef<Path>>(dir: P) -> Result<Option<usize>, SysFsError> {
+    let path = dir.as_ref().join("memory.max");
+    parse_max(&read_file(&path)?, &path)
+}
+
+/// Read `<sys_root>/fs/cgroup/memory.current`
+pub fn memory_current_in(context: &Context) -> Result<usize, SysFsError> {
+    memory_current_at(context.sys_path("fs/cgroup"))
+}
+
+/// Read the memory in use, including page cache, in bytes from the cgroup
+/// directory `dir`
+pub fn memory_current_at<P: AsRef<Path>>(dir: P) -> Result<usize, SysFsError> {
+    let path = dir.as_ref().join("memory.current");
+    parse_value(&read_file(&path)?, &path)
+}
+
+/// Some fields from `memory.stat`, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryStat {
+    /// Anonymous memory, the equivalent of v1's `rss`
+    pub anon: usize,
+    /// Page cache, the equivalent of v1's `cache`
+    pub file: usize,
+    /// Transparent huge pages, included in `anon`
+    pub anon_thp: usize,
+    /// tmpfs and shared memory, included in `file`
+    pub shmem: usize,
+    /// Page cache mapped into processes
+    pub file_mapped: usize,
+    pub active_file: usize,
+    /// Page cache that will be reclaimed first
+    pub inactive_file: usize,
+}
+
+impl MemoryStat {
+    /// Read `<sys_root>/fs/cgroup/memory.stat`
+    pub fn load_in(context: &Context) -> Result<MemoryStat, SysFsError> {
+        MemoryStat::load_at(context.sys_path("fs/cgroup"))
+    }
+
+    /// Read `memory.stat` from the cgroup directory `dir`
+    pub fn load_at<P: AsRef<Path>>(dir: P) -> Result<MemoryStat, SysFsError> {
+        let path = dir.as_ref().join("memory.stat");
+        MemoryStat::parse(&read_file(&path)?, &path)
+    }
+
+    pub(crate) fn parse(contents: &str, path: &Path) -> Result<MemoryStat, SysFsError> {
+        let fields = parse_keyed(contents, path)?;
+        let field = |name| keyed_field(&fields, path, name).map(|v| v as usize);
+        Ok(MemoryStat {
+            anon: field("anon")?,
+            file: field("file")?,
+            anon_thp: field("anon_thp")?,
+            shmem: field("shmem")?,
+            file_mapped: field("file_mapped")?,
+            active_file: field("active_file")?,
+            inactive_file: field("inactive_file")?,
+        })
+    }
+}
+
+/// Counts of memory events since the cgroup was created, from
+/// `memory.events`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryEvents {
+    /// Times the cgroup was reclaimed from while under its `memory.low`
+    pub low: u64,
+    /// Times the cgroup was throttled for going over `memory.high`
+    pub high: u64,
+    /// Times the cgroup hit `memory.max`
+    pub max: u64,
+    /// Times the OOM killer was invoked
+    pub oom: u64,
+    /// Processes killed by the OOM killer
+    pub oom_kill: u64,
+}
+
+impl MemoryEvents {
+    /// Read `<sys_root>/fs/cgroup/memory.events`
+    pub fn load_in(context: &Context) -> Result<MemoryEvents, SysFsError> {
+        MemoryEvents::load_at(context.sys_path("fs/cgroup"))
+    }
+
+    /// Read `memory.events` from the cgroup directory `dir`
+    pub fn load_at<P: AsRef<Path>>(dir: P) -> Result<MemoryEvents, SysFsError> {
+        let path = dir.as_ref().join("memory.events");
+        MemoryEvents::parse(&read_file(&path)?, &path)
+    }
+
+    pub(crate) fn parse(contents: &str, path: &Path) -> Result<MemoryEvents, SysFsError> {
+        let fields = parse_keyed(contents, path)?;
+        let field = |name| keyed_field(&fields, path, name);
+        Ok(MemoryEvents {
+            low: field("low")?,
+            high: field("high")?,
+            max: field("max")?,
+            oom: field("oom")?,
+            oom_kill: field("oom_kill")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use std::path::Path;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::context::fixtures_v2;
+
+    #[test]
+    fn parses_cpu_max() {
+        let path = Path::new("cpu.max");
+        let max = CpuMax::parse("max 100000\n", path).unwrap();
+        assert_eq!(max.quota, None);
+        assert_eq!(max.cpus(), None);
+        let max = CpuMax::parse("150000 100000\n", path).unwrap();
+        assert_eq!(max.quota, Some(Duration::from_millis(150)));
+        assert_eq!(max.cpus(), Some(1.5));
+        assert!(CpuMax::parse("150000\n", path).is_err());
+    }
+
+    #[test]
+    fn converts_weights_to_shares() {
+        assert_eq!(weight_to_shares(1), 2);
+        assert_eq!(weight_to_shares(39), 998);
+        assert_eq!(weight_to_shares(10_000), 262_144);
+    }
+
+    #[test]
+    fn loads_from_fixtures() {
+        let context = fixtures_v2();
+        let stat = CpuStat::load_in(&context).unwrap();
+        assert_eq!(stat.usage, Duration::from_secs(15));
+        assert_eq!(stat.nr_throttled, Some(20));
+        assert_eq!(stat.throttled, Some(Duration::from_secs(2)));
+        assert_eq!(
+            CpuMax::load_in(&context).unwrap().quota,
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(memory_max_in(&context).unwrap(), None);
+        let events = MemoryEvents::load_in(&context).unwrap();
+        assert_eq!(events.oom_kill, 1);
+    }
+}
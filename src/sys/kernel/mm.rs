@@ -0,0 +1,176 @@
+//! Memory management from `/sys/kernel/mm`
+//!
+//! https://www.kernel.org/doc/html/latest/admin-guide/mm/hugetlbpage.html
+//! and https://www.kernel.org/doc/html/latest/admin-guide/mm/transhuge.html
+
+use std::fs;
+use std::path::Path;
+
+use crate::context::Context;
+use crate::procfs::{FieldError, ReadFileError};
+use crate::sys::{parse_value, read_file, SysFsError};
+
+/// A pool of preallocated hugepages of one size
+///
+/// Counts are in pages of `size_kb`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HugePagePool {
+    /// The size of each page in KB
+    pub size_kb: usize,
+    /// The pages in the pool, `nr_hugepages`
+    pub total: u64,
+    /// Pages that are not in use
+    pub free: u64,
+    /// Free pages that have been promised to a mapping but not faulted in
+    /// yet, so can't be used by anything else
+    pub reserved: u64,
+    /// Pages allocated over `total` because of `nr_overcommit_hugepages`
+    pub surplus: u64,
+}
+
+impl HugePagePool {
+    /// Every hugepage size the kernel supports, smallest first
+    pub fn load_all() -> Result<Vec<HugePagePool>, SysFsError> {
+        HugePagePool::load_all_in(&Context::from_env())
+    }
+
+    /// Read every `<sys_root>/kernel/mm/hugepages/hugepages-<size>kB`
+    pub fn load_all_in(context: &Context) -> Result<Vec<HugePagePool>, SysFsError> {
+        let dir = context.sys_path("kernel/mm/hugepages");
+        let entries = fs::read_dir(&dir).map_err(|error| ReadFileError {
+            path: dir.clone(),
+            error,
+        })?;
+        let mut pools = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with("hugepages-")
+            })
+            .map(|entry| HugePagePool::load_at(entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        pools.sort_by_key(|pool| pool.size_kb);
+        Ok(pools)
+    }
+
+    /// Read a pool from its directory, e.g. `hugepages-2048kB`
+    pub fn load_at<P: AsRef<Path>>(dir: P) -> Result<HugePagePool, SysFsError> {
+        let dir = dir.as_ref();
+        let name = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let size_kb = name
+            .strip_prefix("hugepages-")
+            .and_then(|size| size.strip_suffix("kB"))
+            .and_then(|size| size.parse().ok())
+            .ok_or_else(|| {
+                FieldError::new(dir, None, "size", "expected a name like `hugepages-2048kB`")
+            })?;
+        let count = |file: &str| {
+            let path = dir.join(file);
+            parse_value(&read_file(&path)?, &path)
+        };
+        Ok(HugePagePool {
+            size_kb,
+            total: count("nr_hugepages")?,
+            free: count("free_hugepages")?,
+            reserved: count("resv_hugepages")?,
+            surplus: count("surplus_hugepages")?,
+        })
+    }
+
+    /// Free pages that nothing has reserved
+    pub fn available(&self) -> u64 {
+        self.free.saturating_sub(self.reserved)
+    }
+
+    /// The name the kernel gives this size, e.g. `2048kB`
+    pub fn size_name(&self) -> String {
+        format!("{}kB", self.size_kb)
+    }
+}
+
+/// Transparent hugepage settings
+///
+/// Each is one of a list of modes, e.g. `enabled` is `always`, `madvise` or
+/// `never`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransparentHugePages {
+    /// Whether THP is used for every mapping, only `madvise`d ones or never
+    pub enabled: String,
+    /// How hard page faults try to compact memory to get a hugepage
+    pub defrag: String,
+}
+
+impl TransparentHugePages {
+    pub fn load() -> Result<TransparentHugePages, SysFsError> {
+        TransparentHugePages::load_in(&Context::from_env())
+    }
+
+    /// Read `<sys_root>/kernel/mm/transparent_hugepage`
+    pub fn load_in(context: &Context) -> Result<TransparentHugePages, SysFsError> {
+        let dir = context.sys_path("kernel/mm/transparent_hugepage");
+        let mode = |file: &str| {
+            let path = dir.join(file);
+            parse_selected(&read_file(&path)?, &path)
+        };
+        Ok(TransparentHugePages {
+            enabled: mode("enabled")?,
+            defrag: mode("defrag")?,
+        })
+    }
+}
+
+/// Find the selected option in a file like `always [madvise] never`
+pub(crate) fn parse_selected(contents: &str, path: &Path) -> Result<String, SysFsError> {
+    contents
+        .split_whitespace()
+        .find_map(|option| option.strip_prefix('[')?.strip_suffix(']'))
+        .map(String::from)
+        .ok_or_else(|| {
+            let field = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            FieldError::new(path, Some(1), field, "no [selected] option").into()
+        })
+}
+
+#[cfg(test)]
+mod unit {
+    use std::path::Path;
+
+    use super::{parse_selected, HugePagePool, TransparentHugePages};
+    use crate::context::fixtures;
+
+    #[test]
+    fn parses_selected_option() {
+        let path = Path::new("/sys/kernel/mm/transparent_hugepage/defrag");
+        assert_eq!(
+            parse_selected("always defer defer+madvise [madvise] never\n", path).unwrap(),
+            "madvise"
+        );
+        let err = parse_selected("always madvise never\n", path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "/sys/kernel/mm/transparent_hugepage/defrag line 1: invalid defrag: \
+             no [selected] option"
+        );
+    }
+
+    #[test]
+    fn loads_from_fixtures() {
+        let pools = HugePagePool::load_all_in(&fixtures()).unwrap();
+        assert_eq!(pools.len(), 2);
+        assert_eq!(pools[0].size_name(), "2048kB");
+        assert_eq!(pools[0].total, 64);
+        assert_eq!(pools[0].available(), 8);
+        assert_eq!(pools[1].size_kb, 1_048_576);
+        let thp = TransparentHugePages::load_in(&fixtures()).unwrap();
+        assert_eq!(thp.enabled, "never");
+        assert_eq!(thp.defrag, "madvise");
+    }
+}
@@ -0,0 +1,124 @@
+//! Interact with the `/sys` pseudo-file system
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use derive_more::From;
+
+use crate::procfs::{self, FieldError, ProcFsError, ReadFileError};
+use crate::sys::fs::cgroup::Version;
+
+/// Errors reading from sysfs
+#[derive(Debug, From)]
+pub enum SysFsError {
+    /// A file could not be read, usually because this cgroup controller is
+    /// not mounted
+    ReadFile(ReadFileError),
+    /// A field was missing or could not be parsed
+    InvalidField(FieldError),
+    /// A file in procfs that says where things are in sysfs could not be
+    /// read
+    ProcFs(ProcFsError),
+    /// No cgroup, or more than one, matched a search
+    CGroupNotFound(String),
+    /// The cgroup hierarchy doesn't provide what was asked for
+    UnsupportedVersion(Version),
+}
+
+impl fmt::Display for SysFsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            SysFsError::ReadFile(ref e) => write!(f, "{}", e),
+            SysFsError::InvalidField(ref e) => write!(f, "{}", e),
+            SysFsError::ProcFs(ref e) => write!(f, "{}", e),
+            SysFsError::CGroupNotFound(ref e) => write!(f, "{}", e),
+            SysFsError::UnsupportedVersion(version) => {
+                write!(f, "not supported on cgroup {}", version)
+            }
+        }
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, SysFsError> {
+    Ok(procfs::read_file(path)?)
+}
+
+/// Parse a file that holds a single value, like `cpu.shares`
+fn parse_value<T>(contents: &str, path: &Path) -> Result<T, SysFsError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let field = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let value = contents.trim();
+    value.parse().map_err(|e| {
+        FieldError::new(
+            path,
+            Some(1),
+            field,
+            format!("{:?} is not a number: {}", value, e),
+        )
+        .into()
+    })
+}
+
+/// Parse a single value that may be the literal `max`, meaning "no limit"
+fn parse_max<T>(contents: &str, path: &Path) -> Result<Option<T>, SysFsError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    if contents.trim() == "max" {
+        Ok(None)
+    } else {
+        parse_value(contents, path).map(Some)
+    }
+}
+
+/// Parse a file made of `name value` lines, like `memory.stat`
+fn parse_keyed(contents: &str, path: &Path) -> Result<HashMap<String, u64>, SysFsError> {
+    let mut fields = HashMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        let mut parts = line.split_whitespace();
+        let name = match parts.next() {
+            Some(name) => name,
+            None => continue,
+        };
+        let value = parts
+            .next()
+            .ok_or_else(|| FieldError::new(path, Some(i + 1), name, "no value"))?;
+        let value = value.parse().map_err(|e| {
+            FieldError::new(
+                path,
+                Some(i + 1),
+                name,
+                format!("{:?} is not a number: {}", value, e),
+            )
+        })?;
+        fields.insert(name.to_owned(), value);
+    }
+    Ok(fields)
+}
+
+/// Take a field out of the result of `parse_keyed`
+fn keyed_field(fields: &HashMap<String, u64>, path: &Path, name: &str) -> Result<u64, SysFsError> {
+    fields
+        .get(name)
+        .cloned()
+        .ok_or_else(|| FieldError::missing(path, name).into())
+}
+
+pub mod fs {
+    //! Interact with the /sys/fs file system
+    pub mod cgroup;
+}
+
+pub mod kernel {
+    //! Interact with the /sys/kernel file system
+    pub mod mm;
+}
@@ -0,0 +1,261 @@
+//! Nagios-style threshold ranges
+//!
+//! Thresholds use the [nagios plugin guidelines][] range format:
+//!
+//! | Range     | Alert if the value is        |
+//! |-----------|------------------------------|
+//! | `10`      | `< 0` or `> 10`              |
+//! | `10:`     | `< 10`                       |
+//! | `~:10`    | `> 10`                       |
+//! | `10:20`   | `< 10` or `> 20`             |
+//! | `@10:20`  | `>= 10` and `<= 20`          |
+//!
+//! [nagios plugin guidelines]: https://nagios-plugins.org/doc/guidelines.html#THRESHOLDFORMAT
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::{TabinError, TabinResult};
+
+/// A range of values that are either acceptable or, with `@`, alertable
+///
+/// ```
+/// use tabin_plugins::Threshold;
+///
+/// let low_memory: Threshold = "512:".parse().unwrap();
+/// assert!(low_memory.is_violated(128.0));
+/// assert!(!low_memory.is_violated(1024.0));
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Threshold {
+    /// The lowest value in the range, possibly negative infinity
+    pub start: f64,
+    /// The highest value in the range, possibly infinity
+    pub end: f64,
+    /// Alert if the value is inside the range, instead of outside it
+    pub inside: bool,
+}
+
+impl Threshold {
+    /// Alert if the value is greater than `max`, the meaning of a bare number
+    pub fn above(max: f64) -> Threshold {
+        Threshold {
+            start: 0.0,
+            end: max,
+            inside: false,
+        }
+    }
+
+    /// Alert if the value is less than `min`
+    pub fn below(min: f64) -> Threshold {
+        Threshold {
+            start: min,
+            end: f64::INFINITY,
+            inside: false,
+        }
+    }
+
+    /// Should a value trigger an alert
+    pub fn is_violated(&self, value: f64) -> bool {
+        let in_range = self.start <= value && value <= self.end;
+        in_range == self.inside
+    }
+
+    /// Multiply both ends of the range by `factor`
+    pub fn scale(self, factor: f64) -> Threshold {
+        Threshold {
+            start: self.start * factor,
+            end: self.end * factor,
+            ..self
+        }
+    }
+
+    /// A short description of the values that trigger an alert, e.g. `> 80`
+    pub fn describe(&self) -> String {
+        self.description(self.inside)
+    }
+
+    /// A short description of the values that do not trigger an alert, e.g. `<= 80`
+    pub fn describe_ok(&self) -> String {
+        self.description(!self.inside)
+    }
+
+    fn description(&self, inside: bool) -> String {
+        let (start, end) = (self.start, self.end);
+        // A lower bound of 0 is implied by a bare number, but nobody needs to
+        // be told that e.g. a percentage below 0 is bad
+        let unbounded_below = start == f64::NEG_INFINITY || (start == 0.0 && !self.inside);
+        match (inside, unbounded_below, end == f64::INFINITY) {
+            (_, true, true) => if inside { "anything" } else { "nothing" }.to_owned(),
+            (false, true, false) => format!("> {}", end),
+            (true, true, false) => format!("<= {}", end),
+            (false, false, true) => format!("< {}", start),
+            (true, false, true) => format!(">= {}", start),
+            (false, false, false) => format!("outside {}:{}", start, end),
+            (true, false, false) => format!("inside {}:{}", start, end),
+        }
+    }
+}
+
+impl From<f64> for Threshold {
+    fn from(max: f64) -> Threshold {
+        Threshold::above(max)
+    }
+}
+
+fn parse_bound(s: &str, range: &str) -> TabinResult<f64> {
+    s.parse().map_err(|_| {
+        TabinError::UnknownValue(format!(
+            "Invalid threshold {:?}: {:?} is not a number",
+            range, s
+        ))
+    })
+}
+
+impl FromStr for Threshold {
+    type Err = TabinError;
+
+    fn from_str(range: &str) -> TabinResult<Threshold> {
+        let (inside, rest) = match range.strip_prefix('@') {
+            Some(rest) => (true, rest),
+            None => (false, range),
+        };
+        let (start, end) = match rest.find(':') {
+            Some(i) => {
+                let (start, end) = (&rest[..i], &rest[i + 1..]);
+                let start = match start {
+                    "~" => f64::NEG_INFINITY,
+                    "" => 0.0,
+                    start => parse_bound(start, range)?,
+                };
+                let end = match end {
+                    "" => f64::INFINITY,
+                    end => parse_bound(end, range)?,
+                };
+                (start, end)
+            }
+            None => (0.0, parse_bound(rest, range)?),
+        };
+        if start > end {
+            return Err(TabinError::UnknownValue(format!(
+                "Invalid threshold {:?}: start is greater than end",
+                range
+            )));
+        }
+        Ok(Threshold { start, end, inside })
+    }
+}
+
+impl TryFrom<String> for Threshold {
+    type Error = TabinError;
+
+    fn try_from(range: String) -> TabinResult<Threshold> {
+        range.parse()
+    }
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.inside {
+            write!(f, "@")?;
+        }
+        match (self.start, self.end) {
+            (start, end) if start == 0.0 && end != f64::INFINITY => write!(f, "{}", end),
+            (start, end) => {
+                if start == f64::NEG_INFINITY {
+                    write!(f, "~:")?;
+                } else {
+                    write!(f, "{}:", start)?;
+                }
+                if end != f64::INFINITY {
+                    write!(f, "{}", end)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use super::*;
+
+    fn t(range: &str) -> Threshold {
+        range.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_nagios_ranges() {
+        assert_eq!(t("10"), Threshold::above(10.0));
+        assert_eq!(t("10:"), Threshold::below(10.0));
+        assert_eq!(
+            t("~:10"),
+            Threshold {
+                start: f64::NEG_INFINITY,
+                end: 10.0,
+                inside: false
+            }
+        );
+        assert_eq!(
+            t("@10:20.5"),
+            Threshold {
+                start: 10.0,
+                end: 20.5,
+                inside: true
+            }
+        );
+        assert!("20:10".parse::<Threshold>().is_err());
+        assert!("ten".parse::<Threshold>().is_err());
+        assert!("@".parse::<Threshold>().is_err());
+    }
+
+    #[test]
+    fn checks_values() {
+        assert!(t("10").is_violated(-1.0));
+        assert!(!t("10").is_violated(10.0));
+        assert!(t("10").is_violated(10.1));
+
+        assert!(t("10:").is_violated(9.0));
+        assert!(!t("10:").is_violated(1e9));
+
+        assert!(!t("~:10").is_violated(-1e9));
+        assert!(t("~:10").is_violated(11.0));
+
+        assert!(t("10:20").is_violated(9.0));
+        assert!(!t("10:20").is_violated(15.0));
+        assert!(t("10:20").is_violated(21.0));
+
+        assert!(!t("@10:20").is_violated(9.0));
+        assert!(t("@10:20").is_violated(10.0));
+        assert!(t("@10:20").is_violated(20.0));
+        assert!(!t("@10:20").is_violated(21.0));
+    }
+
+    #[test]
+    fn displays_as_parsed() {
+        for range in &["10", "10:", "~:10", "10:20", "@10:20", "@~:0.5", "-5:5"] {
+            assert_eq!(t(range).to_string(), *range);
+        }
+    }
+
+    #[test]
+    fn describes_ranges() {
+        assert_eq!(t("80").describe(), "> 80");
+        assert_eq!(t("80").describe_ok(), "<= 80");
+        assert_eq!(t("10:").describe(), "< 10");
+        assert_eq!(t("10:").describe_ok(), ">= 10");
+        assert_eq!(t("10:20").describe(), "outside 10:20");
+        assert_eq!(t("@10:20").describe(), "inside 10:20");
+        assert_eq!(t("@10:20").describe_ok(), "outside 10:20");
+    }
+
+    #[test]
+    fn scales() {
+        assert_eq!(t("10:20").scale(2.0), t("20:40"));
+        assert_eq!(t("10:").scale(2.0), t("20:"));
+    }
+}
@@ -0,0 +1,94 @@
+//! Give up on checks that take too long
+//!
+//! Some of the things that checks do can block forever, `statvfs` on a dead
+//! NFS mount for example. The watchdog runs on its own thread, and if the
+//! check has not finished by its deadline it prints a result saying which
+//! phase the check was stuck in and exits.
+//!
+//! Checks record what they are doing with `phase`:
+//!
+//! ```
+//! use tabin_plugins::watchdog;
+//!
+//! watchdog::phase("reading mounts");
+//! assert_eq!(watchdog::current_phase(), "reading mounts");
+//! ```
+
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+
+use crate::check::CheckResult;
+use crate::output::Format;
+use crate::Status;
+
+lazy_static! {
+    static ref PHASE: Mutex<String> = Mutex::new("starting".to_owned());
+}
+
+static FINISHED: AtomicBool = AtomicBool::new(false);
+
+/// Record what the check is doing, so that a timeout can say what was slow
+///
+/// Descriptions should read well after "while", e.g. `checking /mnt/nfs`.
+pub fn phase<S: Into<String>>(description: S) {
+    *PHASE.lock().unwrap_or_else(|e| e.into_inner()) = description.into();
+}
+
+/// The most recent description passed to `phase`
+pub fn current_phase() -> String {
+    PHASE.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Claim the right to print a result and exit
+///
+/// Only the first caller gets `true`, so that a check that finishes just as
+/// the watchdog fires does not print two results.
+pub fn finish() -> bool {
+    !FINISHED.swap(true, Ordering::SeqCst)
+}
+
+/// Exit with `status` if the process is still running after `timeout`
+pub fn install(timeout: Duration, status: Status, format: Format) {
+    let started = Instant::now();
+    thread::Builder::new()
+        .name("watchdog".to_owned())
+        .spawn(move || {
+            thread::sleep(timeout);
+            if !finish() {
+                return;
+            }
+            let mut result = timed_out(timeout, status, &current_phase());
+            result.duration = Some(started.elapsed());
+            print!("{}", format.render(&result));
+            process::exit(status.code());
+        })
+        .expect("unable to start the watchdog thread");
+}
+
+fn timed_out(timeout: Duration, status: Status, phase: &str) -> CheckResult {
+    CheckResult::new(
+        status,
+        format!("timed out after {}s while {}", timeout.as_secs_f64(), phase),
+    )
+}
+
+#[cfg(test)]
+mod unit {
+    use super::*;
+
+    #[test]
+    fn describes_the_phase() {
+        let result = timed_out(
+            Duration::from_millis(2500),
+            Status::Critical,
+            "checking /nfs",
+        );
+        assert_eq!(result.status, Status::Critical);
+        assert_eq!(result.summary, "timed out after 2.5s while checking /nfs");
+    }
+}